@@ -0,0 +1,191 @@
+//! Latency of the policy evaluation done by sr at each command.
+//!
+//! `cargo xtask bench` runs it on synthetic policies of several sizes: parsing and linking the
+//! policy, resolving the options of a task and matching a command. The results are written to
+//! `target/bench/policy.json` and compared with `target/bench/policy-baseline.json`, saved with
+//! `--save-baseline`. With `--check`, a slowdown over the tolerance fails the run.
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+    process::exit,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use rootasrole_core::database::{
+    capsets,
+    finder::{Cred, TaskMatcher},
+    make_weak_config,
+    options::OptStack,
+    structs::SConfig,
+};
+use serde_json::{json, Value};
+
+const SAMPLES: usize = 30;
+const SAMPLE_TIME: Duration = Duration::from_millis(5);
+// the noise of a shared machine is often above 10%
+const TOLERANCE: f64 = 0.15;
+
+type Bench<'a> = Box<dyn FnMut() + 'a>;
+
+/// roles, tasks per role, commands per task
+const SIZES: [(&str, usize, usize, usize); 3] = [
+    ("small", 5, 5, 5),
+    ("medium", 50, 20, 10),
+    ("huge", 500, 50, 10),
+];
+
+fn command(role: usize, task: usize, command: usize) -> String {
+    format!("/usr/bin/ls -l /r{}/t{}/c{}", role, task, command)
+}
+
+// only the last role is granted to the caller, so that every role is visited
+fn policy(roles: usize, tasks: usize, commands: usize) -> String {
+    let roles: Vec<Value> = (0..roles)
+        .map(|r| {
+            json!({
+                "name": format!("r_{}", r),
+                "actors": [{ "type": "user", "name": if r + 1 == roles { "root" } else { "nobody" } }],
+                "options": { "env": { "default": "delete", "keep": ["EDITOR"] } },
+                "tasks": (0..tasks).map(|t| json!({
+                    "name": format!("t_{}", t),
+                    "cred": { "setuid": "root", "capabilities": ["@network", "cap_sys_nice"] },
+                    "commands": {
+                        "default": "none",
+                        "add": (0..commands).map(|c| command(r, t, c)).collect::<Vec<_>>(),
+                    },
+                    "options": { "timeout": { "type": "ppid", "duration": "00:05:00" } },
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    json!({
+        "options": { "path": { "default": "delete", "add": ["/usr/bin", "/bin"] } },
+        "roles": roles,
+    })
+    .to_string()
+}
+
+fn load(policy: &str) -> Rc<RefCell<SConfig>> {
+    let config: Rc<RefCell<SConfig>> = serde_json::from_str(policy).expect("invalid policy");
+    capsets::resolve(&config).expect("invalid capability sets");
+    make_weak_config(&config);
+    config
+}
+
+/// The median and the fastest time of one run of `f`, in nanoseconds.
+fn measure(mut f: impl FnMut()) -> (u64, u64) {
+    let start = Instant::now();
+    f();
+    let once = start.elapsed().max(Duration::from_nanos(1));
+    let iterations = (SAMPLE_TIME.as_nanos() / once.as_nanos()).max(1) as u32;
+    let mut samples: Vec<u64> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                f();
+            }
+            (start.elapsed() / iterations).as_nanos() as u64
+        })
+        .collect();
+    samples.sort_unstable();
+    (samples[SAMPLES / 2], samples[0])
+}
+
+fn report_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).with_file_name("target"))
+        .join("bench")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filter = args.iter().skip(1).find(|arg| !arg.starts_with("--"));
+    let cred = Cred::builder().user_id(0).build();
+    let mut results: BTreeMap<String, Value> = BTreeMap::new();
+    for (size, roles, tasks, commands) in SIZES {
+        let policy = policy(roles, tasks, commands);
+        let config = load(&policy);
+        let last = command(roles - 1, tasks - 1, commands - 1);
+        let last: Vec<String> = shell_words::split(&last).unwrap();
+        config
+            .matches(&cred, &None, &last)
+            .expect("the last command must match");
+        let task = config.as_ref().borrow().roles[roles - 1]
+            .as_ref()
+            .borrow()
+            .tasks[tasks - 1]
+            .clone();
+        let benches: [(&str, Bench); 3] = [
+            ("load", Box::new(|| drop(black_box(load(&policy))))),
+            (
+                "options",
+                Box::new(|| drop(black_box(OptStack::from_task(task.clone()).to_opt()))),
+            ),
+            (
+                "matching",
+                Box::new(|| drop(black_box(config.matches(&cred, &None, &last)))),
+            ),
+        ];
+        for (name, f) in benches {
+            let name = format!("{}/{}", name, size);
+            if filter.is_some_and(|filter| !name.contains(filter.as_str())) {
+                continue;
+            }
+            let (median, min) = measure(f);
+            println!("{:<20} median {:>12} ns   min {:>12} ns", name, median, min);
+            results.insert(name, json!({ "median-ns": median, "min-ns": min }));
+        }
+    }
+    let dir = report_dir();
+    fs::create_dir_all(&dir).expect("cannot create the report directory");
+    let report = serde_json::to_string_pretty(&results).unwrap();
+    fs::write(dir.join("policy.json"), &report).expect("cannot write the report");
+    let path = dir.join("policy-baseline.json");
+    let mut baseline = fs::read_to_string(&path)
+        .ok()
+        .and_then(|baseline| serde_json::from_str::<BTreeMap<String, Value>>(&baseline).ok())
+        .unwrap_or_default();
+    if args.iter().any(|arg| arg == "--save-baseline") {
+        // a filtered run only replaces its own benchmarks
+        baseline.extend(results);
+        let baseline = serde_json::to_string_pretty(&baseline).unwrap();
+        fs::write(&path, baseline).expect("cannot write the baseline");
+        println!("Baseline saved to {}", path.display());
+        return;
+    }
+    if baseline.is_empty() {
+        println!("No baseline, save one with --save-baseline");
+        return;
+    }
+    let mut regressions = 0;
+    for (name, result) in results.iter() {
+        let (Some(before), Some(after)) = (
+            baseline.get(name).and_then(|b| b["median-ns"].as_u64()),
+            result["median-ns"].as_u64(),
+        ) else {
+            continue;
+        };
+        let change = after as f64 / before.max(1) as f64 - 1.0;
+        let regressed = change > TOLERANCE;
+        regressions += regressed as usize;
+        println!(
+            "{:<20} {:>+7.1}%{}",
+            name,
+            change * 100.0,
+            if regressed { "   regressed" } else { "" }
+        );
+    }
+    if regressions > 0 && args.iter().any(|arg| arg == "--check") {
+        eprintln!(
+            "{} benchmarks regressed by more than {}%",
+            regressions,
+            TOLERANCE * 100.0
+        );
+        exit(1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Separation of the policy edition from its deployment.
+//!
+//! When activation is configured, sr does not read the policy file but the activation marker,
+//! written by `chsr activate` with a copy of the policy and an HMAC-SHA256 signature made with
+//! a key only readable by root. Edited policies are thus only used once activated.
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    io::Read,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use bon::Builder;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::{capsets, make_weak_config, structs::SConfig, versionning::Versioning},
+    integrity::policy_path,
+    util::{open_with_privileges, write_json_config},
+    SettingsFile,
+};
+
+const MARKER: &str = "/etc/security/rootasrole.activated";
+const KEY: &str = "/etc/security/rootasrole.key";
+
+fn default_marker() -> PathBuf {
+    MARKER.into()
+}
+
+fn default_key() -> PathBuf {
+    KEY.into()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActivationSettings {
+    #[serde(default = "default_marker")]
+    #[builder(default = default_marker(), into)]
+    pub marker: PathBuf,
+    #[serde(default = "default_key")]
+    #[builder(default = default_key(), into)]
+    pub key: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActivationMarker {
+    pub generation: u64,
+    /// The activated policy file, as is
+    pub policy: String,
+    pub signature: String,
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+// constant time comparison, the signature must not be guessed byte per byte
+pub(crate) fn same_signature(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn signature(key: &[u8], generation: u64, policy: &str) -> String {
+    let message = format!("{}\n{}", generation, policy);
+    hex::encode(hmac_sha256(key, message.as_bytes()))
+}
+
+impl ActivationMarker {
+    pub fn new(key: &[u8], generation: u64, policy: String) -> Self {
+        let signature = signature(key, generation, &policy);
+        ActivationMarker {
+            generation,
+            policy,
+            signature,
+        }
+    }
+
+    pub fn verify(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        let expected = signature(key, self.generation, &self.policy);
+        if same_signature(&expected, &self.signature) {
+            Ok(())
+        } else {
+            Err("The activation marker signature is invalid".into())
+        }
+    }
+
+    pub fn config(&self) -> Result<Rc<RefCell<SConfig>>, Box<dyn Error>> {
+        let versionned: Versioning<Rc<RefCell<SConfig>>> = serde_json::from_str(&self.policy)?;
+        capsets::resolve(&versionned.data)?;
+        make_weak_config(&versionned.data);
+        Ok(versionned.data)
+    }
+}
+
+fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String, Box<dyn Error>> {
+    let mut content = String::new();
+    open_with_privileges(path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+pub(crate) fn read_key(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut file = open_with_privileges(path)?;
+    let metadata = file.metadata()?;
+    if metadata.uid() != 0 || metadata.mode() & 0o077 != 0 {
+        return Err(format!(
+            "{} must be owned by root and only readable by root",
+            path.display()
+        )
+        .into());
+    }
+    let mut key = Vec::new();
+    file.read_to_end(&mut key)?;
+    if key.len() < 32 {
+        return Err(format!("{} must hold at least 32 bytes", path.display()).into());
+    }
+    Ok(key)
+}
+
+fn settings_of(settings: &SettingsFile) -> Result<&ActivationSettings, Box<dyn Error>> {
+    settings
+        .storage
+        .activation
+        .as_ref()
+        .ok_or_else(|| "Activation is not configured in the storage settings".into())
+}
+
+/// Sign the current policy file into the activation marker, returns the activated generation.
+pub fn activate<P: AsRef<Path>>(
+    settings: &SettingsFile,
+    settings_path: P,
+) -> Result<u64, Box<dyn Error>> {
+    let activation = settings_of(settings)?;
+    let policy = read_to_string(policy_path(settings, settings_path))?;
+    let versionned: Versioning<SConfig> = serde_json::from_str(&policy)?;
+    let generation = versionned.data.generation.unwrap_or_default();
+    let marker = ActivationMarker::new(&read_key(&activation.key)?, generation, policy);
+    write_json_config(&marker, &activation.marker)?;
+    Ok(generation)
+}
+
+/// The last activated policy, refused when its marker is not signed with the activation key.
+pub fn activated_config<P: AsRef<Path>>(
+    settings: &SettingsFile,
+    settings_path: P,
+) -> Result<Rc<RefCell<SConfig>>, Box<dyn Error>> {
+    let activation = settings_of(settings)?;
+    let marker: ActivationMarker = serde_json::from_str(
+        &read_to_string(&activation.marker)
+            .map_err(|e| format!("No activated policy, run chsr activate: {}", e))?,
+    )?;
+    marker.verify(&read_key(&activation.key)?)?;
+    if read_to_string(policy_path(settings, settings_path)).is_ok_and(|p| p != marker.policy) {
+        info!(
+            "The policy was edited since the activation of generation {}, run chsr activate to use it",
+            marker.generation
+        );
+    }
+    marker.config()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_marker() {
+        let key = [7u8; 32];
+        let policy = r#"{"version":"3.0.5","generation":4,"roles":[{"name":"r1"}]}"#;
+        let marker = ActivationMarker::new(&key, 4, policy.to_string());
+        assert!(marker.verify(&key).is_ok());
+        assert!(marker.verify(&[8u8; 32]).is_err());
+        let config = marker.config().unwrap();
+        assert_eq!(config.as_ref().borrow().generation, Some(4));
+        assert_eq!(
+            config.as_ref().borrow().roles[0].as_ref().borrow().name,
+            "r1"
+        );
+        let mut tampered = marker.clone();
+        tampered.policy = policy.replace("r1", "r2");
+        assert!(tampered.verify(&key).is_err());
+        let mut tampered = marker;
+        tampered.generation = 3;
+        assert!(tampered.verify(&key).is_err());
+    }
+}
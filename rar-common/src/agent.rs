@@ -0,0 +1,318 @@
+//! Persistent agent answering `sr --info` from a policy kept in memory.
+//!
+//! `rootasrole-agent` runs as root, once per boot, and listens on `agent.sock` in the runtime
+//! directory, see [`crate::runtime`]. A client sends one JSON line, a [`Query`], and reads one
+//! JSON line, an [`Answer`]. The agent evaluates the query for the user of the connected
+//! process, as given by the kernel, never for a user named by the client. sr only uses it to
+//! print `--info`: a command to execute is always evaluated again by sr, and the
+//! authentication stays in sr.
+
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{
+        fd::AsRawFd,
+        unix::{
+            fs::{FileTypeExt, MetadataExt},
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::Path,
+    time::Duration,
+};
+
+use log::{debug, warn};
+use nix::unistd::{geteuid, Gid, Group, Pid, Uid, User};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{
+        actor::{SGroups, SUserType},
+        finder::{Cred, MatchError, TaskMatcher},
+        FilterMatcher,
+    },
+    policy::{Info, Policy},
+};
+
+// a query is a command line, larger ones are cut
+const MAX_LINE: u64 = 64 * 1024;
+// a client must not hold the agent
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The command and the filters of `sr -r`, `-t`, `-u` and `-g`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Query {
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<SUserType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<SGroups>,
+}
+
+impl Query {
+    pub fn new(command: &[String], filter: Option<&FilterMatcher>) -> Self {
+        Query {
+            command: command.to_vec(),
+            role: filter.and_then(|f| f.role.clone()),
+            task: filter.and_then(|f| f.task.clone()),
+            user: filter.and_then(|f| f.user.clone()),
+            group: filter.and_then(|f| f.group.clone()),
+        }
+    }
+
+    fn filter(&self) -> Option<FilterMatcher> {
+        if self.role.is_none() && self.task.is_none() && self.user.is_none() && self.group.is_none()
+        {
+            return None;
+        }
+        Some(FilterMatcher {
+            role: self.role.clone(),
+            task: self.task.clone(),
+            env_behavior: None,
+            user: self.user.clone(),
+            group: self.group.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "decision", rename_all = "lowercase")]
+pub enum Answer {
+    Allow(Info),
+    Deny,
+    Conflict,
+    Error { message: String },
+}
+
+/// What the policy answers to the query of `cred`, as sr would.
+pub fn answer(policy: &Policy, cred: &Cred, query: &Query) -> Answer {
+    match policy
+        .config()
+        .matches(cred, &query.filter(), &query.command)
+    {
+        Ok(taskmatch) if taskmatch.fully_matching() => match Info::new(&taskmatch.settings) {
+            Ok(info) => Answer::Allow(info),
+            Err(e) => Answer::Error {
+                message: e.to_string(),
+            },
+        },
+        Err(MatchError::Conflict(_)) => Answer::Conflict,
+        _ => Answer::Deny,
+    }
+}
+
+/// The credentials of the process at the other end of the socket, given by the kernel.
+fn peer(stream: &UnixStream) -> io::Result<libc::ucred> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    if unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred)
+}
+
+// the `Groups:` line of /proc/<pid>/status, what getgroups returns to the process
+fn status_groups(status: &str) -> Vec<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .map(|groups| {
+            groups
+                .split_whitespace()
+                .filter_map(|gid| gid.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The user and groups of the peer, as sr builds them for itself.
+fn peer_cred(peer: &libc::ucred) -> Result<Cred, Box<dyn Error>> {
+    let user = User::from_uid(Uid::from_raw(peer.uid))?
+        .ok_or_else(|| format!("Unknown user {}", peer.uid))?;
+    let status = fs::read_to_string(format!("/proc/{}/status", peer.pid))?;
+    let groups = std::iter::once(user.gid.as_raw())
+        .chain(status_groups(&status))
+        .filter_map(|gid| Group::from_gid(Gid::from_raw(gid)).ok().flatten())
+        .collect();
+    Ok(Cred {
+        user,
+        groups,
+        tty: None,
+        ppid: Pid::from_raw(peer.pid),
+        orchestrated: false,
+    })
+}
+
+fn read_line(stream: &UnixStream) -> io::Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_LINE)).read_line(&mut line)?;
+    Ok(line)
+}
+
+fn write_line<T: Serialize>(mut stream: &UnixStream, value: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// Answers the query of one connection.
+pub fn handle(stream: &UnixStream, policy: &Policy) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let peer = peer(stream)?;
+    let answer = match serde_json::from_str::<Query>(&read_line(stream)?) {
+        Ok(query) => match peer_cred(&peer) {
+            Ok(cred) => answer(policy, &cred, &query),
+            Err(e) => Answer::Error {
+                message: e.to_string(),
+            },
+        },
+        Err(e) => Answer::Error {
+            message: format!("Invalid query: {}", e),
+        },
+    };
+    debug!("Answer to {}: {:?}", peer.uid, answer);
+    write_line(stream, &answer)?;
+    Ok(())
+}
+
+/// Answers the connections one at a time, with the policy given by `policy` at each one.
+pub fn serve<F>(listener: &UnixListener, mut policy: F) -> io::Result<()>
+where
+    F: FnMut() -> Result<Policy, Box<dyn Error>>,
+{
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Unable to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let result = policy().and_then(|policy| handle(&stream, &policy));
+        if let Err(e) = result {
+            warn!("Unable to answer a query: {}", e);
+            let _ = write_line(
+                &stream,
+                &Answer::Error {
+                    message: e.to_string(),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+// only root, or the user itself, may answer, the answer being shown to that user
+fn trusted(uid: u32) -> bool {
+    uid == 0 || uid == geteuid().as_raw()
+}
+
+/// Asks the agent listening on `path`.
+pub fn query<P: AsRef<Path>>(path: P, query: &Query) -> Result<Answer, Box<dyn Error>> {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.file_type().is_socket() || !trusted(metadata.uid()) {
+        return Err(format!("{} is not a socket of root", path.display()).into());
+    }
+    let stream = UnixStream::connect(path)?;
+    if !trusted(peer(&stream)?.uid) {
+        return Err("The agent is not run by root".into());
+    }
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write_line(&stream, query)?;
+    Ok(serde_json::from_str(&read_line(&stream)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use nix::unistd::getuid;
+
+    use super::*;
+
+    #[test]
+    fn test_status_groups() {
+        let status = "Name:\tbash\nUid:\t1000\t1000\t1000\t1000\nGroups:\t4 27 1000 \nNgid:\t0\n";
+        assert_eq!(status_groups(status), [4, 27, 1000]);
+        assert!(status_groups("Groups:\t\n").is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        let path = &std::env::temp_dir().join(format!("rar_agent_{}.sock", std::process::id()));
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path).unwrap();
+        let name = User::from_uid(getuid()).unwrap().unwrap().name;
+        let server = thread::spawn(move || {
+            // the policy is not shared between threads, it is parsed by the agent thread
+            let policy = Policy::from_json(&format!(
+                r#"{{
+                    "roles": [{{
+                        "name": "r_audit",
+                        "actors": [{{ "type": "user", "name": "{}" }}],
+                        "tasks": [{{
+                            "name": "t_ls",
+                            "cred": {{ "capabilities": ["CAP_DAC_READ_SEARCH"] }},
+                            "commands": {{ "default": "none", "add": ["/usr/bin/ls -l /root"] }}
+                        }}]
+                    }}]
+                }}"#,
+                name
+            ))
+            .unwrap();
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                handle(&stream, &policy).unwrap();
+            }
+        });
+        let command = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let Answer::Allow(info) = query(
+            path,
+            &Query::new(&command(&["/usr/bin/ls", "-l", "/root"]), None),
+        )
+        .unwrap() else {
+            panic!("ls is allowed by the policy");
+        };
+        assert_eq!(
+            (info.role.as_str(), info.task.as_str()),
+            ("r_audit", "t_ls")
+        );
+        assert_eq!(info.capabilities, ["CAP_DAC_READ_SEARCH"]);
+        assert_eq!(
+            query(path, &Query::new(&command(&["/usr/bin/cat"]), None)).unwrap(),
+            Answer::Deny
+        );
+        let filter = FilterMatcher::builder().role("r_other".to_string()).build();
+        assert_eq!(
+            query(
+                path,
+                &Query::new(&command(&["/usr/bin/ls", "-l", "/root"]), Some(&filter))
+            )
+            .unwrap(),
+            Answer::Deny
+        );
+        server.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -66,6 +66,8 @@ pub type TaskInformation = fn(task: &STask) -> Option<String>;
 pub type DutySeparation = fn(role: &SRole, actor: &Cred) -> PluginResult;
 #[cfg(feature = "finder")]
 pub type TaskSeparation = fn(task: &STask, actor: &Cred) -> PluginResult;
+#[cfg(feature = "finder")]
+pub type TaskPredicate = fn(task: &STask, actor: &Cred, command: &[String]) -> PluginResult;
 
 pub type CapsFilter = fn(task: &STask, capabilities: &mut CapSet) -> PluginResultAction;
 #[cfg(feature = "finder")]
@@ -93,6 +95,8 @@ pub struct PluginManager {
     duty_separation_plugins: Vec<DutySeparation>,
     #[cfg(feature = "finder")]
     task_separation_plugins: Vec<TaskSeparation>,
+    #[cfg(feature = "finder")]
+    task_predicate_plugins: Vec<TaskPredicate>,
     caps_filter_plugins: Vec<CapsFilter>,
     #[cfg(feature = "finder")]
     execution_checker_plugins: Vec<ExecutionChecker>,
@@ -118,6 +122,8 @@ impl PluginManager {
             duty_separation_plugins: Vec::new(),
             #[cfg(feature = "finder")]
             task_separation_plugins: Vec::new(),
+            #[cfg(feature = "finder")]
+            task_predicate_plugins: Vec::new(),
             caps_filter_plugins: Vec::new(),
             #[cfg(feature = "finder")]
             execution_checker_plugins: Vec::new(),
@@ -150,6 +156,11 @@ impl PluginManager {
         plugin_subscribe!(task_separation_plugins, TaskSeparation, plugin);
     }
 
+    #[cfg(feature = "finder")]
+    pub fn subscribe_task_predicate(plugin: TaskPredicate) {
+        plugin_subscribe!(task_predicate_plugins, TaskPredicate, plugin);
+    }
+
     pub fn subscribe_caps_filter(plugin: CapsFilter) {
         plugin_subscribe!(caps_filter_plugins, CapsFilter, plugin);
     }
@@ -172,9 +183,10 @@ impl PluginManager {
         matcher: &mut TaskMatch,
     ) -> PluginResultAction {
         debug!("Notifying role matchers");
-        let api = API.lock().unwrap();
+        // released before the plugins run, they may match the tasks of other roles
+        let plugins = API.lock().unwrap().role_matcher_plugins.clone();
         let mut result = PluginResultAction::Ignore;
-        for plugin in api.role_matcher_plugins.iter() {
+        for plugin in plugins.iter() {
             debug!("Calling role matcher plugin");
             match plugin(role, user, filter, command, matcher) {
                 PluginResultAction::Override => return PluginResultAction::Override,
@@ -239,6 +251,18 @@ impl PluginManager {
         PluginResult::Neutral
     }
 
+    #[cfg(feature = "finder")]
+    pub fn notify_task_predicate(task: &STask, actor: &Cred, command: &[String]) -> PluginResult {
+        let plugins = API.lock().unwrap().task_predicate_plugins.clone();
+        for plugin in plugins.iter() {
+            match plugin(task, actor, command) {
+                PluginResult::Deny => return PluginResult::Deny,
+                PluginResult::Neutral => continue,
+            }
+        }
+        PluginResult::Neutral
+    }
+
     pub fn notify_caps_filter(task: &STask, capabilities: &mut CapSet) -> PluginResultAction {
         let api = API.lock().unwrap();
         for plugin in api.caps_filter_plugins.iter() {
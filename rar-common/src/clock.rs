@@ -0,0 +1,129 @@
+//! Detection of clock skew.
+//!
+//! A policy file modified in the future means that the clock was set back, or that the file
+//! was forged with a future date. sr reports it, or refuses to run with `on-future-policy:
+//! refuse`, and by default forgets the authentication cookies of the caller, whose age can no
+//! longer be trusted.
+
+use std::{
+    error::Error,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use bon::Builder;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use strum::EnumIs;
+
+use crate::util::open_with_privileges;
+
+const DEFAULT_TOLERANCE: u64 = 60;
+
+fn default_tolerance() -> u64 {
+    DEFAULT_TOLERANCE
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, EnumIs)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkewAction {
+    #[default]
+    Warn,
+    Refuse,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClockSettings {
+    /// Seconds a modification time may be ahead of the clock
+    #[serde(default = "default_tolerance")]
+    #[builder(default = DEFAULT_TOLERANCE)]
+    pub tolerance: u64,
+    #[serde(default)]
+    #[builder(default)]
+    pub on_future_policy: SkewAction,
+    #[serde(default = "default_true")]
+    #[builder(default = true)]
+    pub invalidate_cookies: bool,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        ClockSettings::builder().build()
+    }
+}
+
+/// How far `time` is ahead of `now`, beyond the tolerance.
+pub fn skew(time: SystemTime, now: SystemTime, tolerance: u64) -> Option<Duration> {
+    time.duration_since(now)
+        .ok()
+        .filter(|ahead| *ahead > Duration::from_secs(tolerance))
+}
+
+/// Returns whether the policy file is dated in the future, Err when it must be refused.
+pub fn check_policy<P: AsRef<Path>>(
+    path: P,
+    settings: &ClockSettings,
+    now: SystemTime,
+) -> Result<bool, Box<dyn Error>> {
+    let modified = open_with_privileges(path.as_ref())?
+        .metadata()?
+        .modified()?;
+    let Some(ahead) = skew(modified, now, settings.tolerance) else {
+        return Ok(false);
+    };
+    let error = format!(
+        "The policy file {} was modified {} seconds in the future, the clock may be skewed",
+        path.as_ref().display(),
+        ahead.as_secs()
+    );
+    if settings.on_future_policy.is_refuse() {
+        return Err(error.into());
+    }
+    warn!("{}", error);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File, FileTimes};
+
+    use super::*;
+
+    #[test]
+    fn test_skew() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(skew(now - Duration::from_secs(3600), now, 60), None);
+        assert_eq!(skew(now + Duration::from_secs(30), now, 60), None);
+        assert_eq!(
+            skew(now + Duration::from_secs(3600), now, 60),
+            Some(Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn test_check_policy() {
+        let path = std::env::temp_dir().join("rar_clock_test.json");
+        fs::write(&path, "{}").unwrap();
+        let future = SystemTime::now() + Duration::from_secs(86400);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(future))
+            .unwrap();
+        let warn = ClockSettings::default();
+        let refuse = ClockSettings::builder()
+            .on_future_policy(SkewAction::Refuse)
+            .build();
+        assert!(check_policy(&path, &warn, SystemTime::now()).unwrap());
+        assert!(check_policy(&path, &refuse, SystemTime::now()).is_err());
+        // the clock caught up with the file
+        assert!(!check_policy(&path, &refuse, future).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
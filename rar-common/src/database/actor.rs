@@ -1,6 +1,7 @@
 use std::fmt::{self, Formatter};
 
 use bon::bon;
+use chrono::NaiveDate;
 use nix::unistd::{Group, User};
 use serde::{
     de::{self, Visitor},
@@ -16,6 +17,11 @@ pub enum SGenericActorType {
     Name(String),
 }
 
+/// A numeric id, written `1000` or `#1000`.
+fn parse_id(id: &str) -> Option<u32> {
+    id.strip_prefix('#').unwrap_or(id).parse().ok()
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct SUserType(SGenericActorType);
 
@@ -35,6 +41,14 @@ impl SUserType {
             SGenericActorType::Name(name) => User::from_name(name).ok().flatten(),
         }
     }
+    /// The name of the user with its id, when the policy gives an id known to this system.
+    pub fn resolved(&self) -> String {
+        match (&self.0, self.fetch_user()) {
+            (SGenericActorType::Id(id), Some(user)) => format!("{} (#{})", user.name, id),
+            (SGenericActorType::Id(id), None) => format!("#{}", id),
+            (SGenericActorType::Name(name), _) => name.clone(),
+        }
+    }
     pub fn fetch_eq(&self, other: &Self) -> bool {
         let uid = self.fetch_id();
         let ouid = other.fetch_id();
@@ -84,12 +98,26 @@ impl SGroupType {
             },
         }
     }
+    pub fn name(&self) -> Option<&str> {
+        match &self.0 {
+            SGenericActorType::Id(_) => None,
+            SGenericActorType::Name(name) => Some(name),
+        }
+    }
     pub fn fetch_group(&self) -> Option<Group> {
         match &self.0 {
             SGenericActorType::Id(id) => Group::from_gid((*id).into()).ok().flatten(),
             SGenericActorType::Name(name) => Group::from_name(name).ok().flatten(),
         }
     }
+    /// The name of the group with its id, when the policy gives an id known to this system.
+    pub fn resolved(&self) -> String {
+        match (&self.0, self.fetch_group()) {
+            (SGenericActorType::Id(id), Some(group)) => format!("{} (#{})", group.name, id),
+            (SGenericActorType::Id(id), None) => format!("#{}", id),
+            (SGenericActorType::Name(name), _) => name.clone(),
+        }
+    }
 }
 
 impl std::fmt::Display for SGenericActorType {
@@ -151,15 +179,20 @@ impl<'de> Deserialize<'de> for SGenericActorType {
                 Ok(SGenericActorType::Id(id))
             }
 
+            fn visit_u64<E>(self, id: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                u32::try_from(id)
+                    .map(SGenericActorType::Id)
+                    .map_err(|_| E::custom(format!("invalid ID {}", id)))
+            }
+
             fn visit_str<E>(self, id: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                let rid: Result<u32, _> = id.parse();
-                match rid {
-                    Ok(id) => Ok(SGenericActorType::Id(id)),
-                    Err(_) => Ok(SGenericActorType::Name(id.to_string())),
-                }
+                Ok(id.into())
             }
         }
 
@@ -199,7 +232,10 @@ impl From<Group> for SGroupType {
 
 impl From<&str> for SGenericActorType {
     fn from(name: &str) -> Self {
-        SGenericActorType::Name(name.into())
+        match parse_id(name) {
+            Some(id) => SGenericActorType::Id(id),
+            None => SGenericActorType::Name(name.into()),
+        }
     }
 }
 
@@ -373,13 +409,21 @@ impl core::fmt::Display for SGroups {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, EnumIs)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum SActor {
     #[serde(rename = "user")]
     User {
         #[serde(alias = "name", skip_serializing_if = "Option::is_none")]
         id: Option<SUserType>,
+        // dates of the grant and of its last access review
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        granted: Option<NaiveDate>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reviewed: Option<NaiveDate>,
+        // last day of the grant
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<NaiveDate>,
         #[serde(default, flatten, skip_serializing_if = "Map::is_empty")]
         _extra_fields: Map<String, Value>,
     },
@@ -387,6 +431,12 @@ pub enum SActor {
     Group {
         #[serde(alias = "names", skip_serializing_if = "Option::is_none")]
         groups: Option<SGroups>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        granted: Option<NaiveDate>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reviewed: Option<NaiveDate>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<NaiveDate>,
         #[serde(default, flatten)]
         _extra_fields: Map<String, Value>,
     },
@@ -399,35 +449,105 @@ impl SActor {
     #[builder(finish_fn = build)]
     pub fn user(
         #[builder(start_fn, into)] id: SUserType,
+        granted: Option<NaiveDate>,
+        reviewed: Option<NaiveDate>,
+        expires: Option<NaiveDate>,
         #[builder(default, with = <_>::from_iter)] _extra_fields: Map<String, Value>,
     ) -> Self {
         SActor::User {
             id: Some(id),
+            granted,
+            reviewed,
+            expires,
             _extra_fields,
         }
     }
     #[builder(finish_fn = build)]
     pub fn group(
         #[builder(start_fn, into)] groups: SGroups,
+        granted: Option<NaiveDate>,
+        reviewed: Option<NaiveDate>,
+        expires: Option<NaiveDate>,
         #[builder(default, with = <_>::from_iter)] _extra_fields: Map<String, Value>,
     ) -> Self {
         SActor::Group {
             groups: Some(groups),
+            granted,
+            reviewed,
+            expires,
             _extra_fields,
         }
     }
 }
 
+impl SActor {
+    /// Designates the same user or groups, whatever the grant metadata.
+    pub fn same_actor(&self, other: &SActor) -> bool {
+        match (self, other) {
+            (SActor::User { id, .. }, SActor::User { id: other, .. }) => id == other,
+            (SActor::Group { groups, .. }, SActor::Group { groups: other, .. }) => {
+                match (groups, other) {
+                    // a combination is the same whatever the order of its groups
+                    (Some(SGroups::Multiple(groups)), Some(SGroups::Multiple(other))) => {
+                        groups.len() == other.len() && groups.iter().all(|g| other.contains(g))
+                    }
+                    _ => groups == other,
+                }
+            }
+            (SActor::Unknown(value), SActor::Unknown(other)) => value == other,
+            _ => false,
+        }
+    }
+
+    /// The last date the grant was decided, by granting or reviewing it.
+    pub fn last_certified(&self) -> Option<NaiveDate> {
+        match self {
+            SActor::User {
+                granted, reviewed, ..
+            }
+            | SActor::Group {
+                granted, reviewed, ..
+            } => (*granted).max(*reviewed),
+            SActor::Unknown(_) => None,
+        }
+    }
+
+    pub fn set_granted(&mut self, date: NaiveDate) {
+        if let SActor::User { granted, .. } | SActor::Group { granted, .. } = self {
+            granted.replace(date);
+        }
+    }
+
+    pub fn set_reviewed(&mut self, date: NaiveDate) {
+        if let SActor::User { reviewed, .. } | SActor::Group { reviewed, .. } = self {
+            reviewed.replace(date);
+        }
+    }
+
+    pub fn set_expires(&mut self, date: Option<NaiveDate>) {
+        if let SActor::User { expires, .. } | SActor::Group { expires, .. } = self {
+            *expires = date;
+        }
+    }
+
+    /// An expired grant is kept in the policy but does not match anymore.
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        match self {
+            SActor::User { expires, .. } | SActor::Group { expires, .. } => {
+                expires.is_some_and(|expires| expires < today)
+            }
+            SActor::Unknown(_) => false,
+        }
+    }
+}
+
 impl core::fmt::Display for SActor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SActor::User { id, _extra_fields } => {
-                write!(f, "User: {}", id.as_ref().unwrap())
+            SActor::User { id, .. } => {
+                write!(f, "User: {}", id.as_ref().unwrap().resolved())
             }
-            SActor::Group {
-                groups,
-                _extra_fields,
-            } => {
+            SActor::Group { groups, .. } => {
                 write!(f, "Group: {}", groups.as_ref().unwrap())
             }
             SActor::Unknown(unknown) => {
@@ -494,6 +614,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_is_expired() {
+        let day = |s: &str| s.parse::<NaiveDate>().unwrap();
+        let actor = SActor::user("alice").expires(day("2026-10-14")).build();
+        assert!(!actor.is_expired(day("2026-10-14")));
+        assert!(actor.is_expired(day("2026-10-15")));
+        assert!(!SActor::group("adm").build().is_expired(day("2026-10-15")));
+    }
+
+    #[test]
+    fn test_numeric_ids() {
+        let user: SUserType = serde_json::from_str(r##""#0""##).unwrap();
+        assert_eq!(user, SUserType::from(0));
+        assert_eq!(serde_json::from_str::<SUserType>("0").unwrap(), user);
+        assert_eq!(SUserType::from("#0"), user);
+        assert_eq!(user.resolved(), "root (#0)");
+        assert_eq!(SUserType::from("#4242424").resolved(), "#4242424");
+        assert_eq!(SUserType::from("alice").resolved(), "alice");
+        let groups: SGroups = serde_json::from_str(r##"["#0", "adm"]"##).unwrap();
+        assert_eq!(groups, [SGroupType::from(0), SGroupType::from("adm")]);
+        assert_eq!(SGroupType::from("#0").resolved(), "root (#0)");
+        assert!(serde_json::from_str::<SUserType>("4294967296").is_err());
+        assert_eq!(SActor::user("#0").build().to_string(), "User: root (#0)");
+    }
+
     #[test]
     fn test_is_empty() {
         let groups = SGroups::Multiple(vec![]);
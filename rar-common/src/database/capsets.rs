@@ -0,0 +1,290 @@
+//! Named sets of capabilities.
+//!
+//! The capabilities of a task may name a set with `@name` instead of listing its capabilities:
+//! one of the predefined sets, or a set of the `capability-sets` field of the policy. The sets
+//! are expanded when the policy is read, and saved capabilities are written with the names of
+//! the sets they fully contain.
+use std::{cell::RefCell, cmp::Reverse, error::Error, rc::Rc};
+
+use capctl::{Cap, CapSet};
+
+use super::structs::{SCapabilities, SConfig};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilitySet {
+    pub name: String,
+    pub caps: CapSet,
+}
+
+const PREDEFINED: [(&str, &[Cap]); 3] = [
+    (
+        "network",
+        &[
+            Cap::NET_ADMIN,
+            Cap::NET_BIND_SERVICE,
+            Cap::NET_BROADCAST,
+            Cap::NET_RAW,
+        ],
+    ),
+    (
+        "filesystem",
+        &[
+            Cap::CHOWN,
+            Cap::DAC_OVERRIDE,
+            Cap::DAC_READ_SEARCH,
+            Cap::FOWNER,
+            Cap::FSETID,
+        ],
+    ),
+    (
+        "process",
+        &[Cap::KILL, Cap::SYS_NICE, Cap::SYS_PTRACE, Cap::SYS_RESOURCE],
+    ),
+];
+
+fn predefined(name: &str) -> Option<CapabilitySet> {
+    PREDEFINED
+        .iter()
+        .find(|(predefined, _)| *predefined == name)
+        .map(|(name, caps)| CapabilitySet {
+            name: name.to_string(),
+            caps: caps.iter().copied().collect(),
+        })
+}
+
+/// The predefined sets, then the sets of the policy.
+pub fn sets(config: &SConfig) -> Result<Vec<CapabilitySet>, Box<dyn Error>> {
+    let mut sets = PREDEFINED
+        .iter()
+        .filter_map(|(name, _)| predefined(name))
+        .collect::<Vec<_>>();
+    for (name, caps) in config.capability_sets.iter() {
+        if predefined(name).is_some() {
+            return Err(format!("The capability set @{} is predefined", name).into());
+        }
+        let caps = caps
+            .iter()
+            .map(|cap| {
+                cap.parse::<Cap>().map_err(|_| {
+                    format!("Invalid capability {} in the capability set @{}", cap, name)
+                })
+            })
+            .collect::<Result<CapSet, _>>()?;
+        sets.push(CapabilitySet {
+            name: name.clone(),
+            caps,
+        });
+    }
+    Ok(sets)
+}
+
+/// Read a capability, or a `@name` set whose capabilities are added when predefined, or once
+/// the policy is resolved.
+pub(crate) fn parse(
+    value: &str,
+    caps: &mut CapSet,
+    sets: &mut Vec<CapabilitySet>,
+) -> Result<(), String> {
+    match value.strip_prefix('@') {
+        Some(name) => {
+            let set = predefined(name).unwrap_or(CapabilitySet {
+                name: name.to_string(),
+                caps: CapSet::empty(),
+            });
+            *caps = caps.union(set.caps);
+            sets.push(set);
+        }
+        None => caps.add(
+            value
+                .parse()
+                .map_err(|_| format!("Invalid capability: {}", value))?,
+        ),
+    }
+    Ok(())
+}
+
+/// The names written for `caps`: the sets it fully contains, then the other capabilities.
+pub(crate) fn names(caps: &CapSet, sets: &[CapabilitySet]) -> Vec<String> {
+    let mut covered = CapSet::empty();
+    let mut names = Vec::new();
+    for set in sets.iter().filter(|set| set.caps & *caps == set.caps) {
+        names.push(format!("@{}", set.name));
+        covered = covered.union(set.caps);
+    }
+    names.extend((*caps & !covered).iter().map(|cap| cap.to_string()));
+    names
+}
+
+fn for_each_capabilities(
+    config: &Rc<RefCell<SConfig>>,
+    mut f: impl FnMut(&str, &str, &mut SCapabilities) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    for role in config.as_ref().borrow().roles.iter() {
+        let role = role.as_ref().borrow();
+        for task in role.tasks.iter() {
+            let mut task = task.as_ref().borrow_mut();
+            let name = task.name.to_string();
+            if let Some(caps) = task.cred.capabilities.as_mut() {
+                f(&role.name, &name, caps)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expand the sets named by the tasks of the policy.
+pub fn resolve(config: &Rc<RefCell<SConfig>>) -> Result<(), Box<dyn Error>> {
+    let sets = sets(&config.as_ref().borrow())?;
+    for_each_capabilities(config, |role, task, caps| {
+        for (named, granted) in [
+            (&mut caps.add_sets, &mut caps.add),
+            (&mut caps.sub_sets, &mut caps.sub),
+        ] {
+            for set in named.iter_mut() {
+                *set = sets
+                    .iter()
+                    .find(|known| known.name == set.name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown capability set @{} in the task {} of the role {}",
+                            set.name, task, role
+                        )
+                    })?;
+                *granted = granted.union(set.caps);
+            }
+        }
+        Ok(())
+    })
+}
+
+// the largest sets first, a set is only named when it adds capabilities to the previous ones
+fn covering(sets: &[CapabilitySet], caps: CapSet) -> Vec<CapabilitySet> {
+    let mut covered = CapSet::empty();
+    sets.iter()
+        .filter(|set| {
+            let named = !set.caps.is_empty()
+                && set.caps & caps == set.caps
+                && !(set.caps & !covered).is_empty();
+            if named {
+                covered = covered.union(set.caps);
+            }
+            named
+        })
+        .cloned()
+        .collect()
+}
+
+/// Name the sets fully contained in the capabilities of the tasks, before saving the policy.
+pub fn compact(config: &Rc<RefCell<SConfig>>) -> Result<(), Box<dyn Error>> {
+    let mut sets = sets(&config.as_ref().borrow())?;
+    sets.sort_by_key(|set| Reverse(set.caps.size()));
+    for_each_capabilities(config, |_, _, caps| {
+        caps.add_sets = covering(&sets, caps.add);
+        caps.sub_sets = covering(&sets, caps.sub);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::database::structs::{SCredentials, SRole, STask};
+
+    use super::*;
+
+    fn policy(caps: &str) -> Rc<RefCell<SConfig>> {
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(&format!(
+            r#"{{
+                "capability-sets": {{ "backup": ["cap_dac_read_search", "cap_fowner"] }},
+                "roles": [{{ "name": "r_ops", "tasks": [{{ "name": "t_ops", "cred": {{ "capabilities": {} }} }}] }}]
+            }}"#,
+            caps
+        ))
+        .unwrap();
+        config
+    }
+
+    fn capabilities(config: &Rc<RefCell<SConfig>>) -> SCapabilities {
+        let task = config.as_ref().borrow().roles[0].as_ref().borrow().tasks[0].clone();
+        let caps = task.as_ref().borrow().cred.capabilities.clone().unwrap();
+        caps
+    }
+
+    #[test]
+    fn test_resolve() {
+        let config = policy(r#"["@network", "@backup", "cap_kill"]"#);
+        // the sets of the policy are only known once it is read
+        assert!(capabilities(&config).add.has(Cap::NET_RAW));
+        assert!(!capabilities(&config).add.has(Cap::FOWNER));
+        resolve(&config).unwrap();
+        let caps = capabilities(&config);
+        assert!(caps.add.has(Cap::FOWNER));
+        assert!(caps.add.has(Cap::KILL));
+        assert_eq!(caps.add.size(), 7);
+        assert_eq!(
+            serde_json::to_value(&caps).unwrap(),
+            serde_json::json!(["@network", "@backup", "CAP_KILL"])
+        );
+
+        let unknown = policy(r#"{ "default": "all", "del": ["@unknown"] }"#);
+        assert_eq!(
+            resolve(&unknown).unwrap_err().to_string(),
+            "Unknown capability set @unknown in the task t_ops of the role r_ops"
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        let config = policy(
+            r#"["cap_net_admin", "cap_net_bind_service", "cap_net_broadcast", "cap_net_raw", "cap_dac_read_search", "cap_fowner", "cap_chown"]"#,
+        );
+        resolve(&config).unwrap();
+        compact(&config).unwrap();
+        assert_eq!(
+            serde_json::to_value(capabilities(&config)).unwrap(),
+            serde_json::json!(["@network", "@backup", "CAP_CHOWN"])
+        );
+        // a capability removed from a named set expands it
+        let task = config.as_ref().borrow().roles[0].as_ref().borrow().tasks[0].clone();
+        task.as_ref()
+            .borrow_mut()
+            .cred
+            .capabilities
+            .as_mut()
+            .unwrap()
+            .add
+            .drop(Cap::NET_RAW);
+        assert_eq!(
+            serde_json::to_value(capabilities(&config)).unwrap(),
+            serde_json::json!([
+                "@backup",
+                "CAP_CHOWN",
+                "CAP_NET_BIND_SERVICE",
+                "CAP_NET_BROADCAST",
+                "CAP_NET_ADMIN"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_predefined_redefined() {
+        let config = SConfig::builder()
+            .capability_sets(BTreeMap::from([(
+                "network".to_string(),
+                vec!["cap_net_raw".to_string()],
+            )]))
+            .role(
+                SRole::builder("r_ops")
+                    .task(
+                        STask::builder("t_ops")
+                            .cred(SCredentials::builder().build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        assert!(resolve(&config).is_err());
+    }
+}
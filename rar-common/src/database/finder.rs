@@ -3,7 +3,10 @@ use std::{
     cmp::Ordering,
     error::Error,
     fmt::{Display, Formatter},
-    path::PathBuf,
+    fs::{self, Metadata},
+    io::{BufRead, BufReader},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
     rc::{Rc, Weak},
 };
 
@@ -22,12 +25,15 @@ use strum::EnumIs;
 
 use crate::database::{
     actor::SActor,
-    options::{Opt, OptStack},
+    options::{Opt, OptStack, SLockdown},
     structs::{
         SCommand, SCommands, SConfig, SGroupschooser, SRole, STask, SUserChooser, SetBehavior,
     },
 };
-use crate::util::{capabilities_are_exploitable, final_path, parse_conf_command};
+use crate::util::{
+    capabilities_are_exploitable, final_path, interpolate_command, open_with_privileges,
+    parse_conf_command,
+};
 use crate::{
     api::{PluginManager, PluginResultAction},
     as_borrow,
@@ -184,11 +190,12 @@ pub struct CmdMin(u32);
 bitflags! {
 
     impl CmdMin: u32 {
-        const Match = 0b00001;
-        const WildcardPath = 0b00010;
-        const RegexArgs = 0b00100;
-        const FullRegexArgs = 0b01000;
-        const FullWildcardPath = 0b10000;
+        const Match = 0b000001;
+        const WildcardPath = 0b000010;
+        const WildcardArgs = 0b000100;
+        const RegexArgs = 0b001000;
+        const FullRegexArgs = 0b010000;
+        const FullWildcardPath = 0b100000;
     }
 }
 
@@ -280,6 +287,9 @@ pub struct Cred {
     pub tty: Option<dev_t>,
     #[builder(default = nix::unistd::getppid(), into)]
     pub ppid: Pid,
+    /// The invocation carries a valid orchestrator token
+    #[builder(default)]
+    pub orchestrated: bool,
 }
 
 impl<S: cred_builder::State> CredBuilder<S> {
@@ -408,13 +418,31 @@ fn match_path(input_path: &str, role_path: &String) -> CmdMin {
     match_status
 }
 
+fn is_glob_arg(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Each role arg is a shell pattern matching exactly one input arg
+fn match_glob_args(input_args: &[String], role_args: &[String]) -> bool {
+    role_args.iter().any(|arg| is_glob_arg(arg))
+        && input_args.len() == role_args.len()
+        && input_args.iter().zip(role_args).all(|(input, role)| {
+            input == role
+                || (is_glob_arg(role)
+                    && Pattern::new(role).is_ok_and(|pattern| pattern.matches(input)))
+        })
+}
+
 /// Check if input args is matching with role args and return the score
-/// role args can contains regex
+/// role args can contains shell patterns or a regex
 /// input args is the command line args
 fn match_args(input_args: &[String], role_args: &[String]) -> Result<CmdMin, Box<dyn Error>> {
     if role_args[0] == ".*" {
         return Ok(CmdMin::FullRegexArgs);
     }
+    if match_glob_args(input_args, role_args) {
+        return Ok(CmdMin::WildcardArgs);
+    }
     let commandline = input_args.join(" ");
     let role_args = role_args.join(" ");
     debug!("Matching args {:?} with {:?}", commandline, role_args);
@@ -430,7 +458,8 @@ fn match_args(input_args: &[String], role_args: &[String]) -> Result<CmdMin, Box
 
 #[cfg(feature = "pcre2")]
 fn evaluate_regex_cmd(role_args: String, commandline: String) -> Result<CmdMin, Box<dyn Error>> {
-    let regex = RegexBuilder::new().build(&role_args)?;
+    // the regex must match the whole command line, not a part of it
+    let regex = RegexBuilder::new().build(&format!("^(?:{})$", role_args))?;
     if regex.is_match(commandline.as_bytes())? {
         Ok(CmdMin::RegexArgs)
     } else {
@@ -442,7 +471,9 @@ fn evaluate_regex_cmd(role_args: String, commandline: String) -> Result<CmdMin,
 
 #[cfg(not(feature = "pcre2"))]
 fn evaluate_regex_cmd(_role_args: String, _commandline: String) -> Result<CmdMin, Box<dyn Error>> {
-    Err(Box::new(MatchError::NoMatch))
+    Err(Box::new(MatchError::NoMatch(
+        "Regex matching is not supported".to_string(),
+    )))
 }
 
 /// Check if input command line is matching with role command line and return the score
@@ -542,11 +573,11 @@ fn get_security_min(opt: &Option<Rc<RefCell<Opt>>>) -> SecurityMin {
 }
 
 fn group_is_root(actortype: &SGroupType) -> bool {
-    (*actortype).fetch_id().map_or(false, |id| id == 0)
+    (*actortype).fetch_id().is_some_and(|id| id == 0)
 }
 
 fn user_is_root(actortype: &SUserType) -> bool {
-    (*actortype).fetch_id().map_or(false, |id| id == 0)
+    (*actortype).fetch_id().is_some_and(|id| id == 0)
 }
 
 fn groups_contains_root(list: Option<&SGroups>) -> bool {
@@ -560,6 +591,146 @@ fn groups_contains_root(list: Option<&SGroups>) -> bool {
     }
 }
 
+const LOCKDOWN: &str = "/sys/kernel/security/lockdown";
+
+// the current mode is the bracketed one: "none [integrity] confidentiality"
+fn parse_lockdown(content: &str) -> Option<SLockdown> {
+    let mode = content
+        .split_whitespace()
+        .find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))?;
+    serde_json::from_value(serde_json::Value::String(mode.to_string())).ok()
+}
+
+/// Without the lockdown LSM, the kernel is not locked down.
+pub fn kernel_lockdown() -> SLockdown {
+    std::fs::read_to_string(LOCKDOWN)
+        .ok()
+        .and_then(|content| parse_lockdown(&content))
+        .unwrap_or(SLockdown::None)
+}
+
+/// Stands for the primary and supplementary groups of the target user.
+pub const TARGET_USER_GROUPS: &str = "@target-user";
+
+fn system_groups() -> Vec<Group> {
+    let mut gids = Vec::new();
+    // SAFETY: the group database is only iterated here, and each entry is copied before the next call
+    unsafe {
+        nix::libc::setgrent();
+        loop {
+            let entry = nix::libc::getgrent();
+            if entry.is_null() {
+                break;
+            }
+            gids.push((*entry).gr_gid);
+        }
+        nix::libc::endgrent();
+    }
+    gids.sort_unstable();
+    gids.dedup();
+    gids.into_iter()
+        .filter_map(|gid| Group::from_gid(gid.into()).ok().flatten())
+        .collect()
+}
+
+fn user_groups(user: &User) -> Vec<Gid> {
+    let mut gids = vec![user.gid];
+    if let Ok(name) = std::ffi::CString::new(user.name.as_str()) {
+        if let Ok(groups) = nix::unistd::getgrouplist(&name, user.gid) {
+            gids.extend(groups.into_iter().filter(|gid| *gid != user.gid));
+        }
+    }
+    gids
+}
+
+// a "1000-1999" range of gids, a group name like "docker-ci" is not a range
+fn gid_range(name: &str) -> Option<(u32, u32)> {
+    let (start, end) = name.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// Whether a setgid entry is resolved against the system groups at execution time.
+pub fn is_group_pattern(name: &str) -> bool {
+    name == TARGET_USER_GROUPS || gid_range(name).is_some() || is_glob(name)
+}
+
+fn expand_group(
+    entry: &SGroupType,
+    target: &User,
+    system: &[Group],
+) -> Result<Vec<SGroupType>, MatchError> {
+    let gids = |filter: &dyn Fn(&Group) -> bool| {
+        system
+            .iter()
+            .filter(|group| filter(group))
+            .map(|group| group.gid.as_raw().into())
+            .collect()
+    };
+    Ok(match entry.name() {
+        Some(TARGET_USER_GROUPS) => user_groups(target)
+            .into_iter()
+            .map(|gid| gid.as_raw().into())
+            .collect(),
+        Some(name) => {
+            if let Some((start, end)) = gid_range(name) {
+                gids(&|group| (start..=end).contains(&group.gid.as_raw()))
+            } else if is_glob(name) {
+                let pattern = Pattern::new(name).map_err(|e| {
+                    MatchError::NoMatch(format!("Invalid group pattern {}: {}", name, e))
+                })?;
+                gids(&|group| pattern.matches(&group.name))
+            } else {
+                vec![entry.clone()]
+            }
+        }
+        None => vec![entry.clone()],
+    })
+}
+
+/// Expand the setgid entries that depend on the system state: `@target-user`, glob patterns
+/// on group names and gid ranges. Other entries are kept as written.
+fn resolve_groups_with<F>(
+    groups: &SGroups,
+    target: &User,
+    system_groups: F,
+) -> Result<SGroups, MatchError>
+where
+    F: Fn() -> Vec<Group>,
+{
+    let entries = match groups {
+        SGroups::Single(group) => std::slice::from_ref(group),
+        SGroups::Multiple(groups) => groups.as_slice(),
+    };
+    if !entries
+        .iter()
+        .filter_map(SGroupType::name)
+        .any(is_group_pattern)
+    {
+        return Ok(groups.clone());
+    }
+    let system = system_groups();
+    let mut resolved: Vec<SGroupType> = Vec::new();
+    for entry in entries {
+        for group in expand_group(entry, target, &system)? {
+            if !resolved.contains(&group) {
+                resolved.push(group);
+            }
+        }
+    }
+    match resolved.len() {
+        0 => Err(MatchError::NoMatch(format!(
+            "The setgid entries {:?} match no group",
+            groups
+        ))),
+        1 => Ok(SGroups::Single(resolved.remove(0))),
+        _ => Ok(SGroups::Multiple(resolved)),
+    }
+}
+
 fn groups_len(groups: Option<&SGroups>) -> usize {
     match groups {
         Some(groups) => groups.len(),
@@ -661,6 +832,65 @@ fn get_setuid_min(
     }
 }
 
+/// The parts of the command matched by a pattern or a regex must not contain the
+/// `wildcard-denied` characters.
+fn check_wildcard_denied(
+    task: &Rc<RefCell<STask>>,
+    cmd_min: CmdMin,
+    command: &[String],
+) -> Result<(), MatchError> {
+    // commands allowed by default are not matched against anything
+    if cmd_min == CmdMin::all() {
+        return Ok(());
+    }
+    let path = cmd_min.intersects(CmdMin::WildcardPath | CmdMin::FullWildcardPath);
+    let args = cmd_min.intersects(CmdMin::WildcardArgs | CmdMin::RegexArgs | CmdMin::FullRegexArgs);
+    if !path && !args {
+        return Ok(());
+    }
+    let (_, denied) = OptStack::from_task(task.clone()).get_wildcard();
+    match command
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| if *i == 0 { path } else { args })
+        .find(|(_, part)| part.contains(|c| denied.contains(c)))
+    {
+        Some((_, part)) => Err(MatchError::NoMatch(format!(
+            "{:?} contains a character denied by wildcard-denied",
+            part
+        ))),
+        None => Ok(()),
+    }
+}
+
+// the user the command would run as, used to expand the placeholders of the commands
+fn target_user(task: &STask, cmd_opt: &Option<FilterMatcher>, user: &Cred) -> User {
+    cmd_opt
+        .as_ref()
+        .and_then(|cmd| cmd.user.as_ref())
+        .or(match &task.cred.setuid {
+            Some(SUserChooser::Actor(s)) => Some(s),
+            Some(SUserChooser::ChooserStruct(t)) => Some(&t.fallback),
+            None => None,
+        })
+        .and_then(SUserType::fetch_user)
+        .unwrap_or_else(|| user.user.clone())
+}
+
+fn interpolate_commands(commands: &SCommands, user: &User) -> Result<SCommands, MatchError> {
+    let expand = |commands: &Vec<SCommand>| {
+        commands
+            .iter()
+            .map(|command| interpolate_command(command, user))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(MatchError::NoMatch)
+    };
+    let mut commands = commands.clone();
+    commands.add = expand(&commands.add)?;
+    commands.sub = expand(&commands.sub)?;
+    Ok(commands)
+}
+
 impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
     fn matches(
         &self,
@@ -668,6 +898,15 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
         cmd_opt: &Option<FilterMatcher>,
         command: &[String],
     ) -> Result<TaskMatch, MatchError> {
+        if !self.as_ref().borrow().is_enabled() {
+            debug!("Task {} is disabled", self.as_ref().borrow().name);
+            return Err(MatchError::NoMatch("Task is disabled".to_string()));
+        }
+        if self.as_ref().borrow().container.is_some() {
+            return Err(MatchError::NoMatch(
+                "Task is granted to containers".to_string(),
+            ));
+        }
         if let Some(cmd_opt) = cmd_opt {
             if let Some(task) = &cmd_opt.task {
                 if task != &self.as_ref().borrow().name.to_string() {
@@ -682,11 +921,24 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
         let TaskMatch {
             mut score,
             mut settings,
-        } = self
-            .as_ref()
-            .borrow()
-            .commands
-            .matches(user, cmd_opt, command)?;
+        } = {
+            let task = self.as_ref().borrow();
+            let placeholders = |commands: &Vec<SCommand>| {
+                commands.iter().any(|command| match command {
+                    SCommand::Simple(command) => command.contains('%'),
+                    SCommand::Complex(command) => command.to_string().contains('%'),
+                })
+            };
+            if task.has_placeholders()
+                && (placeholders(&task.commands.add) || placeholders(&task.commands.sub))
+            {
+                interpolate_commands(&task.commands, &target_user(&task, cmd_opt, user))?
+                    .matches(user, cmd_opt, command)?
+            } else {
+                task.commands.matches(user, cmd_opt, command)?
+            }
+        };
+        check_wildcard_denied(self, score.cmd_min, command)?;
 
         // Process capabilities and security
         let capset = self
@@ -698,6 +950,40 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
             .map(|caps| caps.to_capset());
         score.caps_min = get_caps_min(&capset);
         score.security_min = get_security_min(&self.as_ref().borrow().options);
+
+        // Get options stack from the task
+        settings.opt = OptStack::from_task(self.clone());
+        let (_, lockdown) = settings.opt.get_lockdown();
+        if !lockdown.is_ignore() {
+            let kernel = kernel_lockdown();
+            if !lockdown.allows(kernel) {
+                debug!(
+                    "Task {} requires the {:?} lockdown, the kernel is in {:?}",
+                    self.as_ref().borrow().name,
+                    lockdown,
+                    kernel
+                );
+                return Err(MatchError::NoMatch(format!(
+                    "The task requires the kernel lockdown mode {}, the kernel is in mode {}",
+                    lockdown.to_string().to_lowercase(),
+                    kernel.to_string().to_lowercase()
+                )));
+            }
+        }
+        if settings.opt.get_orchestrated() && !user.orchestrated {
+            debug!(
+                "Task {} requires an orchestrator token",
+                self.as_ref().borrow().name
+            );
+            return Err(MatchError::NoMatch(
+                "The task can only be run by the orchestrator".to_string(),
+            ));
+        }
+        if PluginManager::notify_task_predicate(&self.as_ref().borrow(), user, command).is_deny() {
+            return Err(MatchError::NoMatch(
+                "A predicate of the task denied the command".to_string(),
+            ));
+        }
         if cmd_opt
             .as_ref()
             .and_then(|filter| filter.env_behavior) // if the command wants to override the behavior
@@ -725,8 +1011,15 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
         }
         // Processing setuid
         let setuid: Option<SUserChooser> = self.as_ref().borrow().cred.setuid.clone();
+        let requested = cmd_opt.as_ref().and_then(|cmd| cmd.user.as_ref());
         let setuid_result = match setuid {
-            Some(SUserChooser::Actor(s)) => Some(s),
+            // `sr -u` can only select a task that runs as the requested user
+            Some(SUserChooser::Actor(s)) => {
+                if requested.is_some_and(|requested| !requested.fetch_eq(&s)) {
+                    return Err(MatchError::NoMatch(format!("The task only runs as {}", s)));
+                }
+                Some(s)
+            }
             Some(SUserChooser::ChooserStruct(t)) => {
                 match cmd_opt.as_ref().and_then(|cmd| cmd.user.as_ref()) {
                     None => {
@@ -771,7 +1064,14 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
                     }
                 }
             }
-            None => None,
+            None => {
+                if requested.is_some_and(|requested| *requested != user.user) {
+                    return Err(MatchError::NoMatch(
+                        "The task runs as the caller, not as the requested user".into(),
+                    ));
+                }
+                None
+            }
         };
 
         // Set gid processing
@@ -816,6 +1116,16 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
             }
             None => None,
         };
+        let setgid_result = match setgid_result {
+            Some(groups) => {
+                let target = setuid_result
+                    .as_ref()
+                    .and_then(SUserType::fetch_user)
+                    .unwrap_or_else(|| user.user.clone());
+                Some(resolve_groups_with(&groups, &target, system_groups)?)
+            }
+            None => None,
+        };
 
         // Calculate setuid and setgid minimum
         score.setuser_min = get_setuid_min(
@@ -829,10 +1139,6 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<STask>> {
         settings.setgroups = setgid_result.clone();
         settings.caps = capset;
 
-        // Get options stack from the task
-        let stack = OptStack::from_task(self.clone());
-        settings.opt = stack;
-
         // Return the final TaskMatch
         Ok(TaskMatch { score, settings })
     }
@@ -845,6 +1151,57 @@ fn get_default_behavior(commands: &Option<SetBehavior>) -> &SetBehavior {
     }
 }
 
+fn check_root_owned(path: &Path, metadata: &Metadata) -> Result<(), Box<dyn Error>> {
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "{} must be owned by root and not writable by group or others",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Read the commands of a commands file, one per line, ignoring blank lines and # comments.
+/// The file and the directories above it must be owned by root and not writable by anyone
+/// else, like the policy itself, so that no one else can replace it.
+fn read_commands_file(path: &Path) -> Result<Vec<SCommand>, Box<dyn Error>> {
+    if !path.is_absolute() {
+        return Err(format!("{} is not an absolute path", path.display()).into());
+    }
+    for dir in path.ancestors().skip(1) {
+        check_root_owned(dir, &fs::metadata(dir)?)?;
+    }
+    let file = open_with_privileges(path)?;
+    check_root_owned(path, &file.metadata()?)?;
+    let mut commands = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            commands.push(SCommand::Simple(line.to_string()));
+        }
+    }
+    Ok(commands)
+}
+
+/// Read the commands files of the tasks, once per policy. The commands of a file that cannot
+/// be read or is not protected are not allowed.
+pub fn load_commands_files(config: &Rc<RefCell<SConfig>>) {
+    for role in &config.as_ref().borrow().roles {
+        for task in &role.as_ref().borrow().tasks {
+            let commands = &mut task.as_ref().borrow_mut().commands;
+            let Some(path) = &commands.file else {
+                continue;
+            };
+            match read_commands_file(path) {
+                Ok(file_commands) => commands._file_commands = file_commands,
+                Err(e) => warn!("Ignoring commands file : {}", e),
+            }
+        }
+    }
+}
+
 impl TaskMatcher<TaskMatch> for SCommands {
     fn matches(
         &self,
@@ -865,7 +1222,12 @@ impl TaskMatcher<TaskMatch> for SCommands {
         if get_default_behavior(&self.default_behavior).is_none() {
             debug!("Checking if command is allowed by default");
             // if the behavior is No command by default, we check if the command is allowed explicitly.
-            min_score = get_cmd_min(input_command, &self.add);
+            let mut add = get_cmd_min(input_command, &self.add);
+            let file_min = get_cmd_min(input_command, &self._file_commands);
+            if !file_min.is_empty() && (add.is_empty() || file_min < add) {
+                add = file_min;
+            }
+            min_score = add;
             if min_score.is_empty() {
                 return Err(MatchError::NoMatch("Command is not allowed".to_string()));
             }
@@ -924,43 +1286,53 @@ fn match_groups(groups: &[Group], role_groups: &[SGroups]) -> bool {
 
 impl CredMatcher for Rc<RefCell<SRole>> {
     fn user_matches(&self, user: &Cred) -> ActorMatchMin {
-        let borrow = self.as_ref().borrow();
         if PluginManager::notify_duty_separation(&self.as_ref().borrow(), user).is_deny() {
             warn!("You are forbidden to use a role due to a conflict of interest, please contact your administrator");
             return ActorMatchMin::NoMatch;
         }
-        let matches = borrow.actors.iter().filter_map(|actor| {
-            match actor {
-                SActor::User { id, .. } => {
-                    if let Some(id) = id {
-                        if *id == user.user {
-                            return Some(ActorMatchMin::UserMatch);
-                        }
+        actors_match(self, user)
+    }
+}
+
+/// How the actors of `role` grant it to `user`, without the restrictions of the plugins.
+pub fn actors_match(role: &Rc<RefCell<SRole>>, user: &Cred) -> ActorMatchMin {
+    let borrow = role.as_ref().borrow();
+    let today = chrono::Local::now().date_naive();
+    let matches = borrow.actors.iter().filter_map(|actor| {
+        if actor.is_expired(today) {
+            debug!("Role {} : grant {} expired", borrow.name, actor);
+            return None;
+        }
+        match actor {
+            SActor::User { id, .. } => {
+                if let Some(id) = id {
+                    if *id == user.user {
+                        return Some(ActorMatchMin::UserMatch);
                     }
                 }
-                SActor::Group { groups, .. } => {
-                    if let Some(groups) = groups.as_ref() {
-                        if match_groups(&user.groups, &[groups.clone()]) {
-                            return Some(ActorMatchMin::GroupMatch(groups.len()));
-                        }
+            }
+            SActor::Group { groups, .. } => {
+                if let Some(groups) = groups.as_ref() {
+                    if match_groups(&user.groups, std::slice::from_ref(groups)) {
+                        return Some(ActorMatchMin::GroupMatch(groups.len()));
                     }
                 }
-                SActor::Unknown(element) => {
-                    let min = PluginManager::notify_user_matcher(&as_borrow!(self), user, element);
-                    if !min.is_no_match() {
-                        return Some(min);
-                    }
+            }
+            SActor::Unknown(element) => {
+                let min = PluginManager::notify_user_matcher(&as_borrow!(role), user, element);
+                if !min.is_no_match() {
+                    return Some(min);
                 }
             }
-            None
-        });
-        let min = matches.min().unwrap_or(ActorMatchMin::NoMatch);
-        debug!(
-            "Role {} : User {} matches with {:?}",
-            borrow.name, user.user.name, min
-        );
-        min
-    }
+        }
+        None
+    });
+    let min = matches.min().unwrap_or(ActorMatchMin::NoMatch);
+    debug!(
+        "Role {} : User {} matches with {:?}",
+        borrow.name, user.user.name, min
+    );
+    min
 }
 
 impl TaskMatcher<TaskMatch> for Vec<Rc<RefCell<STask>>> {
@@ -1060,6 +1432,10 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<SRole>> {
         cmd_opt: &Option<FilterMatcher>,
         command: &[String],
     ) -> Result<TaskMatch, MatchError> {
+        if !self.as_ref().borrow().is_enabled() {
+            debug!("Role {} is disabled", self.as_ref().borrow().name);
+            return Err(MatchError::NoMatch("Role is disabled".to_string()));
+        }
         if let Some(cmd_opt) = cmd_opt {
             if let Some(role) = &cmd_opt.role {
                 if role != &self.as_ref().borrow().name {
@@ -1111,7 +1487,7 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<SRole>> {
             debug!(
                 "=== Role {} === : Match for task {}\nScore : {}",
                 self.as_ref().borrow().name,
-                min_role.task().as_ref().borrow().name.to_string(),
+                min_role.task().as_ref().borrow().name,
                 min_role.score.prettyprint()
             );
             Ok(min_role)
@@ -1156,6 +1532,61 @@ fn plugin_role_match(
     debug!("nmatch = {}", nmatch);
 }
 
+// the roles and tasks are evaluated in order, the first fully matching task is chosen
+fn first_match(
+    config: &Rc<RefCell<SConfig>>,
+    user: &Cred,
+    cmd_opt: &Option<FilterMatcher>,
+    command: &[String],
+) -> Result<TaskMatch, MatchError> {
+    for role in config.as_ref().borrow().roles.iter() {
+        let borrow = role.as_ref().borrow();
+        if !borrow.is_enabled()
+            || cmd_opt
+                .as_ref()
+                .and_then(|filter| filter.role.as_ref())
+                .is_some_and(|name| name != &borrow.name)
+        {
+            continue;
+        }
+        let user_min = role.user_matches(user);
+        if user_min.is_no_match() {
+            continue;
+        }
+        for task in borrow.tasks.iter() {
+            if let Ok(mut task_match) = task.matches(user, cmd_opt, command) {
+                task_match.score.user_min = user_min;
+                task_match.settings.task = Rc::downgrade(task);
+                if task_match.fully_matching() {
+                    debug!(
+                        "Config : First match for user {} with task {} of role {}",
+                        user.user.name,
+                        task_match.task().as_ref().borrow().name,
+                        borrow.name
+                    );
+                    return Ok(task_match);
+                }
+            }
+        }
+        // then the tasks given by the plugins, such as the inherited ones
+        let mut plugin_match = TaskMatch::default();
+        let mut nmatch = 0;
+        plugin_role_match(
+            user_min,
+            borrow,
+            user,
+            cmd_opt,
+            command,
+            &mut plugin_match,
+            &mut nmatch,
+        );
+        if nmatch == 1 && plugin_match.fully_matching() {
+            return Ok(plugin_match);
+        }
+    }
+    Err(MatchError::NoMatch("No roles matched".into()))
+}
+
 impl TaskMatcher<TaskMatch> for Rc<RefCell<SConfig>> {
     fn matches(
         &self,
@@ -1167,6 +1598,16 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<SConfig>> {
             "Config : Matching user {} with command {:?}",
             user.user.name, command
         );
+        let strategy = self
+            .as_ref()
+            .borrow()
+            .options
+            .as_ref()
+            .and_then(|opt| opt.as_ref().borrow().matching_strategy)
+            .unwrap_or_default();
+        if strategy.is_first() {
+            return first_match(self, user, cmd_opt, command);
+        }
         let mut tasks: Vec<TaskMatch> = Vec::new();
         for role in self.as_ref().borrow().roles.iter() {
             if let Ok(matched) = role.matches(user, cmd_opt, command) {
@@ -1194,7 +1635,7 @@ impl TaskMatcher<TaskMatch> for Rc<RefCell<SConfig>> {
                 "Config : Matched user {}\n - command {:?}\n - with task {}\n - with role {}\n - with score {:?}",
                 user.user.name,
                 command,
-                tasks[0].task().as_ref().borrow().name.to_string(),
+                tasks[0].task().as_ref().borrow().name,
                 tasks[0].role().as_ref().borrow().name,
                 tasks[0].score.prettyprint()
             );
@@ -1214,7 +1655,10 @@ mod tests {
     use crate::{
         database::{
             make_weak_config,
-            options::{EnvBehavior, PathBehavior, SAuthentication, SBounding, SPrivileged},
+            options::{
+                EnvBehavior, Level, PathBehavior, SAuthentication, SBounding, SMatchingStrategy,
+                SPrivileged,
+            },
             structs::{IdTask, RoleGetter, SCredentials, SSetgidSet, SSetuidSet},
             versionning::Versioning,
         },
@@ -1227,28 +1671,28 @@ mod tests {
         // list all users
         let passwd = fs::read_to_string("/etc/passwd").unwrap();
         let passwd: Vec<&str> = passwd.split('\n').collect();
-        return passwd
+        passwd
             .iter()
             .map(|line| {
                 let line: Vec<&str> = line.split(':').collect();
                 line[2].parse::<u32>().unwrap()
             })
             .find(|uid| *uid != 0)
-            .unwrap();
+            .unwrap()
     }
 
     fn get_non_root_gid(nth: usize) -> Option<u32> {
         // list all users
         let passwd = fs::read_to_string("/etc/group").unwrap();
         let passwd: Vec<&str> = passwd.split('\n').collect();
-        return passwd
+        passwd
             .iter()
             .map(|line| {
                 let line: Vec<&str> = line.split(':').collect();
                 line[2].parse::<u32>().unwrap()
             })
             .filter(|uid| *uid != 0)
-            .nth(nth);
+            .nth(nth)
     }
 
     #[test]
@@ -1279,7 +1723,7 @@ mod tests {
 
     #[test]
     fn test_match_path() {
-        let result = match_path(&"/bin/ls".to_string(), &"/bin/ls".to_string());
+        let result = match_path("/bin/ls", &"/bin/ls".to_string());
         assert_eq!(result, CmdMin::Match);
     }
 
@@ -1293,6 +1737,181 @@ mod tests {
         assert_eq!(result.unwrap(), CmdMin::Match);
     }
 
+    #[test]
+    fn test_match_glob_args() {
+        let role = ["restart".to_string(), "httpd*".to_string()];
+        assert_eq!(
+            match_args(&["restart".to_string(), "httpd-worker".to_string()], &role).unwrap(),
+            CmdMin::WildcardArgs
+        );
+        assert!(match_args(&["stop".to_string(), "httpd-worker".to_string()], &role).is_err());
+        assert!(match_args(
+            &[
+                "restart".to_string(),
+                "httpd".to_string(),
+                "sshd".to_string()
+            ],
+            &role
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "pcre2")]
+    #[test]
+    fn test_match_regex_args() {
+        let role = ["(restart|reload) (httpd|nginx)".to_string()];
+        assert_eq!(
+            match_args(&["reload".to_string(), "nginx".to_string()], &role).unwrap(),
+            CmdMin::RegexArgs
+        );
+        // the regex is anchored
+        assert!(match_args(
+            &[
+                "reload".to_string(),
+                "nginx".to_string(),
+                "sshd".to_string()
+            ],
+            &role
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_wildcard_denied() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r1")
+                    .task(
+                        STask::builder("t1")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/systemctl restart httpd*".into()])
+                                    .build(),
+                            )
+                            .options(|opt| opt.wildcard_denied(";&|").build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let task = config.task("r1", "t1").unwrap();
+        let cred = Cred::builder().build();
+        let command = |arg: &str| {
+            vec![
+                "/usr/bin/systemctl".to_string(),
+                "restart".to_string(),
+                arg.to_string(),
+            ]
+        };
+        assert!(task.matches(&cred, &None, &command("httpd-worker")).is_ok());
+        assert!(task.matches(&cred, &None, &command("httpd;id")).is_err());
+        // only the expanded parts are checked
+        task.as_ref().borrow_mut().commands.add = vec!["/usr/bin/system* restart httpd;id".into()];
+        assert!(task.matches(&cred, &None, &command("httpd;id")).is_ok());
+        task.as_ref()
+            .borrow_mut()
+            .options
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .wildcard_denied = Some("t".to_string());
+        assert!(task.matches(&cred, &None, &command("httpd;id")).is_err());
+    }
+
+    #[test]
+    fn test_matching_strategy() {
+        let uid = nix::unistd::getuid().as_raw();
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_broad")
+                    .actor(SActor::user(uid).build())
+                    .task(
+                        STask::builder("t_all")
+                            .commands(SCommands::builder(SetBehavior::All).build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_exact")
+                    .actor(SActor::user(uid).build())
+                    .task(STask::builder("t_off").enabled(false).build())
+                    .task(
+                        STask::builder("t_ls")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/ls".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let cred = Cred::builder().build();
+        let command = vec!["/usr/bin/ls".to_string()];
+        let task = |result: Result<TaskMatch, MatchError>| {
+            result.unwrap().task().as_ref().borrow().name.to_string()
+        };
+        assert_eq!(task(config.matches(&cred, &None, &command)), "t_ls");
+        config.as_ref().borrow_mut().options = Some(
+            Opt::builder(Level::Global)
+                .matching_strategy(SMatchingStrategy::First)
+                .build(),
+        );
+        assert_eq!(task(config.matches(&cred, &None, &command)), "t_all");
+        let filter = Some(FilterMatcher::builder().role("r_exact".to_string()).build());
+        assert_eq!(task(config.matches(&cred, &filter, &command)), "t_ls");
+        let filter = Some(FilterMatcher::builder().role("r_none".to_string()).build());
+        assert!(config.matches(&cred, &filter, &command).is_err());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r1")
+                    .task(
+                        STask::builder("t1")
+                            .placeholders(true)
+                            .cred(SCredentials::builder().setuid("root").build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/chown %u %h/.config".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let task = config.task("r1", "t1").unwrap();
+        let cred = Cred::builder().build();
+        let root = User::from_uid(0.into()).unwrap().unwrap();
+        let command = |user: &str, dir: &str| {
+            vec![
+                "/usr/bin/chown".to_string(),
+                user.to_string(),
+                format!("{}/.config", dir),
+            ]
+        };
+        assert!(task
+            .matches(&cred, &None, &command("root", &root.dir.to_string_lossy()))
+            .is_ok());
+        assert!(task.matches(&cred, &None, &command("%u", "%h")).is_err());
+        // an unknown placeholder is kept as is
+        task.as_ref().borrow_mut().commands.add = vec!["/usr/bin/date +%Y-%m-%d".into()];
+        let date = vec!["/usr/bin/date".to_string(), "+%Y-%m-%d".to_string()];
+        assert!(task.matches(&cred, &None, &date).is_ok());
+        // without the opt-in, %u is a literal
+        task.as_ref().borrow_mut().placeholders = None;
+        task.as_ref().borrow_mut().commands.add = vec!["/usr/bin/chown %u %h/.config".into()];
+        assert!(task
+            .matches(&cred, &None, &command("root", &root.dir.to_string_lossy()))
+            .is_err());
+        assert!(task.matches(&cred, &None, &command("%u", "%h")).is_ok());
+    }
+
     #[test]
     fn test_match_command_line() {
         let result = match_command_line(
@@ -1315,6 +1934,84 @@ mod tests {
         assert_eq!(result, CmdMin::Match);
     }
 
+    #[test]
+    fn test_commands_file() {
+        // every directory above the file must be protected, unlike the temporary directory, so
+        // only root can create it
+        let run = Path::new("/run");
+        if !nix::unistd::getuid().is_root()
+            || run
+                .ancestors()
+                .any(|dir| fs::metadata(dir).map_or(true, |m| check_root_owned(dir, &m).is_err()))
+        {
+            return;
+        }
+        let dir = PathBuf::from(format!(
+            "/run/rar_test_commands_file_{}",
+            std::process::id()
+        ));
+        let path = dir.join("backup.list");
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        fs::write(
+            &path,
+            "# backup commands\n\n/usr/bin/tar -czf .*\n  /usr/bin/rsync .*\n",
+        )
+        .unwrap();
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_backup")
+                    .task(
+                        STask::builder("t_backup")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/ls".into()])
+                                    .file(path.clone())
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        load_commands_files(&config);
+        let commands = config.role("r_backup").unwrap().as_ref().borrow().tasks[0]
+            .as_ref()
+            .borrow()
+            .commands
+            .clone();
+        // the file is read once, with the policy
+        fs::write(&path, "/usr/bin/cat\n").unwrap();
+        let cred = Cred::builder().build();
+        assert!(commands
+            .matches(&cred, &None, &["/usr/bin/rsync".into(), "-a".into()])
+            .is_ok());
+        assert!(commands
+            .matches(&cred, &None, &["/usr/bin/ls".into()])
+            .is_ok());
+        assert!(commands
+            .matches(&cred, &None, &["/usr/bin/cat".into()])
+            .is_err());
+        assert!(read_commands_file(&path).is_ok());
+        assert!(read_commands_file(Path::new("backup.list")).is_err());
+        // a file writable by others is ignored
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o666)).unwrap();
+        assert!(read_commands_file(&path).is_err());
+        // so is a file in a directory writable by others
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+        fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o777)).unwrap();
+        assert!(read_commands_file(&path).is_err());
+        load_commands_files(&config);
+        assert!(config.role("r_backup").unwrap().as_ref().borrow().tasks[0]
+            .as_ref()
+            .borrow()
+            .commands
+            .matches(&cred, &None, &["/usr/bin/rsync".into(), "-a".into()])
+            .is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_get_caps_min_all() {
         let caps = !CapSet::empty();
@@ -1613,15 +2310,19 @@ mod tests {
         with_config: Option<Rc<RefCell<SConfig>>>,
     ) -> Rc<RefCell<SRole>> {
         let role = role.unwrap_or_else(|| {
-            let mut role = SRole::default();
-            role.name = "test".to_string();
-            role._config = with_config.map(|config| Rc::downgrade(&config));
+            let role = SRole {
+                name: "test".to_string(),
+                _config: with_config.map(|config| Rc::downgrade(&config)),
+                ..Default::default()
+            };
             Rc::new(RefCell::new(role))
         });
         for i in 0..num_tasks {
-            let mut task = STask::default();
-            task.name = IdTask::Name(format!("{}_task_{}", role.as_ref().borrow().name, i));
-            task._role = Some(Rc::downgrade(&role));
+            let task = STask {
+                name: IdTask::Name(format!("{}_task_{}", role.as_ref().borrow().name, i)),
+                _role: Some(Rc::downgrade(&role)),
+                ..Default::default()
+            };
             role.as_ref().borrow_mut().tasks.push(Rc::new(task.into()));
         }
         role
@@ -1684,6 +2385,7 @@ mod tests {
             user: User::from_uid(Uid::from_raw(0)).unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -1775,6 +2477,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -1824,6 +2527,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -1874,6 +2578,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -1922,6 +2627,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
         // Commande de test
@@ -1971,6 +2677,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2018,6 +2725,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2068,6 +2776,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2115,6 +2824,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2190,6 +2900,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
         let command = vec!["/bin/ls".to_string()];
@@ -2225,6 +2936,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
         let command = vec!["/bin/ls".to_string()];
@@ -2249,6 +2961,34 @@ mod tests {
         );
     }
     #[test]
+    fn test_disabled_role_and_task() {
+        let config: Versioning<Rc<RefCell<SConfig>>> =
+            serde_json::from_str(&fs::read_to_string("../resources/rootasrole.json").unwrap())
+                .unwrap();
+        let config = config.data;
+        make_weak_config(&config);
+        config.as_ref().borrow_mut()[0].as_ref().borrow_mut().actors[0] =
+            SActor::user("root").build();
+        let cred = Cred {
+            user: User::from_name("root").unwrap().unwrap(),
+            groups: vec![Group::from_name("root").unwrap().unwrap()],
+            ppid: Pid::from_raw(0),
+            orchestrated: false,
+            tty: None,
+        };
+        let command = vec!["/usr/bin/chsr".to_string(), "show".to_string()];
+        let task = config.task("r_root", "t_chsr").unwrap();
+        task.as_ref().borrow_mut().enabled = Some(false);
+        // falls back to the t_root task
+        let result = config.matches(&cred, &None, &command).unwrap();
+        assert_eq!(
+            result.task().as_ref().borrow().name,
+            IdTask::Name("t_root".to_string())
+        );
+        config.role("r_root").unwrap().as_ref().borrow_mut().enabled = Some(false);
+        assert!(config.matches(&cred, &None, &command).is_err());
+    }
+    #[test]
 
     fn test_schooseruser_setuid_types() {
         let config = SConfig::builder()
@@ -2355,6 +3095,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_requested_user() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .actor(SActor::user("root").build())
+                    .task(
+                        STask::builder("t_fixed")
+                            .cred(SCredentials::builder().setuid("root").build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/bin/pwd".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(
+                        STask::builder("t_caller")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/bin/cat".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let cred = Cred::builder().user_name("root").group_name("root").build();
+        let other = || Some(FilterMatcher::builder().user(get_non_root_uid()).build());
+        let root = || Some(FilterMatcher::builder().user("root").build());
+        let pwd = vec!["/bin/pwd".to_string()];
+        let cat = vec!["/bin/cat".to_string()];
+        assert!(config.matches(&cred, &root(), &pwd).is_ok());
+        assert!(config
+            .matches(&cred, &other(), &pwd)
+            .is_err_and(|e| e.is_no_match()));
+        // the caller is root, the command already runs as root
+        assert!(config.matches(&cred, &root(), &cat).is_ok());
+        assert!(config
+            .matches(&cred, &other(), &cat)
+            .is_err_and(|e| e.is_no_match()));
+    }
+
     #[test]
     fn test_setgid_fallback_single_valid() {
         // Configuration de test
@@ -2385,6 +3169,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2442,6 +3227,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2499,6 +3285,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2547,6 +3334,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2602,6 +3390,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2663,6 +3452,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2716,6 +3506,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2772,6 +3563,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2828,6 +3620,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2881,6 +3674,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2934,6 +3728,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -2990,6 +3785,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3052,6 +3848,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3108,6 +3905,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3167,6 +3965,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3220,6 +4019,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3279,6 +4079,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3338,6 +4139,7 @@ mod tests {
             user: User::from_name("root").unwrap().unwrap(),
             groups: vec![Group::from_name("root").unwrap().unwrap()],
             ppid: Pid::from_raw(0),
+            orchestrated: false,
             tty: None,
         };
 
@@ -3363,4 +4165,113 @@ mod tests {
 
         println!("Test réussi : Le groupe spécifié ne correspond pas ");
     }
+
+    #[test]
+    fn test_lockdown() {
+        assert_eq!(
+            parse_lockdown("none [integrity] confidentiality\n"),
+            Some(SLockdown::Integrity)
+        );
+        assert_eq!(
+            parse_lockdown("[none] integrity confidentiality"),
+            Some(SLockdown::None)
+        );
+        assert_eq!(parse_lockdown(""), None);
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_modules")
+                    .actor(SActor::user("root").build())
+                    .task(
+                        STask::builder("t_modprobe")
+                            .options(|opt| opt.lockdown(SLockdown::None).build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/bin/ls".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let cred = Cred::builder().user_name("root").build();
+        let result = config.matches(&cred, &None, &["/bin/ls".to_string()]);
+        if kernel_lockdown().is_none() {
+            assert!(result.is_ok());
+        } else {
+            assert!(result.unwrap_err().is_no_match());
+        }
+    }
+
+    #[test]
+    fn test_orchestrated() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_rotate")
+                    .actor(SActor::user("root").build())
+                    .task(
+                        STask::builder("t_rotate")
+                            .options(|opt| opt.orchestrated(true).build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/bin/ls".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let command = ["/bin/ls".to_string()];
+        let cred = Cred::builder().user_name("root").build();
+        assert!(config
+            .matches(&cred, &None, &command)
+            .unwrap_err()
+            .is_no_match());
+        let cred = Cred::builder().user_name("root").orchestrated(true).build();
+        assert!(config.matches(&cred, &None, &command).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_groups() {
+        let group = |name: &str, gid: u32| Group {
+            name: name.to_string(),
+            passwd: std::ffi::CString::new("x").unwrap(),
+            gid: Gid::from_raw(gid),
+            mem: vec![],
+        };
+        let system = || {
+            vec![
+                group("root", 0),
+                group("docker", 998),
+                group("docker-ci", 999),
+                group("users", 1000),
+            ]
+        };
+        let root = User::from_name("root").unwrap().unwrap();
+        assert_eq!(
+            resolve_groups_with(&SGroups::from("docker*"), &root, system),
+            Ok(SGroups::Multiple(vec![998.into(), 999.into()]))
+        );
+        assert_eq!(
+            resolve_groups_with(&SGroups::from("990-1000"), &root, system),
+            Ok(SGroups::Multiple(vec![998.into(), 999.into(), 1000.into()]))
+        );
+        assert_eq!(
+            resolve_groups_with(&SGroups::from(["users", "docker-ci"]), &root, || {
+                panic!("static groups must not read the group database")
+            }),
+            Ok(SGroups::from(["users", "docker-ci"]))
+        );
+        let resolved =
+            resolve_groups_with(&SGroups::from([TARGET_USER_GROUPS, "users"]), &root, system)
+                .unwrap();
+        let SGroups::Multiple(resolved) = resolved else {
+            panic!("expected several groups");
+        };
+        // the primary group of the target user comes first
+        assert_eq!(resolved.first(), Some(&0.into()));
+        assert_eq!(resolved.last(), Some(&"users".into()));
+        assert!(resolve_groups_with(&SGroups::from("nomatch*"), &root, system).is_err());
+    }
 }
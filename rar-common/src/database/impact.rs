@@ -0,0 +1,91 @@
+//! What the capabilities of a task allow, in plain words.
+//!
+//! Reviewers of a policy are rarely fluent in capability names. `sr --info` and `chsr list`
+//! print, next to the capabilities, what they allow, such as "can mount filesystems". Several
+//! capabilities may share a statement, which is only written once.
+use capctl::{Cap, CapSet};
+
+fn statement(cap: Cap) -> Option<&'static str> {
+    Some(match cap {
+        Cap::CHOWN => "can change the owner of any file",
+        Cap::DAC_OVERRIDE => "can read and write any file",
+        Cap::DAC_READ_SEARCH => "can read any file",
+        Cap::FOWNER => "can change the permissions of any file",
+        Cap::FSETID => "can keep the setuid bit of modified files",
+        Cap::KILL => "can signal any process",
+        Cap::SETGID => "can become any group",
+        Cap::SETUID => "can become any user",
+        Cap::SETPCAP => "can change its own capabilities",
+        Cap::LINUX_IMMUTABLE => "can make files immutable or append-only",
+        Cap::NET_BIND_SERVICE => "can listen on privileged ports",
+        Cap::NET_BROADCAST => "can broadcast on the network",
+        Cap::NET_ADMIN => "can configure the network",
+        Cap::NET_RAW => "can capture and forge network packets",
+        Cap::IPC_LOCK => "can lock memory",
+        Cap::IPC_OWNER => "can access the shared memory of any process",
+        Cap::SYS_MODULE => "can load kernel modules",
+        Cap::SYS_RAWIO => "can access devices and memory directly",
+        Cap::SYS_CHROOT => "can change its root directory",
+        Cap::SYS_PTRACE => "can inspect and control any process",
+        Cap::SYS_PACCT => "can configure process accounting",
+        Cap::SYS_ADMIN => "can mount filesystems and administer the system",
+        Cap::SYS_BOOT => "can reboot the system",
+        Cap::SYS_NICE => "can change the priority of any process",
+        Cap::SYS_RESOURCE => "can exceed resource limits",
+        Cap::SYS_TIME => "can set the clock",
+        Cap::SYS_TTY_CONFIG => "can configure terminals",
+        Cap::MKNOD => "can create device files",
+        Cap::LEASE => "can take leases on any file",
+        Cap::AUDIT_WRITE => "can write to the audit log",
+        Cap::AUDIT_CONTROL => "can configure auditing",
+        Cap::SETFCAP => "can grant capabilities to files",
+        Cap::MAC_OVERRIDE | Cap::MAC_ADMIN => "can bypass or configure the security modules",
+        Cap::SYSLOG => "can read and configure the kernel log",
+        Cap::WAKE_ALARM => "can wake up the system",
+        Cap::BLOCK_SUSPEND => "can prevent the system from suspending",
+        Cap::AUDIT_READ => "can read the audit log",
+        Cap::PERFMON => "can monitor the performance of the system",
+        Cap::BPF => "can load eBPF programs",
+        Cap::CHECKPOINT_RESTORE => "can checkpoint and restore processes",
+        _ => return None,
+    })
+}
+
+/// What `caps` allow, in the order of the capabilities.
+pub fn impact(caps: &CapSet) -> Vec<String> {
+    let mut statements: Vec<String> = Vec::new();
+    for cap in caps.iter() {
+        let statement = statement(cap).map_or_else(|| format!("can use {}", cap), str::to_string);
+        if !statements.contains(&statement) {
+            statements.push(statement);
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impact() {
+        let caps: CapSet = [
+            Cap::SYS_MODULE,
+            Cap::SYS_ADMIN,
+            Cap::MAC_ADMIN,
+            Cap::MAC_OVERRIDE,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            impact(&caps),
+            [
+                "can load kernel modules",
+                "can mount filesystems and administer the system",
+                "can bypass or configure the security modules",
+            ]
+        );
+        assert!(impact(&CapSet::empty()).is_empty());
+        assert!(Cap::iter().all(|cap| statement(cap).is_some()));
+    }
+}
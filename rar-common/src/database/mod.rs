@@ -1,12 +1,12 @@
 use std::path::Path;
 use std::{cell::RefCell, error::Error, rc::Rc};
 
-use crate::save_settings;
 use crate::util::{toggle_lock_config, ImmutableLock};
 use crate::version::PACKAGE_VERSION;
+use crate::{include, save_settings};
 
 use actor::{SGroups, SUserType};
-use bon::{builder, Builder};
+use bon::Builder;
 use chrono::Duration;
 use linked_hash_set::LinkedHashSet;
 use log::debug;
@@ -17,12 +17,14 @@ use self::{migration::Migration, options::EnvKey, structs::SConfig, versionning:
 
 use crate::util::warn_if_mutable;
 use crate::SettingsFile;
-use crate::{open_with_privileges, write_json_config};
+use crate::{open_with_privileges, util::json_unchanged, write_json_config};
 use crate::{util::immutable_effective, RemoteStorageSettings, ROOTASROLE};
 
 pub mod actor;
+pub mod capsets;
 #[cfg(feature = "finder")]
 pub mod finder;
+pub mod impact;
 pub mod migration;
 pub mod options;
 pub mod structs;
@@ -61,11 +63,8 @@ pub fn read_json_config<P: AsRef<Path>>(
         .unwrap_or(&default_remote)
         .path
         .as_ref();
-    if path.is_none() || path.is_some_and(|p| p == settings_path.as_ref()) {
-        make_weak_config(&settings.as_ref().borrow().config);
-        return Ok(settings.as_ref().borrow().config.clone());
-    } else {
-        let file = open_with_privileges(path.unwrap())?;
+    if let Some(path) = path.filter(|p| *p != settings_path.as_ref()) {
+        let file = open_with_privileges(path)?;
         warn_if_mutable(
             &file,
             settings
@@ -89,14 +88,52 @@ pub fn read_json_config<P: AsRef<Path>>(
         } else {
             debug!("No migrations needed");
         }
+        include_roles(&settings.as_ref().borrow(), &config)?;
+        capsets::resolve(&config)?;
+        #[cfg(feature = "finder")]
+        finder::load_commands_files(&config);
+        make_weak_config(&config);
+        Ok(config)
+    } else {
+        let config = settings.as_ref().borrow().config.clone();
+        include_roles(&settings.as_ref().borrow(), &config)?;
+        capsets::resolve(&config)?;
+        #[cfg(feature = "finder")]
+        finder::load_commands_files(&config);
         make_weak_config(&config);
         Ok(config)
     }
 }
 
+fn include_roles(
+    settings: &SettingsFile,
+    config: &Rc<RefCell<SConfig>>,
+) -> Result<(), Box<dyn Error>> {
+    match &settings.storage.include {
+        Some(include) => include::include(config, include),
+        None => Ok(()),
+    }
+}
+
+/// Save the policy, the included roles being written back to their own files.
 pub fn save_json(
     settings: Rc<RefCell<SettingsFile>>,
     config: Rc<RefCell<SConfig>>,
+) -> Result<(), Box<dyn Error>> {
+    capsets::compact(&config)?;
+    let included = include::detach(&config);
+    let result = save_policy(settings.clone(), config.clone());
+    config.as_ref().borrow_mut().roles.extend(included);
+    result?;
+    if let Some(include) = &settings.as_ref().borrow().storage.include {
+        include::save(&config, include)?;
+    }
+    Ok(())
+}
+
+fn save_policy(
+    settings: Rc<RefCell<SettingsFile>>,
+    config: Rc<RefCell<SConfig>>,
 ) -> Result<(), Box<dyn Error>> {
     let default_remote: RemoteStorageSettings = RemoteStorageSettings::default();
     let into = ROOTASROLE.into();
@@ -119,6 +156,11 @@ pub fn save_json(
         version: PACKAGE_VERSION.to_owned().parse()?,
         data: config,
     };
+    if json_unchanged(&versionned, path) {
+        debug!("Config file unchanged");
+        return Ok(());
+    }
+    versionned.data.as_ref().borrow_mut().bump_generation();
     if let Some(settings) = &settings.as_ref().borrow().storage.settings {
         if settings.immutable.unwrap_or(true) {
             debug!("Toggling immutable on for config file");
@@ -230,7 +272,7 @@ where
     }
 }
 
-fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+pub(crate) fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
 where
     D: de::Deserializer<'de>,
 {
@@ -249,14 +291,6 @@ where
     Err(de::Error::custom("Invalid duration format"))
 }
 
-fn serialize_capset<S>(value: &capctl::CapSet, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    let v: Vec<String> = value.iter().map(|cap| cap.to_string()).collect();
-    v.serialize(serializer)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -319,7 +353,7 @@ mod tests {
         where
             S: serde::Serializer,
         {
-            serialize_duration(&Some(self.0.clone()), serializer)
+            serialize_duration(&Some(self.0), serializer)
         }
     }
 
@@ -1,9 +1,10 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
 #[cfg(feature = "finder")]
 use std::path::PathBuf;
 use std::{borrow::Borrow, cell::RefCell, rc::Rc};
 
-use bon::{bon, builder, Builder};
+use bon::{bon, Builder};
 use chrono::Duration;
 
 #[cfg(feature = "finder")]
@@ -21,6 +22,8 @@ use log::debug;
 use log::warn;
 
 use crate::rc_refcell;
+#[cfg(feature = "finder")]
+use crate::util::interpolate;
 
 #[cfg(feature = "finder")]
 use super::finder::Cred;
@@ -91,6 +94,7 @@ pub struct STimeout {
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Builder)]
+#[allow(clippy::should_implement_trait)]
 pub struct SPathOptions {
     #[serde(rename = "default", default, skip_serializing_if = "is_default")]
     #[builder(start_fn)]
@@ -129,7 +133,7 @@ pub enum EnvBehavior {
 }
 
 #[derive(Serialize, Hash, Deserialize, PartialEq, Eq, Debug, EnumIs, Clone)]
-enum EnvKeyType {
+pub enum EnvKeyType {
     Wildcarded,
     Normal,
 }
@@ -215,12 +219,228 @@ pub enum SPrivileged {
 #[serde(rename_all = "kebab-case")]
 #[derive(Default)]
 pub enum SAuthentication {
+    #[serde(alias = "none")]
     Skip,
     #[default]
+    #[serde(alias = "required")]
     Perform,
     Inherit,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SNetwork {
+    AllowAll,
+    // only the loopback interface of a dedicated network namespace
+    DenyAll,
+    // only these destinations, from the network of the caller
+    Allow(Vec<SNetworkRule>),
+    #[default]
+    Inherit,
+}
+
+/// A destination the command may connect to, any address or any port when not given.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct SNetworkRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(into)]
+    pub cidr: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default, with = FromIterator::from_iter)]
+    pub ports: Vec<u16>,
+}
+
+impl SNetworkRule {
+    /// The address and prefix length of the `cidr`, a single address without a prefix.
+    pub fn network(&self) -> Result<Option<(IpAddr, u8)>, String> {
+        let Some(cidr) = &self.cidr else {
+            return Ok(None);
+        };
+        let invalid = || format!("Invalid network {}", cidr);
+        let (address, prefix) = match cidr.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (cidr.as_str(), None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Some((address, prefix)))
+    }
+}
+
+/// Kernel lockdown mode required by a task, also used for the current mode of the kernel.
+#[derive(
+    Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, EnumIs, Display, Clone, Copy,
+)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SLockdown {
+    #[default]
+    Ignore,
+    // only when the kernel is not locked down
+    None,
+    // integrity or confidentiality
+    Integrity,
+    Confidentiality,
+}
+
+impl SLockdown {
+    pub fn allows(&self, kernel: SLockdown) -> bool {
+        match self {
+            SLockdown::Ignore => true,
+            SLockdown::None => kernel <= SLockdown::None,
+            required => kernel >= *required,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SAuditArgs {
+    #[default]
+    Full,
+    // the executable path only, for tasks receiving secrets as arguments
+    CommandOnly,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SAuditEnv {
+    #[default]
+    Names,
+    None,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SAuditContext {
+    #[default]
+    None,
+    // digest of the caller argv, environment and parent process
+    Hash,
+    // the digest and a copy of the context, only readable by root
+    Snapshot,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SAuditBackend {
+    // through the log destinations of the settings
+    #[default]
+    Log,
+    Syslog,
+    // native journal entries, each field of the record is a journal field
+    Journald,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SAuditVerbosity {
+    // the user, role, task and command only
+    Minimal,
+    // also the terminal, parent process, working directory, capabilities and exit status
+    #[default]
+    Standard,
+}
+
+/// What sr records about an execution, fields are inherited separately. The input and output of
+/// the command are never captured.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Builder)]
+pub struct SAudit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<SAuditArgs>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<SAuditEnv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<SAuditContext>,
+    // RAR_ROLE, RAR_TASK, RAR_CAPS and RAR_AUDIT_ID given to the command, true by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<SAuditBackend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<SAuditVerbosity>,
+    #[serde(default)]
+    #[serde(flatten, skip_serializing_if = "Map::is_empty")]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
+}
+
+/// How sr chooses among the tasks matching a command, only read at the global level.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
+pub enum SMatchingStrategy {
+    // the first matching task, in the order of the roles and tasks
+    First,
+    // the most precise matching task, refusing ties
+    #[default]
+    Best,
+}
+
+/// Named sets of characters for `wildcard-denied`, expanded when chosen.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, EnumIs, Display, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SWildcardPreset {
+    #[serde(alias = "shell")]
+    ShellMetacharacters,
+    Whitespace,
+    Redirections,
+}
+
+impl SWildcardPreset {
+    pub fn chars(&self) -> &'static str {
+        match self {
+            SWildcardPreset::ShellMetacharacters => ";&|<>()$`\\\"'*?[]{}~#!",
+            SWildcardPreset::Whitespace => " \t\n\r\x0b\x0c",
+            SWildcardPreset::Redirections => "<>|&",
+        }
+    }
+
+    /// The characters of all the presets, each once, in the order of the presets.
+    pub fn expand(presets: &[SWildcardPreset]) -> String {
+        let mut chars = String::new();
+        for c in presets.iter().flat_map(|preset| preset.chars().chars()) {
+            if !chars.contains(c) {
+                chars.push(c);
+            }
+        }
+        chars
+    }
+}
+
+pub const DEFAULT_PROMPT_TIMEOUT: u64 = 60;
+pub const DEFAULT_PROMPT_RETRIES: u8 = 3;
+
+/// Limits of the authentication prompt, fields are inherited separately.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Builder)]
+pub struct SAuthPrompt {
+    /// Seconds to wait for each answer, 0 waits forever
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Attempts allowed per invocation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u8>,
+    #[serde(default)]
+    #[serde(flatten, skip_serializing_if = "Map::is_empty")]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Opt {
@@ -238,8 +458,34 @@ pub struct Opt {
     pub authentication: Option<SAuthentication>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub wildcard_denied: Option<String>,
+    // the presets `wildcard-denied` was expanded from, only for display
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wildcard_denied_preset: Vec<SWildcardPreset>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub timeout: Option<STimeout>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dangerous_env_allowed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<SNetwork>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockdown: Option<SLockdown>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<SAudit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_package_integrity: Option<bool>,
+    // a writable directory of the task, owned by its target user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<bool>,
+    // only matches invocations carrying a valid orchestrator token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orchestrated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_prompt: Option<SAuthPrompt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matching_strategy: Option<SMatchingStrategy>,
+    // PAM service authenticating the user instead of sr, e.g. one demanding a second factor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pam_service: Option<String>,
     #[serde(default, flatten)]
     pub _extra_fields: Map<String, Value>,
 }
@@ -255,7 +501,18 @@ impl Opt {
         bounding: Option<SBounding>,
         authentication: Option<SAuthentication>,
         #[builder(into)] wildcard_denied: Option<String>,
+        #[builder(default)] wildcard_denied_preset: Vec<SWildcardPreset>,
         timeout: Option<STimeout>,
+        dangerous_env_allowed: Option<bool>,
+        network: Option<SNetwork>,
+        lockdown: Option<SLockdown>,
+        audit: Option<SAudit>,
+        verify_package_integrity: Option<bool>,
+        state_dir: Option<bool>,
+        orchestrated: Option<bool>,
+        auth_prompt: Option<SAuthPrompt>,
+        matching_strategy: Option<SMatchingStrategy>,
+        #[builder(into)] pam_service: Option<String>,
         #[builder(default)] _extra_fields: Map<String, Value>,
     ) -> Rc<RefCell<Self>> {
         rc_refcell!(Opt {
@@ -266,7 +523,18 @@ impl Opt {
             bounding,
             authentication,
             wildcard_denied,
+            wildcard_denied_preset,
             timeout,
+            dangerous_env_allowed,
+            network,
+            lockdown,
+            audit,
+            verify_package_integrity,
+            state_dir,
+            orchestrated,
+            auth_prompt,
+            matching_strategy,
+            pam_service,
             _extra_fields,
         })
     }
@@ -354,7 +622,18 @@ impl Default for Opt {
             bounding: Some(SBounding::default()),
             authentication: None,
             wildcard_denied: None,
+            wildcard_denied_preset: Vec::new(),
             timeout: None,
+            dangerous_env_allowed: None,
+            network: None,
+            lockdown: None,
+            audit: None,
+            verify_package_integrity: None,
+            state_dir: None,
+            orchestrated: None,
+            auth_prompt: None,
+            matching_strategy: None,
+            pam_service: None,
             _extra_fields: Map::default(),
             level: Level::Default,
         }
@@ -498,7 +777,7 @@ impl EnvSet for LinkedHashSet<EnvKey> {
 
 impl EnvSet for Option<LinkedHashSet<EnvKey>> {
     fn env_matches(&self, needle: &EnvKey) -> bool {
-        self.as_ref().map_or(false, |set| set.env_matches(needle))
+        self.as_ref().is_some_and(|set| set.env_matches(needle))
     }
 }
 
@@ -564,6 +843,24 @@ fn tz_is_safe(tzval: &str) -> bool {
     true
 }
 
+// Loader and interpreter variables that are never passed to the command,
+// whatever the keep/check/set lists say.
+#[cfg(feature = "finder")]
+const DANGEROUS_ENV: [&str; 7] = [
+    "LD_PRELOAD",
+    "LD_LIBRARY_PATH",
+    "IFS",
+    "BASH_ENV",
+    "ENV",
+    "PYTHONPATH",
+    "PERL5LIB",
+];
+
+#[cfg(feature = "finder")]
+fn is_dangerous_env(key: &str) -> bool {
+    key.starts_with("LD_") || DANGEROUS_ENV.contains(&key)
+}
+
 #[cfg(feature = "finder")]
 fn check_env(key: &str, value: &str) -> bool {
     debug!("Checking env: {}={}", key, value);
@@ -745,8 +1042,8 @@ impl OptStack {
         for opt in self.stack.iter().rev() {
             if let Some(opt) = opt.to_owned() {
                 let res = f(&opt.as_ref().borrow());
-                if res.is_some() {
-                    debug!("res: {:?}", res.as_ref().unwrap().0);
+                if let Some((level, _)) = res.as_ref() {
+                    debug!("res: {:?}", level);
                     return res;
                 }
             }
@@ -1004,7 +1301,23 @@ impl OptStack {
                 })
                 .collect()),
         }?;
-        final_env.insert("PATH".into(), self.calculate_path());
+        let placeholders = self
+            .task
+            .as_ref()
+            .is_some_and(|task| task.as_ref().borrow().has_placeholders());
+        let expand = |value: String| {
+            if placeholders {
+                interpolate(&value, &target.user)
+            } else {
+                Ok(value)
+            }
+        };
+        final_env.insert("PATH".into(), expand(self.calculate_path())?);
+        let set = env
+            .set
+            .into_iter()
+            .map(|(key, value)| Ok((key, expand(value)?)))
+            .collect::<Result<Vec<_>, String>>()?;
         final_env.insert("LOGNAME".into(), target.user.name.clone());
         final_env.insert("USER".into(), target.user.name);
         final_env.insert("HOME".into(), target.user.dir.to_string_lossy().to_string());
@@ -1015,7 +1328,19 @@ impl OptStack {
             "SHELL".into(),
             target.user.shell.to_string_lossy().to_string(),
         );
-        final_env.extend(env.set);
+        final_env.extend(set);
+        if self.get_dangerous_env_allowed() {
+            warn!("Dangerous environment variables are allowed for this task");
+        } else {
+            final_env.retain(|key, _| {
+                if is_dangerous_env(key) {
+                    warn!("Dropping dangerous env: {}", key);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
         Ok(final_env)
     }
 
@@ -1208,6 +1533,17 @@ impl OptStack {
         .unwrap_or((Level::None, "".to_owned()))
     }
 
+    /// The presets of the `wildcard-denied` in effect, when it was set from presets.
+    pub fn get_wildcard_preset(&self) -> Vec<SWildcardPreset> {
+        self.find_in_options(|opt| {
+            opt.wildcard_denied
+                .as_ref()
+                .map(|_| (opt.level, opt.wildcard_denied_preset.clone()))
+        })
+        .map(|(_, presets)| presets)
+        .unwrap_or_default()
+    }
+
     pub fn get_timeout(&self) -> (Level, STimeout) {
         self.find_in_options(|opt| {
             if let Some(p) = &opt.borrow().timeout {
@@ -1218,6 +1554,144 @@ impl OptStack {
         .unwrap_or((Level::None, STimeout::default()))
     }
 
+    pub fn get_network(&self) -> (Level, SNetwork) {
+        self.find_in_options(|opt| {
+            if let Some(p) = opt.borrow().network.clone().filter(|p| !p.is_inherit()) {
+                return Some((opt.level, p));
+            }
+            None
+        })
+        .unwrap_or((Level::None, SNetwork::default()))
+    }
+
+    pub fn get_lockdown(&self) -> (Level, SLockdown) {
+        self.find_in_options(|opt| {
+            if let Some(p) = opt.borrow().lockdown.filter(|p| !p.is_ignore()) {
+                return Some((opt.level, p));
+            }
+            None
+        })
+        .unwrap_or((Level::None, SLockdown::default()))
+    }
+
+    pub fn get_audit(&self) -> SAudit {
+        SAudit::builder()
+            .maybe_args(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.args)
+                        .map(|args| (opt.level, args))
+                })
+                .map(|(_, args)| args),
+            )
+            .maybe_env(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.env)
+                        .map(|env| (opt.level, env))
+                })
+                .map(|(_, env)| env),
+            )
+            .maybe_context(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.context)
+                        .map(|context| (opt.level, context))
+                })
+                .map(|(_, context)| context),
+            )
+            .maybe_export(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.export)
+                        .map(|export| (opt.level, export))
+                })
+                .map(|(_, export)| export),
+            )
+            .maybe_backend(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.backend)
+                        .map(|backend| (opt.level, backend))
+                })
+                .map(|(_, backend)| backend),
+            )
+            .maybe_verbosity(
+                self.find_in_options(|opt| {
+                    opt.audit
+                        .as_ref()
+                        .and_then(|audit| audit.verbosity)
+                        .map(|verbosity| (opt.level, verbosity))
+                })
+                .map(|(_, verbosity)| verbosity),
+            )
+            .build()
+    }
+
+    /// The prompt timeout and the number of attempts, with their defaults.
+    pub fn get_auth_prompt(&self) -> (u64, u8) {
+        let timeout = self
+            .find_in_options(|opt| {
+                opt.auth_prompt
+                    .as_ref()
+                    .and_then(|prompt| prompt.timeout)
+                    .map(|timeout| (opt.level, timeout))
+            })
+            .map_or(DEFAULT_PROMPT_TIMEOUT, |(_, timeout)| timeout);
+        let retries = self
+            .find_in_options(|opt| {
+                opt.auth_prompt
+                    .as_ref()
+                    .and_then(|prompt| prompt.retries)
+                    .map(|retries| (opt.level, retries))
+            })
+            .map_or(DEFAULT_PROMPT_RETRIES, |(_, retries)| retries);
+        (timeout, retries.max(1))
+    }
+
+    pub fn get_verify_package_integrity(&self) -> bool {
+        self.find_in_options(|opt| {
+            opt.verify_package_integrity
+                .map(|verify| (opt.level, verify))
+        })
+        .map(|(_, verify)| verify)
+        .unwrap_or(false)
+    }
+
+    pub fn get_state_dir(&self) -> bool {
+        self.find_in_options(|opt| opt.state_dir.map(|state| (opt.level, state)))
+            .map(|(_, state)| state)
+            .unwrap_or(false)
+    }
+
+    pub fn get_orchestrated(&self) -> bool {
+        self.find_in_options(|opt| {
+            opt.orchestrated
+                .map(|orchestrated| (opt.level, orchestrated))
+        })
+        .map(|(_, orchestrated)| orchestrated)
+        .unwrap_or(false)
+    }
+
+    /// The PAM service of the authentication, `None` for the one of sr.
+    pub fn get_pam_service(&self) -> Option<String> {
+        self.find_in_options(|opt| opt.pam_service.clone().map(|service| (opt.level, service)))
+            .map(|(_, service)| service)
+    }
+
+    /// Only the task level is allowed to lift the dangerous environment denylist.
+    pub fn get_dangerous_env_allowed(&self) -> bool {
+        self.stack[Level::Task as usize]
+            .as_ref()
+            .and_then(|opt| opt.as_ref().borrow().dangerous_env_allowed)
+            .unwrap_or(false)
+    }
+
     fn get_level(&self) -> Level {
         let (level, _) = self
             .find_in_options(|opt| Some((opt.level, ())))
@@ -1253,10 +1727,58 @@ impl OptStack {
                 })
                 .map(|(_, wildcard)| wildcard),
             )
+            .wildcard_denied_preset(self.get_wildcard_preset())
             .maybe_timeout(
                 self.find_in_options(|opt| opt.timeout.clone().map(|timeout| (opt.level, timeout)))
                     .map(|(_, timeout)| timeout),
             )
+            .maybe_dangerous_env_allowed(
+                self.stack[Level::Task as usize]
+                    .as_ref()
+                    .and_then(|opt| opt.as_ref().borrow().dangerous_env_allowed),
+            )
+            .maybe_network(
+                self.find_in_options(|opt| opt.network.clone().map(|network| (opt.level, network)))
+                    .map(|(_, network)| network),
+            )
+            .maybe_lockdown(
+                self.find_in_options(|opt| opt.lockdown.map(|lockdown| (opt.level, lockdown)))
+                    .map(|(_, lockdown)| lockdown),
+            )
+            .maybe_audit(
+                self.find_in_options(|opt| opt.audit.clone().map(|audit| (opt.level, audit)))
+                    .map(|(_, audit)| audit),
+            )
+            .maybe_verify_package_integrity(
+                self.find_in_options(|opt| {
+                    opt.verify_package_integrity
+                        .map(|verify| (opt.level, verify))
+                })
+                .map(|(_, verify)| verify),
+            )
+            .maybe_state_dir(
+                self.find_in_options(|opt| opt.state_dir.map(|state| (opt.level, state)))
+                    .map(|(_, state)| state),
+            )
+            .maybe_orchestrated(
+                self.find_in_options(|opt| {
+                    opt.orchestrated
+                        .map(|orchestrated| (opt.level, orchestrated))
+                })
+                .map(|(_, orchestrated)| orchestrated),
+            )
+            .maybe_auth_prompt(
+                self.find_in_options(|opt| {
+                    opt.auth_prompt.clone().map(|prompt| (opt.level, prompt))
+                })
+                .map(|(_, prompt)| prompt),
+            )
+            .maybe_matching_strategy(
+                self.stack[Level::Global as usize]
+                    .as_ref()
+                    .and_then(|opt| opt.as_ref().borrow().matching_strategy),
+            )
+            .maybe_pam_service(self.get_pam_service())
             .build()
     }
 }
@@ -1784,6 +2306,29 @@ mod tests {
         assert!(options.1.type_field.is_none());
     }
 
+    #[test]
+    fn test_get_pam_service() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_admin")
+                    .options(|opt| opt.pam_service("sr-totp").build())
+                    .task(STask::builder("t_admin").build())
+                    .build(),
+            )
+            .role(SRole::builder("r_user").build())
+            .build();
+        let task = config.role("r_admin").unwrap().as_ref().borrow().tasks[0].clone();
+        assert_eq!(
+            OptStack::from_task(task).get_pam_service().as_deref(),
+            Some("sr-totp")
+        );
+        assert!(OptStack::from_role(config.role("r_user").unwrap())
+            .get_pam_service()
+            .is_none());
+        let opt: Opt = serde_json::from_str(r#"{"pam-service": "sr-totp"}"#).unwrap();
+        assert_eq!(opt.pam_service.as_deref(), Some("sr-totp"));
+    }
+
     #[test]
     fn test_get_root_behavior() {
         let config = SConfig::builder()
@@ -1816,6 +2361,237 @@ mod tests {
         assert_eq!(bounding, SBounding::Strict);
     }
 
+    #[test]
+    fn test_get_network() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder("t")
+                            .options(|opt| opt.network(SNetwork::DenyAll).build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .options(|opt| opt.network(SNetwork::AllowAll).build())
+            .build();
+        let (level, network) = OptStack::from_task(config.task("test", "t").unwrap()).get_network();
+        assert_eq!(level, Level::Task);
+        assert_eq!(network, SNetwork::DenyAll);
+        let (level, network) = OptStack::from_role(config.role("test").unwrap()).get_network();
+        assert_eq!(level, Level::Global);
+        assert_eq!(network, SNetwork::AllowAll);
+        let network: SNetwork = serde_json::from_str(
+            r#"{"allow": [{"cidr": "10.0.0.0/8"}, {"cidr": "2001:db8::1", "ports": [443]}]}"#,
+        )
+        .unwrap();
+        let SNetwork::Allow(rules) = network else {
+            panic!("Expected an allow list");
+        };
+        assert_eq!(
+            rules[0].network().unwrap(),
+            Some(("10.0.0.0".parse().unwrap(), 8))
+        );
+        assert_eq!(
+            rules[1].network().unwrap(),
+            Some(("2001:db8::1".parse().unwrap(), 128))
+        );
+        assert_eq!(rules[1].ports, [443]);
+        assert_eq!(SNetworkRule::builder().build().network().unwrap(), None);
+        assert!(SNetworkRule::builder()
+            .cidr("10.0.0.0/33")
+            .build()
+            .network()
+            .is_err());
+        assert!(SNetworkRule::builder()
+            .cidr("example.com")
+            .build()
+            .network()
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_lockdown() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder("t")
+                            .options(|opt| opt.lockdown(SLockdown::None).build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .options(|opt| opt.lockdown(SLockdown::Integrity).build())
+            .build();
+        let (level, lockdown) =
+            OptStack::from_task(config.task("test", "t").unwrap()).get_lockdown();
+        assert_eq!(level, Level::Task);
+        assert_eq!(lockdown, SLockdown::None);
+        let (level, lockdown) = OptStack::from_role(config.role("test").unwrap()).get_lockdown();
+        assert_eq!(level, Level::Global);
+        assert_eq!(lockdown, SLockdown::Integrity);
+        assert!(SLockdown::Ignore.allows(SLockdown::Confidentiality));
+        assert!(SLockdown::None.allows(SLockdown::None));
+        assert!(!SLockdown::None.allows(SLockdown::Integrity));
+        assert!(SLockdown::Integrity.allows(SLockdown::Confidentiality));
+        assert!(!SLockdown::Confidentiality.allows(SLockdown::Integrity));
+    }
+
+    #[test]
+    fn test_get_audit() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder("t")
+                            .options(|opt| {
+                                opt.audit(
+                                    SAudit::builder()
+                                        .args(SAuditArgs::CommandOnly)
+                                        .context(SAuditContext::Hash)
+                                        .verbosity(SAuditVerbosity::Minimal)
+                                        .build(),
+                                )
+                                .build()
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .options(|opt| {
+                opt.audit(
+                    SAudit::builder()
+                        .env(SAuditEnv::None)
+                        .export(false)
+                        .backend(SAuditBackend::Journald)
+                        .build(),
+                )
+                .build()
+            })
+            .build();
+        let audit = OptStack::from_task(config.task("test", "t").unwrap()).get_audit();
+        assert_eq!(audit.export, Some(false));
+        assert_eq!(audit.args, Some(SAuditArgs::CommandOnly));
+        assert_eq!(audit.env, Some(SAuditEnv::None));
+        assert_eq!(audit.context, Some(SAuditContext::Hash));
+        assert_eq!(audit.backend, Some(SAuditBackend::Journald));
+        assert_eq!(audit.verbosity, Some(SAuditVerbosity::Minimal));
+        let audit = OptStack::from_role(config.role("test").unwrap()).get_audit();
+        assert_eq!(audit.args, None);
+        assert_eq!(audit.context, None);
+        assert_eq!(audit.env, Some(SAuditEnv::None));
+        assert_eq!(audit.verbosity, None);
+    }
+
+    #[test]
+    fn test_get_auth_prompt() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder("t")
+                            .options(|opt| {
+                                opt.auth_prompt(SAuthPrompt::builder().retries(0).build())
+                                    .build()
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .options(|opt| {
+                opt.auth_prompt(SAuthPrompt::builder().timeout(30).retries(5).build())
+                    .build()
+            })
+            .build();
+        assert_eq!(
+            OptStack::from_role(config.role("test").unwrap()).get_auth_prompt(),
+            (30, 5)
+        );
+        // at least one attempt is always allowed
+        assert_eq!(
+            OptStack::from_task(config.task("test", "t").unwrap()).get_auth_prompt(),
+            (30, 1)
+        );
+        assert_eq!(
+            OptStack::default().get_auth_prompt(),
+            (DEFAULT_PROMPT_TIMEOUT, DEFAULT_PROMPT_RETRIES)
+        );
+    }
+
+    #[test]
+    fn test_get_verify_package_integrity() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder("t")
+                            .options(|opt| opt.verify_package_integrity(false).build())
+                            .build(),
+                    )
+                    .task(STask::builder("t2").build())
+                    .build(),
+            )
+            .options(|opt| opt.verify_package_integrity(true).build())
+            .build();
+        assert!(
+            !OptStack::from_task(config.task("test", "t").unwrap()).get_verify_package_integrity()
+        );
+        assert!(
+            OptStack::from_task(config.task("test", "t2").unwrap()).get_verify_package_integrity()
+        );
+        assert!(!OptStack::default().get_verify_package_integrity());
+    }
+
+    #[test]
+    fn test_get_orchestrated() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .options(|opt| opt.orchestrated(true).build())
+                    .task(STask::builder("t").build())
+                    .task(
+                        STask::builder("t2")
+                            .options(|opt| opt.orchestrated(false).build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        assert!(OptStack::from_task(config.task("test", "t").unwrap()).get_orchestrated());
+        assert!(!OptStack::from_task(config.task("test", "t2").unwrap()).get_orchestrated());
+        assert!(!OptStack::default().get_orchestrated());
+    }
+
+    #[test]
+    fn test_authentication_aliases() {
+        let none: SAuthentication = serde_json::from_str("\"none\"").unwrap();
+        let required: SAuthentication = serde_json::from_str("\"required\"").unwrap();
+        assert_eq!(none, SAuthentication::Skip);
+        assert_eq!(required, SAuthentication::Perform);
+        assert_eq!(serde_json::to_string(&none).unwrap(), "\"skip\"");
+    }
+
+    #[test]
+    fn test_get_state_dir() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(STask::builder("t").build())
+                    .task(
+                        STask::builder("t2")
+                            .options(|opt| opt.state_dir(false).build())
+                            .build(),
+                    )
+                    .options(|opt| opt.state_dir(true).build())
+                    .build(),
+            )
+            .build();
+        assert!(OptStack::from_task(config.task("test", "t").unwrap()).get_state_dir());
+        assert!(!OptStack::from_task(config.task("test", "t2").unwrap()).get_state_dir());
+        assert!(!OptStack::default().get_state_dir());
+    }
+
     #[test]
     fn test_get_wildcard() {
         let config = SConfig::builder()
@@ -1831,6 +2607,43 @@ mod tests {
         assert_eq!(wildcard, "b");
     }
 
+    #[test]
+    fn test_get_wildcard_preset() {
+        let presets = [SWildcardPreset::Redirections, SWildcardPreset::Whitespace];
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .options(|opt| opt.wildcard_denied("b").build())
+                    .build(),
+            )
+            .options(|opt| {
+                let presets = [SWildcardPreset::Redirections, SWildcardPreset::Whitespace];
+                opt.wildcard_denied(SWildcardPreset::expand(&presets))
+                    .wildcard_denied_preset(presets.to_vec())
+                    .build()
+            })
+            .build();
+        assert_eq!(SWildcardPreset::expand(&presets), "<>|& \t\n\r\x0b\x0c");
+        assert_eq!(
+            OptStack::from_roles(config.clone()).get_wildcard_preset(),
+            presets
+        );
+        // the role level does not come from a preset
+        let optstack = OptStack::from_role(config.role("test").unwrap());
+        assert!(optstack.get_wildcard_preset().is_empty());
+        assert!(optstack
+            .to_opt()
+            .as_ref()
+            .borrow()
+            .wildcard_denied_preset
+            .is_empty());
+        let opt: Opt = serde_json::from_str(r#"{"wildcard-denied-preset": ["shell"]}"#).unwrap();
+        assert_eq!(
+            opt.wildcard_denied_preset,
+            [SWildcardPreset::ShellMetacharacters]
+        );
+    }
+
     #[cfg(feature = "finder")]
     #[test]
     fn test_tz_is_safe() {
@@ -1901,7 +2714,7 @@ mod tests {
             .unwrap();
         assert_eq!(result.get("env1").unwrap(), "value1");
         assert_eq!(result.get("env3").unwrap(), "value3");
-        assert!(result.get("env2").is_none());
+        assert!(!result.contains_key("env2"));
         assert_eq!(result.get("env4").unwrap(), "value4");
     }
 
@@ -1958,10 +2771,55 @@ mod tests {
             .unwrap();
         assert_eq!(result.get("env1").unwrap(), "value1");
         assert_eq!(result.get("env3").unwrap(), "value3");
-        assert!(result.get("env2").is_none());
+        assert!(!result.contains_key("env2"));
         assert_eq!(result.get("env4").unwrap(), "value4");
     }
 
+    #[cfg(feature = "finder")]
+    #[test]
+    fn test_env_set_forced() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder(IdTask::Number(1))
+                            .options(|opt| {
+                                opt.env(
+                                    SEnvOptions::builder(EnvBehavior::Inherit)
+                                        .set([("EDITOR".to_string(), "/usr/bin/vim".to_string())])
+                                        .build(),
+                                )
+                                .build()
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .options(|opt| {
+                opt.env(
+                    SEnvOptions::builder(EnvBehavior::Keep)
+                        .set([
+                            ("EDITOR".to_string(), "/usr/bin/nano".to_string()),
+                            ("LANG".to_string(), "C.UTF-8".to_string()),
+                        ])
+                        .build(),
+                )
+                .build()
+            })
+            .build();
+        let options = OptStack::from_task(config.task("test", 1).unwrap());
+        let test_env = [
+            ("EDITOR".to_string(), "/tmp/evil".to_string()),
+            ("LANG".to_string(), "fr_FR.UTF-8".to_string()),
+        ];
+        let cred = Cred::builder().user_id(0).group_id(0).build();
+        let result = options
+            .calculate_filtered_env(None, cred, test_env.into_iter())
+            .unwrap();
+        assert_eq!(result.get("EDITOR").unwrap(), "/usr/bin/vim");
+        assert_eq!(result.get("LANG").unwrap(), "C.UTF-8");
+    }
+
     #[test]
     fn is_wildcard_env_key() {
         assert!(!is_valid_env_name("TEST_.*"));
@@ -2002,7 +2860,7 @@ mod tests {
             .unwrap();
         assert_eq!(result.get("TEST_A").unwrap(), "value1");
         assert_eq!(result.get("TEST_B").unwrap(), "value2");
-        assert!(result.get("TESTaA").is_none());
+        assert!(!result.contains_key("TESTaA"));
     }
 
     #[test]
@@ -2159,9 +3017,9 @@ mod tests {
         let result = options
             .calculate_filtered_env(None, cred, test_env.into_iter())
             .unwrap();
-        assert!(result.get("env1").is_none());
-        assert!(result.get("env2").is_none());
-        assert!(result.get("env3").is_none());
+        assert!(!result.contains_key("env1"));
+        assert!(!result.contains_key("env2"));
+        assert!(!result.contains_key("env3"));
         assert_eq!(result.get("env4").unwrap(), "value4");
         assert_eq!(result.get("env5").unwrap(), "value5");
     }
@@ -2205,8 +3063,89 @@ mod tests {
                 test_env.into_iter(),
             )
             .unwrap();
-        assert!(result.get("envA").is_none());
+        assert!(!result.contains_key("envA"));
         assert_eq!(result.get("envB").unwrap(), "value2");
         assert_eq!(result.get("envC").unwrap(), "value3");
     }
+
+    #[cfg(feature = "finder")]
+    #[test]
+    fn test_dangerous_env_denied() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder(IdTask::Number(1))
+                            .options(|opt| {
+                                opt.env(
+                                    SEnvOptions::builder(EnvBehavior::Delete)
+                                        .keep(["LD_PRELOAD", "LD_AUDIT", "IFS", "env1"])
+                                        .unwrap()
+                                        .set([("BASH_ENV", "/tmp/evil")])
+                                        .build(),
+                                )
+                                .build()
+                            })
+                            .build(),
+                    )
+                    .options(|opt| opt.dangerous_env_allowed(true).build())
+                    .build(),
+            )
+            .build();
+        let options = OptStack::from_task(config.task("test", 1).unwrap());
+        assert!(!options.get_dangerous_env_allowed());
+        let test_env = [
+            ("LD_PRELOAD", "/tmp/evil.so"),
+            ("LD_AUDIT", "/tmp/evil.so"),
+            ("IFS", " "),
+            ("env1", "value1"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let cred = Cred::builder().user_id(0).group_id(0).build();
+        let result = options
+            .calculate_filtered_env(None, cred, test_env)
+            .unwrap();
+        assert!(!result.contains_key("LD_PRELOAD"));
+        assert!(!result.contains_key("LD_AUDIT"));
+        assert!(!result.contains_key("IFS"));
+        assert!(!result.contains_key("BASH_ENV"));
+        assert_eq!(result.get("env1").unwrap(), "value1");
+    }
+
+    #[cfg(feature = "finder")]
+    #[test]
+    fn test_dangerous_env_allowed() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("test")
+                    .task(
+                        STask::builder(IdTask::Number(1))
+                            .options(|opt| {
+                                opt.env(
+                                    SEnvOptions::builder(EnvBehavior::Delete)
+                                        .keep(["LD_LIBRARY_PATH"])
+                                        .unwrap()
+                                        .build(),
+                                )
+                                .dangerous_env_allowed(true)
+                                .build()
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let options = OptStack::from_task(config.task("test", 1).unwrap());
+        assert!(options.get_dangerous_env_allowed());
+        let test_env = [("LD_LIBRARY_PATH", "/opt/lib"), ("PYTHONPATH", "/opt/py")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let cred = Cred::builder().user_id(0).group_id(0).build();
+        let result = options
+            .calculate_filtered_env(None, cred, test_env)
+            .unwrap();
+        assert_eq!(result.get("LD_LIBRARY_PATH").unwrap(), "/opt/lib");
+        assert!(!result.contains_key("PYTHONPATH"));
+    }
 }
@@ -1,4 +1,4 @@
-use bon::{bon, builder, Builder};
+use bon::{bon, Builder};
 use capctl::{Cap, CapSet};
 use derivative::Derivative;
 use serde::{
@@ -11,20 +11,33 @@ use strum::{Display, EnumIs};
 
 use std::{
     cell::RefCell,
+    collections::BTreeMap,
     error::Error,
     fmt,
     ops::{Index, Not},
+    path::PathBuf,
     rc::{Rc, Weak},
 };
 
 use super::{
     actor::{SActor, SGroups, SUserType},
+    capsets::{self, CapabilitySet},
     is_default,
     options::{Level, Opt, OptBuilder},
 };
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
 pub struct SConfig {
+    // incremented at each save, to tell the activated policy from the edited one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+    // named sets of capabilities, written @name in the capabilities of the tasks
+    #[serde(
+        default,
+        rename = "capability-sets",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub capability_sets: BTreeMap<String, Vec<String>>,
     #[serde(
         default,
         skip_serializing_if = "Option::is_none",
@@ -52,6 +65,18 @@ where
 #[derivative(PartialEq, Eq)]
 pub struct SRole {
     pub name: String,
+    // a disabled role is kept in the policy but never matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    // free text explaining the grant, never used for matching
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    // command line used by `sr -r <role>` without command, it must still be granted by a task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_command: Option<String>,
+    // shown by sr before the first command of the role in a timestamp session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<SBanner>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub actors: Vec<SActor>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -67,6 +92,10 @@ pub struct SRole {
     #[serde(skip)]
     #[derivative(PartialEq = "ignore")]
     pub _config: Option<Weak<RefCell<SConfig>>>,
+    // the included file defining the role, see [`crate::include`]
+    #[serde(skip)]
+    #[derivative(PartialEq = "ignore")]
+    pub _file: Option<PathBuf>,
 }
 
 fn srole_opt<'de, D>(deserializer: D) -> Result<Option<Rc<RefCell<Opt>>>, D::Error>
@@ -101,6 +130,52 @@ pub struct STask {
     pub name: IdTask,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub purpose: Option<String>,
+    // a disabled task is kept in the policy but never matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    // arguments are given as RAR_ARG1..N environment variables, out of /proc/<pid>/cmdline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args_via_env: Option<bool>,
+    // %u and %h are only expanded in the commands, the path and the env values of these tasks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholders: Option<bool>,
+    // added to or removed from the arguments once the command matched the task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<SArguments>,
+    // opened by sr and inherited read-only by the command, instead of granting DAC capabilities
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read_files: Vec<PathBuf>,
+    // run without capabilities after the command, whatever its result
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_run: Vec<String>,
+    // the command starts in this directory, inside the chroot if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    // new root directory of the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chroot: Option<PathBuf>,
+    // granted by the OCI hook to the matching containers, never matched by sr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<SContainer>,
+    // SELinux role and type of the command, the user and range of the caller are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selinux_role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selinux_type: Option<String>,
+    // sr refuses the task when the kernel lacks one of them, before changing any credential
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<KernelFeature>,
+    // only a warning when the kernel lacks one of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recommends: Vec<KernelFeature>,
+    // from -1000, never killed when out of memory, to 1000, killed first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oom_score_adj: Option<i16>,
+    // memory.max of the cgroup v2 of the command, e.g. "512M"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<String>,
     #[serde(default, skip_serializing_if = "is_default")]
     pub cred: SCredentials,
     #[serde(default, skip_serializing_if = "is_default")]
@@ -127,6 +202,145 @@ where
     Ok(Some(Rc::new(RefCell::new(opt))))
 }
 
+/// A message displayed before executing a command with the role.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Builder, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SBanner {
+    #[builder(start_fn, into)]
+    pub text: String,
+    /// Whether `sr --quiet` may hide it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_quiet: Option<bool>,
+    #[serde(default, flatten, skip_serializing_if = "Map::is_empty")]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
+}
+
+/// A kernel feature a task depends on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum KernelFeature {
+    AmbientCapabilities,
+    UserNamespaces,
+    Ebpf,
+    Seccomp,
+    CgroupV2,
+    Landlock,
+    /// Written by a newer version, never supported
+    #[serde(other)]
+    Unknown,
+}
+
+/// The arguments a task enforces on its command.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Builder, PartialEq, Eq)]
+pub struct SArguments {
+    /// Given before the arguments of the user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default, with = |iter: impl IntoIterator<Item = impl ToString>| {
+        iter.into_iter().map(|arg| arg.to_string()).collect()
+    })]
+    pub prepend: Vec<String>,
+    /// Given after the arguments of the user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default, with = |iter: impl IntoIterator<Item = impl ToString>| {
+        iter.into_iter().map(|arg| arg.to_string()).collect()
+    })]
+    pub append: Vec<String>,
+    /// Flags removed from the arguments of the user, `--flag=` for a flag taking a value
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default, with = |iter: impl IntoIterator<Item = impl ToString>| {
+        iter.into_iter().map(|arg| arg.to_string()).collect()
+    })]
+    pub strip: Vec<String>,
+    #[serde(default, flatten, skip_serializing_if = "Map::is_empty")]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
+}
+
+impl SArguments {
+    // the number of arguments taken by a stripped flag at the start of `args`
+    fn stripped(&self, args: &[String]) -> usize {
+        let arg = args[0].as_str();
+        for flag in self.strip.iter() {
+            match flag.strip_suffix('=') {
+                None if arg == flag => return 1,
+                None => (),
+                Some(name) if arg == name => return args.len().min(2),
+                // --config=file, and -cfile for a short flag
+                Some(name)
+                    if arg.starts_with(flag.as_str())
+                        || (name.len() == 2
+                            && !name.starts_with("--")
+                            && arg.starts_with(name)) =>
+                {
+                    return 1
+                }
+                Some(_) => (),
+            }
+        }
+        0
+    }
+
+    /// The arguments of the user without the stripped flags, between the enforced ones.
+    ///
+    /// The arguments after `--` are never stripped.
+    pub fn apply(&self, args: &[String]) -> Vec<String> {
+        let mut result = self.prepend.clone();
+        let mut i = 0;
+        while i < args.len() {
+            if args[i] == "--" {
+                result.extend_from_slice(&args[i..]);
+                break;
+            }
+            match self.stripped(&args[i..]) {
+                0 => {
+                    result.push(args[i].clone());
+                    i += 1;
+                }
+                n => i += n,
+            }
+        }
+        result.extend(self.append.iter().cloned());
+        result
+    }
+}
+
+/// The containers a task applies to, all the given fields must match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Builder, PartialEq, Eq)]
+pub struct SContainer {
+    /// Image reference, a trailing `*` matches any suffix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(into)]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[builder(default, with = |iter: impl IntoIterator<Item = (impl ToString, impl ToString)>| {
+        iter.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    })]
+    pub annotations: BTreeMap<String, String>,
+    #[serde(default, flatten, skip_serializing_if = "Map::is_empty")]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
+}
+
+impl SContainer {
+    pub fn matches(&self, image: Option<&str>, annotations: &BTreeMap<String, String>) -> bool {
+        let image_matches = match (&self.image, image) {
+            (None, _) => true,
+            (Some(pattern), Some(image)) => match pattern.strip_suffix('*') {
+                Some(prefix) => image.starts_with(prefix),
+                None => pattern == image,
+            },
+            (Some(_), None) => false,
+        };
+        image_matches
+            && self
+                .annotations
+                .iter()
+                .all(|(key, value)| annotations.get(key) == Some(value))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Builder, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct SCredentials {
@@ -178,7 +392,7 @@ impl From<u32> for SUserChooser {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Builder, PartialEq, Eq)]
-
+#[allow(clippy::should_implement_trait)]
 pub struct SSetuidSet {
     #[builder(start_fn, into)]
     pub fallback: SUserType,
@@ -233,6 +447,7 @@ impl From<u32> for SGroupschooser {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Builder, PartialEq, Eq)]
+#[allow(clippy::should_implement_trait)]
 pub struct SSetgidSet {
     #[builder(start_fn, into)]
     pub fallback: SGroups,
@@ -247,7 +462,7 @@ pub struct SSetgidSet {
     pub sub: Vec<SGroups>,
 }
 
-#[derive(PartialEq, Eq, Debug, Builder)]
+#[derive(PartialEq, Eq, Debug, Clone, Builder)]
 pub struct SCapabilities {
     #[builder(start_fn)]
     pub default_behavior: SetBehavior,
@@ -255,6 +470,11 @@ pub struct SCapabilities {
     pub add: CapSet,
     #[builder(field)]
     pub sub: CapSet,
+    // the named sets written in add and sub, their capabilities are in add and sub
+    #[builder(default)]
+    pub add_sets: Vec<CapabilitySet>,
+    #[builder(default)]
+    pub sub_sets: Vec<CapabilitySet>,
     #[builder(default, with = <_>::from_iter)]
     pub _extra_fields: Map<String, Value>,
 }
@@ -284,19 +504,17 @@ impl Serialize for SCapabilities {
         S: serde::Serializer,
     {
         if self.default_behavior.is_none() && self.sub.is_empty() && self._extra_fields.is_empty() {
-            super::serialize_capset(&self.add, serializer)
+            capsets::names(&self.add, &self.add_sets).serialize(serializer)
         } else {
             let mut map = serializer.serialize_map(Some(3))?;
             if self.default_behavior.is_none() {
                 map.serialize_entry("default", &self.default_behavior)?;
             }
             if !self.add.is_empty() {
-                let v: Vec<String> = self.add.iter().map(|cap| cap.to_string()).collect();
-                map.serialize_entry("add", &v)?;
+                map.serialize_entry("add", &capsets::names(&self.add, &self.add_sets))?;
             }
             if !self.sub.is_empty() {
-                let v: Vec<String> = self.sub.iter().map(|cap| cap.to_string()).collect();
-                map.serialize_entry("del", &v)?;
+                map.serialize_entry("del", &capsets::names(&self.sub, &self.sub_sets))?;
             }
             for (key, value) in &self._extra_fields {
                 map.serialize_entry(key, value)?;
@@ -324,14 +542,17 @@ impl<'de> Deserialize<'de> for SCapabilities {
                 A: SeqAccess<'de>,
             {
                 let mut add = CapSet::default();
+                let mut add_sets = Vec::new();
                 while let Some(cap) = seq.next_element::<String>()? {
-                    add.add(cap.parse().map_err(de::Error::custom)?);
+                    capsets::parse(&cap, &mut add, &mut add_sets).map_err(de::Error::custom)?;
                 }
 
                 Ok(SCapabilities {
                     default_behavior: SetBehavior::None,
                     add,
                     sub: CapSet::default(),
+                    add_sets,
+                    sub_sets: Vec::new(),
                     _extra_fields: Map::new(),
                 })
             }
@@ -343,6 +564,8 @@ impl<'de> Deserialize<'de> for SCapabilities {
                 let mut default_behavior = SetBehavior::None;
                 let mut add = CapSet::default();
                 let mut sub = CapSet::default();
+                let mut add_sets = Vec::new();
+                let mut sub_sets = Vec::new();
                 let mut _extra_fields = Map::new();
 
                 while let Some(key) = map.next_key::<String>()? {
@@ -356,18 +579,16 @@ impl<'de> Deserialize<'de> for SCapabilities {
                             let values: Vec<String> =
                                 map.next_value().expect("add entry must be a list");
                             for value in values {
-                                add.add(value.parse().map_err(|_| {
-                                    de::Error::custom(format!("Invalid capability: {}", value))
-                                })?);
+                                capsets::parse(&value, &mut add, &mut add_sets)
+                                    .map_err(de::Error::custom)?;
                             }
                         }
                         "sub" | "del" => {
                             let values: Vec<String> =
                                 map.next_value().expect("sub entry must be a list");
                             for value in values {
-                                sub.add(value.parse().map_err(|_| {
-                                    de::Error::custom(format!("Invalid capability: {}", value))
-                                })?);
+                                capsets::parse(&value, &mut sub, &mut sub_sets)
+                                    .map_err(de::Error::custom)?;
                             }
                         }
                         other => {
@@ -380,6 +601,8 @@ impl<'de> Deserialize<'de> for SCapabilities {
                     default_behavior,
                     add,
                     sub,
+                    add_sets,
+                    sub_sets,
                     _extra_fields,
                 })
             }
@@ -396,7 +619,7 @@ pub enum SCommand {
     Complex(Value),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct SCommands {
     #[serde(rename = "default")]
     pub default_behavior: Option<SetBehavior>,
@@ -404,8 +627,16 @@ pub struct SCommands {
     pub add: Vec<SCommand>,
     #[serde(default, alias = "del", skip_serializing_if = "Vec::is_empty")]
     pub sub: Vec<SCommand>,
+    // a root-owned file with one more allowed command per line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
     #[serde(default, flatten, skip_serializing_if = "Map::is_empty")]
     pub _extra_fields: Map<String, Value>,
+    // the commands of `file`, read once when the policy is loaded
+    #[serde(skip)]
+    pub _file_commands: Vec<SCommand>,
 }
 
 // ------------------------
@@ -415,6 +646,8 @@ pub struct SCommands {
 impl Default for SConfig {
     fn default() -> Self {
         SConfig {
+            generation: None,
+            capability_sets: BTreeMap::new(),
             options: Some(Rc::new(RefCell::new(Opt::default()))),
             roles: Vec::new(),
             _extra_fields: Map::default(),
@@ -426,11 +659,16 @@ impl Default for SRole {
     fn default() -> Self {
         SRole {
             name: "".to_string(),
+            enabled: None,
+            comment: None,
+            default_command: None,
+            banner: None,
             actors: Vec::new(),
             tasks: Vec::new(),
             options: None,
             _extra_fields: Map::default(),
             _config: None,
+            _file: None,
         }
     }
 }
@@ -440,6 +678,22 @@ impl Default for STask {
         STask {
             name: IdTask::Number(0),
             purpose: None,
+            enabled: None,
+            comment: None,
+            args_via_env: None,
+            placeholders: None,
+            arguments: None,
+            read_files: Vec::new(),
+            post_run: Vec::new(),
+            cwd: None,
+            chroot: None,
+            container: None,
+            selinux_role: None,
+            selinux_type: None,
+            requires: Vec::new(),
+            recommends: Vec::new(),
+            oom_score_adj: None,
+            memory_max: None,
             cred: SCredentials::default(),
             commands: SCommands::default(),
             options: None,
@@ -467,7 +721,10 @@ impl Default for SCommands {
             default_behavior: Some(SetBehavior::default()),
             add: Vec::new(),
             sub: Vec::new(),
+            file: None,
+            comment: None,
             _extra_fields: Map::default(),
+            _file_commands: Vec::new(),
         }
     }
 }
@@ -478,6 +735,8 @@ impl Default for SCapabilities {
             default_behavior: SetBehavior::default(),
             add: CapSet::empty(),
             sub: CapSet::empty(),
+            add_sets: Vec::new(),
+            sub_sets: Vec::new(),
             _extra_fields: Map::default(),
         }
     }
@@ -548,9 +807,13 @@ impl SConfig {
         #[builder(field)] roles: Vec<Rc<RefCell<SRole>>>,
         #[builder(with = |f : fn(OptBuilder) -> Rc<RefCell<Opt>> | f(Opt::builder(Level::Global)))]
         options: Option<Rc<RefCell<Opt>>>,
+        generation: Option<u64>,
+        #[builder(default)] capability_sets: BTreeMap<String, Vec<String>>,
         _extra_fields: Option<Map<String, Value>>,
     ) -> Rc<RefCell<Self>> {
         let c = Rc::new(RefCell::new(SConfig {
+            generation,
+            capability_sets,
             roles: roles.clone(),
             options: options.clone(),
             _extra_fields: _extra_fields.unwrap_or_default().clone(),
@@ -560,6 +823,10 @@ impl SConfig {
         }
         c
     }
+
+    pub fn bump_generation(&mut self) {
+        self.generation = Some(self.generation.unwrap_or_default() + 1);
+    }
 }
 
 pub trait RoleGetter {
@@ -636,17 +903,26 @@ impl SRole {
         #[builder(start_fn, into)] name: String,
         #[builder(field)] tasks: Vec<Rc<RefCell<STask>>>,
         #[builder(field)] actors: Vec<SActor>,
+        enabled: Option<bool>,
+        #[builder(into)] comment: Option<String>,
+        #[builder(into)] default_command: Option<String>,
+        banner: Option<SBanner>,
         #[builder(with = |f : fn(OptBuilder) -> Rc<RefCell<Opt>> | f(Opt::builder(Level::Role)))]
         options: Option<Rc<RefCell<Opt>>>,
         #[builder(default)] _extra_fields: Map<String, Value>,
     ) -> Rc<RefCell<Self>> {
         let s = Rc::new(RefCell::new(SRole {
             name,
+            enabled,
+            comment,
+            default_command,
+            banner,
             actors,
             tasks,
             options,
             _extra_fields,
             _config: None,
+            _file: None,
         }));
         for task in s.as_ref().borrow_mut().tasks.iter() {
             task.borrow_mut()._role = Some(Rc::downgrade(&s));
@@ -656,6 +932,9 @@ impl SRole {
     pub fn config(&self) -> Option<Rc<RefCell<SConfig>>> {
         self._config.as_ref()?.upgrade()
     }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
     pub fn task(&self, name: &IdTask) -> Option<&Rc<RefCell<STask>>> {
         self.tasks
             .iter()
@@ -669,6 +948,22 @@ impl STask {
     pub fn new(
         #[builder(start_fn, into)] name: IdTask,
         purpose: Option<String>,
+        enabled: Option<bool>,
+        #[builder(into)] comment: Option<String>,
+        args_via_env: Option<bool>,
+        placeholders: Option<bool>,
+        arguments: Option<SArguments>,
+        #[builder(default)] read_files: Vec<PathBuf>,
+        #[builder(default, with = FromIterator::from_iter)] post_run: Vec<String>,
+        #[builder(into)] cwd: Option<PathBuf>,
+        #[builder(into)] chroot: Option<PathBuf>,
+        container: Option<SContainer>,
+        #[builder(into)] selinux_role: Option<String>,
+        #[builder(into)] selinux_type: Option<String>,
+        #[builder(default, with = FromIterator::from_iter)] requires: Vec<KernelFeature>,
+        #[builder(default, with = FromIterator::from_iter)] recommends: Vec<KernelFeature>,
+        oom_score_adj: Option<i16>,
+        #[builder(into)] memory_max: Option<String>,
         #[builder(default)] cred: SCredentials,
         #[builder(default)] commands: SCommands,
         #[builder(with = |f : fn(OptBuilder) -> Rc<RefCell<Opt>> | f(Opt::builder(Level::Task)))]
@@ -679,6 +974,22 @@ impl STask {
         Rc::new(RefCell::new(STask {
             name,
             purpose,
+            enabled,
+            comment,
+            args_via_env,
+            placeholders,
+            arguments,
+            read_files,
+            post_run,
+            cwd,
+            chroot,
+            container,
+            selinux_role,
+            selinux_type,
+            requires,
+            recommends,
+            oom_score_adj,
+            memory_max,
             cred,
             commands,
             options,
@@ -689,6 +1000,12 @@ impl STask {
     pub fn role(&self) -> Option<Rc<RefCell<SRole>>> {
         self._role.as_ref()?.upgrade()
     }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+    pub fn has_placeholders(&self) -> bool {
+        self.placeholders.unwrap_or(false)
+    }
 }
 
 impl Index<usize> for SConfig {
@@ -710,17 +1027,23 @@ impl Index<usize> for SRole {
 #[bon]
 impl SCommands {
     #[builder]
+    #[allow(clippy::should_implement_trait)]
     pub fn new(
         #[builder(start_fn)] default_behavior: SetBehavior,
         #[builder(default, with = FromIterator::from_iter)] add: Vec<SCommand>,
         #[builder(default, with = FromIterator::from_iter)] sub: Vec<SCommand>,
+        #[builder(into)] file: Option<PathBuf>,
+        #[builder(into)] comment: Option<String>,
         #[builder(default, with = <_>::from_iter)] _extra_fields: Map<String, Value>,
     ) -> Self {
         SCommands {
             default_behavior: Some(default_behavior),
             add,
             sub,
+            file,
+            comment,
             _extra_fields,
+            _file_commands: Vec::new(),
         }
     }
 }
@@ -766,6 +1089,54 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_container_matches() {
+        let container = SContainer::builder()
+            .image("registry.example.com/monitoring/*")
+            .annotations([("rootasrole.task", "probe")])
+            .build();
+        let annotations = BTreeMap::from([
+            ("rootasrole.task".to_string(), "probe".to_string()),
+            ("other".to_string(), "x".to_string()),
+        ]);
+        assert!(container.matches(
+            Some("registry.example.com/monitoring/node-exporter:1.8"),
+            &annotations
+        ));
+        assert!(!container.matches(Some("docker.io/library/alpine"), &annotations));
+        assert!(!container.matches(None, &annotations));
+        assert!(!container.matches(
+            Some("registry.example.com/monitoring/node-exporter:1.8"),
+            &BTreeMap::new()
+        ));
+        assert!(SContainer::default().matches(None, &BTreeMap::new()));
+    }
+
+    #[test]
+    fn test_arguments_apply() {
+        let arguments = SArguments::builder()
+            .prepend(["--config", "/etc/app/safe.conf"])
+            .append(["--no-plugins"])
+            .strip(["--config=", "-c=", "--unsafe"])
+            .build();
+        let args = |args: &str| args.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            arguments.apply(&args(
+                "--config /tmp/evil.conf -c/tmp/evil.conf --config=/tmp/evil.conf -v --unsafe run"
+            )),
+            args("--config /etc/app/safe.conf -v run --no-plugins")
+        );
+        assert_eq!(
+            arguments.apply(&args("run -- --unsafe")),
+            args("--config /etc/app/safe.conf run -- --unsafe --no-plugins")
+        );
+        assert_eq!(
+            arguments.apply(&args("-c")),
+            args("--config /etc/app/safe.conf --no-plugins")
+        );
+        assert_eq!(SArguments::default().apply(&args("-c x")), args("-c x"));
+    }
+
     #[test]
     fn test_deserialize() {
         println!("START");
@@ -875,6 +1246,9 @@ mod tests {
             actor0,
             &SActor::User {
                 id: Some("user1".into()),
+                granted: None,
+                reviewed: None,
+                expires: None,
                 _extra_fields: Map::default()
             }
         );
@@ -1004,7 +1378,9 @@ mod tests {
         assert_eq!(config._extra_fields.get("unknown").unwrap(), "unknown");
         let actor0 = &as_borrow!(config.roles[0]).actors[0];
         match actor0 {
-            SActor::User { id, _extra_fields } => {
+            SActor::User {
+                id, _extra_fields, ..
+            } => {
                 assert_eq!(id.as_ref().unwrap(), "user1");
                 assert_eq!(_extra_fields.get("unknown").unwrap(), "unknown");
             }
@@ -1267,4 +1643,116 @@ mod tests {
             "{\"options\":{\"env\":{\"override_behavior\":true}}}"
         );
     }
+
+    #[test]
+    fn test_comments_roundtrip() {
+        let config = r#"{
+            "roles": [{
+                "name": "role1",
+                "comment": "on-call rotation",
+                "tasks": [{
+                    "name": "task1",
+                    "comment": "needed for the 2024 migration",
+                    "commands": {
+                        "default": "none",
+                        "add": ["/usr/bin/true"],
+                        "comment": "no arguments on purpose"
+                    }
+                }]
+            }]
+        }"#;
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(config).unwrap();
+        let role = &as_borrow!(config).roles[0];
+        assert_eq!(
+            as_borrow!(role).comment.as_deref(),
+            Some("on-call rotation")
+        );
+        let task = &as_borrow!(role).tasks[0];
+        assert_eq!(
+            as_borrow!(task).comment.as_deref(),
+            Some("needed for the 2024 migration")
+        );
+        assert_eq!(
+            as_borrow!(task).commands.comment.as_deref(),
+            Some("no arguments on purpose")
+        );
+        assert!(as_borrow!(task).commands._extra_fields.is_empty());
+        let saved = serde_json::to_string(&config).unwrap();
+        assert!(saved.contains("\"comment\":\"on-call rotation\""));
+        assert!(saved.contains("\"comment\":\"no arguments on purpose\""));
+    }
+
+    #[test]
+    fn test_banner_roundtrip() {
+        let config = r#"{
+            "roles": [{
+                "name": "role1",
+                "banner": {
+                    "text": "Changes are audited",
+                    "allow-quiet": true
+                }
+            }]
+        }"#;
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(config).unwrap();
+        let role = &as_borrow!(config).roles[0];
+        assert_eq!(
+            as_borrow!(role).banner,
+            Some(
+                SBanner::builder("Changes are audited")
+                    .allow_quiet(true)
+                    .build()
+            )
+        );
+        assert!(as_borrow!(role)._extra_fields.is_empty());
+        let saved = serde_json::to_string(&config).unwrap();
+        assert!(saved.contains("\"allow-quiet\":true"));
+    }
+
+    #[test]
+    fn test_selinux_roundtrip() {
+        let config = r#"{
+            "roles": [{
+                "name": "role1",
+                "tasks": [{
+                    "name": "task1",
+                    "selinux_role": "sysadm_r",
+                    "selinux_type": "sysadm_t"
+                }]
+            }]
+        }"#;
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(config).unwrap();
+        let task = as_borrow!(as_borrow!(config).roles[0]).tasks[0].clone();
+        assert_eq!(as_borrow!(task).selinux_role.as_deref(), Some("sysadm_r"));
+        assert_eq!(as_borrow!(task).selinux_type.as_deref(), Some("sysadm_t"));
+        assert!(as_borrow!(task)._extra_fields.is_empty());
+        let saved: Rc<RefCell<SConfig>> =
+            serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(
+            as_borrow!(as_borrow!(saved).roles[0]).tasks[0],
+            as_borrow!(config).roles[0].as_ref().borrow().tasks[0]
+        );
+    }
+
+    #[test]
+    fn test_kernel_features() {
+        let config = r#"{
+            "roles": [{
+                "name": "role1",
+                "tasks": [{
+                    "name": "task1",
+                    "requires": ["user-namespaces", "ebpf"],
+                    "recommends": ["io-uring"]
+                }]
+            }]
+        }"#;
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(config).unwrap();
+        let task = as_borrow!(as_borrow!(config).roles[0]).tasks[0].clone();
+        assert_eq!(
+            as_borrow!(task).requires,
+            [KernelFeature::UserNamespaces, KernelFeature::Ebpf]
+        );
+        // a feature unknown to this version is never supported
+        assert_eq!(as_borrow!(task).recommends, [KernelFeature::Unknown]);
+        assert_eq!(KernelFeature::CgroupV2.to_string(), "cgroup-v2");
+    }
 }
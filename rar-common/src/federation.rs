@@ -0,0 +1,219 @@
+//! Roles published by a central team, included from a URL.
+//!
+//! `chsr remote refresh` downloads each `remote` source of the include settings, with its
+//! detached signature at `<url>.sig`: the hexadecimal HMAC-SHA256 of the document, made with the
+//! `key` shared with the publisher. A signed document is kept in the cache directory, and its
+//! roles are included after the local included files, so a local role always wins over a
+//! remote one. The signature is verified again each time the cache is read, and sr never
+//! downloads anything.
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use bon::Builder;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    activation::{hmac_sha256, read_key, same_signature},
+    database::structs::SConfig,
+    include::IncludeSettings,
+    util::{open_with_privileges, write_json_config},
+};
+
+pub(crate) const CACHE_DIR: &str = "/var/cache/rootasrole/remote";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct RemoteInclude {
+    #[builder(into)]
+    pub url: String,
+    /// The key shared with the publisher, only readable by root
+    #[builder(into)]
+    pub key: PathBuf,
+}
+
+/// A downloaded document with its signature.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct CachedRoles {
+    pub url: String,
+    pub fetched: DateTime<Utc>,
+    pub document: String,
+    pub signature: String,
+}
+
+impl CachedRoles {
+    fn verify(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        let expected = hex::encode(hmac_sha256(key, self.document.as_bytes()));
+        if same_signature(&expected, &self.signature) {
+            Ok(())
+        } else {
+            Err(format!("The signature of {} is invalid", self.url).into())
+        }
+    }
+
+    fn config(&self) -> Result<SConfig, Box<dyn Error>> {
+        let mut config: SConfig = serde_json::from_str(&self.document)?;
+        config._extra_fields.remove("version");
+        Ok(config)
+    }
+}
+
+/// The cache file of a source.
+pub fn cache_path(cache: &Path, url: &str) -> PathBuf {
+    cache.join(format!(
+        "{}.json",
+        &hex::encode(Sha256::digest(url.as_bytes()))[..16]
+    ))
+}
+
+fn fetch(url: &str) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--max-time",
+            "30",
+            "--proto",
+            "=https,file",
+            "--",
+            url,
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Download a source and replace its cache when it is signed, returns its number of roles.
+///
+/// The previous cache is kept when the download or the verification fails.
+pub fn refresh(source: &RemoteInclude, cache: &Path) -> Result<usize, Box<dyn Error>> {
+    let cached = CachedRoles {
+        url: source.url.clone(),
+        fetched: Utc::now(),
+        document: fetch(&source.url)?,
+        signature: fetch(&format!("{}.sig", source.url))?.trim().to_string(),
+    };
+    cached.verify(&read_key(&source.key)?)?;
+    let roles = cached.config()?.roles.len();
+    fs::create_dir_all(cache)?;
+    write_json_config(&cached, cache_path(cache, &source.url))?;
+    Ok(roles)
+}
+
+fn read_cache(source: &RemoteInclude, path: &Path) -> Result<SConfig, Box<dyn Error>> {
+    let cached: CachedRoles = serde_json::from_reader(open_with_privileges(path)?)
+        .map_err(|e| format!("{}, run chsr remote refresh", e))?;
+    if cached.url != source.url {
+        return Err(format!("{} caches {}", path.display(), cached.url).into());
+    }
+    cached.verify(&read_key(&source.key)?)?;
+    cached.config()
+}
+
+/// The cached roles of each source, with their cache file.
+///
+/// A source that was never downloaded, or whose cache is not signed, is ignored with a warning.
+pub fn cached(settings: &IncludeSettings) -> Vec<(PathBuf, SConfig)> {
+    settings
+        .remote
+        .iter()
+        .filter_map(|source| {
+            let path = cache_path(&settings.cache, &source.url);
+            match read_cache(source, &path) {
+                Ok(config) => Some((path, config)),
+                Err(e) => {
+                    warn!("Ignoring the roles of {}: {}", source.url, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, os::unix::fs::PermissionsExt, rc::Rc};
+
+    use crate::{
+        database::structs::SRole,
+        include::{include, save},
+    };
+
+    use super::*;
+
+    fn names(config: &Rc<RefCell<SConfig>>) -> Vec<String> {
+        config
+            .as_ref()
+            .borrow()
+            .roles
+            .iter()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_refresh() {
+        let dir = std::env::temp_dir().join(format!("rar_federation_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("published")).unwrap();
+        let key = dir.join("sec.key");
+        fs::write(&key, [7u8; 32]).unwrap();
+        fs::set_permissions(&key, fs::Permissions::from_mode(0o600)).unwrap();
+        let document =
+            r#"{"version": "3.0.5", "roles": [{"name": "r_base"}, {"name": "r_local"}]}"#;
+        let published = dir.join("published/base.json");
+        fs::write(&published, document).unwrap();
+        fs::write(
+            dir.join("published/base.json.sig"),
+            hex::encode(hmac_sha256(&[7u8; 32], document.as_bytes())),
+        )
+        .unwrap();
+        let source = RemoteInclude::builder()
+            .url(format!("file://{}", published.display()))
+            .key(&key)
+            .build();
+        let settings = IncludeSettings::builder()
+            .dir(dir.join("rootasrole.d"))
+            .cache(dir.join("cache"))
+            .remote(vec![source.clone()])
+            .build();
+        // never downloaded
+        assert!(cached(&settings).is_empty());
+        assert_eq!(refresh(&source, &settings.cache).unwrap(), 2);
+
+        let config = SConfig::builder()
+            .role(SRole::builder("r_local").build())
+            .build();
+        include(&config, &settings).unwrap();
+        assert_eq!(names(&config), ["r_local", "r_base"]);
+        // the remote roles are not written back
+        save(&config, &settings).unwrap();
+        assert!(!settings.dir.exists());
+
+        // a document not signed with the key does not replace the cache
+        fs::write(&published, r#"{"roles": [{"name": "r_evil"}]}"#).unwrap();
+        assert!(refresh(&source, &settings.cache).is_err());
+        assert_eq!(cached(&settings)[0].1.roles.len(), 2);
+
+        // nor does a cache modified in place
+        let path = cache_path(&settings.cache, &source.url);
+        let mut tampered: CachedRoles =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        tampered.document = r#"{"roles": [{"name": "r_evil"}]}"#.to_string();
+        fs::write(&path, serde_json::to_string(&tampered).unwrap()).unwrap();
+        assert!(cached(&settings).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+//! The groups of the caller known to the name service.
+//!
+//! The supplementary groups of a process are set at login, so they miss the groups added to the
+//! user since, and the directory groups (LDAP, SSSD) that the login did not add. With the
+//! `groups` setting, sr also asks the name service for the groups of the user, like
+//! initgroups(3) does with every source of nsswitch.conf. The answer is kept in the runtime
+//! directory for `cache` seconds, so that a slow directory is not asked at each command.
+
+use std::{
+    error::Error,
+    ffi::CString,
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bon::Builder;
+use capctl::Cap;
+use log::{debug, warn};
+use nix::unistd::{chown, getgrouplist, Gid, Uid, User};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    runtime::RuntimeDirs,
+    util::{cap_effective, create_with_privileges, open_with_privileges, remove_with_privileges},
+};
+
+const DEFAULT_CACHE: u64 = 300;
+
+fn default_cache() -> u64 {
+    DEFAULT_CACHE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupsSettings {
+    /// Seconds the groups of a user are kept, 0 asks the name service at each command
+    #[serde(default = "default_cache")]
+    #[builder(default = DEFAULT_CACHE)]
+    pub cache: u64,
+}
+
+impl Default for GroupsSettings {
+    fn default() -> Self {
+        GroupsSettings::builder().build()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedGroups {
+    uid: u32,
+    time: u64,
+    groups: Vec<u32>,
+}
+
+fn cache_path(dir: &Path, uid: Uid) -> PathBuf {
+    dir.join(uid.to_string())
+}
+
+// only a file written by sr is trusted, the user must not choose their groups
+fn read_cache(path: &Path, uid: Uid, now: u64, cache: u64) -> Option<Vec<Gid>> {
+    let file = open_with_privileges(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        warn!("Ignoring {}, not owned by root", path.display());
+        return None;
+    }
+    let cached: CachedGroups = serde_json::from_reader(file).ok()?;
+    (cached.uid == uid.as_raw() && cached.time <= now && now < cached.time + cache)
+        .then(|| cached.groups.into_iter().map(Gid::from_raw).collect())
+}
+
+fn write_cache(path: &Path, uid: Uid, now: u64, groups: &[Gid]) -> Result<(), Box<dyn Error>> {
+    let cached = CachedGroups {
+        uid: uid.as_raw(),
+        time: now,
+        groups: groups.iter().map(|gid| gid.as_raw()).collect(),
+    };
+    // a new file, still owned by the user until it is given to root
+    let _ = remove_with_privileges(path);
+    serde_json::to_writer(create_with_privileges(path)?, &cached)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    cap_effective(Cap::CHOWN, true)?;
+    let res = chown(path, Some(Uid::from_raw(0)), Some(Gid::from_raw(0)));
+    cap_effective(Cap::CHOWN, false)?;
+    Ok(res?)
+}
+
+fn resolve_with(
+    user: &User,
+    settings: &GroupsSettings,
+    dir: &Path,
+    now: SystemTime,
+    lookup: impl Fn(&User) -> Result<Vec<Gid>, Box<dyn Error>>,
+) -> Result<Vec<Gid>, Box<dyn Error>> {
+    let now = now.duration_since(UNIX_EPOCH)?.as_secs();
+    let path = cache_path(dir, user.uid);
+    if let Some(groups) = read_cache(&path, user.uid, now, settings.cache) {
+        debug!("Groups of {} read from {}", user.name, path.display());
+        return Ok(groups);
+    }
+    let groups = lookup(user)?;
+    if settings.cache > 0 {
+        if let Err(e) = write_cache(&path, user.uid, now, &groups) {
+            warn!("Unable to cache the groups of {}: {}", user.name, e);
+        }
+    }
+    Ok(groups)
+}
+
+fn lookup(user: &User) -> Result<Vec<Gid>, Box<dyn Error>> {
+    Ok(getgrouplist(&CString::new(user.name.as_str())?, user.gid)?)
+}
+
+/// The groups of `user` according to the name service, or to the cache when it is recent.
+pub fn resolve(user: &User, settings: &GroupsSettings) -> Result<Vec<Gid>, Box<dyn Error>> {
+    let runtime = RuntimeDirs::default();
+    runtime.init()?;
+    resolve_with(user, settings, &runtime.groups(), SystemTime::now(), lookup)
+}
+
+/// The gids of `directory` missing from `groups`, in their order.
+pub fn missing(groups: &[Gid], directory: &[Gid]) -> Vec<Gid> {
+    let mut missing: Vec<Gid> = Vec::new();
+    for gid in directory {
+        if !groups.contains(gid) && !missing.contains(gid) {
+            missing.push(*gid);
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_cache() {
+        let dir = std::env::temp_dir().join(format!("rar_groups_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let user = User::from_uid(Uid::from_raw(0)).unwrap().unwrap();
+        let settings = GroupsSettings::default();
+        let lookups = Cell::new(0);
+        let directory = |_: &User| {
+            lookups.set(lookups.get() + 1);
+            Ok(vec![Gid::from_raw(0), Gid::from_raw(4242)])
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let groups = resolve_with(&user, &settings, &dir, now, directory).unwrap();
+        assert_eq!(groups, [Gid::from_raw(0), Gid::from_raw(4242)]);
+        // kept for the cache duration
+        resolve_with(
+            &user,
+            &settings,
+            &dir,
+            now + Duration::from_secs(299),
+            directory,
+        )
+        .unwrap();
+        assert_eq!(lookups.get(), 1);
+        resolve_with(
+            &user,
+            &settings,
+            &dir,
+            now + Duration::from_secs(300),
+            directory,
+        )
+        .unwrap();
+        assert_eq!(lookups.get(), 2);
+        // a cache anyone may have written is not trusted
+        let path = cache_path(&dir, user.uid);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        resolve_with(
+            &user,
+            &settings,
+            &dir,
+            now + Duration::from_secs(301),
+            directory,
+        )
+        .unwrap();
+        assert_eq!(lookups.get(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing() {
+        let gids = |gids: &[u32]| gids.iter().copied().map(Gid::from_raw).collect::<Vec<_>>();
+        assert_eq!(
+            missing(&gids(&[1000, 27]), &gids(&[1000, 5000, 27, 5001, 5000])),
+            gids(&[5000, 5001])
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Roles dropped by packages in a directory, next to the policy.
+//!
+//! When the `include` setting is present, the roles of each `*.json` file of its directory are
+//! added after the roles of the policy, the files being read in the order of their names. A role
+//! whose name is already defined, by the policy or by a previous file, is ignored with a
+//! warning: a package cannot replace a role of the administrator. The options of the included
+//! files are not used, and a file not owned by root or writable by other users is ignored.
+//! The roles of the `remote` sources come last, see [`crate::federation`].
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use bon::Builder;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{
+        structs::{SConfig, SRole},
+        versionning::Versioning,
+    },
+    federation::{self, RemoteInclude, CACHE_DIR},
+    util::{json_unchanged, open_with_privileges, write_json_config},
+};
+
+const INCLUDE_DIR: &str = "/etc/security/rootasrole.d";
+
+fn default_dir() -> PathBuf {
+    INCLUDE_DIR.into()
+}
+
+fn default_cache() -> PathBuf {
+    CACHE_DIR.into()
+}
+
+fn is_default_cache(cache: &PathBuf) -> bool {
+    *cache == default_cache()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct IncludeSettings {
+    #[serde(default = "default_dir")]
+    #[builder(default = default_dir(), into)]
+    pub dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default)]
+    pub remote: Vec<RemoteInclude>,
+    #[serde(default = "default_cache", skip_serializing_if = "is_default_cache")]
+    #[builder(default = default_cache(), into)]
+    pub cache: PathBuf,
+}
+
+impl Default for IncludeSettings {
+    fn default() -> Self {
+        IncludeSettings::builder().build()
+    }
+}
+
+impl IncludeSettings {
+    /// The `*.json` files of the directory, in the order of their names.
+    pub fn files(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_included(path))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    /// The file of the directory named by `chsr role r1 add --file`.
+    pub fn file(&self, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.dir.join(name);
+        if path.parent() != Some(self.dir.as_path()) || !is_included(&path) {
+            return Err(format!(
+                "{} is not a .json file of {}",
+                path.display(),
+                self.dir.display()
+            )
+            .into());
+        }
+        Ok(path)
+    }
+}
+
+// hidden files are the temporary files of the writers
+fn is_included(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+        && path
+            .file_name()
+            .is_some_and(|name| !name.to_string_lossy().starts_with('.'))
+}
+
+fn read_file(path: &Path) -> Result<SConfig, Box<dyn Error>> {
+    let file = open_with_privileges(path)?;
+    let metadata = file.metadata()?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "{} must be owned by root and only writable by root",
+            path.display()
+        )
+        .into());
+    }
+    let mut config: SConfig = serde_json::from_reader(file)?;
+    // the version is written again when saving
+    config._extra_fields.remove("version");
+    Ok(config)
+}
+
+fn defined(config: &SConfig, name: &str) -> Option<Option<PathBuf>> {
+    config
+        .roles
+        .iter()
+        .find(|role| role.as_ref().borrow().name == name)
+        .map(|role| role.as_ref().borrow()._file.clone())
+}
+
+fn merge(config: &Rc<RefCell<SConfig>>, path: &Path, included: SConfig) {
+    for role in included.roles {
+        let name = role.as_ref().borrow().name.clone();
+        match defined(&config.as_ref().borrow(), &name) {
+            Some(Some(file)) if file == path => continue,
+            Some(_) => {
+                warn!(
+                    "Ignoring the role {} of {}, it is already defined",
+                    name,
+                    path.display()
+                );
+                continue;
+            }
+            None => {}
+        }
+        role.as_ref().borrow_mut()._file = Some(path.to_path_buf());
+        config.as_ref().borrow_mut().roles.push(role);
+    }
+}
+
+/// Add the roles of the included files, then of the remote sources, to the policy.
+pub fn include(
+    config: &Rc<RefCell<SConfig>>,
+    settings: &IncludeSettings,
+) -> Result<(), Box<dyn Error>> {
+    for path in settings.files()? {
+        match read_file(&path) {
+            Ok(included) => merge(config, &path, included),
+            Err(e) => warn!("Ignoring the included file {}: {}", path.display(), e),
+        }
+    }
+    for (path, included) in federation::cached(settings) {
+        merge(config, &path, included);
+    }
+    Ok(())
+}
+
+/// Remove the included roles from the policy, before it is saved.
+pub fn detach(config: &Rc<RefCell<SConfig>>) -> Vec<Rc<RefCell<SRole>>> {
+    let (included, own) = config
+        .as_ref()
+        .borrow_mut()
+        .roles
+        .drain(..)
+        .partition(|role| role.as_ref().borrow()._file.is_some());
+    config.as_ref().borrow_mut().roles = own;
+    included
+}
+
+/// Write the included roles back to their files.
+///
+/// The roles of a file that were ignored because their name is defined elsewhere are kept.
+pub fn save(
+    config: &Rc<RefCell<SConfig>>,
+    settings: &IncludeSettings,
+) -> Result<(), Box<dyn Error>> {
+    let mut paths = settings.files()?;
+    for role in config.as_ref().borrow().roles.iter() {
+        if let Some(file) = &role.as_ref().borrow()._file {
+            // the remote roles are replaced at each refresh, they are not written
+            if file.parent() == Some(settings.dir.as_path()) && !paths.contains(file) {
+                paths.push(file.clone());
+            }
+        }
+    }
+    for path in paths {
+        let mut current = if path.exists() {
+            match read_file(&path) {
+                Ok(current) => current,
+                Err(e) => {
+                    debug!("Not writing {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        } else {
+            SConfig::default()
+        };
+        let shadowed = current
+            .roles
+            .drain(..)
+            .filter(|role| {
+                defined(&config.as_ref().borrow(), &role.as_ref().borrow().name)
+                    .is_some_and(|file| file.as_ref() != Some(&path))
+            })
+            .collect::<Vec<_>>();
+        current.roles = config
+            .as_ref()
+            .borrow()
+            .roles
+            .iter()
+            .filter(|role| role.as_ref().borrow()._file.as_ref() == Some(&path))
+            .cloned()
+            .chain(shadowed)
+            .collect();
+        let versionned = Versioning::new(current);
+        if json_unchanged(&versionned, &path) {
+            continue;
+        }
+        debug!("Writing the included file {}", path.display());
+        write_json_config(&versionned, &path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> IncludeSettings {
+        let dir = std::env::temp_dir().join(format!("rar_include_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        IncludeSettings::builder().dir(dir).build()
+    }
+
+    fn names(config: &Rc<RefCell<SConfig>>) -> Vec<String> {
+        config
+            .as_ref()
+            .borrow()
+            .roles
+            .iter()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_file() {
+        let settings = IncludeSettings::builder()
+            .dir("/etc/security/rootasrole.d")
+            .build();
+        assert_eq!(
+            settings.file("nginx.json").unwrap(),
+            PathBuf::from("/etc/security/rootasrole.d/nginx.json")
+        );
+        assert!(settings.file("/etc/security/rootasrole.json").is_err());
+        assert!(settings.file("../rootasrole.json").is_err());
+        assert!(settings.file("nginx.conf").is_err());
+        assert!(settings.file(".nginx.json").is_err());
+    }
+
+    #[test]
+    fn test_include() {
+        let settings = dir("merge");
+        fs::write(
+            settings.dir.join("10-web.json"),
+            r#"{"roles": [{"name": "r_web"}, {"name": "r_admin"}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            settings.dir.join("20-db.json"),
+            r#"{"version": "3.0.5", "roles": [{"name": "r_web"}, {"name": "r_db"}]}"#,
+        )
+        .unwrap();
+        fs::write(settings.dir.join("notes.txt"), "{}").unwrap();
+        let config = SConfig::builder()
+            .role(SRole::builder("r_admin").build())
+            .build();
+        include(&config, &settings).unwrap();
+        assert_eq!(names(&config), ["r_admin", "r_web", "r_db"]);
+        // read again, the roles of the files are not conflicts with themselves
+        include(&config, &settings).unwrap();
+        assert_eq!(names(&config), ["r_admin", "r_web", "r_db"]);
+
+        let included = detach(&config);
+        assert_eq!(names(&config), ["r_admin"]);
+        config.as_ref().borrow_mut().roles.extend(included);
+        config
+            .as_ref()
+            .borrow_mut()
+            .roles
+            .retain(|role| role.as_ref().borrow().name != "r_db");
+        save(&config, &settings).unwrap();
+        let web = read_file(&settings.dir.join("10-web.json")).unwrap();
+        // r_admin is shadowed by the policy, it stays in the file of its package
+        assert_eq!(web.roles.len(), 2);
+        let db = read_file(&settings.dir.join("20-db.json")).unwrap();
+        // r_web is shadowed by 10-web.json, r_db was deleted
+        assert_eq!(db.roles.len(), 1);
+        assert_eq!(db.roles[0].as_ref().borrow().name, "r_web");
+        assert!(db._extra_fields.is_empty());
+        fs::remove_dir_all(&settings.dir).unwrap();
+    }
+
+    #[test]
+    fn test_untrusted_file() {
+        let settings = dir("untrusted");
+        let path = settings.dir.join("pkg.json");
+        fs::write(&path, r#"{"roles": [{"name": "r_pkg"}]}"#).unwrap();
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o666)).unwrap();
+        let config = SConfig::builder().build();
+        include(&config, &settings).unwrap();
+        assert!(names(&config).is_empty());
+        fs::remove_dir_all(&settings.dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Verification of the policy file against a digest sealed in the TPM.
+//!
+//! The SHA256 digest of the policy file, in hexadecimal, is sealed in a persistent TPM object
+//! with tpm2-tools. sr unseals it at startup and compares it with the current policy file.
+//! The handle is read from a separate root-owned file, so that editing the policy cannot
+//! disable the check.
+
+use std::{
+    error::Error,
+    io::{ErrorKind, Read},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use bon::Builder;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use strum::EnumIs;
+
+use crate::{util::open_with_privileges, RemoteStorageSettings, SettingsFile};
+
+const TPM2_UNSEAL: &str = "/usr/bin/tpm2_unseal";
+pub const INTEGRITY: &str = "/etc/security/rootasrole.integrity.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, EnumIs)]
+#[serde(rename_all = "kebab-case")]
+pub enum MismatchAction {
+    #[default]
+    Refuse,
+    Warn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct IntegritySettings {
+    /// Persistent handle of the sealed digest, as given to `tpm2_unseal -c`
+    #[builder(into)]
+    pub handle: String,
+    #[serde(default)]
+    #[builder(default)]
+    pub on_mismatch: MismatchAction,
+}
+
+fn check_root_owned(path: &Path) -> Result<(), Box<dyn Error>> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "{} must be owned by root and not writable by group or others",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The integrity settings of the host, None when the policy is not sealed in a TPM.
+pub fn integrity_settings() -> Result<Option<IntegritySettings>, Box<dyn Error>> {
+    read_integrity_settings(Path::new(INTEGRITY))
+}
+
+fn read_integrity_settings(path: &Path) -> Result<Option<IntegritySettings>, Box<dyn Error>> {
+    let file = match open_with_privileges(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    for path in path.ancestors() {
+        check_root_owned(path)?;
+    }
+    Ok(Some(serde_json::from_reader(file)?))
+}
+
+/// The file actually holding the roles, which may be the settings file itself.
+pub fn policy_path<P: AsRef<Path>>(settings: &SettingsFile, settings_path: P) -> PathBuf {
+    let default_remote = RemoteStorageSettings::default();
+    settings
+        .storage
+        .settings
+        .as_ref()
+        .unwrap_or(&default_remote)
+        .path
+        .clone()
+        .unwrap_or_else(|| settings_path.as_ref().to_path_buf())
+}
+
+pub fn policy_digest<P: AsRef<Path>>(path: P) -> Result<String, Box<dyn Error>> {
+    let mut content = Vec::new();
+    open_with_privileges(path.as_ref())?.read_to_end(&mut content)?;
+    Ok(hex::encode(sha2::Sha256::digest(&content)))
+}
+
+fn unseal(handle: &str) -> Result<String, Box<dyn Error>> {
+    // TPM2TOOLS_TCTI could redirect the unsealing to a fake TPM
+    let output = Command::new(TPM2_UNSEAL)
+        .args(["-c", handle])
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to unseal the policy digest: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_lowercase())
+}
+
+/// Ok when the policy matches the sealed digest, or when the mismatch is only reported.
+pub fn verify_policy<P: AsRef<Path>>(
+    path: P,
+    integrity: &IntegritySettings,
+) -> Result<(), Box<dyn Error>> {
+    verify_with(path.as_ref(), integrity, unseal)
+}
+
+fn verify_with<F>(
+    path: &Path,
+    integrity: &IntegritySettings,
+    unseal: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(&str) -> Result<String, Box<dyn Error>>,
+{
+    let error = match (unseal(&integrity.handle), policy_digest(path)) {
+        (Ok(sealed), Ok(current)) if sealed == current => return Ok(()),
+        (Ok(_), Ok(_)) => format!(
+            "The policy file {} does not match the digest sealed in the TPM",
+            path.display()
+        ),
+        (Err(e), _) | (_, Err(e)) => e.to_string(),
+    };
+    if integrity.on_mismatch.is_warn() {
+        warn!("{}", error);
+        Ok(())
+    } else {
+        Err(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_policy() {
+        let path = std::env::temp_dir().join("rar_integrity_test.json");
+        fs::write(&path, "{}").unwrap();
+        let digest = policy_digest(&path).unwrap();
+        assert_eq!(
+            digest,
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        let refuse = IntegritySettings::builder().handle("0x81010001").build();
+        let warn = IntegritySettings::builder()
+            .handle("0x81010001")
+            .on_mismatch(MismatchAction::Warn)
+            .build();
+        assert!(verify_with(&path, &refuse, |_| Ok(digest.clone())).is_ok());
+        assert!(verify_with(&path, &refuse, |_| Ok("00".to_string())).is_err());
+        assert!(verify_with(&path, &refuse, |_| Err("no tpm".into())).is_err());
+        assert!(verify_with(&path, &warn, |_| Ok("00".to_string())).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_integrity_settings() {
+        let dir = PathBuf::from(format!("/run/rar_test_integrity_{}", std::process::id()));
+        if fs::create_dir(&dir).is_err() || check_root_owned(&dir).is_err() {
+            let _ = fs::remove_dir(&dir);
+            return;
+        }
+        let path = dir.join("integrity.json");
+        assert!(read_integrity_settings(&path).unwrap().is_none());
+        fs::write(&path, r#"{"handle":"0x81010001","on-mismatch":"warn"}"#).unwrap();
+        let settings = read_integrity_settings(&path).unwrap().unwrap();
+        assert_eq!(settings.handle, "0x81010001");
+        assert!(settings.on_mismatch.is_warn());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(read_integrity_settings(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
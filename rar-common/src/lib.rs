@@ -57,18 +57,46 @@ use std::{cell::RefCell, error::Error, ffi::OsStr, path::PathBuf, rc::Rc};
 use bon::Builder;
 use log::debug;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
+pub mod activation;
+#[cfg(feature = "finder")]
+pub mod agent;
 pub mod api;
+pub mod clock;
 pub mod database;
+pub mod federation;
+pub mod groups;
+pub mod include;
+pub mod integrity;
+pub mod lock;
+pub mod logger;
+pub mod orchestrator;
 pub mod plugin;
+#[cfg(feature = "finder")]
+pub mod policy;
+pub mod retention;
+pub mod runtime;
+#[cfg(any(feature = "testkit", all(test, feature = "finder")))]
+pub mod testkit;
+pub mod trace;
 pub mod util;
 pub mod version;
 
 use util::{
-    dac_override_effective, open_with_privileges, read_effective, toggle_lock_config,
-    write_json_config, ImmutableLock,
+    dac_override_effective, json_unchanged, open_with_privileges, read_effective,
+    toggle_lock_config, write_json_config, ImmutableLock,
 };
 
+use activation::ActivationSettings;
+use clock::ClockSettings;
+use groups::GroupsSettings;
+use include::IncludeSettings;
+use logger::LogSettings;
+use orchestrator::OrchestratorSettings;
+use retention::RetentionSettings;
+use trace::TraceSettings;
+
 use database::{
     migration::Migration,
     structs::SConfig,
@@ -106,6 +134,26 @@ pub struct Settings {
     pub settings: Option<RemoteStorageSettings>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ldap: Option<LdapSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activation: Option<ActivationSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<GroupsSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<TraceSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orchestrator: Option<OrchestratorSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<IncludeSettings>,
+    // kept as is when saving, for settings written by newer versions
+    #[serde(default, flatten)]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Builder, Default)]
@@ -130,6 +178,9 @@ pub struct RemoteStorageSettings {
     pub table_prefix: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<Properties>,
+    #[serde(default, flatten)]
+    #[builder(default)]
+    pub _extra_fields: Map<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -189,6 +240,15 @@ impl Default for Settings {
             method: StorageMethod::JSON,
             settings: None,
             ldap: None,
+            log: None,
+            activation: None,
+            retention: None,
+            clock: None,
+            groups: None,
+            trace: None,
+            orchestrator: None,
+            include: None,
+            _extra_fields: Map::default(),
         }
     }
 }
@@ -206,6 +266,12 @@ pub fn save_settings(settings: Rc<RefCell<SettingsFile>>) -> Result<(), Box<dyn
         .path
         .as_ref()
         .unwrap_or(&into);
+    let versionned: Versioning<Rc<RefCell<SettingsFile>>> = Versioning::new(settings.clone());
+    if json_unchanged(&versionned, ROOTASROLE) {
+        debug!("Config file unchanged");
+        return Ok(());
+    }
+    binding.config.as_ref().borrow_mut().bump_generation();
     if let Some(settings) = &settings.as_ref().borrow().storage.settings {
         if settings.immutable.unwrap_or(true) {
             debug!("Toggling immutable on for config file");
@@ -213,7 +279,6 @@ pub fn save_settings(settings: Rc<RefCell<SettingsFile>>) -> Result<(), Box<dyn
         }
     }
     debug!("Writing config file");
-    let versionned: Versioning<Rc<RefCell<SettingsFile>>> = Versioning::new(settings.clone());
     write_json_config(&versionned, ROOTASROLE)?;
     if let Some(settings) = &settings.as_ref().borrow().storage.settings {
         if settings.immutable.unwrap_or(true) {
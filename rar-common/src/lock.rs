@@ -0,0 +1,136 @@
+//! Serializes the edits of the policy.
+//!
+//! chsr holds an advisory lock on a file of the runtime locks directory while it writes the
+//! policy, and refuses to write when the generation of the policy on disk is not the one it
+//! read: another administrator saved the policy in the meantime, and writing would lose their
+//! changes.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+use serde_json::Value;
+
+use crate::{
+    database::structs::SConfig,
+    runtime::RuntimeDirs,
+    util::{append_with_privileges, open_with_privileges},
+};
+
+fn lock_path(runtime: &RuntimeDirs, path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    runtime.locks().join(format!("{}.lock", name))
+}
+
+/// The generation of the policy file, as last written.
+fn generation_on_disk(path: &Path) -> Result<Option<u64>, Box<dyn Error>> {
+    let file = match open_with_privileges(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let value: Value = serde_json::from_reader(BufReader::new(file))?;
+    Ok(value.get("generation").and_then(Value::as_u64))
+}
+
+/// An exclusive lock on the policy, released when dropped.
+pub struct PolicyLock {
+    path: PathBuf,
+    _lock: Flock<File>,
+}
+
+impl PolicyLock {
+    /// Lock the policy file, waiting for the other editors.
+    pub fn acquire<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        let runtime = RuntimeDirs::default();
+        runtime.init()?;
+        let file = append_with_privileges(lock_path(&runtime, &path))?;
+        let lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((file, Errno::EWOULDBLOCK)) => {
+                info!("Waiting for another edition of {}", path.display());
+                Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e)?
+            }
+            Err((_, e)) => return Err(e.into()),
+        };
+        debug!("Locked {}", path.display());
+        Ok(PolicyLock { path, _lock: lock })
+    }
+
+    /// Fail when the policy was saved since `config` was read.
+    pub fn check_unmodified(&self, config: &SConfig) -> Result<(), Box<dyn Error>> {
+        let current = generation_on_disk(&self.path)?;
+        if current != config.generation {
+            return Err(format!(
+                "{} was modified since it was read (generation {} on disk, {} read)",
+                self.path.display(),
+                current.unwrap_or_default(),
+                config.generation.unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_lock_path() {
+        assert_eq!(
+            lock_path(
+                &RuntimeDirs::new("/run/rootasrole"),
+                Path::new("/etc/security/rootasrole.json")
+            ),
+            PathBuf::from("/run/rootasrole/locks/rootasrole.json.lock")
+        );
+    }
+
+    #[test]
+    fn test_check_unmodified() {
+        let path = std::env::temp_dir().join(format!("rar_lock_{}.json", std::process::id()));
+        let lock = PolicyLock::acquire(&path).unwrap();
+        let config = SConfig::builder().generation(3).build();
+        // not written yet
+        assert!(lock
+            .check_unmodified(&SConfig::builder().build().as_ref().borrow())
+            .is_ok());
+        fs::write(&path, r#"{"version": "3.0.5", "generation": 3}"#).unwrap();
+        assert!(lock.check_unmodified(&config.as_ref().borrow()).is_ok());
+        fs::write(&path, r#"{"version": "3.0.5", "generation": 4}"#).unwrap();
+        assert_eq!(
+            lock.check_unmodified(&config.as_ref().borrow())
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "{} was modified since it was read (generation 4 on disk, 3 read)",
+                path.display()
+            )
+        );
+        // the lock is taken by another open file description until dropped
+        let other = append_with_privileges(lock_path(&RuntimeDirs::default(), &path)).unwrap();
+        let other = Flock::lock(other, FlockArg::LockExclusiveNonblock);
+        assert!(matches!(other, Err((_, Errno::EWOULDBLOCK))));
+        drop(lock);
+        let other = append_with_privileges(lock_path(&RuntimeDirs::default(), &path)).unwrap();
+        assert!(Flock::lock(other, FlockArg::LockExclusiveNonblock).is_ok());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(lock_path(&RuntimeDirs::default(), &path)).unwrap();
+    }
+}
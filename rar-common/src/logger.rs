@@ -0,0 +1,365 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use bon::Builder;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use syslog::{Facility, Formatter3164, LoggerBackend};
+
+use crate::util::append_with_privileges;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogDestination {
+    Stderr,
+    #[serde(alias = "journald")]
+    Syslog,
+    File(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Builder)]
+pub struct LogSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(into)]
+    pub level: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[builder(default, with = |iter: impl IntoIterator<Item = (impl ToString, impl ToString)>| {
+        iter.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    })]
+    pub filters: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default, with = FromIterator::from_iter)]
+    pub destinations: Vec<LogDestination>,
+}
+
+enum Sink {
+    Stderr,
+    File(File),
+    Syslog(syslog::Logger<LoggerBackend, Formatter3164>),
+}
+
+struct LoggerState {
+    level: LevelFilter,
+    filters: Vec<(String, LevelFilter)>,
+    sinks: Vec<Sink>,
+}
+
+struct RarLogger;
+
+static LOGGER: RarLogger = RarLogger;
+static STATE: Mutex<Option<LoggerState>> = Mutex::new(None);
+static SESSION: OnceLock<String> = OnceLock::new();
+
+// Keys of KEY=value pairs containing one of these are redacted in every message.
+const SECRET_MARKERS: [&str; 5] = ["PASS", "SECRET", "TOKEN", "CREDENTIAL", "API_KEY"];
+
+fn default_settings() -> LogSettings {
+    if cfg!(debug_assertions) {
+        LogSettings::builder()
+            .level("debug")
+            .destinations([LogDestination::Stderr])
+            .build()
+    } else {
+        LogSettings::builder()
+            .level("info")
+            .destinations([LogDestination::Syslog])
+            .build()
+    }
+}
+
+fn parse_level(level: &str) -> Option<LevelFilter> {
+    level
+        .parse::<LevelFilter>()
+        .inspect_err(|_| eprintln!("Invalid log level: {}", level))
+        .ok()
+}
+
+// A filter matches a full module path prefix or any module path segments of the target.
+// Returns where the match ends in the target, the deepest match is the most specific.
+fn module_match_end(target: &str, module: &str) -> Option<usize> {
+    let target = format!("::{}::", target);
+    target
+        .rfind(&format!("::{}::", module))
+        .map(|pos| pos + module.len())
+}
+
+impl LoggerState {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .filter_map(|(module, level)| module_match_end(target, module).map(|end| (end, level)))
+            .max_by_key(|(end, _)| *end)
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, |acc, level| acc.max(level))
+    }
+}
+
+fn open_sink(tool: &str, destination: &LogDestination) -> Result<Sink, Box<dyn Error>> {
+    Ok(match destination {
+        LogDestination::Stderr => Sink::Stderr,
+        LogDestination::File(path) => Sink::File(append_with_privileges(path)?),
+        LogDestination::Syslog => Sink::Syslog(syslog::unix(Formatter3164 {
+            facility: Facility::LOG_AUTH,
+            hostname: None,
+            process: tool.to_string(),
+            pid: std::process::id(),
+        })?),
+    })
+}
+
+/// Send one message to the private authorization facility of syslog, out of the log settings.
+pub fn authpriv(tool: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    syslog::unix(Formatter3164 {
+        facility: Facility::LOG_AUTHPRIV,
+        hostname: None,
+        process: tool.to_string(),
+        pid: std::process::id(),
+    })?
+    .info(message)
+    .map_err(|e| e.to_string().into())
+}
+
+/// Redact the values of KEY=value pairs whose key looks like a secret.
+pub fn scrub(message: &str) -> Cow<'_, str> {
+    if !message.contains('=') {
+        return Cow::Borrowed(message);
+    }
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(pos) = rest.find('=') {
+        let (before, after) = rest.split_at(pos);
+        let key_start = before
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let key = before[key_start..].to_ascii_uppercase();
+        result.push_str(before);
+        result.push('=');
+        let after = &after[1..];
+        if SECRET_MARKERS.iter().any(|marker| key.contains(marker)) {
+            let value_end = after
+                .find(|c: char| c.is_whitespace() || c == ',' || c == '"')
+                .unwrap_or(after.len());
+            result.push_str("***");
+            rest = &after[value_end..];
+        } else {
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+/// Tag every following message of the process with the id of its execution, see [`tag`].
+pub fn set_session(id: &str) {
+    let _ = SESSION.set(id.to_string());
+}
+
+/// The message as logged, prefixed by the execution id when there is one.
+pub fn tag(session: Option<&str>, message: &str) -> String {
+    match session {
+        Some(id) => format!("[{}] {}", id, message),
+        None => message.to_string(),
+    }
+}
+
+impl Sink {
+    fn write(&mut self, record: &Record, message: &str) {
+        // Errors are ignored, there is no better place to report them.
+        let _ = match self {
+            Sink::Stderr => writeln!(
+                std::io::stderr(),
+                "[{} {} {}] {}",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                record.level(),
+                record.target(),
+                message
+            ),
+            Sink::File(file) => writeln!(
+                file,
+                "[{} {} {}] {}",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                record.level(),
+                record.target(),
+                message
+            ),
+            Sink::Syslog(logger) => match record.level() {
+                Level::Error => logger.err(message),
+                Level::Warn => logger.warning(message),
+                Level::Info => logger.info(message),
+                Level::Debug | Level::Trace => logger.debug(message),
+            }
+            .map_err(std::io::Error::other),
+        };
+    }
+
+    fn flush(&mut self) {
+        let _ = match self {
+            Sink::Stderr => std::io::stderr().flush(),
+            Sink::File(file) => file.flush(),
+            Sink::Syslog(logger) => logger.backend.flush(),
+        };
+    }
+}
+
+impl Log for RarLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .as_ref()
+            .is_some_and(|state| metadata.level() <= state.level_for(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(state) = state.as_mut() {
+            if record.level() > state.level_for(record.target()) {
+                return;
+            }
+            let message = tag(
+                SESSION.get().map(String::as_str),
+                &record.args().to_string(),
+            );
+            let message = scrub(&message);
+            for sink in state.sinks.iter_mut() {
+                sink.write(record, &message);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(state) = state.as_mut() {
+            state.sinks.iter_mut().for_each(Sink::flush);
+        }
+    }
+}
+
+/// Install the logger, or replace its configuration if it is already installed.
+/// Without settings, debug builds log everything to stderr and release builds log info to syslog.
+pub fn configure(tool: &str, settings: Option<&LogSettings>) -> Result<(), Box<dyn Error>> {
+    let default = default_settings();
+    let settings = settings.unwrap_or(&default);
+    let level = settings
+        .level
+        .as_deref()
+        .or(default.level.as_deref())
+        .and_then(parse_level)
+        .unwrap_or(LevelFilter::Info);
+    let filters = settings
+        .filters
+        .iter()
+        .filter_map(|(module, level)| parse_level(level).map(|level| (module.clone(), level)))
+        .collect();
+    let destinations = if settings.destinations.is_empty() {
+        &default.destinations
+    } else {
+        &settings.destinations
+    };
+    let mut sinks = Vec::new();
+    for destination in destinations {
+        match open_sink(tool, destination) {
+            Ok(sink) => sinks.push(sink),
+            Err(e) => eprintln!("Unable to open log destination {:?}: {}", destination, e),
+        }
+    }
+    if sinks.is_empty() {
+        sinks.push(Sink::Stderr);
+    }
+    let state = LoggerState {
+        level,
+        filters,
+        sinks,
+    };
+    let max_level = state.max_level();
+    STATE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(state);
+    // Already installed on reconfiguration
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_for() {
+        let state = LoggerState {
+            level: LevelFilter::Warn,
+            filters: vec![
+                ("finder".to_string(), LevelFilter::Debug),
+                ("rar_common::database".to_string(), LevelFilter::Error),
+                ("sr::pam".to_string(), LevelFilter::Off),
+            ],
+            sinks: vec![],
+        };
+        assert_eq!(
+            state.level_for("rar_common::database::finder"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            state.level_for("rar_common::database::options"),
+            LevelFilter::Error
+        );
+        assert_eq!(state.level_for("sr::pam::rpassword"), LevelFilter::Off);
+        assert_eq!(state.level_for("sr::timeout"), LevelFilter::Warn);
+        assert_eq!(state.level_for("rar_common::finderx"), LevelFilter::Warn);
+        assert_eq!(state.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_scrub() {
+        assert_eq!(scrub("Keeping env: HOME=/root"), "Keeping env: HOME=/root");
+        assert_eq!(
+            scrub("Keeping env: DB_PASSWORD=hunter2 and GH_TOKEN=abc,USER=me"),
+            "Keeping env: DB_PASSWORD=*** and GH_TOKEN=***,USER=me"
+        );
+        assert_eq!(scrub("no pairs here"), "no pairs here");
+    }
+
+    #[test]
+    fn test_tag() {
+        assert_eq!(tag(Some("7f"), "Role r_db denied"), "[7f] Role r_db denied");
+        assert_eq!(tag(None, "Role r_db denied"), "Role r_db denied");
+    }
+
+    #[test]
+    fn test_deserialize_log_settings() {
+        let settings: LogSettings = serde_json::from_str(
+            r#"{
+                "level": "info",
+                "filters": { "finder": "debug", "pam": "warn" },
+                "destinations": ["stderr", "journald", { "file": "/var/log/rar.log" }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.level.as_deref(), Some("info"));
+        assert_eq!(settings.filters.get("pam").unwrap(), "warn");
+        assert_eq!(
+            settings.destinations,
+            vec![
+                LogDestination::Stderr,
+                LogDestination::Syslog,
+                LogDestination::File("/var/log/rar.log".into())
+            ]
+        );
+    }
+}
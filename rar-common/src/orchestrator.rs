@@ -0,0 +1,299 @@
+//! Tokens issued by a trusted orchestrator.
+//!
+//! Tasks with the `orchestrated` option only match when sr is given a token in the environment,
+//! signed with HMAC-SHA256 and a key shared with the orchestrator. The token is bound to the
+//! caller and to the command, expires after `max-age` seconds and is accepted once, so these
+//! tasks can be run by automation, never manually. Its nonce is only recorded when the command
+//! is about to be executed, so that sr executing itself again after an upgrade accepts it.
+
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::ErrorKind,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bon::Builder;
+use capctl::Cap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    activation::{hmac_sha256, read_key},
+    runtime::RuntimeDirs,
+    util::{cap_effective, dac_override_effective},
+};
+
+const KEY: &str = "/etc/security/rootasrole.orchestrator.key";
+const ENV: &str = "RAR_ORCHESTRATOR_TOKEN";
+const DEFAULT_MAX_AGE: u64 = 60;
+// seconds a token may be issued in the future, for clocks slightly ahead of this host
+const MAX_SKEW: u64 = 5;
+
+fn default_key() -> PathBuf {
+    KEY.into()
+}
+
+fn default_env() -> String {
+    ENV.to_string()
+}
+
+fn default_max_age() -> u64 {
+    DEFAULT_MAX_AGE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct OrchestratorSettings {
+    #[serde(default = "default_key")]
+    #[builder(default = default_key(), into)]
+    pub key: PathBuf,
+    /// Environment variable holding the token
+    #[serde(default = "default_env")]
+    #[builder(default = default_env(), into)]
+    pub env: String,
+    /// Seconds a token is valid after its issuance
+    #[serde(default = "default_max_age")]
+    #[builder(default = DEFAULT_MAX_AGE)]
+    pub max_age: u64,
+}
+
+impl Default for OrchestratorSettings {
+    fn default() -> Self {
+        OrchestratorSettings::builder().build()
+    }
+}
+
+fn signature(key: &[u8], timestamp: u64, nonce: &str, user: &str, command: &[String]) -> String {
+    let message = format!(
+        "{}\n{}\n{}\n{}",
+        timestamp,
+        nonce,
+        user,
+        shell_words::join(command)
+    );
+    hex::encode(hmac_sha256(key, message.as_bytes()))
+}
+
+/// The token the orchestrator gives to sr: `<timestamp>:<nonce>:<signature>`.
+pub fn token(key: &[u8], timestamp: u64, nonce: &str, user: &str, command: &[String]) -> String {
+    format!(
+        "{}:{}:{}",
+        timestamp,
+        nonce,
+        signature(key, timestamp, nonce, user, command)
+    )
+}
+
+/// Checks that the token was issued for this user and command less than `max_age` seconds ago,
+/// and gives its nonce and issuance time.
+pub fn verify<'a>(
+    key: &[u8],
+    token: &'a str,
+    user: &str,
+    command: &[String],
+    now: SystemTime,
+    max_age: u64,
+) -> Result<(&'a str, SystemTime), Box<dyn Error>> {
+    let mut parts = token.splitn(3, ':');
+    let (Some(timestamp), Some(nonce), Some(given)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err("The orchestrator token is malformed".into());
+    };
+    let timestamp: u64 = timestamp
+        .parse()
+        .map_err(|_| "The orchestrator token is malformed")?;
+    let issued = SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp);
+    match now.duration_since(issued) {
+        Ok(age) if age > Duration::from_secs(max_age) => {
+            return Err("The orchestrator token has expired".into())
+        }
+        // its nonce would be forgotten before the token expires
+        Err(e) if e.duration() > Duration::from_secs(MAX_SKEW) => {
+            return Err("The orchestrator token is issued in the future".into())
+        }
+        _ => {}
+    }
+    // constant time comparison, the signature must not be guessed byte per byte
+    let expected = signature(key, timestamp, nonce, user, command);
+    if expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    {
+        Ok((nonce, issued))
+    } else {
+        Err("The orchestrator token signature is invalid".into())
+    }
+}
+
+// a nonce is kept while a token carrying it could still be accepted, the modification time of
+// its file is the issuance time of the token
+fn prune_nonces(dir: &Path, now: SystemTime, max_age: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|issued| {
+                now.duration_since(issued)
+                    .is_ok_and(|age| age > Duration::from_secs(max_age))
+            });
+        if expired {
+            if let Err(e) = fs::remove_file(entry.path()) {
+                debug!("Unable to remove {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+}
+
+fn record_nonce_in(dir: &Path, nonce: &str, issued: SystemTime) -> Result<(), Box<dyn Error>> {
+    // the file is created exclusively, two executions cannot both accept the same nonce
+    let path = dir.join(hex::encode(Sha256::digest(nonce.as_bytes())));
+    let file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err("The orchestrator token was already used".into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    file.set_modified(issued)?;
+    // the caller must not change the time it was issued at
+    cap_effective(Cap::CHOWN, true)?;
+    let chown = std::os::unix::fs::fchown(&file, Some(0), Some(0));
+    cap_effective(Cap::CHOWN, false)?;
+    Ok(chown?)
+}
+
+/// Records the nonce of an accepted token in `dir`, refusing a nonce already used.
+pub fn record_nonce(
+    dir: &Path,
+    nonce: &str,
+    issued: SystemTime,
+    now: SystemTime,
+    max_age: u64,
+) -> Result<(), Box<dyn Error>> {
+    dac_override_effective(true)?;
+    prune_nonces(dir, now, max_age);
+    let recorded = record_nonce_in(dir, nonce, issued);
+    dac_override_effective(false)?;
+    recorded
+}
+
+/// A valid token, which is only accepted once its nonce is recorded.
+#[derive(Debug)]
+pub struct Accepted {
+    nonce: String,
+    issued: SystemTime,
+    max_age: u64,
+}
+
+impl Accepted {
+    /// Records the nonce, the token cannot be used again afterwards.
+    pub fn consume(&self) -> Result<(), Box<dyn Error>> {
+        let dirs = RuntimeDirs::default();
+        dirs.init()?;
+        record_nonce(
+            &dirs.nonces(),
+            &self.nonce,
+            self.issued,
+            SystemTime::now(),
+            self.max_age,
+        )
+    }
+}
+
+/// The token of the environment when it is valid for this user and command.
+pub fn check_token(
+    settings: &OrchestratorSettings,
+    token: Option<&str>,
+    user: &str,
+    command: &[String],
+) -> Result<Option<Accepted>, Box<dyn Error>> {
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    let (nonce, issued) = verify(
+        &read_key(&settings.key)?,
+        token,
+        user,
+        command,
+        SystemTime::now(),
+        settings.max_age,
+    )?;
+    Ok(Some(Accepted {
+        nonce: nonce.to_string(),
+        issued,
+        max_age: settings.max_age,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let key = [3u8; 32];
+        let command = vec!["/usr/bin/rotate".to_string(), "--all".to_string()];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let token = token(&key, 999_990, "f00d", "deploy", &command);
+        assert!(verify(&key, &token, "deploy", &command, now, 60).is_ok());
+        assert!(verify(&[4u8; 32], &token, "deploy", &command, now, 60).is_err());
+        assert!(verify(&key, &token, "alice", &command, now, 60).is_err());
+        assert!(verify(&key, &token, "deploy", &command[..1], now, 60).is_err());
+        assert!(verify(&key, &token, "deploy", &command, now, 5).is_err());
+        assert!(verify(
+            &key,
+            &token.replace("f00d", "beef"),
+            "deploy",
+            &command,
+            now,
+            60
+        )
+        .is_err());
+        assert!(verify(&key, "999990:f00d", "deploy", &command, now, 60).is_err());
+        // issued too far in the future
+        let early = now - Duration::from_secs(3600);
+        assert!(verify(&key, &token, "deploy", &command, early, 60).is_err());
+        let early = now - Duration::from_secs(16);
+        assert!(verify(&key, &token, "deploy", &command, early, 60).is_err());
+        let early = now - Duration::from_secs(14);
+        assert!(verify(&key, &token, "deploy", &command, early, 60).is_ok());
+    }
+
+    #[test]
+    fn test_record_nonce() {
+        let dir = std::env::temp_dir().join(format!("rar_nonces_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        assert!(record_nonce(&dir, "f00d", now, now, 60).is_ok());
+        // a replayed token is refused while it has not expired
+        assert!(record_nonce(&dir, "f00d", now, now, 60).is_err());
+        assert!(record_nonce(&dir, "beef", now, now, 60).is_ok());
+        // a token used long after its issuance is forgotten when it expires, not later
+        let issued = now - Duration::from_secs(20);
+        let later = now + Duration::from_secs(30);
+        assert!(record_nonce(&dir, "d00d", issued, later, 60).is_ok());
+        assert!(record_nonce(&dir, "d00d", issued, later, 60).is_err());
+        let later = now + Duration::from_secs(41);
+        assert!(record_nonce(&dir, "cafe", later, later, 60).is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        let later = now + Duration::from_secs(120);
+        assert!(record_nonce(&dir, "face", later, later, 60).is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,22 @@
-use std::{fs::File, io::Read, os::fd::AsRawFd};
+use std::{
+    error::Error,
+    fs::File,
+    io::Read,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     api::PluginManager,
-    database::structs::SCommand,
+    database::structs::{SCommand, STask},
     open_with_privileges,
     util::{final_path, parse_conf_command},
 };
 use log::{debug, warn};
-use nix::unistd::{access, AccessFlags};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    unistd::{access, AccessFlags},
+};
 use serde::{Deserialize, Serialize};
 
 use libc::FS_IOC_GETFLAGS;
@@ -113,6 +122,95 @@ fn complex_command_parse(
     }
 }
 
+fn file_digest(hash_type: &HashType, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut buf = Vec::new();
+    open_with_privileges(path)?.read_to_end(&mut buf)?;
+    Ok(compute(hash_type, &buf))
+}
+
+/// The entries of the task pinning the digest of an executable, with their executable.
+fn pinned_entries(task: &STask) -> impl Iterator<Item = (HashChecker, PathBuf)> + '_ {
+    task.commands
+        .add
+        .iter()
+        .filter_map(|command| match command {
+            SCommand::Complex(value) => {
+                let checker = serde_json::from_value::<HashChecker>(value.clone()).ok()?;
+                let path = final_path(parse_conf_command(&checker.command).ok()?.first()?);
+                Some((checker, path))
+            }
+            SCommand::Simple(_) => None,
+        })
+}
+
+/// A command entry pinning the SHA256 digest of its executable.
+pub fn pin_command(command: &[String]) -> Result<SCommand, Box<dyn Error>> {
+    let program = command.first().ok_or("Empty command")?;
+    let digest = file_digest(&HashType::SHA256, &final_path(program))?;
+    Ok(SCommand::Complex(serde_json::json!({
+        "hash_type": HashType::SHA256,
+        "hash": hex::encode(digest),
+        "command": shell_words::join(command),
+    })))
+}
+
+/// Compute again the digests pinned by the task, after an upgrade of its executables.
+pub fn update_digests(task: &mut STask) -> Result<usize, Box<dyn Error>> {
+    let mut updated = 0;
+    for command in task.commands.add.iter_mut() {
+        let SCommand::Complex(value) = command else {
+            continue;
+        };
+        let Ok(checker) = serde_json::from_value::<HashChecker>(value.clone()) else {
+            continue;
+        };
+        let path = final_path(
+            parse_conf_command(&checker.command)?
+                .first()
+                .ok_or("Empty command")?,
+        );
+        let digest = hex::encode(file_digest(&checker.hash_type, &path)?);
+        if digest != checker.hash.to_lowercase() {
+            value["hash"] = digest.into();
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+/// Whether `content` has one of the digests the task pins for `path`, None when it pins none.
+pub fn digest_matches(task: &STask, path: &Path, content: &[u8]) -> Option<bool> {
+    let mut checkers = pinned_entries(task)
+        .filter(|(_, pinned)| pinned == path)
+        .peekable();
+    checkers.peek()?;
+    Some(checkers.any(|(checker, _)| {
+        hex::decode(checker.hash.as_bytes())
+            .is_ok_and(|hash| hash == compute(&checker.hash_type, content))
+    }))
+}
+
+/// Before the execution, the executable must still have one of the digests pinned by the task.
+/// The file is opened once: the command must be executed from the returned descriptor, which
+/// the digest was computed on, so that the file cannot be swapped in between.
+pub fn verify_digest(task: &STask, path: &Path) -> Result<Option<File>, Box<dyn Error>> {
+    let path = final_path(&path.to_string_lossy());
+    if !pinned_entries(task).any(|(_, pinned)| pinned == path) {
+        return Ok(None);
+    }
+    let mut file = open_with_privileges(&path)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    if digest_matches(task, &path, &buf) == Some(false) {
+        return Err(format!("The digest of {} does not match the policy", path.display()).into());
+    }
+    // the interpreter of a script reads it again from /dev/fd
+    if buf.starts_with(b"#!") {
+        fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+    }
+    Ok(Some(file))
+}
+
 pub fn register() {
     PluginManager::subscribe_complex_command_parser(complex_command_parse)
 }
@@ -121,7 +219,10 @@ pub fn register() {
 #[cfg(test)]
 mod tests {
 
-    use std::{io::Write, rc::Rc};
+    use std::{
+        io::{Seek, Write},
+        rc::Rc,
+    };
 
     use nix::unistd::{Pid, User};
 
@@ -139,7 +240,7 @@ mod tests {
         register();
         // create a file in /tmp
         let mut file = std::fs::File::create("/tmp/hashchecker").unwrap();
-        file.write("test".as_bytes()).unwrap();
+        file.write_all("test".as_bytes()).unwrap();
         file.sync_all().unwrap();
 
         let config = rc_refcell!(SConfig::default());
@@ -169,13 +270,47 @@ mod tests {
             user: User::from_uid(0.into()).unwrap().unwrap(),
             groups: vec![],
             ppid: Pid::parent(),
+            orchestrated: false,
             tty: None,
         };
 
         let matching = config
-            .matches(&cred, &None, &vec!["/tmp/hashchecker".to_string()])
+            .matches(&cred, &None, &["/tmp/hashchecker".to_string()])
             .unwrap();
         assert!(matching.fully_matching());
         std::fs::remove_file("/tmp/hashchecker").unwrap();
     }
+
+    #[test]
+    fn test_digests() {
+        let path = std::env::temp_dir().join(format!("rar_digest_{}", std::process::id()));
+        std::fs::write(&path, "test").unwrap();
+        let command = vec![path.to_string_lossy().to_string(), "-l".to_string()];
+        let mut task = STask::default();
+        // without a pinned digest, any executable runs
+        assert!(verify_digest(&task, &path).unwrap().is_none());
+        task.commands.add.push(pin_command(&command).unwrap());
+        let SCommand::Complex(entry) = &task.commands.add[0] else {
+            panic!("Expected a complex command");
+        };
+        assert_eq!(
+            entry["hash"],
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+        let mut verified = verify_digest(&task, &path).unwrap().unwrap();
+        // the verified descriptor still reads the verified file once the path is replaced
+        let swap = path.with_extension("swap");
+        std::fs::write(&swap, "swapped").unwrap();
+        std::fs::rename(&swap, &path).unwrap();
+        let mut content = String::new();
+        verified.rewind().unwrap();
+        verified.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "test");
+        // a swapped binary is refused, until the digests are updated
+        assert!(verify_digest(&task, &path).is_err());
+        assert_eq!(update_digests(&mut task).unwrap(), 1);
+        assert!(verify_digest(&task, &path).is_ok());
+        assert_eq!(update_digests(&mut task).unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,10 +1,10 @@
-use std::cmp::Ordering;
+use std::{cell::RefCell, cmp::Ordering, error::Error, rc::Rc};
 
 use crate::{
     api::{PluginManager, PluginResultAction},
     database::{
         finder::{Cred, TaskMatch, TaskMatcher},
-        structs::{RoleGetter, SRole},
+        structs::{RoleGetter, SConfig, SRole},
         FilterMatcher,
     },
 };
@@ -21,6 +21,61 @@ fn get_parents(role: &SRole) -> Option<Result<Parents, serde_json::Error>> {
         .map(|parents| serde_json::from_value::<Parents>(parents.clone()))
 }
 
+fn visit(
+    config: &Rc<RefCell<SConfig>>,
+    role: &SRole,
+    path: &mut Vec<String>,
+    found: &mut Vec<Rc<RefCell<SRole>>>,
+) -> Result<(), String> {
+    let parents = match get_parents(role) {
+        Some(Ok(parents)) => parents.0,
+        Some(Err(e)) => return Err(format!("Invalid parents of role {}: {}", role.name, e)),
+        None => return Ok(()),
+    };
+    path.push(role.name.clone());
+    for parent in parents {
+        if path.contains(&parent) {
+            return Err(format!(
+                "Cycle in the role hierarchy: {} -> {}",
+                path.join(" -> "),
+                parent
+            ));
+        }
+        let Some(parent_role) = config.role(&parent) else {
+            return Err(format!(
+                "Parent role {} of role {} not found",
+                parent, role.name
+            ));
+        };
+        // a role reached twice through different parents is only matched once
+        if found.iter().any(|r| Rc::ptr_eq(r, &parent_role)) {
+            continue;
+        }
+        found.push(parent_role.clone());
+        visit(config, &parent_role.as_ref().borrow(), path, found)?;
+    }
+    path.pop();
+    Ok(())
+}
+
+/// The roles inherited by the role, directly or through its parents.
+pub fn ancestors(
+    config: &Rc<RefCell<SConfig>>,
+    role: &SRole,
+) -> Result<Vec<Rc<RefCell<SRole>>>, String> {
+    let mut found = Vec::new();
+    visit(config, role, &mut Vec::new(), &mut found)?;
+    Ok(found)
+}
+
+/// Refuse cycles and unknown parents, so that a policy is not saved with a broken hierarchy.
+pub fn check(config: &Rc<RefCell<SConfig>>) -> Result<(), Box<dyn Error>> {
+    for role in config.as_ref().borrow().roles.iter() {
+        ancestors(config, &role.as_ref().borrow())?;
+    }
+    Ok(())
+}
+
 fn find_in_parents(
     role: &SRole,
     user: &Cred,
@@ -34,39 +89,38 @@ fn find_in_parents(
     }
     let mut result = PluginResultAction::Ignore;
     let config = role._config.as_ref().unwrap().upgrade().unwrap();
-    match get_parents(role) {
-        Some(Ok(parents)) => {
-            debug!("Found parents {:?}", parents.0);
-            for parent in parents.0.iter() {
-                if let Some(role) = config.role(parent) {
-                    debug!("Checking parent role {}", parent);
-                    match role.as_ref().borrow().tasks.matches(user, filter, command) {
-                        Ok(matches) => {
-                            debug!("Parent role {} matched", parent);
-                            if !matcher.command_matching()
-                                || (matches.command_matching()
-                                    && matches.score.cmd_cmp(&matcher.score) == Ordering::Less)
-                            {
-                                debug!("Parent role {} is better", parent);
-                                matcher.score.cmd_min = matches.score.cmd_min;
-                                matcher.settings = matches.settings;
-                                result = PluginResultAction::Edit;
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to match parent role {}", e);
-                        }
-                    }
-                } else {
-                    warn!("Parent role {} not found", parent);
-                }
-            }
-        }
-        _ => {
-            warn!("No parents found for role {}", role.name);
+    if get_parents(role).is_none() {
+        return PluginResultAction::Ignore;
+    }
+    let parents = match ancestors(&config, role) {
+        Ok(parents) => parents,
+        Err(e) => {
+            // nothing is inherited from a broken hierarchy
+            warn!("{}", e);
             return PluginResultAction::Ignore;
         }
     };
+    for parent in parents {
+        let parent = parent.as_ref().borrow();
+        debug!("Checking parent role {}", parent.name);
+        match parent.tasks.matches(user, filter, command) {
+            Ok(matches) => {
+                debug!("Parent role {} matched", parent.name);
+                if !matcher.command_matching()
+                    || (matches.command_matching()
+                        && matches.score.cmd_cmp(&matcher.score) == Ordering::Less)
+                {
+                    debug!("Parent role {} is better", parent.name);
+                    matcher.score.cmd_min = matches.score.cmd_min;
+                    matcher.settings = matches.settings;
+                    result = PluginResultAction::Edit;
+                }
+            }
+            Err(e) => {
+                debug!("Parent role {} does not match: {}", parent.name, e);
+            }
+        }
+    }
     result
 }
 
@@ -127,6 +181,7 @@ mod tests {
             user: User::from_uid(0.into()).unwrap().unwrap(),
             groups: vec![],
             ppid: Pid::parent(),
+            orchestrated: false,
             tty: None,
         };
         let mut matcher = TaskMatch::default();
@@ -141,6 +196,57 @@ mod tests {
         assert_eq!(res, PluginResultAction::Edit);
     }
 
+    fn role(name: &str, parents: &[&str]) -> Rc<RefCell<SRole>> {
+        let mut extra = serde_json::Map::new();
+        extra.insert("parents".to_string(), serde_json::json!(parents));
+        SRole::builder(name).extra_fields(extra).build()
+    }
+
+    fn config(roles: Vec<Rc<RefCell<SRole>>>) -> Rc<RefCell<SConfig>> {
+        roles
+            .into_iter()
+            .fold(SConfig::builder(), |builder, role| builder.role(role))
+            .build()
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let config = config(vec![
+            role("admin", &["operator", "auditor"]),
+            role("operator", &["user"]),
+            role("auditor", &["user"]),
+            role("user", &[]),
+        ]);
+        let admin = config.role("admin").unwrap();
+        let names: Vec<String> = ancestors(&config, &admin.as_ref().borrow())
+            .unwrap()
+            .iter()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .collect();
+        assert_eq!(names, ["operator", "user", "auditor"]);
+        assert!(check(&config).is_ok());
+    }
+
+    #[test]
+    fn test_hierarchy_errors() {
+        let config = config(vec![
+            role("admin", &["operator"]),
+            role("operator", &["user"]),
+            role("user", &["admin"]),
+        ]);
+        let admin = config.role("admin").unwrap();
+        assert_eq!(
+            ancestors(&config, &admin.as_ref().borrow()).unwrap_err(),
+            "Cycle in the role hierarchy: admin -> operator -> user -> admin"
+        );
+        assert!(check(&config).is_err());
+        let config = self::config(vec![role("admin", &["nobody"])]);
+        assert_eq!(
+            check(&config).unwrap_err().to_string(),
+            "Parent role nobody of role admin not found"
+        );
+    }
+
     #[test]
     fn test_plugin_implemented() {
         register();
@@ -179,6 +285,7 @@ mod tests {
             user: User::from_uid(0.into()).unwrap().unwrap(),
             groups: vec![],
             ppid: Pid::parent(),
+            orchestrated: false,
             tty: None,
         };
         let mut matcher = TaskMatch::default();
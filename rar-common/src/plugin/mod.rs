@@ -3,8 +3,23 @@ mod hashchecker;
 #[cfg(feature = "finder")]
 mod hierarchy;
 #[cfg(feature = "finder")]
+mod predicate;
+#[cfg(feature = "finder")]
+mod role_activation;
+#[cfg(feature = "finder")]
+mod session;
+#[cfg(feature = "finder")]
+mod shells;
+#[cfg(feature = "finder")]
 mod ssd;
 
+#[cfg(feature = "finder")]
+pub use hashchecker::{digest_matches, pin_command, update_digests, verify_digest};
+#[cfg(feature = "finder")]
+pub use hierarchy::check as check_hierarchy;
+#[cfg(feature = "finder")]
+pub use role_activation::{activate_role, requires_activation};
+
 pub fn register_plugins() {
     #[cfg(feature = "finder")]
     hashchecker::register();
@@ -12,4 +27,12 @@ pub fn register_plugins() {
     ssd::register();
     #[cfg(feature = "finder")]
     hierarchy::register();
+    #[cfg(feature = "finder")]
+    shells::register();
+    #[cfg(feature = "finder")]
+    session::register();
+    #[cfg(feature = "finder")]
+    role_activation::register();
+    #[cfg(feature = "finder")]
+    predicate::register();
 }
@@ -0,0 +1,352 @@
+//! Site-specific predicates on tasks.
+//!
+//! The `predicate` entry of a task names a program, owned by root, that receives the caller,
+//! the command and the environment as JSON on its standard input, and answers `allow`, `deny`
+//! or `abstain` on its first output line. It runs as a dedicated unprivileged user, so the caller
+//! cannot trace it or forge its answer, without capabilities, with no_new_privs and resource
+//! limits. A predicate that fails, times out or gives another answer denies the task.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{Read, Write},
+    os::unix::{fs::MetadataExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use capctl::CapState;
+use log::{debug, warn};
+use nix::unistd::{setgroups, setresgid, setresuid, Gid, Uid, User};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use strum::EnumIs;
+
+use crate::{
+    api::{PluginManager, PluginResult},
+    database::{finder::Cred, structs::STask},
+};
+
+const DEFAULT_TIMEOUT: u64 = 2;
+const DEFAULT_MEMORY: u64 = 64 * 1024 * 1024;
+const DEFAULT_USER: &str = "nobody";
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT
+}
+
+fn default_memory() -> u64 {
+    DEFAULT_MEMORY
+}
+
+fn default_user() -> String {
+    DEFAULT_USER.to_string()
+}
+
+#[derive(Deserialize, Debug)]
+struct Predicate {
+    path: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    /// Seconds before the predicate is killed
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+    /// Bytes of address space
+    #[serde(default = "default_memory")]
+    memory: u64,
+    /// Unprivileged user running the predicate
+    #[serde(default = "default_user")]
+    user: String,
+}
+
+#[derive(Debug, PartialEq, Eq, EnumIs)]
+enum Verdict {
+    Allow,
+    Deny,
+    Abstain,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    user: &'a str,
+    uid: u32,
+    groups: Vec<&'a str>,
+    role: String,
+    task: String,
+    command: &'a [String],
+    env: HashMap<String, String>,
+}
+
+fn get_predicate_entry(task: &STask) -> Option<Result<Predicate, JsonError>> {
+    task._extra_fields
+        .get("predicate")
+        .map(|predicate| serde_json::from_value::<Predicate>(predicate.clone()))
+}
+
+fn parse_verdict(output: &str) -> Result<Verdict, Box<dyn Error>> {
+    match output.lines().next().map(str::trim) {
+        Some("allow") => Ok(Verdict::Allow),
+        Some("deny") => Ok(Verdict::Deny),
+        Some("abstain") => Ok(Verdict::Abstain),
+        answer => Err(format!("unexpected answer {:?}", answer.unwrap_or_default()).into()),
+    }
+}
+
+// anyone able to edit the program could grant or deny any task
+fn check_owner(path: &Path) -> Result<(), Box<dyn Error>> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "{} must be owned by root and only writable by root",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn set_limit(resource: libc::__rlimit_resource_t, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// a predicate running as the caller could be traced by the caller and its answer forged
+fn runas(name: &str, caller: Uid) -> Result<(Uid, Gid), Box<dyn Error>> {
+    let user = User::from_name(name)?.ok_or_else(|| format!("unknown user {}", name))?;
+    if user.uid.is_root() || user.uid == caller {
+        return Err(format!("{} cannot run a predicate of this caller", name).into());
+    }
+    Ok((user.uid, user.gid))
+}
+
+fn sandbox(uid: Uid, gid: Gid, timeout: u64, memory: u64) -> std::io::Result<()> {
+    let mut state = CapState::get_current()?;
+    state.effective = state.permitted;
+    state.set_current()?;
+    setgroups(&[gid])?;
+    setresgid(gid, gid, gid)?;
+    setresuid(uid, uid, uid)?;
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    capctl::ambient::clear()?;
+    CapState::empty().set_current()?;
+    set_limit(libc::RLIMIT_CPU, timeout)?;
+    set_limit(libc::RLIMIT_AS, memory)?;
+    set_limit(libc::RLIMIT_FSIZE, 0)?;
+    set_limit(libc::RLIMIT_CORE, 0)
+}
+
+fn run(predicate: &Predicate, caller: Uid, input: Vec<u8>) -> Result<Verdict, Box<dyn Error>> {
+    check_owner(&predicate.path)?;
+    let (uid, gid) = runas(&predicate.user, caller)?;
+    let (timeout, memory) = (predicate.timeout, predicate.memory);
+    let mut command = Command::new(&predicate.path);
+    command
+        .args(&predicate.args)
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // SAFETY: the closure only performs system calls between fork and exec
+    unsafe {
+        command.pre_exec(move || sandbox(uid, gid, timeout, memory));
+    }
+    let mut child = command.spawn()?;
+    // a predicate that does not read its input must not hold sr past the deadline
+    let writer = child
+        .stdin
+        .take()
+        .map(|mut stdin| thread::spawn(move || stdin.write_all(&input)));
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(format!("timed out after {} seconds", timeout).into());
+        }
+        sleep(Duration::from_millis(10));
+    };
+    if let Some(Ok(Err(e))) = writer.map(|writer| writer.join()) {
+        return Err(format!("unable to give the input: {}", e).into());
+    }
+    if !status.success() {
+        return Err(format!("exited with {}", status).into());
+    }
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut output)?;
+    }
+    parse_verdict(&output)
+}
+
+fn input(task: &STask, actor: &Cred, command: &[String]) -> Result<Vec<u8>, JsonError> {
+    serde_json::to_vec(&Input {
+        user: &actor.user.name,
+        uid: actor.user.uid.as_raw(),
+        groups: actor
+            .groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect(),
+        role: task
+            .role()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .unwrap_or_default(),
+        task: task.name.to_string(),
+        command,
+        env: std::env::vars().collect(),
+    })
+}
+
+fn check_predicate(task: &STask, actor: &Cred, command: &[String]) -> PluginResult {
+    let predicate = match get_predicate_entry(task) {
+        Some(Ok(predicate)) => predicate,
+        Some(Err(e)) => {
+            // a broken restriction must not grant access
+            warn!("Invalid predicate entry for task {} : {}", task.name, e);
+            return PluginResult::Deny;
+        }
+        None => return PluginResult::Neutral,
+    };
+    let verdict = input(task, actor, command)
+        .map_err(|e| e.into())
+        .and_then(|input| run(&predicate, actor.user.uid, input));
+    match verdict {
+        Ok(verdict) => {
+            debug!(
+                "Predicate {} answered {:?} for task {}",
+                predicate.path.display(),
+                verdict,
+                task.name
+            );
+            if verdict.is_deny() {
+                PluginResult::Deny
+            } else {
+                PluginResult::Neutral
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Predicate {} of task {} failed, the task is denied: {}",
+                predicate.path.display(),
+                task.name,
+                e
+            );
+            PluginResult::Deny
+        }
+    }
+}
+
+pub fn register() {
+    PluginManager::subscribe_task_predicate(check_predicate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str, timeout: u64) -> Predicate {
+        Predicate {
+            path: "/bin/sh".into(),
+            args: vec!["-c".to_string(), script.to_string()],
+            timeout,
+            memory: DEFAULT_MEMORY,
+            user: DEFAULT_USER.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        assert_eq!(parse_verdict("allow\n").unwrap(), Verdict::Allow);
+        assert_eq!(parse_verdict(" deny \nallow").unwrap(), Verdict::Deny);
+        assert_eq!(parse_verdict("abstain").unwrap(), Verdict::Abstain);
+        assert!(parse_verdict("").is_err());
+        assert!(parse_verdict("yes").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        if check_owner(Path::new("/bin/sh")).is_err() {
+            return;
+        }
+        if User::from_name(DEFAULT_USER).ok().flatten().is_none() {
+            return;
+        }
+        let input = br#"{"user":"root"}"#;
+        assert_eq!(
+            run(
+                &shell("grep -q root && echo allow", 1),
+                Uid::current(),
+                input.to_vec()
+            )
+            .unwrap(),
+            Verdict::Allow
+        );
+        assert_eq!(
+            run(
+                &shell("cat >/dev/null; echo deny", 1),
+                Uid::current(),
+                input.to_vec()
+            )
+            .unwrap(),
+            Verdict::Deny
+        );
+        assert!(run(
+            &shell("echo allow; exit 1", 1),
+            Uid::current(),
+            input.to_vec()
+        )
+        .is_err());
+        assert!(run(
+            &shell("sleep 5; echo allow", 1),
+            Uid::current(),
+            input.to_vec()
+        )
+        .is_err());
+        // no file can be written
+        let path = std::env::temp_dir().join("rar_predicate_test");
+        let script = format!("echo x > {} && echo allow", path.display());
+        assert!(run(&shell(&script, 1), Uid::current(), input.to_vec()).is_err());
+        let _ = std::fs::remove_file(&path);
+        // the predicate does not run as the caller
+        let script = format!("[ $(id -u) != {} ] && echo allow", Uid::current());
+        assert_eq!(
+            run(&shell(&script, 1), Uid::current(), input.to_vec()).unwrap(),
+            Verdict::Allow
+        );
+        let nobody = User::from_name(DEFAULT_USER).unwrap().unwrap();
+        assert!(run(&shell("echo allow", 1), nobody.uid, input.to_vec()).is_err());
+        // an unread input does not hold sr past the timeout
+        let start = Instant::now();
+        assert!(run(&shell("sleep 5", 1), Uid::current(), vec![b' '; 1 << 20]).is_err());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_check_predicate() {
+        let mut task = STask::default();
+        let cred = Cred::builder().build();
+        assert_eq!(check_predicate(&task, &cred, &[]), PluginResult::Neutral);
+        task._extra_fields
+            .insert("predicate".to_string(), serde_json::json!({ "args": [] }));
+        assert_eq!(check_predicate(&task, &cred, &[]), PluginResult::Deny);
+        task._extra_fields.insert(
+            "predicate".to_string(),
+            serde_json::json!({ "path": "/nonexistent/predicate" }),
+        );
+        assert_eq!(check_predicate(&task, &cred, &[]), PluginResult::Deny);
+    }
+}
@@ -0,0 +1,274 @@
+//! Roles used only once activated by their actors.
+//!
+//! A role with an `activation` entry is not matched until the user runs `sr --activate <role>`,
+//! which authenticates the user and records a token for their terminal, or for their shell
+//! without a terminal, for at most `max-duration`. The tokens are kept in the runtime directory
+//! and are owned by root, so the user cannot forge them and they do not survive a reboot.
+
+use std::{
+    error::Error,
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use capctl::Cap;
+use chrono::Duration;
+use log::{debug, warn};
+use nix::unistd::{chown, Gid, Uid};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+
+use crate::{
+    api::{PluginManager, PluginResult},
+    database::{deserialize_duration, finder::Cred, structs::SRole},
+    runtime::RuntimeDirs,
+    util::{cap_effective, create_with_privileges, open_with_privileges, remove_with_privileges},
+};
+
+fn default_max_duration() -> Option<Duration> {
+    Some(Duration::hours(1))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ActivationConstraint {
+    #[serde(
+        default = "default_max_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    max_duration: Option<Duration>,
+}
+
+/// Where the role is active: the terminal of the user, or their shell without a terminal.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Scope {
+    Tty(u64),
+    Ppid(i32),
+}
+
+impl Scope {
+    fn of(user: &Cred) -> Self {
+        match user.tty {
+            Some(tty) => Scope::Tty(tty),
+            None => Scope::Ppid(user.ppid.as_raw()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Token {
+    role: String,
+    scope: Scope,
+    until: u64,
+}
+
+fn get_activation_entry(role: &SRole) -> Option<Result<ActivationConstraint, JsonError>> {
+    role._extra_fields
+        .get("activation")
+        .map(|activation| serde_json::from_value::<ActivationConstraint>(activation.clone()))
+}
+
+fn tokens_path(dir: &Path, uid: Uid) -> PathBuf {
+    dir.join(uid.to_string())
+}
+
+// only a file written by sr is trusted, the user must not activate roles by themselves
+fn read_tokens(path: &Path) -> Vec<Token> {
+    let Ok(file) = open_with_privileges(path) else {
+        return Vec::new();
+    };
+    match file.metadata() {
+        Ok(metadata) if metadata.uid() == 0 && metadata.mode() & 0o022 == 0 => {
+            serde_json::from_reader(file).unwrap_or_default()
+        }
+        _ => {
+            warn!("Ignoring {}, not owned by root", path.display());
+            Vec::new()
+        }
+    }
+}
+
+fn write_tokens(path: &Path, tokens: &[Token]) -> Result<(), Box<dyn Error>> {
+    // a new file, still owned by the user until it is given to root
+    let _ = remove_with_privileges(path);
+    serde_json::to_writer(create_with_privileges(path)?, tokens)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    cap_effective(Cap::CHOWN, true)?;
+    let res = chown(path, Some(Uid::from_raw(0)), Some(Gid::from_raw(0)));
+    cap_effective(Cap::CHOWN, false)?;
+    Ok(res?)
+}
+
+fn unix_time(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+fn activate_with(
+    role: &SRole,
+    user: &Cred,
+    duration: Option<Duration>,
+    dir: &Path,
+    now: u64,
+) -> Result<Duration, Box<dyn Error>> {
+    let max = get_activation_entry(role)
+        .ok_or_else(|| format!("the role {} does not need to be activated", role.name))??
+        .max_duration
+        .unwrap_or_default();
+    let duration = duration.map_or(max, |duration| duration.min(max));
+    if duration <= Duration::zero() {
+        return Err("the activation duration must be positive".into());
+    }
+    let path = tokens_path(dir, user.user.uid);
+    let scope = Scope::of(user);
+    let mut tokens = read_tokens(&path);
+    tokens.retain(|token| now < token.until && !(token.role == role.name && token.scope == scope));
+    tokens.push(Token {
+        role: role.name.clone(),
+        scope,
+        until: now + duration.num_seconds() as u64,
+    });
+    write_tokens(&path, &tokens)?;
+    Ok(duration)
+}
+
+/// Whether `role` must be activated before being used.
+pub fn requires_activation(role: &SRole) -> bool {
+    role._extra_fields.contains_key("activation")
+}
+
+/// Activate `role` for `user` during `duration`, at most the `max-duration` of the role, which
+/// is also the default. The duration of the activation is returned.
+pub fn activate_role(
+    role: &SRole,
+    user: &Cred,
+    duration: Option<Duration>,
+) -> Result<Duration, Box<dyn Error>> {
+    let runtime = RuntimeDirs::default();
+    runtime.init()?;
+    activate_with(
+        role,
+        user,
+        duration,
+        &runtime.activations(),
+        unix_time(SystemTime::now()),
+    )
+}
+
+fn check_activation_with(role: &SRole, actor: &Cred, dir: &Path, now: u64) -> PluginResult {
+    match get_activation_entry(role) {
+        Some(Ok(_)) => (),
+        Some(Err(e)) => {
+            // a broken restriction must not grant access
+            warn!("Invalid activation entry for role {} : {}", role.name, e);
+            return PluginResult::Deny;
+        }
+        None => return PluginResult::Neutral,
+    }
+    let scope = Scope::of(actor);
+    let active = read_tokens(&tokens_path(dir, actor.user.uid))
+        .iter()
+        .any(|token| token.role == role.name && token.scope == scope && now < token.until);
+    if active {
+        PluginResult::Neutral
+    } else {
+        debug!("The role {} is not activated", role.name);
+        PluginResult::Deny
+    }
+}
+
+fn check_activation(role: &SRole, actor: &Cred) -> PluginResult {
+    check_activation_with(
+        role,
+        actor,
+        &RuntimeDirs::default().activations(),
+        unix_time(SystemTime::now()),
+    )
+}
+
+pub fn register() {
+    PluginManager::subscribe_duty_separation(check_activation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use nix::unistd::Pid;
+    use serde_json::json;
+
+    use super::*;
+
+    fn role(name: &str, activation: serde_json::Value) -> Rc<RefCell<SRole>> {
+        let role = SRole::builder(name).build();
+        role.as_ref()
+            .borrow_mut()
+            ._extra_fields
+            .insert("activation".to_string(), activation);
+        role
+    }
+
+    #[test]
+    fn test_activation() {
+        let dir = std::env::temp_dir().join(format!("rar_activation_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let shell = Cred::builder().user_id(0).ppid(Pid::from_raw(4242)).build();
+        let other_shell = Cred::builder().user_id(0).ppid(Pid::from_raw(4243)).build();
+        let dba = role("r_dba", json!({ "max-duration": "01:00:00" }));
+        let dba = dba.as_ref().borrow();
+        let now = 1_000_000;
+        assert_eq!(
+            check_activation_with(&dba, &shell, &dir, now),
+            PluginResult::Deny
+        );
+        // capped by the max-duration of the role
+        let duration = activate_with(&dba, &shell, Some(Duration::hours(2)), &dir, now).unwrap();
+        assert_eq!(duration, Duration::hours(1));
+        assert_eq!(
+            check_activation_with(&dba, &shell, &dir, now + 3599),
+            PluginResult::Neutral
+        );
+        assert_eq!(
+            check_activation_with(&dba, &shell, &dir, now + 3600),
+            PluginResult::Deny
+        );
+        // the token is scoped to the shell that activated the role
+        assert_eq!(
+            check_activation_with(&dba, &other_shell, &dir, now),
+            PluginResult::Deny
+        );
+        // a token anyone may have written is not trusted
+        fs::set_permissions(
+            tokens_path(&dir, shell.user.uid),
+            fs::Permissions::from_mode(0o666),
+        )
+        .unwrap();
+        assert_eq!(
+            check_activation_with(&dba, &shell, &dir, now),
+            PluginResult::Deny
+        );
+        let default = role("r_backup", json!({}));
+        let default = default.as_ref().borrow();
+        assert_eq!(
+            activate_with(&default, &shell, None, &dir, now).unwrap(),
+            Duration::hours(1)
+        );
+        let invalid = role("r_invalid", json!({ "max-duration": "30m" }));
+        assert_eq!(
+            check_activation_with(&invalid.as_ref().borrow(), &shell, &dir, now),
+            PluginResult::Deny
+        );
+        let unrestricted = SRole::builder("r_any").build();
+        assert_eq!(
+            check_activation_with(&unrestricted.as_ref().borrow(), &shell, &dir, now),
+            PluginResult::Neutral
+        );
+        assert!(activate_with(&unrestricted.as_ref().borrow(), &shell, None, &dir, now).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
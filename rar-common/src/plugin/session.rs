@@ -0,0 +1,204 @@
+//! Roles bound to the systemd-logind session of the caller.
+//!
+//! The `session` entry of a role restricts it to the sessions with the given seat, remote flag,
+//! classes or types. The session is the one of the cgroup of the process running sr, and its
+//! properties are asked to logind over D-Bus by `loginctl` when the role is matched. A caller
+//! outside of any session, or whose session cannot be read, is denied the role.
+
+use std::{error::Error, fs, process::Command};
+
+use log::{debug, warn};
+use nix::unistd::Pid;
+use serde::Deserialize;
+use serde_json::Error as JsonError;
+
+use crate::{
+    api::{PluginManager, PluginResult},
+    database::{finder::Cred, structs::SRole},
+};
+
+const LOGINCTL: &str = "/usr/bin/loginctl";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SessionConstraint {
+    #[serde(default)]
+    seat: Option<String>,
+    #[serde(default)]
+    remote: Option<bool>,
+    /// `user`, `greeter`, `lock-screen`...
+    #[serde(default)]
+    class: Vec<String>,
+    /// `tty`, `x11`, `wayland`, `mir` or `unspecified`
+    #[serde(default, rename = "type")]
+    types: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Session {
+    seat: String,
+    remote: bool,
+    class: String,
+    session_type: String,
+}
+
+fn get_session_entry(role: &SRole) -> Option<Result<SessionConstraint, JsonError>> {
+    role._extra_fields
+        .get("session")
+        .map(|session| serde_json::from_value::<SessionConstraint>(session.clone()))
+}
+
+/// The logind session of a `/proc/<pid>/cgroup`, the `session-<id>.scope` of the cgroup v2.
+fn session_id(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))?
+        .split('/')
+        .find_map(|unit| unit.strip_prefix("session-")?.strip_suffix(".scope"))
+        .map(str::to_string)
+}
+
+fn parse_properties(output: &str) -> Session {
+    let mut session = Session::default();
+    for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+        match key {
+            "Seat" => session.seat = value.to_string(),
+            "Remote" => session.remote = value == "yes",
+            "Class" => session.class = value.to_string(),
+            "Type" => session.session_type = value.to_string(),
+            _ => (),
+        }
+    }
+    session
+}
+
+fn session_of(pid: Pid) -> Result<Session, Box<dyn Error>> {
+    let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid))?;
+    let id = session_id(&cgroup).ok_or("not in a login session")?;
+    let output = Command::new(LOGINCTL)
+        .args(["show-session", &id, "--property=Seat,Remote,Class,Type"])
+        .env_clear()
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    Ok(parse_properties(&String::from_utf8(output.stdout)?))
+}
+
+fn matches(constraint: &SessionConstraint, session: &Session) -> bool {
+    constraint.seat.iter().all(|seat| *seat == session.seat)
+        && constraint
+            .remote
+            .iter()
+            .all(|remote| *remote == session.remote)
+        && (constraint.class.is_empty() || constraint.class.contains(&session.class))
+        && (constraint.types.is_empty() || constraint.types.contains(&session.session_type))
+}
+
+fn check_session_with(
+    role: &SRole,
+    actor: &Cred,
+    session_of: impl Fn(Pid) -> Result<Session, Box<dyn Error>>,
+) -> PluginResult {
+    let constraint = match get_session_entry(role) {
+        Some(Ok(constraint)) => constraint,
+        Some(Err(e)) => {
+            // a broken restriction must not grant access
+            warn!("Invalid session entry for role {} : {}", role.name, e);
+            return PluginResult::Deny;
+        }
+        None => return PluginResult::Neutral,
+    };
+    match session_of(actor.ppid) {
+        Ok(session) if matches(&constraint, &session) => PluginResult::Neutral,
+        Ok(session) => {
+            debug!(
+                "The session {:?} does not match the role {}",
+                session, role.name
+            );
+            PluginResult::Deny
+        }
+        Err(e) => {
+            debug!("No session for the role {} : {}", role.name, e);
+            PluginResult::Deny
+        }
+    }
+}
+
+fn check_session(role: &SRole, actor: &Cred) -> PluginResult {
+    check_session_with(role, actor, session_of)
+}
+
+pub fn register() {
+    PluginManager::subscribe_duty_separation(check_session)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn role(session: serde_json::Value) -> Rc<RefCell<SRole>> {
+        let role = SRole::builder("r_console").build();
+        role.as_ref()
+            .borrow_mut()
+            ._extra_fields
+            .insert("session".to_string(), session);
+        role
+    }
+
+    fn local_wayland(_: Pid) -> Result<Session, Box<dyn Error>> {
+        Ok(parse_properties(
+            "Seat=seat0\nRemote=no\nClass=user\nType=wayland\n",
+        ))
+    }
+
+    #[test]
+    fn test_session_id() {
+        assert_eq!(
+            session_id("0::/user.slice/user-1000.slice/session-3.scope\n"),
+            Some("3".to_string())
+        );
+        assert_eq!(
+            session_id("0::/user.slice/user-1000.slice/user@1000.service/app.slice\n"),
+            None
+        );
+        assert_eq!(session_id("0::/system.slice/sshd.service\n"), None);
+    }
+
+    #[test]
+    fn test_check_session() {
+        let actor = Cred::builder().build();
+        let graphical =
+            role(json!({ "seat": "seat0", "remote": false, "type": ["x11", "wayland"] }));
+        assert_eq!(
+            check_session_with(&graphical.as_ref().borrow(), &actor, local_wayland),
+            PluginResult::Neutral
+        );
+        let ssh = role(json!({ "remote": true }));
+        assert_eq!(
+            check_session_with(&ssh.as_ref().borrow(), &actor, local_wayland),
+            PluginResult::Deny
+        );
+        // outside of a session
+        assert_eq!(
+            check_session_with(&graphical.as_ref().borrow(), &actor, |_| Err(
+                "not in a login session".into()
+            )),
+            PluginResult::Deny
+        );
+        let invalid = role(json!({ "seats": "seat0" }));
+        assert_eq!(
+            check_session_with(&invalid.as_ref().borrow(), &actor, local_wayland),
+            PluginResult::Deny
+        );
+        let unrestricted = SRole::builder("r_any").build();
+        assert_eq!(
+            check_session_with(&unrestricted.as_ref().borrow(), &actor, local_wayland),
+            PluginResult::Neutral
+        );
+    }
+}
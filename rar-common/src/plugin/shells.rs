@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::Error;
+
+use crate::{
+    api::{PluginManager, PluginResult},
+    database::{finder::Cred, structs::SRole},
+};
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Shells {
+    #[serde(default)]
+    allow: Option<Vec<PathBuf>>,
+    #[serde(default)]
+    deny: Vec<PathBuf>,
+}
+
+fn get_shells_entry(role: &SRole) -> Option<Result<Shells, Error>> {
+    role._extra_fields
+        .get("shells")
+        .map(|shells| serde_json::from_value::<Shells>(shells.clone()))
+}
+
+fn shell_is_forbidden(shells: &Shells, shell: &PathBuf) -> bool {
+    shells.deny.contains(shell)
+        || shells
+            .allow
+            .as_ref()
+            .is_some_and(|allow| !allow.contains(shell))
+}
+
+fn check_login_shell(role: &SRole, actor: &Cred) -> PluginResult {
+    match get_shells_entry(role) {
+        Some(Ok(shells)) => {
+            debug!(
+                "Checking login shell {:?} of {} against {:?}",
+                actor.user.shell, actor.user.name, shells
+            );
+            if shell_is_forbidden(&shells, &actor.user.shell) {
+                warn!(
+                    "User {} login shell {:?} is not allowed for role {}",
+                    actor.user.name, actor.user.shell, role.name
+                );
+                PluginResult::Deny
+            } else {
+                PluginResult::Neutral
+            }
+        }
+        Some(Err(e)) => {
+            // a broken restriction must not grant access
+            warn!("Invalid shells entry for role {} : {}", role.name, e);
+            PluginResult::Deny
+        }
+        None => PluginResult::Neutral,
+    }
+}
+
+pub fn register() {
+    PluginManager::subscribe_duty_separation(check_login_shell)
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::{Pid, User};
+    use serde_json::json;
+
+    use super::*;
+
+    fn root_cred() -> Cred {
+        Cred {
+            user: User::from_uid(0.into()).unwrap().unwrap(),
+            groups: vec![],
+            tty: None,
+            ppid: Pid::parent(),
+            orchestrated: false,
+        }
+    }
+
+    fn role_with_shells(shells: serde_json::Value) -> SRole {
+        let mut role = SRole {
+            name: "role1".to_string(),
+            ..Default::default()
+        };
+        role._extra_fields.insert("shells".to_string(), shells);
+        role
+    }
+
+    #[test]
+    fn test_check_login_shell() {
+        let cred = root_cred();
+        let shell = cred.user.shell.to_string_lossy().to_string();
+        assert_eq!(
+            check_login_shell(&SRole::default(), &cred),
+            PluginResult::Neutral
+        );
+        assert_eq!(
+            check_login_shell(&role_with_shells(json!({ "deny": [shell] })), &cred),
+            PluginResult::Deny
+        );
+        assert_eq!(
+            check_login_shell(&role_with_shells(json!({ "allow": [shell] })), &cred),
+            PluginResult::Neutral
+        );
+        assert_eq!(
+            check_login_shell(
+                &role_with_shells(json!({ "allow": ["/nonexistent/shell"] })),
+                &cred
+            ),
+            PluginResult::Deny
+        );
+        assert_eq!(
+            check_login_shell(&role_with_shells(json!({ "deny": "/bin/sh" })), &cred),
+            PluginResult::Deny
+        );
+    }
+}
@@ -218,6 +218,7 @@ mod tests {
             groups: vec![Group::from_gid(0.into()).unwrap().unwrap()],
             tty: None,
             ppid: Pid::parent(),
+            orchestrated: false,
         };
         assert_eq!(
             check_separation_of_duty(&role.as_ref().borrow(), &actor),
@@ -0,0 +1,297 @@
+//! Evaluation of the policy as a library, for tools that must know whether a user would be
+//! allowed to run a command without running sr.
+//!
+//! ```no_run
+//! use rootasrole_core::{database::finder::Cred, policy::{Decision, Policy}};
+//!
+//! let policy = Policy::load().unwrap();
+//! let alice = Cred::builder().user_name("alice").build();
+//! let command = ["/usr/bin/systemctl".to_string(), "restart".to_string(), "nginx".to_string()];
+//! if let Decision::Allow(grant) = policy.evaluate(&alice, &command) {
+//!     println!("granted by {}/{}", grant.role, grant.task);
+//! }
+//! ```
+//!
+//! The plugins are only consulted once registered with [`crate::plugin::register_plugins`].
+
+use std::{cell::RefCell, collections::BTreeMap, error::Error, path::Path, path::PathBuf, rc::Rc};
+
+use capctl::CapSet;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    database::{
+        actor::{SGroups, SUserType},
+        finder::{Cred, ExecSettings, MatchError, TaskMatch, TaskMatcher},
+        impact::impact,
+        make_weak_config,
+        options::{Level, OptStack, SAuthentication},
+        read_json_config,
+        structs::SConfig,
+        FilterMatcher,
+    },
+    get_settings, ROOTASROLE,
+};
+
+/// What sr would do with the command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow(Grant),
+    /// No task of the roles of the user grants the command
+    Deny,
+    /// Several tasks grant the command with the same precision, sr refuses to choose
+    Conflict,
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allow(_))
+    }
+}
+
+/// The task granting a command and the execution it leads to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub role: String,
+    pub task: String,
+    pub exec_path: PathBuf,
+    pub exec_args: Vec<String>,
+    pub setuid: Option<SUserType>,
+    pub setgroups: Option<SGroups>,
+    pub capabilities: CapSet,
+    pub authentication: SAuthentication,
+}
+
+impl From<&TaskMatch> for Grant {
+    fn from(taskmatch: &TaskMatch) -> Self {
+        let settings = &taskmatch.settings;
+        Grant {
+            role: taskmatch.role().as_ref().borrow().name.clone(),
+            task: taskmatch.task().as_ref().borrow().name.to_string(),
+            exec_path: settings.exec_path.clone(),
+            exec_args: settings.exec_args.clone(),
+            setuid: settings.setuid.clone(),
+            setgroups: settings.setgroups.clone(),
+            capabilities: settings.caps.unwrap_or_default(),
+            authentication: settings.opt.get_authentication().1,
+        }
+    }
+}
+
+/// An option in effect, with the level defining it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub value: Value,
+    pub level: String,
+}
+
+impl Provenance {
+    fn new<T: Serialize>((level, value): (Level, T)) -> Result<Self, Box<dyn Error>> {
+        Ok(Provenance {
+            value: serde_json::to_value(value)?,
+            level: format!("{:?}", level).to_lowercase(),
+        })
+    }
+}
+
+/// The options in effect, with the level defining them.
+pub fn options(optstack: &OptStack) -> Result<BTreeMap<String, Provenance>, Box<dyn Error>> {
+    Ok(BTreeMap::from([
+        (
+            "root".into(),
+            Provenance::new(optstack.get_root_behavior())?,
+        ),
+        ("bounding".into(), Provenance::new(optstack.get_bounding())?),
+        (
+            "authentication".into(),
+            Provenance::new(optstack.get_authentication())?,
+        ),
+        (
+            "wildcard-denied".into(),
+            Provenance::new(optstack.get_wildcard())?,
+        ),
+        ("timeout".into(), Provenance::new(optstack.get_timeout())?),
+        ("network".into(), Provenance::new(optstack.get_network())?),
+    ]))
+}
+
+/// What `sr --info` shows of the task chosen for a command.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Info {
+    pub role: String,
+    pub task: String,
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<String>,
+    pub capabilities: Vec<String>,
+    /// What the capabilities allow, in plain words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub impact: Vec<String>,
+    pub options: BTreeMap<String, Provenance>,
+}
+
+impl Info {
+    pub fn new(execcfg: &ExecSettings) -> Result<Self, Box<dyn Error>> {
+        Ok(Info {
+            role: execcfg.role().as_ref().borrow().name.clone(),
+            task: execcfg.task().as_ref().borrow().name.to_string(),
+            command: std::iter::once(execcfg.exec_path.to_string_lossy().into_owned())
+                .chain(execcfg.exec_args.iter().cloned())
+                .collect(),
+            user: execcfg.setuid.as_ref().map(|user| user.to_string()),
+            groups: execcfg.setgroups.as_ref().map(|groups| groups.to_string()),
+            capabilities: execcfg
+                .caps
+                .unwrap_or_default()
+                .iter()
+                .map(|cap| cap.to_string())
+                .collect(),
+            impact: impact(&execcfg.caps.unwrap_or_default()),
+            options: options(&execcfg.opt)?,
+        })
+    }
+}
+
+/// A loaded policy, evaluated like sr does.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    config: Rc<RefCell<SConfig>>,
+}
+
+impl Policy {
+    /// The policy of the installed settings file.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Self::load_from(ROOTASROLE)
+    }
+
+    /// The policy of a settings file, or of the file it designates.
+    pub fn load_from<P: AsRef<Path>>(settings: P) -> Result<Self, Box<dyn Error>> {
+        let path = settings.as_ref();
+        let config = read_json_config(get_settings(path)?, path)?;
+        Ok(Policy { config })
+    }
+
+    /// A policy given as the `options` and `roles` of a configuration file.
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_config(serde_json::from_str(json)?))
+    }
+
+    pub fn from_config(config: Rc<RefCell<SConfig>>) -> Self {
+        make_weak_config(&config);
+        Policy { config }
+    }
+
+    pub fn config(&self) -> &Rc<RefCell<SConfig>> {
+        &self.config
+    }
+
+    /// Whether the user may run the command, the role and task being chosen as sr does.
+    pub fn evaluate(&self, cred: &Cred, command: &[String]) -> Decision {
+        self.evaluate_with(cred, None, command)
+    }
+
+    /// Like [`Policy::evaluate`], with the role, task or target of `sr -r`, `-t`, `-u` or `-g`.
+    pub fn evaluate_with(
+        &self,
+        cred: &Cred,
+        filter: Option<FilterMatcher>,
+        command: &[String],
+    ) -> Decision {
+        match self.config.matches(cred, &filter, command) {
+            Ok(taskmatch) if taskmatch.fully_matching() => Decision::Allow((&taskmatch).into()),
+            Err(MatchError::Conflict(_)) => Decision::Conflict,
+            _ => Decision::Deny,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+
+    use super::*;
+    use crate::testkit::{fake_cred, fake_group, fake_user};
+
+    #[test]
+    fn test_evaluate() {
+        let policy = Policy::from_json(
+            r#"{
+                "roles": [{
+                    "name": "r_ops",
+                    "actors": [{ "type": "group", "groups": "4300" }],
+                    "tasks": [{
+                        "name": "t_ls",
+                        "cred": { "capabilities": ["CAP_DAC_READ_SEARCH"] },
+                        "commands": { "default": "none", "add": ["/usr/bin/ls -l /root"] }
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let alice = fake_cred(
+            fake_user("alice", 4301, 4300),
+            vec![fake_group("netops", 4300)],
+        );
+        let bob = fake_cred(fake_user("bob", 4302, 4302), vec![]);
+        let command = shell_words::split("/usr/bin/ls -l /root").unwrap();
+        let Decision::Allow(grant) = policy.evaluate(&alice, &command) else {
+            panic!("ls is not allowed for alice");
+        };
+        assert_eq!(
+            (grant.role.as_str(), grant.task.as_str()),
+            ("r_ops", "t_ls")
+        );
+        assert!(grant.capabilities.has(Cap::DAC_READ_SEARCH));
+        assert_eq!(policy.evaluate(&bob, &command), Decision::Deny);
+        assert!(!policy
+            .evaluate(&alice, &["/usr/bin/cat".to_string()])
+            .is_allowed());
+        let filter = FilterMatcher::builder().role("r_other".to_string()).build();
+        assert_eq!(
+            policy.evaluate_with(&alice, Some(filter), &command),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn test_info() {
+        let policy = Policy::from_json(
+            r#"{
+                "roles": [{
+                    "name": "r_web",
+                    "actors": [{ "type": "group", "groups": "4310" }],
+                    "tasks": [{
+                        "name": "t_bind",
+                        "cred": { "capabilities": ["CAP_NET_BIND_SERVICE"] },
+                        "commands": { "default": "none", "add": ["/usr/bin/ls"] },
+                        "options": { "bounding": "ignore" }
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let alice = fake_cred(
+            fake_user("alice", 4311, 4310),
+            vec![fake_group("web", 4310)],
+        );
+        let taskmatch = policy
+            .config()
+            .matches(&alice, &None, &["/usr/bin/ls".to_string()])
+            .unwrap();
+        let info = Info::new(&taskmatch.settings).unwrap();
+        assert_eq!(
+            (info.role.as_str(), info.task.as_str()),
+            ("r_web", "t_bind")
+        );
+        assert_eq!(info.capabilities, ["CAP_NET_BIND_SERVICE"]);
+        assert_eq!(info.options["bounding"].value, "ignore");
+        assert_eq!(info.options["bounding"].level, "task");
+        assert_eq!(info.options["root"].level, "default");
+        let json = serde_json::to_value(&info).unwrap();
+        assert!(json.get("user").is_none());
+        assert_eq!(serde_json::from_value::<Info>(json).unwrap(), info);
+    }
+}
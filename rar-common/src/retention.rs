@@ -0,0 +1,212 @@
+//! Retention of the audit context snapshots saved by sr.
+//!
+//! Snapshots are removed by `chsr audit gc` when they are older than `max-age`, beyond the
+//! `task-quota` most recent snapshots of their task, or the oldest first until the directory
+//! fits in `max-size`. sr also collects them when saving a snapshot fails, then retries once.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    error::Error,
+    ffi::OsStr,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bon::Builder;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use strum::EnumIs;
+
+use crate::util::{dac_override_effective, read_effective, remove_with_privileges};
+
+pub const CONTEXT_DIR: &str = "/var/log/rootasrole/context";
+/// Certificate the snapshots are encrypted to
+pub const SNAPSHOT_RECIPIENT: &str = "/etc/security/rootasrole.audit.pem";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, EnumIs)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureAction {
+    /// The command runs without its snapshot
+    #[default]
+    FailOpen,
+    FailClosed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetentionSettings {
+    /// Bytes kept in the snapshot directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    /// Days a snapshot is kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    /// Snapshots kept per task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_quota: Option<usize>,
+    /// When a snapshot cannot be saved
+    #[serde(default)]
+    #[builder(default)]
+    pub on_failure: FailureAction,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+    // role and task, unknown for snapshots saved before they were recorded
+    task: Option<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotTask {
+    role: String,
+    task: String,
+}
+
+fn entries(dir: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("json")) {
+            continue;
+        }
+        let metadata = path.metadata()?;
+        let task = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, SnapshotTask>(BufReader::new(file)).ok())
+            .map(|snapshot| (snapshot.role, snapshot.task));
+        entries.push(Entry {
+            path,
+            modified: metadata.modified()?,
+            size: metadata.len(),
+            task,
+        });
+    }
+    Ok(entries)
+}
+
+// indexes of the entries to remove
+fn plan(entries: &mut [Entry], settings: &RetentionSettings, now: SystemTime) -> Vec<usize> {
+    // most recent first
+    entries.sort_by_key(|entry| Reverse(entry.modified));
+    let max_age = settings
+        .max_age
+        .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let mut per_task: HashMap<&(String, String), usize> = HashMap::new();
+    let mut removed = Vec::new();
+    let mut kept_size = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let expired = max_age.is_some_and(|max_age| {
+            now.duration_since(entry.modified)
+                .is_ok_and(|age| age > max_age)
+        });
+        let over_quota = entry.task.as_ref().is_some_and(|task| {
+            let count = per_task.entry(task).or_default();
+            *count += 1;
+            settings.task_quota.is_some_and(|quota| *count > quota)
+        });
+        let over_size = settings
+            .max_size
+            .is_some_and(|max_size| kept_size + entry.size > max_size);
+        if expired || over_quota || over_size {
+            removed.push(i);
+        } else {
+            kept_size += entry.size;
+        }
+    }
+    removed
+}
+
+/// Remove the snapshots of `dir` that the retention settings do not keep.
+pub fn collect(
+    dir: &Path,
+    settings: &RetentionSettings,
+    now: SystemTime,
+) -> Result<GcReport, Box<dyn Error>> {
+    read_effective(true).or(dac_override_effective(true))?;
+    let entries = entries(dir);
+    read_effective(false)?;
+    dac_override_effective(false)?;
+    let mut entries = entries?;
+    let mut report = GcReport::default();
+    for i in plan(&mut entries, settings, now) {
+        debug!("Removing the audit snapshot {}", entries[i].path.display());
+        remove_with_privileges(&entries[i].path)?;
+        report.removed += 1;
+        report.freed += entries[i].size;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    fn entry(name: &str, days: u64, size: u64, task: Option<&str>) -> Entry {
+        Entry {
+            path: name.into(),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(days * 24 * 60 * 60),
+            size,
+            task: task.map(|task| ("r1".to_string(), task.to_string())),
+        }
+    }
+
+    fn planned(entries: &mut [Entry], settings: &RetentionSettings, now: u64) -> Vec<String> {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(now * 24 * 60 * 60);
+        let mut names: Vec<String> = plan(entries, settings, now)
+            .into_iter()
+            .map(|i| entries[i].path.display().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut entries = [
+            entry("a", 10, 100, Some("t1")),
+            entry("b", 20, 100, Some("t1")),
+            entry("c", 30, 100, Some("t1")),
+            entry("d", 5, 100, None),
+            entry("e", 29, 100, Some("t2")),
+        ];
+        let settings = RetentionSettings::default();
+        assert!(planned(&mut entries, &settings, 31).is_empty());
+        let settings = RetentionSettings::builder().max_age(15).build();
+        assert_eq!(planned(&mut entries, &settings, 31), ["a", "d"]);
+        let settings = RetentionSettings::builder().task_quota(2).build();
+        assert_eq!(planned(&mut entries, &settings, 31), ["a"]);
+        let settings = RetentionSettings::builder().max_size(250).build();
+        assert_eq!(planned(&mut entries, &settings, 31), ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn test_collect() {
+        let dir = temp_dir().join("rar_test_retention");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("00.json"), r#"{"role":"r1","task":"t1"}"#).unwrap();
+        std::fs::write(dir.join("01.json"), r#"{"role":"r1","task":"t1"}"#).unwrap();
+        std::fs::write(dir.join("notes.txt"), "kept").unwrap();
+        let settings = RetentionSettings::builder().task_quota(1).build();
+        let report = collect(&dir, &settings, SystemTime::now()).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.freed, 25);
+        assert!(dir.join("notes.txt").exists());
+        let report = collect(&dir, &settings, SystemTime::now()).unwrap();
+        assert_eq!(report, GcReport::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
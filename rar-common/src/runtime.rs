@@ -0,0 +1,205 @@
+//! The runtime state of RootAsRole, lost at each boot.
+//!
+//! Every binary finds its runtime files through [`RuntimeDirs`], and creates the directories it
+//! needs with [`RuntimeDirs::init`], so no tmpfiles.d configuration is needed. The layout is
+//! versioned: when a binary finds the state of another layout, left by a previous version
+//! before an upgrade, it removes it before creating its own.
+//!
+//! ```text
+//! /run/rootasrole/
+//!   layout        the version of the layout
+//!   agent.sock    the socket of rootasrole-agent
+//!   ts/           the authentication timestamps of sr, one file per user
+//!   locks/        the lock files, of the timestamps and of the policy
+//!   groups/       the groups of the users known to the name service
+//!   activations/  the roles activated by the users
+//!   quotas/       the usage counters
+//!   audit/        the audit records not yet delivered
+//!   nonces/       the nonces of the orchestrator tokens already used
+//! ```
+
+use std::{
+    error::Error,
+    fs::{self, DirBuilder},
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use capctl::Cap;
+use log::{debug, info};
+use nix::unistd::{chown, Gid, Uid};
+
+use crate::util::{cap_effective, dac_override_effective};
+
+#[cfg(not(test))]
+pub const RUNTIME_DIR: &str = "/run/rootasrole";
+#[cfg(test)]
+pub const RUNTIME_DIR: &str = "target/run";
+
+const LAYOUT_VERSION: u32 = 4;
+const LAYOUT_FILE: &str = "layout";
+const TIMESTAMPS: &str = "ts";
+const LOCKS: &str = "locks";
+const GROUPS: &str = "groups";
+const ACTIVATIONS: &str = "activations";
+const QUOTAS: &str = "quotas";
+const AUDIT_SPOOL: &str = "audit";
+const NONCES: &str = "nonces";
+
+/// The runtime directory and its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeDirs {
+    root: PathBuf,
+}
+
+impl Default for RuntimeDirs {
+    fn default() -> Self {
+        RuntimeDirs::new(RUNTIME_DIR)
+    }
+}
+
+impl RuntimeDirs {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        RuntimeDirs { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn agent_socket(&self) -> PathBuf {
+        self.root.join("agent.sock")
+    }
+
+    pub fn timestamps(&self) -> PathBuf {
+        self.root.join(TIMESTAMPS)
+    }
+
+    pub fn locks(&self) -> PathBuf {
+        self.root.join(LOCKS)
+    }
+
+    pub fn groups(&self) -> PathBuf {
+        self.root.join(GROUPS)
+    }
+
+    pub fn activations(&self) -> PathBuf {
+        self.root.join(ACTIVATIONS)
+    }
+
+    pub fn quotas(&self) -> PathBuf {
+        self.root.join(QUOTAS)
+    }
+
+    pub fn audit_spool(&self) -> PathBuf {
+        self.root.join(AUDIT_SPOOL)
+    }
+
+    pub fn nonces(&self) -> PathBuf {
+        self.root.join(NONCES)
+    }
+
+    fn layout_version(&self) -> Option<u32> {
+        fs::read_to_string(self.root.join(LAYOUT_FILE))
+            .ok()
+            .and_then(|version| version.trim().parse().ok())
+    }
+
+    // the directories of another layout, the sockets of running daemons are kept
+    fn remove_previous_layout(&self) -> Result<(), Box<dyn Error>> {
+        for entry in fs::read_dir(&self.root)?.flatten() {
+            if entry.file_type()?.is_dir() {
+                info!("Removing {} of a previous layout", entry.path().display());
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn create(&self) -> Result<(), Box<dyn Error>> {
+        // sr looks up its files by name, as the user, without listing the directories
+        let mut builder = DirBuilder::new();
+        builder.recursive(true).mode(0o711);
+        builder.create(&self.root)?;
+        // the agent socket is reached by every user
+        fs::set_permissions(&self.root, fs::Permissions::from_mode(0o755))?;
+        debug!(
+            "Runtime layout {:?} found, creating the layout {}",
+            self.layout_version(),
+            LAYOUT_VERSION
+        );
+        self.remove_previous_layout()?;
+        let layout = self.root.join(LAYOUT_FILE);
+        fs::write(&layout, format!("{}\n", LAYOUT_VERSION))?;
+        let dirs = [
+            self.timestamps(),
+            self.locks(),
+            self.groups(),
+            self.activations(),
+            self.quotas(),
+            self.audit_spool(),
+            self.nonces(),
+        ];
+        for dir in dirs.iter() {
+            builder.create(dir)?;
+        }
+        // created by sr on behalf of a user, the state belongs to root
+        for path in [self.root.clone(), layout].iter().chain(dirs.iter()) {
+            if fs::metadata(path)?.uid() != 0 {
+                chown(path, Some(Uid::from_raw(0)), Some(Gid::from_raw(0)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the directories of the layout, replacing the ones of a previous layout.
+    pub fn init(&self) -> Result<(), Box<dyn Error>> {
+        if self.layout_version() == Some(LAYOUT_VERSION) {
+            return Ok(());
+        }
+        dac_override_effective(true)?;
+        cap_effective(Cap::CHOWN, true)?;
+        let res = self.create();
+        cap_effective(Cap::CHOWN, false)?;
+        dac_override_effective(false)?;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init() {
+        let dirs = RuntimeDirs::new(
+            std::env::temp_dir().join(format!("rar_runtime_{}", std::process::id())),
+        );
+        let _ = fs::remove_dir_all(dirs.root());
+        // the state of a previous version, and the socket of a running agent
+        fs::create_dir_all(dirs.root().join("ts")).unwrap();
+        fs::write(dirs.root().join("ts/1000"), "cookies").unwrap();
+        fs::write(dirs.agent_socket(), "").unwrap();
+        dirs.init().unwrap();
+        assert!(!dirs.timestamps().join("1000").exists());
+        assert!(dirs.agent_socket().exists());
+        for dir in [
+            dirs.timestamps(),
+            dirs.locks(),
+            dirs.groups(),
+            dirs.activations(),
+            dirs.quotas(),
+            dirs.audit_spool(),
+            dirs.nonces(),
+        ] {
+            let metadata = fs::metadata(&dir).unwrap();
+            assert_eq!(metadata.mode() & 0o777, 0o711, "{}", dir.display());
+        }
+        assert_eq!(dirs.layout_version(), Some(LAYOUT_VERSION));
+        // the layout is kept once created
+        fs::write(dirs.timestamps().join("1000"), "cookies").unwrap();
+        dirs.init().unwrap();
+        assert!(dirs.timestamps().join("1000").exists());
+        fs::remove_dir_all(dirs.root()).unwrap();
+    }
+}
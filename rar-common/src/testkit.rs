@@ -0,0 +1,120 @@
+//! Helpers to test policies without root, real accounts or PAM.
+//!
+//! Fake users and groups are not known by the system, so policies evaluated against them
+//! must designate actors by their numeric id, e.g. `{ "type": "user", "id": "4242" }`.
+
+use std::{cell::RefCell, error::Error, ffi::CString, path::PathBuf, rc::Rc};
+
+use nix::unistd::{Gid, Group, Pid, Uid, User};
+
+use crate::database::{
+    finder::{Cred, TaskMatch, TaskMatcher},
+    make_weak_config,
+    structs::SConfig,
+    FilterMatcher,
+};
+
+pub fn fake_user(name: &str, uid: u32, gid: u32) -> User {
+    User {
+        name: name.to_string(),
+        passwd: CString::new("x").unwrap(),
+        uid: Uid::from_raw(uid),
+        gid: Gid::from_raw(gid),
+        gecos: CString::default(),
+        dir: PathBuf::from(format!("/home/{}", name)),
+        shell: PathBuf::from("/bin/sh"),
+    }
+}
+
+pub fn fake_group(name: &str, gid: u32) -> Group {
+    Group {
+        name: name.to_string(),
+        passwd: CString::new("x").unwrap(),
+        gid: Gid::from_raw(gid),
+        mem: vec![],
+    }
+}
+
+/// A credential as sr would build it for the caller, without a tty.
+pub fn fake_cred(user: User, groups: Vec<Group>) -> Cred {
+    Cred {
+        user,
+        groups,
+        tty: None,
+        ppid: Pid::parent(),
+        orchestrated: false,
+    }
+}
+
+/// Build an in-memory policy from the `options` and `roles` part of a configuration file.
+pub fn policy(json: &str) -> Result<Rc<RefCell<SConfig>>, Box<dyn Error>> {
+    let config: Rc<RefCell<SConfig>> = serde_json::from_str(json)?;
+    make_weak_config(&config);
+    Ok(config)
+}
+
+pub fn evaluate(
+    config: &Rc<RefCell<SConfig>>,
+    cred: &Cred,
+    filter: Option<FilterMatcher>,
+    command: &str,
+) -> Result<TaskMatch, Box<dyn Error>> {
+    let command = shell_words::split(command)?;
+    Ok(config.matches(cred, &filter, &command)?)
+}
+
+/// Panics unless the command is granted, returns the match to inspect the selected task.
+pub fn assert_allowed(config: &Rc<RefCell<SConfig>>, cred: &Cred, command: &str) -> TaskMatch {
+    match evaluate(config, cred, None, command) {
+        Ok(taskmatch) if taskmatch.fully_matching() => taskmatch,
+        Ok(_) => panic!("{} is not allowed for {}", command, cred.user.name),
+        Err(e) => panic!("{} is not allowed for {}: {}", command, cred.user.name, e),
+    }
+}
+
+pub fn assert_denied(config: &Rc<RefCell<SConfig>>, cred: &Cred, command: &str) {
+    if let Ok(taskmatch) = evaluate(config, cred, None, command) {
+        if taskmatch.fully_matching() {
+            panic!(
+                "{} is allowed for {} by task {} of role {}",
+                command,
+                cred.user.name,
+                taskmatch.task().as_ref().borrow().name,
+                taskmatch.role().as_ref().borrow().name
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_testkit() {
+        let config = policy(
+            r#"{
+                "roles": [{
+                    "name": "r1",
+                    "actors": [{ "type": "user", "id": "4242" }],
+                    "tasks": [{
+                        "name": "t1",
+                        "commands": { "default": "none", "add": ["/usr/bin/ls -l"] }
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let alice = fake_cred(
+            fake_user("alice", 4242, 4242),
+            vec![fake_group("alice", 4242)],
+        );
+        let bob = fake_cred(fake_user("bob", 4243, 4243), vec![]);
+        let taskmatch = assert_allowed(&config, &alice, "/usr/bin/ls -l");
+        assert_eq!(taskmatch.role().as_ref().borrow().name, "r1");
+        assert_denied(&config, &alice, "/usr/bin/cat /etc/shadow");
+        assert_denied(&config, &bob, "/usr/bin/ls -l");
+    }
+}
@@ -0,0 +1,455 @@
+//! Traces of the last policy evaluations.
+//!
+//! When the `trace` setting is present, sr appends how each role and task answered to its
+//! request, allowed or denied, to a root-only file that keeps the `size` most recent traces.
+//! `chsr debug last` prints them.
+
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use bon::Builder;
+use capctl::Cap;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use strum::EnumIs;
+
+#[cfg(feature = "finder")]
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(feature = "finder")]
+use crate::database::{
+    finder::{Cred, CredMatcher, TaskMatch, TaskMatcher},
+    options::OptStack,
+    structs::SConfig,
+    FilterMatcher,
+};
+use crate::util::{
+    cap_effective, create_dir_all_with_privileges, dac_override_effective, open_with_privileges,
+    rename_with_privileges,
+};
+
+#[cfg(not(test))]
+pub const TRACE_FILE: &str = "/var/log/rootasrole/traces.jsonl";
+#[cfg(test)]
+pub const TRACE_FILE: &str = "target/traces.jsonl";
+
+const DEFAULT_SIZE: usize = 100;
+
+fn default_size() -> usize {
+    DEFAULT_SIZE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Builder)]
+#[serde(rename_all = "kebab-case")]
+pub struct TraceSettings {
+    /// Traces kept, the oldest are dropped first
+    #[serde(default = "default_size")]
+    #[builder(default = DEFAULT_SIZE)]
+    pub size: usize,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        TraceSettings::builder().build()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, EnumIs)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// The answer of a role, or of one of its tasks when `task` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    pub result: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    /// The execution id of sr, also given to the audit records and to the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub time: DateTime<Local>,
+    pub user: String,
+    pub uid: u32,
+    pub command: Vec<String>,
+    pub decision: Decision,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+}
+
+/// Replay the request on each role and task, in the order of the policy.
+#[cfg(feature = "finder")]
+pub fn evaluate(
+    config: &Rc<RefCell<SConfig>>,
+    user: &Cred,
+    cmd_opt: &Option<FilterMatcher>,
+    command: &[String],
+    result: &Result<TaskMatch, Box<dyn Error>>,
+) -> Trace {
+    let mut steps = Vec::new();
+    for role in config.as_ref().borrow().roles.iter() {
+        let name = role.as_ref().borrow().name.clone();
+        if !role.as_ref().borrow().is_enabled() {
+            steps.push(Step {
+                role: name,
+                task: None,
+                result: "The role is disabled".to_string(),
+            });
+            continue;
+        }
+        if role.user_matches(user).is_no_match() {
+            steps.push(Step {
+                role: name,
+                task: None,
+                result: "The actors do not match".to_string(),
+            });
+            continue;
+        }
+        for task in role.as_ref().borrow().tasks.iter() {
+            steps.push(Step {
+                role: name.clone(),
+                task: Some(task.as_ref().borrow().name.to_string()),
+                result: match task.matches(user, cmd_opt, command) {
+                    Ok(_) => "Matched".to_string(),
+                    Err(e) => e.to_string(),
+                },
+            });
+        }
+    }
+    let matched = result.as_ref().ok().filter(|m| m.fully_matching());
+    Trace {
+        id: None,
+        time: Local::now(),
+        user: user.user.name.clone(),
+        uid: user.user.uid.as_raw(),
+        command: command.to_vec(),
+        decision: if matched.is_some() {
+            Decision::Allow
+        } else {
+            Decision::Deny
+        },
+        role: matched.map(|m| m.role().as_ref().borrow().name.clone()),
+        task: matched.map(|m| m.task().as_ref().borrow().name.to_string()),
+        reason: result.as_ref().err().map(|e| e.to_string()),
+        steps,
+    }
+}
+
+/// Keep only the executable of the recorded command when the `args` audit setting of the
+/// granting task, or of the policy for a denied command, is `command-only`, or when the task
+/// passes its arguments through the environment.
+#[cfg(feature = "finder")]
+pub fn redact(
+    trace: &mut Trace,
+    config: &Rc<RefCell<SConfig>>,
+    result: &Result<TaskMatch, Box<dyn Error>>,
+) {
+    let keeps_arguments = match result.as_ref().ok().filter(|m| m.fully_matching()) {
+        Some(matched) => {
+            matched
+                .settings
+                .opt
+                .get_audit()
+                .args
+                .unwrap_or_default()
+                .is_full()
+                && !matched
+                    .task()
+                    .as_ref()
+                    .borrow()
+                    .args_via_env
+                    .unwrap_or(false)
+        }
+        None => OptStack::from_roles(config.clone())
+            .get_audit()
+            .args
+            .unwrap_or_default()
+            .is_full(),
+    };
+    if !keeps_arguments {
+        trace.command.truncate(1);
+    }
+}
+
+// the lines kept once `line` is appended to the buffer
+fn ring(mut lines: Vec<String>, line: String, size: usize) -> Vec<String> {
+    lines.push(line);
+    let excess = lines.len().saturating_sub(size);
+    lines.drain(..excess);
+    lines
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(BufReader::new(open_with_privileges(path)?)
+        .lines()
+        .collect::<Result<_, _>>()?)
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        create_dir_all_with_privileges(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    dac_override_effective(true)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp);
+    dac_override_effective(false)?;
+    let mut file = file?;
+    // sr runs with the caller uid, the traces of all users must only be readable by root
+    cap_effective(Cap::CHOWN, true)?;
+    let chown = std::os::unix::fs::fchown(&file, Some(0), Some(0));
+    cap_effective(Cap::CHOWN, false)?;
+    chown?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.sync_all()?;
+    Ok(rename_with_privileges(&tmp, path)?)
+}
+
+/// Append the trace, dropping the oldest ones beyond the size of the buffer.
+pub fn record(settings: &TraceSettings, trace: &Trace) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(TRACE_FILE);
+    let lines = ring(
+        read_lines(path)?,
+        serde_json::to_string(trace)?,
+        settings.size,
+    );
+    write_lines(path, &lines)
+}
+
+/// The traces of the buffer, oldest first. Lines that cannot be read are skipped.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Trace>, Box<dyn Error>> {
+    Ok(read_lines(path.as_ref())?
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Print the traces, only those of `user` (a name or a uid) when given.
+pub fn render(traces: &[Trace], user: Option<&str>) -> String {
+    let mut output = String::new();
+    for trace in traces
+        .iter()
+        .filter(|trace| !user.is_some_and(|u| trace.user != u && trace.uid.to_string() != u))
+    {
+        let _ = write!(
+            output,
+            "{} {} ({}) ",
+            trace.time.format("%Y-%m-%d %H:%M:%S"),
+            trace.user,
+            trace.uid
+        );
+        let _ = match (&trace.decision, &trace.role, &trace.task) {
+            (Decision::Allow, Some(role), Some(task)) => {
+                writeln!(output, "allowed by role {}, task {}", role, task)
+            }
+            _ => writeln!(
+                output,
+                "denied: {}",
+                trace.reason.as_deref().unwrap_or("no task matched")
+            ),
+        };
+        let _ = writeln!(output, "  command: {}", shell_words::join(&trace.command));
+        if let Some(id) = &trace.id {
+            let _ = writeln!(output, "  id: {}", id);
+        }
+        for step in trace.steps.iter() {
+            let _ = match &step.task {
+                Some(task) => writeln!(output, "  {}/{}: {}", step.role, task, step.result),
+                None => writeln!(output, "  {}: {}", step.role, step.result),
+            };
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(user: &str, decision: Decision) -> Trace {
+        Trace {
+            id: Some("7f".to_string()),
+            time: DateTime::parse_from_rfc3339("2026-10-14T09:30:00+00:00")
+                .unwrap()
+                .with_timezone(&Local),
+            user: user.to_string(),
+            uid: 1000,
+            command: vec!["/usr/bin/ip".to_string(), "link".to_string()],
+            decision,
+            role: decision.is_allow().then(|| "r_net".to_string()),
+            task: decision.is_allow().then(|| "t_ip".to_string()),
+            reason: decision
+                .is_deny()
+                .then(|| "No match because : No roles matched".to_string()),
+            steps: vec![
+                Step {
+                    role: "r_net".to_string(),
+                    task: Some("t_ip".to_string()),
+                    result: "Matched".to_string(),
+                },
+                Step {
+                    role: "r_db".to_string(),
+                    task: None,
+                    result: "The actors do not match".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[cfg(feature = "finder")]
+    #[test]
+    fn test_evaluate() {
+        use crate::database::{
+            actor::SActor,
+            finder::{Cred, TaskMatcher},
+            structs::{SCommands, SConfig, SRole, STask, SetBehavior},
+        };
+
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_net")
+                    .actor(SActor::user(nix::unistd::getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_ip")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/ip link".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(STask::builder("t_other").build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_db")
+                    .actor(SActor::user("rar_nobody").build())
+                    .build(),
+            )
+            .build();
+        let cred = Cred::builder().build();
+        let command = vec!["/usr/bin/ip".to_string(), "link".to_string()];
+        let result = config.matches(&cred, &None, &command).map_err(Into::into);
+        let trace = evaluate(&config, &cred, &None, &command, &result);
+        assert!(trace.decision.is_allow());
+        assert_eq!(trace.task.as_deref(), Some("t_ip"));
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(trace.steps[0].result, "Matched");
+        assert_eq!(trace.steps[1].task.as_deref(), Some("t_other"));
+        assert_eq!(trace.steps[2].result, "The actors do not match");
+        let command = vec!["/usr/bin/id".to_string()];
+        let result = config.matches(&cred, &None, &command).map_err(Into::into);
+        let trace = evaluate(&config, &cred, &None, &command, &result);
+        assert!(trace.decision.is_deny() && trace.reason.is_some());
+    }
+
+    #[cfg(feature = "finder")]
+    #[test]
+    fn test_redact() {
+        use crate::database::{
+            actor::SActor,
+            finder::{Cred, TaskMatcher},
+            options::{Level, Opt, SAudit, SAuditArgs},
+            structs::{SCommands, SConfig, SRole, STask, SetBehavior},
+        };
+
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_deploy")
+                    .actor(SActor::user(nix::unistd::getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_push")
+                            .options(|opt| {
+                                opt.audit(SAudit::builder().args(SAuditArgs::CommandOnly).build())
+                                    .build()
+                            })
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/push .*".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(
+                        STask::builder("t_ip")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/ip link".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let cred = Cred::builder().build();
+        let redacted = |command: &[&str]| {
+            let command: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+            let result = config.matches(&cred, &None, &command).map_err(Into::into);
+            let mut trace = evaluate(&config, &cred, &None, &command, &result);
+            redact(&mut trace, &config, &result);
+            trace.command
+        };
+        assert_eq!(redacted(&["/usr/bin/push", "s3cr3t"]), ["/usr/bin/push"]);
+        assert_eq!(redacted(&["/usr/bin/ip", "link"]), ["/usr/bin/ip", "link"]);
+        assert_eq!(redacted(&["/usr/bin/id", "-u"]), ["/usr/bin/id", "-u"]);
+        config.as_ref().borrow_mut().options = Some(
+            Opt::builder(Level::Global)
+                .audit(SAudit::builder().args(SAuditArgs::CommandOnly).build())
+                .build(),
+        );
+        assert_eq!(redacted(&["/usr/bin/id", "-u"]), ["/usr/bin/id"]);
+    }
+
+    #[test]
+    fn test_ring() {
+        let lines = (0..3).map(|i| i.to_string()).collect();
+        assert_eq!(ring(lines, "3".to_string(), 3), vec!["1", "2", "3"]);
+        assert_eq!(ring(Vec::new(), "0".to_string(), 3), vec!["0"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let traces = [
+            trace("alice", Decision::Allow),
+            trace("bob", Decision::Deny),
+        ];
+        let output = render(&traces, Some("alice"));
+        assert!(output.ends_with(
+            "alice (1000) allowed by role r_net, task t_ip\n  command: /usr/bin/ip link\n  id: 7f\n  r_net/t_ip: Matched\n  r_db: The actors do not match\n"
+        ));
+        assert!(!output.contains("bob"));
+        assert!(render(&traces, None).contains("bob (1000) denied: No match because"));
+        assert_eq!(render(&traces, Some("carol")), "");
+        let line = serde_json::to_string(&traces[1]).unwrap();
+        assert_eq!(serde_json::from_str::<Trace>(&line).unwrap(), traces[1]);
+    }
+}
@@ -186,6 +186,90 @@ fn remove_outer_quotes(input: &str) -> String {
     }
 }
 
+// the expanded values end up in commands, patterns and regexes, they must not change their structure
+fn is_safe_value(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-/@".contains(c))
+}
+
+/// Expand `%u` to the name and `%h` to the home directory of the target user, `%%` to `%`.
+/// Any other `%` is kept as is, e.g. in `date +%Y-%m-%d`.
+pub fn interpolate(template: &str, user: &nix::unistd::User) -> Result<String, String> {
+    if !template.contains('%') {
+        return Ok(template.to_string());
+    }
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let value = match chars.peek() {
+            Some('u') => user.name.clone(),
+            Some('h') => user.dir.to_string_lossy().to_string(),
+            Some('%') => {
+                chars.next();
+                result.push('%');
+                continue;
+            }
+            _ => {
+                result.push('%');
+                continue;
+            }
+        };
+        chars.next();
+        if !is_safe_value(&value) {
+            return Err(format!(
+                "{:?} of user {} cannot be expanded in {:?}",
+                value, user.name, template
+            ));
+        }
+        result.push_str(&value);
+    }
+    Ok(result)
+}
+
+pub fn interpolate_command(
+    command: &SCommand,
+    user: &nix::unistd::User,
+) -> Result<SCommand, String> {
+    Ok(match command {
+        SCommand::Simple(command) => SCommand::Simple(interpolate(command, user)?),
+        SCommand::Complex(serde_json::Value::Array(args)) => SCommand::Complex(
+            args.iter()
+                .map(|arg| match arg {
+                    serde_json::Value::String(arg) => {
+                        interpolate(arg, user).map(serde_json::Value::String)
+                    }
+                    arg => Ok(arg.clone()),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        SCommand::Complex(value) => match commented_command(value) {
+            Some(inner) => {
+                let mut value = value.clone();
+                value["command"] = serde_json::to_value(interpolate_command(&inner, user)?)
+                    .map_err(|e| e.to_string())?;
+                SCommand::Complex(value)
+            }
+            None => command.clone(),
+        },
+    })
+}
+
+/// The command of an entry written `{"command": ..., "comment": "..."}`, which explains a
+/// single grant.
+fn commented_command(command: &serde_json::Value) -> Option<SCommand> {
+    let entry = command.as_object()?;
+    if !entry.contains_key("comment") || entry.keys().any(|k| k != "command" && k != "comment") {
+        return None;
+    }
+    serde_json::from_value(entry.get("command")?.clone()).ok()
+}
+
 pub fn parse_conf_command(command: &SCommand) -> Result<Vec<String>, Box<dyn Error>> {
     match command {
         SCommand::Simple(command) => parse_simple_command(command),
@@ -198,7 +282,9 @@ fn parse_simple_command(command: &str) -> Result<Vec<String>, Box<dyn Error>> {
 }
 
 fn parse_complex_command(command: &serde_json::Value) -> Result<Vec<String>, Box<dyn Error>> {
-    if let Some(array) = command.as_array() {
+    if let Some(command) = commented_command(command) {
+        parse_conf_command(&command)
+    } else if let Some(array) = command.as_array() {
         let result: Result<Vec<String>, _> = array
             .iter()
             .map(|item| {
@@ -254,22 +340,8 @@ pub fn final_path(path: &str) -> PathBuf {
     }
 }
 
-#[cfg(debug_assertions)]
-pub fn subsribe(_: &str) -> Result<(), Box<dyn Error>> {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Debug)
-        .format_module_path(true)
-        .init();
-    Ok(())
-}
-
-#[cfg(not(debug_assertions))]
 pub fn subsribe(tool: &str) -> Result<(), Box<dyn Error>> {
-    use env_logger::Env;
-    use log::LevelFilter;
-    use syslog::{BasicLogger, Facility, Formatter3164};
-    syslog::init(Facility::LOG_AUTH, LevelFilter::Info, Some(tool))?;
-    Ok(())
+    crate::logger::configure(tool, None)
 }
 
 pub fn drop_effective() -> Result<(), capctl::Error> {
@@ -316,15 +388,56 @@ pub fn activates_no_new_privs() -> Result<(), capctl::Error> {
     prctl::set_no_new_privs()
 }
 
+/// Forbid ptrace attachment and core dumps of the current process.
+/// The kernel resets the dumpable flag of the executed program on execve.
+pub fn harden_process() -> Result<(), capctl::Error> {
+    prctl::set_dumpable(false)?;
+    // Revoke any Yama ptracer exception, EINVAL only means Yama is not enabled
+    unsafe { libc::prctl(libc::PR_SET_PTRACER, 0, 0, 0, 0) };
+    Ok(())
+}
+
+/// The file is written next to the target then renamed over it, so a crash never leaves a truncated policy.
 pub fn write_json_config<T: Serialize, S>(settings: &T, path: S) -> Result<(), Box<dyn Error>>
 where
     S: std::convert::AsRef<Path> + Clone,
 {
-    let file = create_with_privileges(path)?;
-    serde_json::to_writer_pretty(file, &settings)?;
+    let path = path.as_ref();
+    let name = path.file_name().ok_or("Invalid configuration path")?;
+    let tmp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let mut file = create_with_privileges(&tmp)?;
+    if let Ok(metadata) = path.metadata() {
+        file.set_permissions(metadata.permissions())?;
+        if let Err(e) = std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()))
+        {
+            debug!("Unable to keep the owner of {}: {}", path.display(), e);
+        }
+    }
+    serde_json::to_writer_pretty(&mut file, &settings)?;
+    file.sync_all()?;
+    rename_with_privileges(&tmp, path)?;
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    open_with_privileges(dir)?.sync_all()?;
     Ok(())
 }
 
+/// Whether the file already holds this value, unknown fields included.
+pub fn json_unchanged<T: Serialize, P: AsRef<Path>>(value: &T, path: P) -> bool {
+    let Ok(file) = open_with_privileges(path) else {
+        return false;
+    };
+    match (
+        serde_json::from_reader::<_, serde_json::Value>(io::BufReader::new(file)),
+        serde_json::to_value(value),
+    ) {
+        (Ok(current), Ok(new)) => current == new,
+        _ => false,
+    }
+}
+
 pub fn create_with_privileges<P: AsRef<Path>>(p: P) -> Result<File, std::io::Error> {
     std::fs::File::create(&p).or_else(|e| {
         debug!(
@@ -343,6 +456,21 @@ pub fn create_with_privileges<P: AsRef<Path>>(p: P) -> Result<File, std::io::Err
     })
 }
 
+pub fn append_with_privileges<P: AsRef<Path>>(p: P) -> Result<File, std::io::Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    options.open(&p).or_else(|e| {
+        debug!(
+            "Error opening file without privilege, trying with privileges: {}",
+            e
+        );
+        dac_override_effective(true)?;
+        let res = options.open(p);
+        dac_override_effective(false)?;
+        res
+    })
+}
+
 pub fn open_with_privileges<P: AsRef<Path>>(p: P) -> Result<File, std::io::Error> {
     std::fs::File::open(&p).or_else(|e| {
         debug!(
@@ -370,6 +498,22 @@ pub fn remove_with_privileges<P: AsRef<Path>>(p: P) -> Result<(), std::io::Error
     })
 }
 
+pub fn rename_with_privileges<P: AsRef<Path>, Q: AsRef<Path>>(
+    from: P,
+    to: Q,
+) -> Result<(), std::io::Error> {
+    std::fs::rename(&from, &to).or_else(|e| {
+        debug!(
+            "Error renaming file without privilege, trying with privileges: {}",
+            e
+        );
+        dac_override_effective(true)?;
+        let res = std::fs::rename(from, to);
+        dac_override_effective(false)?;
+        res
+    })
+}
+
 pub fn create_dir_all_with_privileges<P: AsRef<Path>>(p: P) -> Result<(), std::io::Error> {
     std::fs::create_dir_all(&p).or_else(|e| {
         debug!(
@@ -390,6 +534,84 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_write_json_config() {
+        let path = env::temp_dir().join("rar_write_json_config.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o640)).unwrap();
+        let value = serde_json::json!({ "roles": [], "unknown": { "kept": true } });
+        assert!(!json_unchanged(&value, &path));
+        write_json_config(&value, &path).unwrap();
+        assert!(json_unchanged(&value, &path));
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o640);
+        assert!(!env::temp_dir()
+            .join(".rar_write_json_config.json.tmp")
+            .exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_harden_process() {
+        harden_process().unwrap();
+        assert!(!prctl::get_dumpable().unwrap());
+        prctl::set_dumpable(true).unwrap();
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut root = nix::unistd::User::from_uid(0.into()).unwrap().unwrap();
+        root.dir = PathBuf::from("/root");
+        assert_eq!(
+            interpolate("/usr/bin/chown %u %h/.config", &root).unwrap(),
+            "/usr/bin/chown root /root/.config"
+        );
+        assert_eq!(interpolate("100%%", &root).unwrap(), "100%");
+        assert_eq!(
+            interpolate("/usr/bin/date +%Y-%m-%d", &root).unwrap(),
+            "/usr/bin/date +%Y-%m-%d"
+        );
+        assert_eq!(interpolate("50%", &root).unwrap(), "50%");
+        root.dir = PathBuf::from("/home/a b");
+        assert!(interpolate("%h/.config", &root).is_err());
+        assert_eq!(
+            interpolate_command(&SCommand::Complex(serde_json::json!(["ls", "%u"])), &root)
+                .unwrap(),
+            SCommand::Complex(serde_json::json!(["ls", "root"]))
+        );
+        assert_eq!(
+            interpolate_command(
+                &SCommand::Complex(serde_json::json!({"command": "ls %u", "comment": "own files"})),
+                &root
+            )
+            .unwrap(),
+            SCommand::Complex(serde_json::json!({"command": "ls root", "comment": "own files"}))
+        );
+    }
+
+    #[test]
+    fn test_commented_command() {
+        let command = SCommand::Complex(serde_json::json!({
+            "command": "/usr/bin/systemctl restart nginx",
+            "comment": "until the reload is fixed"
+        }));
+        assert_eq!(
+            parse_conf_command(&command).unwrap(),
+            ["/usr/bin/systemctl", "restart", "nginx"]
+        );
+        let command = SCommand::Complex(serde_json::json!({
+            "command": ["/usr/bin/ls", "-l"],
+            "comment": "listing"
+        }));
+        assert_eq!(parse_conf_command(&command).unwrap(), ["/usr/bin/ls", "-l"]);
+        // other keys are left to the plugins
+        assert!(commented_command(&serde_json::json!({
+            "command": "/usr/bin/ls",
+            "comment": "pinned",
+            "hash": "00"
+        }))
+        .is_none());
+    }
+
     #[test]
     fn test_remove_outer_quotes() {
         assert_eq!(remove_outer_quotes("'test'"), "test");
@@ -474,7 +696,7 @@ mod test {
             .expect("Failed to find CapEff line");
         let effhex = capeff
             .split(':')
-            .last()
+            .next_back()
             .expect("Failed to get effective capabilities")
             .trim();
         let eff = u64::from_str_radix(effhex, 16).expect("Failed to parse effective capabilities");
@@ -0,0 +1,134 @@
+//! Agent keeping the policy in memory to answer `sr --info`.
+//!
+//! It is started once per boot by `rootasrole-agent.service` and is optional: without it, sr
+//! reads and evaluates the policy itself. The policy is read again when the settings file, the
+//! policy file, the activation marker, the include directory or the cache of the remote
+//! includes changes.
+use std::{
+    error::Error,
+    fs::{self, Permissions},
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::{error, info};
+use rar_common::{
+    activation::activated_config, agent::serve, database::read_json_config, integrity::policy_path,
+    logger::configure as configure_logger, plugin::register_plugins, policy::Policy,
+    runtime::RuntimeDirs, StorageMethod,
+};
+
+#[cfg(not(test))]
+const ROOTASROLE: &str = "/etc/security/rootasrole.json";
+#[cfg(test)]
+const ROOTASROLE: &str = "target/rootasrole.json";
+
+// a missing file, like an inactive marker, is a state too
+fn mtimes(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// The loaded policy, read again when one of its files changed.
+struct Cache {
+    files: Vec<PathBuf>,
+    mtimes: Vec<Option<SystemTime>>,
+    policy: Policy,
+}
+
+impl Cache {
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let settings = rar_common::get_settings(ROOTASROLE)?;
+        let mut files = vec![
+            PathBuf::from(ROOTASROLE),
+            policy_path(&settings.as_ref().borrow(), ROOTASROLE),
+        ];
+        if let Some(activation) = &settings.as_ref().borrow().storage.activation {
+            files.push(activation.marker.clone());
+        }
+        // the directory changes when an included file is added, removed or replaced
+        if let Some(include) = &settings.as_ref().borrow().storage.include {
+            files.push(include.dir.clone());
+            files.push(include.cache.clone());
+        }
+        files.dedup();
+        let method = settings.as_ref().borrow().storage.method.clone();
+        let config = match method {
+            StorageMethod::JSON if settings.as_ref().borrow().storage.activation.is_some() => {
+                activated_config(&settings.as_ref().borrow(), ROOTASROLE)?
+            }
+            StorageMethod::JSON => read_json_config(settings, ROOTASROLE)?,
+            _ => return Err("Unsupported storage method".into()),
+        };
+        Ok(Cache {
+            mtimes: mtimes(&files),
+            files,
+            policy: Policy::from_config(config),
+        })
+    }
+
+    fn policy(&mut self) -> Result<Policy, Box<dyn Error>> {
+        if mtimes(&self.files) != self.mtimes {
+            info!("The policy changed, reading it again");
+            *self = Cache::load()?;
+        }
+        Ok(self.policy.clone())
+    }
+}
+
+fn bind(path: &Path) -> Result<UnixListener, Box<dyn Error>> {
+    // a socket left by a previous agent
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // every user may ask, the agent answers for the user of the connection
+    fs::set_permissions(path, Permissions::from_mode(0o666))?;
+    Ok(listener)
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let settings = rar_common::get_settings(ROOTASROLE)?;
+    configure_logger(
+        "rootasrole-agent",
+        settings.as_ref().borrow().storage.log.as_ref(),
+    )?;
+    register_plugins();
+    let mut cache = Cache::load()?;
+    let runtime = RuntimeDirs::default();
+    runtime.init()?;
+    let socket = runtime.agent_socket();
+    let listener = bind(&socket)?;
+    info!("Listening on {}", socket.display());
+    serve(&listener, || cache.policy())?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        error!("{}", e);
+        eprintln!("rootasrole-agent: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mtimes() {
+        let file = std::env::temp_dir().join(format!("rar_agent_{}.json", std::process::id()));
+        let files = [file.clone()];
+        assert_eq!(mtimes(&files), [None]);
+        fs::write(&file, "{}").unwrap();
+        let written = mtimes(&files);
+        assert!(written[0].is_some());
+        assert_eq!(mtimes(&files), written);
+        fs::remove_file(&file).unwrap();
+        assert_ne!(mtimes(&files), written);
+    }
+}
@@ -0,0 +1,159 @@
+//! `chsr audit show <id>`, what was recorded about one execution of sr.
+//!
+//! sr tags its log lines, its audit records and its evaluation trace with the id of the
+//! execution, also given to the command as `RAR_SESSION_ID`. The id, or its beginning, is
+//! searched in the evaluation traces, in the log files of the settings, in the syslog files
+//! and in the journal.
+
+use std::{
+    error::Error,
+    fmt::Write,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use log::debug;
+use rar_common::{
+    logger::{LogDestination, LogSettings},
+    trace::{self, TRACE_FILE},
+};
+
+// where the authpriv facility is written by Debian and by Red Hat
+const SYSLOG_FILES: [&str; 2] = ["/var/log/auth.log", "/var/log/secure"];
+const JOURNALCTL: &str = "/usr/bin/journalctl";
+
+/// The lines of `reader` mentioning `id`.
+fn find<R: BufRead>(reader: R, id: &str) -> Vec<String> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| line.contains(id))
+        .collect()
+}
+
+/// The files sr may have logged to, the log files of the settings first.
+fn log_files(settings: Option<&LogSettings>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = settings
+        .iter()
+        .flat_map(|settings| settings.destinations.iter())
+        .filter_map(|destination| match destination {
+            LogDestination::File(path) => Some(path.clone()),
+            _ => None,
+        })
+        .collect();
+    for path in SYSLOG_FILES.iter().map(PathBuf::from) {
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+fn journal(id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut child = Command::new(JOURNALCTL)
+        .args([
+            "--quiet",
+            "--no-pager",
+            "--output=short-iso",
+            "--identifier=sr",
+        ])
+        .env_clear()
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let lines = find(BufReader::new(child.stdout.take().ok_or("no output")?), id);
+    child.wait()?;
+    Ok(lines)
+}
+
+fn section(output: &mut String, source: &Path, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    let _ = writeln!(output, "{}:", source.display());
+    for line in lines {
+        let _ = writeln!(output, "  {}", line);
+    }
+}
+
+/// The traces and log lines of the execution `id`.
+pub fn show(id: &str, settings: Option<&LogSettings>) -> Result<String, Box<dyn Error>> {
+    let mut output = String::new();
+    let traces: Vec<_> = trace::read(TRACE_FILE)?
+        .into_iter()
+        .filter(|trace| trace.id.as_deref().is_some_and(|t| t.starts_with(id)))
+        .collect();
+    output.push_str(&trace::render(&traces, None));
+    for path in log_files(settings) {
+        match File::open(&path) {
+            Ok(file) => section(&mut output, &path, &find(BufReader::new(file), id)),
+            Err(e) => debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    match journal(id) {
+        Ok(lines) => section(&mut output, Path::new("journal"), &lines),
+        Err(e) => debug!("Skipping the journal: {}", e),
+    }
+    if output.is_empty() {
+        return Err(format!("No record of the execution {}", id).into());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let log = "\
+Oct 15 09:30:00 host sr[42]: id=7f3a user=alice role=r_db task=t_psql command=/usr/bin/psql
+Oct 15 09:30:00 host sr[43]: id=0c1d user=bob role=r_web task=t_nginx command=/usr/sbin/nginx
+Oct 15 09:30:05 host sr[42]: id=7f3a status=0
+";
+        assert_eq!(
+            find(Cursor::new(log), "7f3a"),
+            [
+                "Oct 15 09:30:00 host sr[42]: id=7f3a user=alice role=r_db task=t_psql command=/usr/bin/psql",
+                "Oct 15 09:30:05 host sr[42]: id=7f3a status=0",
+            ]
+        );
+        assert!(find(Cursor::new(log), "ffff").is_empty());
+    }
+
+    #[test]
+    fn test_log_files() {
+        let settings = LogSettings::builder()
+            .destinations([
+                LogDestination::Stderr,
+                LogDestination::File("/var/log/rar.log".into()),
+            ])
+            .build();
+        assert_eq!(
+            log_files(Some(&settings)),
+            [
+                PathBuf::from("/var/log/rar.log"),
+                PathBuf::from("/var/log/auth.log"),
+                PathBuf::from("/var/log/secure"),
+            ]
+        );
+        assert_eq!(log_files(None).len(), 2);
+    }
+
+    #[test]
+    fn test_section() {
+        let mut output = String::new();
+        section(&mut output, Path::new("journal"), &[]);
+        assert!(output.is_empty());
+        section(
+            &mut output,
+            Path::new("journal"),
+            &["id=7f3a status=0".to_string()],
+        );
+        assert_eq!(output, "journal:\n  id=7f3a status=0\n");
+    }
+}
@@ -0,0 +1,203 @@
+// Bulk grant or revoke from a CSV export: `actor,role,expiry`, one grant per row.
+use std::{cell::RefCell, error::Error, io::BufRead, rc::Rc};
+
+use chrono::NaiveDate;
+use rar_common::database::{
+    actor::SActor,
+    structs::{RoleGetter, SConfig},
+};
+
+use super::review::parse_actor;
+
+#[derive(Debug)]
+pub struct Row {
+    line: usize,
+    actor: SActor,
+    role: String,
+    expires: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub applied: usize,
+    pub unchanged: usize,
+    pub errors: Vec<String>,
+}
+
+// fields may be quoted, to hold the commas of a group combination
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+        .iter()
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+fn parse_row(line: usize, fields: &[String]) -> Result<Row, Box<dyn Error>> {
+    let (actor, role, expires) = match fields {
+        [actor, role] => (actor, role, None),
+        [actor, role, expires] if expires.is_empty() => (actor, role, None),
+        [actor, role, expires] => (
+            actor,
+            role,
+            Some(
+                expires
+                    .parse::<NaiveDate>()
+                    .map_err(|e| format!("invalid expiry {}: {}", expires, e))?,
+            ),
+        ),
+        _ => return Err("expected actor,role[,expiry]".into()),
+    };
+    if role.is_empty() {
+        return Err("missing role".into());
+    }
+    Ok(Row {
+        line,
+        actor: parse_actor(actor)?,
+        role: role.clone(),
+        expires,
+    })
+}
+
+/// The rows that could not be parsed are reported with their line number.
+pub fn parse_csv<R: BufRead>(reader: R) -> Result<(Vec<Row>, Vec<String>), Box<dyn Error>> {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields = split_fields(&line);
+        if line.trim().is_empty()
+            || line.trim_start().starts_with('#')
+            || (i == 0 && fields[0].eq_ignore_ascii_case("actor"))
+        {
+            continue;
+        }
+        match parse_row(i + 1, &fields) {
+            Ok(row) => rows.push(row),
+            Err(e) => errors.push(format!("line {}: {}", i + 1, e)),
+        }
+    }
+    Ok((rows, errors))
+}
+
+/// Grant each row with its expiry, or revoke it, a failing row does not stop the others.
+pub fn apply(
+    config: &Rc<RefCell<SConfig>>,
+    rows: Vec<Row>,
+    grant: bool,
+    today: NaiveDate,
+) -> Summary {
+    let mut summary = Summary::default();
+    for Row {
+        line,
+        mut actor,
+        role,
+        expires,
+    } in rows
+    {
+        let Some(role) = config.role(&role) else {
+            summary
+                .errors
+                .push(format!("line {}: role {} not found", line, role));
+            continue;
+        };
+        let mut role = role.as_ref().borrow_mut();
+        let existing = role.actors.iter().position(|a| a.same_actor(&actor));
+        match (grant, existing) {
+            (true, Some(i)) => {
+                if role.actors[i].is_expired(today) || expires.is_some() {
+                    role.actors[i].set_expires(expires);
+                    summary.applied += 1;
+                } else {
+                    summary.unchanged += 1;
+                }
+            }
+            (true, None) => {
+                actor.set_granted(today);
+                actor.set_expires(expires);
+                role.actors.push(actor);
+                summary.applied += 1;
+            }
+            (false, Some(i)) => {
+                role.actors.remove(i);
+                summary.applied += 1;
+            }
+            (false, None) => summary.errors.push(format!(
+                "line {}: {} is not granted role {}",
+                line, actor, role.name
+            )),
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::structs::SRole;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "actor,role,expiry\nuser:alice,r_dev,2026-12-31\n\"group:adm,wheel\",r_ops,\n# offboarded\nuser:bob,r_dev,31/12/2026\nbob,r_dev\nuser:carol\n";
+        let (rows, errors) = parse_csv(csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].expires, Some(date("2026-12-31")));
+        assert!(rows[1]
+            .actor
+            .same_actor(&SActor::group(["wheel", "adm"]).build()));
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("line 5: invalid expiry"));
+        assert!(errors[1].starts_with("line 6: Invalid actor"));
+        assert_eq!(errors[2], "line 7: expected actor,role[,expiry]");
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_dev")
+                    .actor(SActor::user("bob").build())
+                    .build(),
+            )
+            .build();
+        let today = date("2026-10-14");
+        let (rows, _) =
+            parse_csv("user:alice,r_dev,2026-12-31\nuser:bob,r_dev\nuser:dan,r_none\n".as_bytes())
+                .unwrap();
+        let summary = apply(&config, rows, true, today);
+        assert_eq!(summary.applied, 1);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.errors, ["line 3: role r_none not found"]);
+        let role = config.role("r_dev").unwrap();
+        assert_eq!(role.as_ref().borrow().actors.len(), 2);
+        assert!(!role.as_ref().borrow().actors[1].is_expired(today));
+        assert!(role.as_ref().borrow().actors[1].is_expired(date("2027-01-01")));
+
+        let (rows, _) = parse_csv("user:alice,r_dev\nuser:carol,r_dev\n".as_bytes()).unwrap();
+        let summary = apply(&config, rows, false, today);
+        assert_eq!(summary.applied, 1);
+        assert_eq!(
+            summary.errors,
+            ["line 2: User: carol is not granted role r_dev"]
+        );
+        assert_eq!(role.as_ref().borrow().actors.len(), 1);
+    }
+}
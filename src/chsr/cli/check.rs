@@ -0,0 +1,466 @@
+// `chsr check`: lint the whole policy before deploying it.
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt::{self, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use rar_common::{
+    database::{
+        actor::{SActor, SGroups},
+        finder::is_group_pattern,
+        options::Opt,
+        structs::{IdTask, SCommand, SConfig, SGroupschooser, STask, SUserChooser, SetBehavior},
+    },
+    plugin::check_hierarchy,
+    util::parse_conf_command,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use strum::EnumIs;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, EnumIs)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn push(
+        &mut self,
+        severity: Severity,
+        role: Option<&str>,
+        task: Option<&str>,
+        message: String,
+    ) {
+        self.0.push(Finding {
+            severity,
+            role: role.map(str::to_string),
+            task: task.map(str::to_string),
+            message,
+        });
+    }
+}
+
+fn check_groups(
+    groups: &SGroups,
+    what: &str,
+    role: &str,
+    task: Option<&str>,
+    findings: &mut Findings,
+) {
+    let entries = match groups {
+        SGroups::Single(group) => std::slice::from_ref(group),
+        SGroups::Multiple(groups) => groups.as_slice(),
+    };
+    for group in entries {
+        // patterns are resolved at execution time and may match nothing yet
+        if group.name().is_some_and(is_group_pattern) {
+            continue;
+        }
+        if group.fetch_group().is_none() {
+            findings.push(
+                Severity::Error,
+                Some(role),
+                task,
+                format!("Unknown group {} in {}", group, what),
+            );
+        }
+    }
+}
+
+// commands that may not be a plain path: patterns, regexes and placeholders
+fn is_plain_path(path: &str) -> bool {
+    path.starts_with('/') && !path.contains(['*', '?', '[', '(', '|', '^', '$', '\\', '%'])
+}
+
+fn check_commands(task: &STask, role: &str, findings: &mut Findings) {
+    let name = task.name.to_string();
+    let commands = &task.commands;
+    if commands.default_behavior != Some(SetBehavior::All)
+        && commands.add.is_empty()
+        && commands.file.is_none()
+    {
+        findings.push(
+            Severity::Warning,
+            Some(role),
+            Some(&name),
+            "The task allows no command".to_string(),
+        );
+    }
+    if let Some(file) = &commands.file {
+        if !file.exists() {
+            findings.push(
+                Severity::Warning,
+                Some(role),
+                Some(&name),
+                format!("The commands file {} does not exist", file.display()),
+            );
+        }
+    }
+    for command in commands.add.iter().chain(commands.sub.iter()) {
+        match parse_conf_command(command) {
+            Ok(args) => match args.first() {
+                Some(path) if is_plain_path(path) && !Path::new(path).exists() => findings.push(
+                    Severity::Warning,
+                    Some(role),
+                    Some(&name),
+                    format!("The command {} does not exist", path),
+                ),
+                Some(_) => {}
+                None => findings.push(
+                    Severity::Error,
+                    Some(role),
+                    Some(&name),
+                    "Empty command".to_string(),
+                ),
+            },
+            Err(e) => findings.push(
+                Severity::Error,
+                Some(role),
+                Some(&name),
+                format!(
+                    "Invalid command {}: {}",
+                    match command {
+                        SCommand::Simple(command) => command.clone(),
+                        SCommand::Complex(command) => command.to_string(),
+                    },
+                    e
+                ),
+            ),
+        }
+    }
+}
+
+fn check_credentials(task: &STask, role: &str, findings: &mut Findings) {
+    let name = task.name.to_string();
+    let task_name = Some(name.as_str());
+    let mut unknown_user = |user: &rar_common::database::actor::SUserType| {
+        if user.fetch_user().is_none() {
+            findings.push(
+                Severity::Error,
+                Some(role),
+                task_name,
+                format!("Unknown user {} in setuid", user),
+            );
+        }
+    };
+    match &task.cred.setuid {
+        Some(SUserChooser::Actor(user)) => unknown_user(user),
+        Some(SUserChooser::ChooserStruct(chooser)) => {
+            std::iter::once(&chooser.fallback)
+                .chain(chooser.add.iter())
+                .chain(chooser.sub.iter())
+                .for_each(&mut unknown_user);
+            if chooser.sub.contains(&chooser.fallback) {
+                findings.push(
+                    Severity::Error,
+                    Some(role),
+                    task_name,
+                    format!("The setuid fallback {} is also denied", chooser.fallback),
+                );
+            }
+            for user in chooser.add.iter().filter(|user| chooser.sub.contains(user)) {
+                findings.push(
+                    Severity::Error,
+                    Some(role),
+                    task_name,
+                    format!("The setuid user {} is both allowed and denied", user),
+                );
+            }
+        }
+        None => {}
+    }
+    match &task.cred.setgid {
+        Some(SGroupschooser::Group(groups)) => {
+            check_groups(groups, "setgid", role, task_name, findings)
+        }
+        Some(SGroupschooser::StructChooser(chooser)) => {
+            for groups in std::iter::once(&chooser.fallback)
+                .chain(chooser.add.iter())
+                .chain(chooser.sub.iter())
+            {
+                check_groups(groups, "setgid", role, task_name, findings);
+            }
+            if chooser.sub.contains(&chooser.fallback) {
+                findings.push(
+                    Severity::Error,
+                    Some(role),
+                    task_name,
+                    format!("The setgid fallback {} is also denied", chooser.fallback),
+                );
+            }
+            for groups in chooser
+                .add
+                .iter()
+                .filter(|groups| chooser.sub.contains(groups))
+            {
+                findings.push(
+                    Severity::Error,
+                    Some(role),
+                    task_name,
+                    format!("The setgid groups {} are both allowed and denied", groups),
+                );
+            }
+        }
+        None => {}
+    }
+    if let Some(caps) = &task.cred.capabilities {
+        for cap in caps.add.iter().filter(|cap| !cap.is_supported()) {
+            findings.push(
+                Severity::Error,
+                Some(role),
+                task_name,
+                format!("The kernel does not support {}", cap),
+            );
+        }
+    }
+}
+
+/// Everything that looks wrong in the policy, in the order of the roles and tasks.
+// the matching strategy is a choice of the whole policy
+fn check_global_only(
+    options: &Option<Rc<RefCell<Opt>>>,
+    role: &str,
+    task: Option<&str>,
+    findings: &mut Findings,
+) {
+    if options
+        .as_ref()
+        .is_some_and(|opt| opt.as_ref().borrow().matching_strategy.is_some())
+    {
+        findings.push(
+            Severity::Warning,
+            Some(role),
+            task,
+            "matching-strategy is only read in the global options".to_string(),
+        );
+    }
+}
+
+pub fn check(config: &Rc<RefCell<SConfig>>) -> Vec<Finding> {
+    let mut findings = Findings::default();
+    if let Err(e) = check_hierarchy(config) {
+        findings.push(Severity::Error, None, None, e.to_string());
+    }
+    let mut roles = HashSet::new();
+    for role in config.as_ref().borrow().roles.iter() {
+        let role = role.as_ref().borrow();
+        let name = role.name.as_str();
+        if !roles.insert(name.to_string()) {
+            findings.push(
+                Severity::Error,
+                Some(name),
+                None,
+                "Duplicate role name".to_string(),
+            );
+        }
+        for actor in role.actors.iter() {
+            match actor {
+                SActor::User { id: Some(user), .. } if user.fetch_user().is_none() => findings
+                    .push(
+                        Severity::Error,
+                        Some(name),
+                        None,
+                        format!("Unknown user {}", user),
+                    ),
+                SActor::Group {
+                    groups: Some(groups),
+                    ..
+                } => check_groups(groups, "actors", name, None, &mut findings),
+                SActor::Unknown(actor) => findings.push(
+                    Severity::Warning,
+                    Some(name),
+                    None,
+                    format!("Unknown actor {}", actor),
+                ),
+                _ => {}
+            }
+        }
+        check_global_only(&role.options, name, None, &mut findings);
+        let mut tasks = HashSet::new();
+        for task in role.tasks.iter() {
+            let task = task.as_ref().borrow();
+            if let IdTask::Name(task_name) = &task.name {
+                if !tasks.insert(task_name.clone()) {
+                    findings.push(
+                        Severity::Error,
+                        Some(name),
+                        Some(task_name),
+                        "Duplicate task name".to_string(),
+                    );
+                }
+            }
+            check_commands(&task, name, &mut findings);
+            check_credentials(&task, name, &mut findings);
+            check_global_only(
+                &task.options,
+                name,
+                Some(&task.name.to_string()),
+                &mut findings,
+            );
+        }
+    }
+    findings.0
+}
+
+pub fn errors(findings: &[Finding]) -> usize {
+    findings
+        .iter()
+        .filter(|finding| finding.severity.is_error())
+        .count()
+}
+
+pub fn document(findings: &[Finding]) -> Value {
+    json!({
+        "errors": errors(findings),
+        "warnings": findings.len() - errors(findings),
+        "findings": findings,
+    })
+}
+
+pub fn to_text(findings: &[Finding]) -> String {
+    let mut output = String::new();
+    for finding in findings {
+        let _ = write!(output, "{}: ", finding.severity);
+        if let Some(role) = &finding.role {
+            let _ = write!(output, "role {}", role);
+            if let Some(task) = &finding.task {
+                let _ = write!(output, ", task {}", task);
+            }
+            output.push_str(": ");
+        }
+        let _ = writeln!(output, "{}", finding.message);
+    }
+    let _ = write!(
+        output,
+        "{} errors, {} warnings",
+        errors(findings),
+        findings.len() - errors(findings)
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use rar_common::database::{
+        actor::SActor,
+        options::SMatchingStrategy,
+        structs::{SCapabilities, SCommands, SCredentials, SRole, SSetuidSet},
+    };
+
+    use super::*;
+
+    fn messages(findings: &[Finding]) -> Vec<(Severity, &str)> {
+        findings
+            .iter()
+            .map(|finding| (finding.severity, finding.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r1")
+                    .actor(SActor::user("root").build())
+                    .actor(SActor::user("rar_nobody_here").build())
+                    .task(
+                        STask::builder("t1")
+                            .cred(
+                                SCredentials::builder()
+                                    .setuid(
+                                        SSetuidSet::builder("root", SetBehavior::None)
+                                            .add(["root".into()])
+                                            .sub(["root".into()])
+                                            .build(),
+                                    )
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::NET_RAW)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add(["/usr/bin/rar_missing".into(), "/usr/bin/l* -a".into()])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(STask::builder("t1").build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r1")
+                    .options(|opt| opt.matching_strategy(SMatchingStrategy::First).build())
+                    .build(),
+            )
+            .build();
+        let findings = check(&config);
+        assert_eq!(
+            messages(&findings),
+            vec![
+                (Severity::Error, "Unknown user rar_nobody_here"),
+                (
+                    Severity::Warning,
+                    "The command /usr/bin/rar_missing does not exist"
+                ),
+                (Severity::Error, "The setuid fallback root is also denied"),
+                (
+                    Severity::Error,
+                    "The setuid user root is both allowed and denied"
+                ),
+                (Severity::Error, "Duplicate task name"),
+                (Severity::Warning, "The task allows no command"),
+                (Severity::Error, "Duplicate role name"),
+                (
+                    Severity::Warning,
+                    "matching-strategy is only read in the global options"
+                ),
+            ]
+        );
+        assert_eq!(errors(&findings), 5);
+        assert_eq!(document(&findings)["warnings"], 3);
+        let text = to_text(&findings);
+        assert!(text.starts_with("error: role r1: Unknown user rar_nobody_here\n"));
+        assert!(text.contains("warning: role r1, task t1: The task allows no command\n"));
+        assert!(text.ends_with("5 errors, 3 warnings"));
+    }
+
+    #[test]
+    fn test_is_plain_path() {
+        assert!(is_plain_path("/usr/bin/ls"));
+        assert!(!is_plain_path("ls"));
+        assert!(!is_plain_path("/usr/bin/l*"));
+        assert!(!is_plain_path("^/usr/bin/.*$"));
+        assert!(!is_plain_path("%h/bin/tool"));
+    }
+}
@@ -9,7 +9,7 @@ use rar_common::database::{
     actor::{SActor, SGroups, SUserType},
     options::{
         EnvBehavior, EnvKey, OptType, PathBehavior, SAuthentication, SBounding, SPrivileged,
-        TimestampType,
+        SWildcardPreset, TimestampType,
     },
     structs::{IdTask, SetBehavior},
 };
@@ -32,6 +32,13 @@ pub enum TaskType {
     Credentials,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    Json,
+    Yaml,
+    Text,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum InputAction {
     Help,
@@ -69,12 +76,19 @@ pub struct Inputs {
     pub timeout_max_usage: Option<u64>,
     pub role_id: Option<String>,
     pub role_type: Option<RoleType>,
+    pub role_template: Option<String>,
+    pub role_file: Option<String>,
     pub actors: Option<Vec<SActor>>,
     pub task_id: Option<IdTask>,
     pub task_type: Option<TaskType>,
     pub cmd_policy: Option<SetBehavior>,
     pub cmd_id: Option<Vec<String>>,
+    // pin the SHA256 digest of the added command
+    pub cmd_digest: bool,
+    pub cmd_digest_update: bool,
     pub cred_caps: Option<CapSet>,
+    // the @name sets given with the capabilities, expanded with the sets of the policy
+    pub cred_cap_sets: Vec<String>,
     pub cred_setuid: Option<SUserType>,
     pub cred_setgid: Option<SGroups>,
     pub cred_policy: Option<SetBehavior>,
@@ -88,7 +102,33 @@ pub struct Inputs {
     pub options_root: Option<SPrivileged>,
     pub options_bounding: Option<SBounding>,
     pub options_wildcard: Option<String>,
+    pub options_wildcard_preset: Option<Vec<SWildcardPreset>>,
     pub options_auth: Option<SAuthentication>,
+    pub import_sudo_logs: Option<String>,
+    pub import_sudoers: Option<String>,
+    pub progress: bool,
+    pub enabled: Option<bool>,
+    pub verbose: bool,
+    pub list_format: Option<ListFormat>,
+    pub mine: bool,
+    pub whoami: bool,
+    pub init_admin: Option<String>,
+    pub check: bool,
+    pub report: bool,
+    // the proposed policy, after the current one when two files are compared
+    pub diff_paths: Vec<String>,
+    pub tui: bool,
+    pub debug_last: bool,
+    pub debug_user: Option<String>,
+    pub export_user: Option<String>,
+    pub activate: bool,
+    pub audit_gc: bool,
+    pub audit_show: Option<String>,
+    pub remote_refresh: bool,
+    pub batch_csv: Option<String>,
+    pub review: bool,
+    pub review_days: Option<u64>,
+    pub review_decisions: Option<String>,
 }
 
 impl Default for Inputs {
@@ -102,12 +142,17 @@ impl Default for Inputs {
             timeout_max_usage: None,
             role_id: None,
             role_type: None,
+            role_template: None,
+            role_file: None,
             actors: None,
             task_id: None,
             task_type: None,
             cmd_policy: None,
             cmd_id: None,
+            cmd_digest: false,
+            cmd_digest_update: false,
             cred_caps: None,
+            cred_cap_sets: Vec::new(),
             cred_setuid: None,
             cred_setgid: None,
             cred_policy: None,
@@ -121,7 +166,32 @@ impl Default for Inputs {
             options_root: None,
             options_bounding: None,
             options_wildcard: None,
+            options_wildcard_preset: None,
             options_auth: None,
+            import_sudo_logs: None,
+            import_sudoers: None,
+            progress: false,
+            enabled: None,
+            verbose: false,
+            list_format: None,
+            mine: false,
+            whoami: false,
+            init_admin: None,
+            check: false,
+            report: false,
+            diff_paths: Vec::new(),
+            tui: false,
+            debug_last: false,
+            debug_user: None,
+            export_user: None,
+            activate: false,
+            audit_gc: false,
+            audit_show: None,
+            remote_refresh: false,
+            batch_csv: None,
+            review: false,
+            review_days: None,
+            review_decisions: None,
         }
     }
 }
@@ -0,0 +1,337 @@
+// `chsr diff`: review the changes between two policies before applying one.
+use std::{cell::RefCell, error::Error, fmt::Write, path::Path, rc::Rc};
+
+use capctl::CapSet;
+use rar_common::{
+    database::{
+        capsets,
+        options::Opt,
+        structs::{RoleGetter, SCommand, SConfig, SRole, STask},
+    },
+    util::open_with_privileges,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// `role`, `actor`, `task`, `command`, `denied-command`, `capability`, `setuid`, `setgid`
+    /// or `option`
+    pub item: &'static str,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+#[derive(Default)]
+struct Changes {
+    changes: Vec<Change>,
+    role: Option<String>,
+    task: Option<String>,
+}
+
+impl Changes {
+    fn push(&mut self, kind: ChangeKind, item: &'static str, name: String) {
+        self.changes.push(Change {
+            kind,
+            role: self.role.clone(),
+            task: self.task.clone(),
+            item,
+            name,
+            before: None,
+            after: None,
+        });
+    }
+
+    fn changed(&mut self, item: &'static str, name: String, before: Value, after: Value) {
+        if before != after {
+            self.changes.push(Change {
+                kind: ChangeKind::Changed,
+                role: self.role.clone(),
+                task: self.task.clone(),
+                item,
+                name,
+                before: Some(before),
+                after: Some(after),
+            });
+        }
+    }
+
+    /// The items only in `old` are removed, the ones only in `new` are added.
+    fn compare_lists<T>(
+        &mut self,
+        item: &'static str,
+        old: &[T],
+        new: &[T],
+        same: impl Fn(&T, &T) -> bool,
+        name: impl Fn(&T) -> String,
+    ) {
+        for removed in old.iter().filter(|o| !new.iter().any(|n| same(o, n))) {
+            self.push(ChangeKind::Removed, item, name(removed));
+        }
+        for added in new.iter().filter(|n| !old.iter().any(|o| same(o, n))) {
+            self.push(ChangeKind::Added, item, name(added));
+        }
+    }
+
+    fn compare_options(&mut self, old: &Option<Rc<RefCell<Opt>>>, new: &Option<Rc<RefCell<Opt>>>) {
+        let fields = |opt: &Option<Rc<RefCell<Opt>>>| match serde_json::to_value(opt) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Default::default(),
+        };
+        let (old, new) = (fields(old), fields(new));
+        for (key, before) in old.iter() {
+            match new.get(key) {
+                Some(after) => self.changed("option", key.clone(), before.clone(), after.clone()),
+                None => self.push(ChangeKind::Removed, "option", key.clone()),
+            }
+        }
+        for key in new.keys().filter(|key| !old.contains_key(*key)) {
+            self.push(ChangeKind::Added, "option", key.clone());
+        }
+    }
+
+    fn compare_tasks(&mut self, old: &STask, new: &STask) {
+        self.task = Some(new.name.to_string());
+        let command = |command: &SCommand| match command {
+            SCommand::Simple(command) => command.clone(),
+            SCommand::Complex(command) => command.to_string(),
+        };
+        self.compare_lists(
+            "command",
+            &old.commands.add,
+            &new.commands.add,
+            PartialEq::eq,
+            command,
+        );
+        self.compare_lists(
+            "denied-command",
+            &old.commands.sub,
+            &new.commands.sub,
+            PartialEq::eq,
+            command,
+        );
+        self.changed(
+            "command",
+            "default".to_string(),
+            json!(old.commands.default_behavior),
+            json!(new.commands.default_behavior),
+        );
+        let caps = |task: &STask| {
+            task.cred
+                .capabilities
+                .as_ref()
+                .map_or(CapSet::empty(), |caps| caps.to_capset())
+        };
+        let (old_caps, new_caps) = (caps(old), caps(new));
+        for cap in (old_caps & !new_caps).iter() {
+            self.push(ChangeKind::Removed, "capability", cap.to_string());
+        }
+        for cap in (new_caps & !old_caps).iter() {
+            self.push(ChangeKind::Added, "capability", cap.to_string());
+        }
+        self.changed(
+            "setuid",
+            "setuid".to_string(),
+            json!(old.cred.setuid),
+            json!(new.cred.setuid),
+        );
+        self.changed(
+            "setgid",
+            "setgid".to_string(),
+            json!(old.cred.setgid),
+            json!(new.cred.setgid),
+        );
+        self.compare_options(&old.options, &new.options);
+        self.task = None;
+    }
+
+    fn compare_roles(&mut self, old: &SRole, new: &SRole) {
+        self.role = Some(new.name.clone());
+        self.compare_lists(
+            "actor",
+            &old.actors,
+            &new.actors,
+            |old, new| old.same_actor(new),
+            ToString::to_string,
+        );
+        let tasks = |role: &SRole| {
+            role.tasks
+                .iter()
+                .map(|task| task.as_ref().borrow().name.to_string())
+                .collect::<Vec<_>>()
+        };
+        self.compare_lists(
+            "task",
+            &tasks(old),
+            &tasks(new),
+            PartialEq::eq,
+            Clone::clone,
+        );
+        for task in new.tasks.iter() {
+            let task = task.as_ref().borrow();
+            if let Some(previous) = old
+                .tasks
+                .iter()
+                .find(|previous| previous.as_ref().borrow().name == task.name)
+            {
+                self.compare_tasks(&previous.as_ref().borrow(), &task);
+            }
+        }
+        self.compare_options(&old.options, &new.options);
+        self.role = None;
+    }
+}
+
+/// The changes from the policy `old` to the policy `new`.
+pub fn diff(old: &Rc<RefCell<SConfig>>, new: &Rc<RefCell<SConfig>>) -> Vec<Change> {
+    let mut changes = Changes::default();
+    let roles = |config: &SConfig| {
+        config
+            .roles
+            .iter()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .collect::<Vec<_>>()
+    };
+    changes.compare_lists(
+        "role",
+        &roles(&old.as_ref().borrow()),
+        &roles(&new.as_ref().borrow()),
+        PartialEq::eq,
+        Clone::clone,
+    );
+    for role in new.as_ref().borrow().roles.iter() {
+        let role = role.as_ref().borrow();
+        if let Some(previous) = old.role(&role.name) {
+            changes.compare_roles(&previous.as_ref().borrow(), &role);
+        }
+    }
+    changes.compare_options(
+        &old.as_ref().borrow().options,
+        &new.as_ref().borrow().options,
+    );
+    changes.changes
+}
+
+/// Read a policy file, with its capability sets expanded. Its included files are not read.
+pub fn read_policy<P: AsRef<Path>>(path: P) -> Result<Rc<RefCell<SConfig>>, Box<dyn Error>> {
+    let config: Rc<RefCell<SConfig>> = serde_json::from_reader(
+        open_with_privileges(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.as_ref().display(), e))?,
+    )
+    .map_err(|e| format!("Invalid policy {}: {}", path.as_ref().display(), e))?;
+    capsets::resolve(&config)?;
+    Ok(config)
+}
+
+pub fn document(changes: &[Change]) -> Value {
+    json!({
+        "changes": changes.len(),
+        "diff": changes,
+    })
+}
+
+pub fn to_text(changes: &[Change]) -> String {
+    let mut output = String::new();
+    for change in changes {
+        if let Some(role) = &change.role {
+            let _ = write!(output, "role {}", role);
+            if let Some(task) = &change.task {
+                let _ = write!(output, ", task {}", task);
+            }
+            output.push_str(": ");
+        }
+        let sign = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        let _ = write!(output, "{} {} {}", sign, change.item, change.name);
+        if let (Some(before), Some(after)) = (&change.before, &change.after) {
+            let _ = write!(output, ": {} -> {}", before, after);
+        }
+        output.push('\n');
+    }
+    match changes.len() {
+        0 => output.push_str("No changes"),
+        n => {
+            let _ = write!(output, "{} changes", n);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(json: &str) -> Rc<RefCell<SConfig>> {
+        let config: Rc<RefCell<SConfig>> = serde_json::from_str(json).unwrap();
+        capsets::resolve(&config).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = policy(
+            r#"{
+                "options": { "timeout": { "type": "ppid", "duration": "00:05:00" } },
+                "roles": [
+                    { "name": "r_old" },
+                    { "name": "r_net", "actors": [{ "type": "user", "name": "alice" }, { "type": "group", "groups": "adm" }],
+                      "tasks": [{ "name": "t_ip", "cred": { "capabilities": ["cap_net_admin", "cap_net_raw"] },
+                                  "commands": { "default": "none", "add": ["/usr/bin/ip"] } },
+                                { "name": "t_old" }] }
+                ]
+            }"#,
+        );
+        let new = policy(
+            r#"{
+                "options": { "timeout": { "type": "ppid", "duration": "00:10:00" } },
+                "roles": [
+                    { "name": "r_net", "actors": [{ "type": "group", "groups": "adm" }, { "type": "user", "name": "bob" }],
+                      "tasks": [{ "name": "t_ip", "cred": { "capabilities": ["@network"] },
+                                  "commands": { "default": "none", "add": ["/usr/bin/ip", "/usr/bin/ss"], "del": ["/usr/bin/ip xfrm"] },
+                                  "options": { "env": { "default": "delete" } } }] },
+                    { "name": "r_new" }
+                ]
+            }"#,
+        );
+        let changes = diff(&old, &new);
+        assert_eq!(
+            to_text(&changes),
+            r#"- role r_old
++ role r_new
+role r_net: - actor User: alice
+role r_net: + actor User: bob
+role r_net: - task t_old
+role r_net, task t_ip: + command /usr/bin/ss
+role r_net, task t_ip: + denied-command /usr/bin/ip xfrm
+role r_net, task t_ip: + capability CAP_NET_BIND_SERVICE
+role r_net, task t_ip: + capability CAP_NET_BROADCAST
+role r_net, task t_ip: + option env
+~ option timeout: {"duration":"00:05:00","type":"ppid"} -> {"duration":"00:10:00","type":"ppid"}
+11 changes"#
+        );
+        assert_eq!(
+            document(&changes)["diff"][0],
+            json!({ "kind": "removed", "item": "role", "name": "r_old" })
+        );
+        assert_eq!(to_text(&diff(&new, &new)), "No changes");
+    }
+}
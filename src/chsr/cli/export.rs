@@ -0,0 +1,132 @@
+// Policy pruned to what one user can do, to hand to an auditor or to a user-facing agent.
+use std::{cell::RefCell, error::Error, ffi::CString, rc::Rc};
+
+use nix::unistd::{getgrouplist, User};
+use rar_common::database::{
+    finder::{Cred, CredMatcher},
+    options::OptStack,
+    structs::{SConfig, SRole, STask},
+};
+use serde_json::{json, Value};
+
+/// The credentials of the user with its groups, as sr would see them.
+pub fn cred_of(name: &str) -> Result<Cred, Box<dyn Error>> {
+    let user = User::from_name(name)?.ok_or_else(|| format!("Unknown user {}", name))?;
+    let groups = getgrouplist(&CString::new(name)?, user.gid)?;
+    Ok(Cred::builder().user_id(user.uid).groups(groups).build())
+}
+
+fn actor_matches(role: &SRole, index: usize, cred: &Cred) -> bool {
+    let alone = SRole::builder(role.name.clone())
+        .actor(role.actors[index].clone())
+        .build();
+    !alone.user_matches(cred).is_no_match()
+}
+
+// the options of the global and role levels are resolved into the task
+fn task_export(task: &Rc<RefCell<STask>>) -> Result<Value, Box<dyn Error>> {
+    let options = serde_json::to_value(&*OptStack::from_task(task.clone()).to_opt())?;
+    let mut document = serde_json::to_value(&*task.as_ref().borrow())?;
+    document["options"] = options;
+    Ok(document)
+}
+
+fn role_export(role: &Rc<RefCell<SRole>>, cred: &Cred) -> Result<Value, Box<dyn Error>> {
+    let role = role.as_ref().borrow();
+    let mut document = serde_json::to_value(&*role)?;
+    let map = document.as_object_mut().ok_or("Invalid role")?;
+    map.remove("options");
+    // the other actors of the role are not disclosed
+    map.insert(
+        "actors".to_string(),
+        json!((0..role.actors.len())
+            .filter(|index| actor_matches(&role, *index, cred))
+            .map(|index| &role.actors[index])
+            .collect::<Vec<_>>()),
+    );
+    map.insert(
+        "tasks".to_string(),
+        Value::Array(
+            role.tasks
+                .iter()
+                .filter(|task| task.as_ref().borrow().is_enabled())
+                .map(task_export)
+                .collect::<Result<_, _>>()?,
+        ),
+    );
+    Ok(document)
+}
+
+/// A standalone policy with the enabled roles of the user, its own grants and the effective
+/// options of each task.
+pub fn export(config: &Rc<RefCell<SConfig>>, cred: &Cred) -> Result<Value, Box<dyn Error>> {
+    let roles = config
+        .as_ref()
+        .borrow()
+        .roles
+        .iter()
+        .filter(|role| {
+            role.as_ref().borrow().is_enabled() && !role.user_matches(cred).is_no_match()
+        })
+        .map(|role| role_export(role, cred))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({ "roles": roles }))
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        make_weak_config,
+        options::{Level, SPrivileged},
+        structs::{SCommand, SCommands, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_mine")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .actor(SActor::user("nobody").build())
+                    .task(
+                        STask::builder("t_ls")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(STask::builder("t_off").enabled(false).build())
+                    .options(|opt| opt.root(SPrivileged::Privileged).build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_other")
+                    .actor(SActor::group("rar_export_test").build())
+                    .build(),
+            )
+            .build();
+        let document = export(&config, &Cred::builder().build()).unwrap();
+        let roles = document["roles"].as_array().unwrap();
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0]["name"], "r_mine");
+        assert_eq!(roles[0]["actors"].as_array().unwrap().len(), 1);
+        assert!(roles[0].get("options").is_none());
+        let tasks = roles[0]["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0]["options"]["root"], "privileged");
+        // the export is itself a policy
+        let exported: Rc<RefCell<SConfig>> = serde_json::from_value(document).unwrap();
+        make_weak_config(&exported);
+        let task = exported.as_ref().borrow().roles[0].as_ref().borrow().tasks[0].clone();
+        assert_eq!(
+            OptStack::from_task(task).get_root_behavior(),
+            (Level::Task, SPrivileged::Privileged)
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// `chsr init`: the first policy of an installation, with the default options and an admin role.
+use std::{cell::RefCell, error::Error, rc::Rc};
+
+use nix::unistd::{Uid, User};
+use rar_common::{
+    database::{make_weak_config, structs::SConfig, versionning::Versioning},
+    SettingsFile,
+};
+
+// the policy deployed by the installer, whose admin is ROOTADMINISTRATOR
+const DEFAULT_POLICY: &str = include_str!("../../../resources/rootasrole.json");
+
+fn admin_name(admin: &str) -> Result<String, Box<dyn Error>> {
+    let user = match admin.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid))?,
+        Err(_) => User::from_name(admin)?,
+    };
+    Ok(user.ok_or_else(|| format!("Unknown user {}", admin))?.name)
+}
+
+/// Fill an empty policy with the default options and the r_root role granted to `admin`.
+pub fn init(config: &Rc<RefCell<SConfig>>, admin: &str) -> Result<(), Box<dyn Error>> {
+    if !config.as_ref().borrow().roles.is_empty() {
+        return Err(
+            "The policy already has roles, chsr init only configures a new installation".into(),
+        );
+    }
+    let default: Versioning<SettingsFile> = serde_json::from_str(&DEFAULT_POLICY.replace(
+        "\"ROOTADMINISTRATOR\"",
+        &serde_json::to_string(&admin_name(admin)?)?,
+    ))?;
+    let default = default.data.config.as_ref().borrow();
+    {
+        let mut config = config.as_ref().borrow_mut();
+        config.options = default.options.clone();
+        config.roles = default.roles.clone();
+    }
+    make_weak_config(config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::{actor::SActor, structs::RoleGetter};
+
+    use super::*;
+
+    #[test]
+    fn test_init() {
+        let config = SConfig::builder().build();
+        assert!(init(&config, "unknown_admin_user").is_err());
+        init(&config, "0").unwrap();
+        let role = config.role("r_root").unwrap();
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .all(|actor| actor.same_actor(&SActor::user("root").build())));
+        assert_eq!(role.as_ref().borrow().tasks.len(), 2);
+        assert!(config.as_ref().borrow().options.is_some());
+        assert!(init(&config, "root").is_err());
+    }
+}
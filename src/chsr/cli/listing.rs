@@ -0,0 +1,417 @@
+// Listing of the policy for scripts, with the effective options of each role and task.
+use std::{cell::RefCell, error::Error, fmt::Write, rc::Rc};
+
+use capctl::CapSet;
+use rar_common::database::{
+    actor::{SGroupType, SUserType},
+    impact::impact,
+    options::OptStack,
+    structs::{IdTask, SConfig, SRole, STask},
+};
+use serde_json::{json, Map, Value};
+
+use super::data::ListFormat;
+
+/// Remove the comments of a policy, or of a part of it, down to the single commands.
+pub fn strip_comments(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(strip_comments),
+        Value::Object(map) => {
+            map.remove("comment");
+            for key in ["roles", "tasks", "commands", "add", "sub"] {
+                if let Some(child) = map.get_mut(key) {
+                    strip_comments(child);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn task_document(task: &Rc<RefCell<STask>>, verbose: bool) -> Result<Value, Box<dyn Error>> {
+    let options = serde_json::to_value(&*OptStack::from_task(task.clone()).to_opt())?;
+    let task = task.as_ref().borrow();
+    let capset = task
+        .cred
+        .capabilities
+        .as_ref()
+        .map_or(CapSet::empty(), |caps| caps.to_capset());
+    let capabilities: Vec<String> = capset.iter().map(|cap| cap.to_string()).collect();
+    let mut document = json!({
+        "name": task.name.to_string(),
+        "enabled": task.is_enabled(),
+        "commands": {
+            "default": task.commands.default_behavior,
+            "add": task.commands.add,
+            "sub": task.commands.sub,
+        },
+        "capabilities": capabilities,
+        "impact": impact(&capset),
+        "setuid": task.cred.setuid,
+        "setgid": task.cred.setgid,
+        "options": options,
+    });
+    if let Some(purpose) = &task.purpose {
+        document["purpose"] = json!(purpose);
+    }
+    if verbose {
+        if let Some(comment) = &task.comment {
+            document["comment"] = json!(comment);
+        }
+        if let Some(comment) = &task.commands.comment {
+            document["commands"]["comment"] = json!(comment);
+        }
+    } else {
+        strip_comments(&mut document["commands"]);
+    }
+    Ok(document)
+}
+
+fn role_document(
+    role: &Rc<RefCell<SRole>>,
+    task_id: Option<&IdTask>,
+    verbose: bool,
+) -> Result<Value, Box<dyn Error>> {
+    let options = serde_json::to_value(&*OptStack::from_role(role.clone()).to_opt())?;
+    let role = role.as_ref().borrow();
+    let tasks = role
+        .tasks
+        .iter()
+        .filter(|task| task_id.map_or(true, |id| task.as_ref().borrow().name == *id))
+        .map(|task| task_document(task, verbose))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut document = json!({
+        "name": role.name,
+        "enabled": role.is_enabled(),
+        "actors": role.actors,
+        "options": options,
+        "tasks": tasks,
+    });
+    if verbose {
+        if let Some(comment) = &role.comment {
+            document["comment"] = json!(comment);
+        }
+    }
+    Ok(document)
+}
+
+/// The roles, or only the given role and task, with their effective options.
+pub fn document(
+    config: &Rc<RefCell<SConfig>>,
+    role_id: Option<&str>,
+    task_id: Option<&IdTask>,
+    verbose: bool,
+) -> Result<Value, Box<dyn Error>> {
+    let roles = config
+        .as_ref()
+        .borrow()
+        .roles
+        .iter()
+        .filter(|role| role_id.map_or(true, |id| role.as_ref().borrow().name == id))
+        .map(|role| role_document(role, task_id, verbose))
+        .collect::<Result<Vec<_>, _>>()?;
+    if role_id.is_some() && roles.is_empty() {
+        return Err("Role not found".into());
+    }
+    if task_id.is_some()
+        && roles
+            .iter()
+            .all(|role| role["tasks"].as_array().map_or(true, Vec::is_empty))
+    {
+        return Err("Task not found".into());
+    }
+    Ok(json!({ "roles": roles }))
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !["true", "false", "null", "yes", "no", "on", "off", "y", "n"]
+            .contains(&key.to_ascii_lowercase().as_str());
+    if plain {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+// JSON strings are valid YAML double-quoted scalars, so only the structure has to be written
+fn write_yaml(output: &mut String, value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = yaml_key(key);
+                match value {
+                    Value::Object(child) if !child.is_empty() => {
+                        let _ = writeln!(output, "{}{}:", pad, key);
+                        write_yaml(output, value, indent + 1);
+                    }
+                    Value::Array(items) if !items.is_empty() => {
+                        let _ = writeln!(output, "{}{}:", pad, key);
+                        write_yaml(output, value, indent);
+                    }
+                    scalar => {
+                        let _ = writeln!(output, "{}{}: {}", pad, key, scalar);
+                    }
+                }
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                let mut nested = String::new();
+                write_yaml(&mut nested, item, indent + 1);
+                let nested = nested.trim_start();
+                let _ = write!(output, "{}- {}", pad, nested);
+                if !nested.ends_with('\n') {
+                    output.push('\n');
+                }
+            }
+        }
+        scalar => {
+            let _ = writeln!(output, "{}{}", pad, scalar);
+        }
+    }
+}
+
+pub fn to_yaml(value: &Value) -> String {
+    let mut output = String::new();
+    write_yaml(&mut output, value, 0);
+    output
+}
+
+fn list(value: &Value) -> String {
+    match value.as_array() {
+        Some(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| match (item, &item["command"], &item["comment"]) {
+                (Value::String(s), _, _) => s.clone(),
+                // a single command with its comment, in verbose listings
+                (_, Value::String(command), Value::String(comment)) => {
+                    format!("{} ({})", command, comment)
+                }
+                (item, _, _) => item.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => "none".to_string(),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "unchanged".to_string(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+// the ids of the policy are printed with the names of this system
+fn resolved(value: &Value, group: bool) -> Value {
+    match value {
+        Value::Number(id) => match id.as_u64().and_then(|id| u32::try_from(id).ok()) {
+            Some(id) if group => json!(SGroupType::from(id).resolved()),
+            Some(id) => json!(SUserType::from(id).resolved()),
+            None => value.clone(),
+        },
+        Value::Array(items) => items.iter().map(|item| resolved(item, group)).collect(),
+        value => value.clone(),
+    }
+}
+
+fn options_line(options: &Map<String, Value>) -> String {
+    options
+        .iter()
+        .filter(|(key, _)| !["env", "path"].contains(&key.as_str()))
+        .map(|(key, value)| format!("{}={}", key, scalar(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn to_text(document: &Value) -> String {
+    let mut output = String::new();
+    for role in document["roles"].as_array().into_iter().flatten() {
+        let _ = writeln!(
+            output,
+            "Role {}{}",
+            scalar(&role["name"]),
+            if role["enabled"] == json!(false) {
+                " (disabled)"
+            } else {
+                ""
+            }
+        );
+        let actors: Vec<Value> = role["actors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|actor| {
+                let id = actor
+                    .get("name")
+                    .or_else(|| actor.get("id"))
+                    .or_else(|| actor.get("groups"))
+                    .or_else(|| actor.get("names"))
+                    .map(|id| resolved(id, actor["type"] == "group"))
+                    .unwrap_or_default();
+                json!(format!(
+                    "{}:{}",
+                    scalar(&actor["type"]),
+                    match id {
+                        Value::Array(_) => list(&id),
+                        id => scalar(&id),
+                    }
+                ))
+            })
+            .collect();
+        if let Some(comment) = role["comment"].as_str() {
+            let _ = writeln!(output, "  comment: {}", comment);
+        }
+        let _ = writeln!(output, "  actors: {}", list(&Value::Array(actors)));
+        if let Some(options) = role["options"].as_object() {
+            let _ = writeln!(output, "  options: {}", options_line(options));
+        }
+        for task in role["tasks"].as_array().into_iter().flatten() {
+            let _ = writeln!(
+                output,
+                "  Task {}{}",
+                scalar(&task["name"]),
+                if task["enabled"] == json!(false) {
+                    " (disabled)"
+                } else {
+                    ""
+                }
+            );
+            if let Some(comment) = task["comment"].as_str() {
+                let _ = writeln!(output, "    comment: {}", comment);
+            }
+            let commands = &task["commands"];
+            let _ = writeln!(
+                output,
+                "    commands: default {}, add {}, sub {}",
+                scalar(&commands["default"]),
+                list(&commands["add"]),
+                list(&commands["sub"])
+            );
+            if let Some(comment) = commands["comment"].as_str() {
+                let _ = writeln!(output, "    commands comment: {}", comment);
+            }
+            let _ = writeln!(output, "    capabilities: {}", list(&task["capabilities"]));
+            if task["impact"]
+                .as_array()
+                .is_some_and(|impact| !impact.is_empty())
+            {
+                let _ = writeln!(output, "    impact: {}", list(&task["impact"]));
+            }
+            let _ = writeln!(
+                output,
+                "    setuid: {}, setgid: {}",
+                scalar(&resolved(&task["setuid"], false)),
+                scalar(&resolved(&task["setgid"], true))
+            );
+            if let Some(options) = task["options"].as_object() {
+                let _ = writeln!(output, "    options: {}", options_line(options));
+            }
+        }
+    }
+    output
+}
+
+pub fn render(document: &Value, format: &ListFormat) -> Result<String, Box<dyn Error>> {
+    Ok(match format {
+        ListFormat::Json => serde_json::to_string_pretty(document)?,
+        ListFormat::Yaml => to_yaml(document),
+        ListFormat::Text => to_text(document),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use rar_common::database::{
+        actor::SActor,
+        options::SAuthentication,
+        structs::{SCapabilities, SCommand, SCommands, SCredentials, SetBehavior},
+    };
+
+    use super::*;
+
+    fn config() -> Rc<RefCell<SConfig>> {
+        SConfig::builder()
+            .role(
+                SRole::builder("r_net")
+                    .actor(SActor::user("alice").build())
+                    .task(
+                        STask::builder("t_ip")
+                            .cred(
+                                SCredentials::builder()
+                                    .setuid("root")
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::NET_ADMIN)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ip".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(STask::builder("t_other").build())
+                    .build(),
+            )
+            .options(|opt| opt.authentication(SAuthentication::Skip).build())
+            .build()
+    }
+
+    #[test]
+    fn test_document() {
+        let config = config();
+        let document = document(&config, None, None, false).unwrap();
+        let task = &document["roles"][0]["tasks"][0];
+        assert_eq!(task["name"], "t_ip");
+        assert_eq!(task["capabilities"], json!(["CAP_NET_ADMIN"]));
+        assert_eq!(task["impact"], json!(["can configure the network"]));
+        assert_eq!(task["commands"]["add"], json!(["/usr/bin/ip"]));
+        assert_eq!(task["setuid"], "root");
+        // inherited from the global options
+        assert_eq!(task["options"]["authentication"], "skip");
+        let document = super::document(
+            &config,
+            Some("r_net"),
+            Some(&IdTask::Name("t_other".to_string())),
+            false,
+        )
+        .unwrap();
+        assert_eq!(document["roles"][0]["tasks"].as_array().unwrap().len(), 1);
+        assert!(super::document(&config, Some("r_none"), None, false).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let document = json!({
+            "roles": [{ "name": "r1", "actors": [{ "type": "user", "name": "alice" }], "tasks": [], "opt": {} }],
+            "no": [],
+            "a b": 1
+        });
+        assert_eq!(
+            to_yaml(&document),
+            "\"a b\": 1\n\"no\": []\nroles:\n- actors:\n  - name: \"alice\"\n    type: \"user\"\n  name: \"r1\"\n  opt: {}\n  tasks: []\n"
+        );
+        let text = to_text(&super::document(&config(), None, None, false).unwrap());
+        assert!(text.starts_with("Role r_net\n  actors: user:alice\n"));
+        let numeric = json!({ "roles": [{ "name": "r1", "actors": [
+            { "type": "user", "id": 0 }, { "type": "group", "groups": [0, 4242424] }
+        ], "tasks": [{ "name": "t1", "setuid": 0, "setgid": [0] }] }] });
+        let numeric = to_text(&numeric);
+        assert!(numeric.contains("  actors: user:root (#0), group:root (#0), #4242424\n"));
+        assert!(numeric.contains("    setuid: root (#0), setgid: [\"root (#0)\"]\n"));
+        assert!(text.contains(
+            "  Task t_ip\n    commands: default none, add /usr/bin/ip, sub none\n    capabilities: CAP_NET_ADMIN\n    impact: can configure the network\n    setuid: root, setgid: unchanged\n"
+        ));
+    }
+}
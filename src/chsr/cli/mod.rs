@@ -1,7 +1,25 @@
+pub(crate) mod audit;
+pub(crate) mod batch;
+#[cfg(feature = "finder")]
+pub(crate) mod check;
 pub(crate) mod data;
+pub(crate) mod diff;
+#[cfg(feature = "finder")]
+pub(crate) mod export;
+pub(crate) mod init;
+pub(crate) mod listing;
 pub(crate) mod pair;
 pub(crate) mod process;
+pub(crate) mod progress;
+pub(crate) mod report;
+pub(crate) mod review;
+pub(crate) mod sudo_logs;
+pub(crate) mod sudoers;
+pub(crate) mod templates;
+pub(crate) mod tui;
 pub(crate) mod usage;
+#[cfg(feature = "finder")]
+pub(crate) mod whoami;
 
 use std::error::Error;
 
@@ -37,6 +55,17 @@ where
     process_input(storage, inputs)
 }
 
+/// Whether the command is `chsr list --mine`, which only shows the roles of the caller.
+pub fn lists_own_roles<I, S>(args: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let args = escape_parser_string_vec(args);
+    Cli::parse(Rule::cli, &args)
+        .is_ok_and(|pairs| pairs.flatten().any(|pair| pair.as_rule() == Rule::mine))
+}
+
 #[cfg(test)]
 mod tests {
     use std::{env::current_dir, io::Write};
@@ -406,6 +435,497 @@ mod tests {
         teardown("r_complete_purge_all");
     }
     #[test]
+    fn test_r_netdebug_add_template_network_debug_u_user1() {
+        setup("r_netdebug_add_template_network_debug_u_user1");
+        let path = format!(
+            "{}.{}",
+            ROOTASROLE, "r_netdebug_add_template_network_debug_u_user1"
+        );
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r netdebug add --template network-debug -u user1".split(" "),
+        )
+        .inspect_err(|e| {
+            error!("{}", e);
+        })
+        .is_ok_and(|b| b));
+        let role = config.role("netdebug").expect("Role not created");
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .contains(&SActor::user("user1").build()));
+        let task = role
+            .task(&IdTask::Name("capture".to_string()))
+            .expect("Template task missing");
+        assert!(task
+            .as_ref()
+            .borrow()
+            .cred
+            .capabilities
+            .as_ref()
+            .unwrap()
+            .add
+            .has(Cap::NET_RAW));
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r netdebug add -T network-debug".split(" "),
+        )
+        .is_err());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r other add --template unknown".split(" "),
+        )
+        .is_err());
+        teardown("r_netdebug_add_template_network_debug_u_user1");
+    }
+    #[test]
+    fn test_list_verbose() {
+        setup("list_verbose");
+        let path = format!("{}.{}", ROOTASROLE, "list_verbose");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        let role = config.role("complete").unwrap();
+        role.as_ref().borrow_mut().comment = Some("kept for tests".to_string());
+        {
+            let task = role.as_ref().borrow().tasks[0].clone();
+            let commands = &mut task.as_ref().borrow_mut().commands;
+            commands.comment = Some("listing only".to_string());
+            commands.add.push(SCommand::Complex(serde_json::json!({
+                "command": "/usr/bin/df -h",
+                "comment": "disk usage of the build hosts"
+            })));
+        }
+        let list = |role_id: Option<&str>, role_type, verbose| {
+            process::json::list_json(
+                &config,
+                role_id.map(str::to_string),
+                None,
+                false,
+                None,
+                None,
+                role_type,
+                verbose,
+            )
+            .unwrap()
+        };
+        let listing = list(None, None, true);
+        for comment in [
+            "kept for tests",
+            "listing only",
+            "disk usage of the build hosts",
+        ] {
+            assert!(listing.contains(comment), "{} in {}", comment, listing);
+        }
+        let listing = list(Some("complete"), Some(data::RoleType::Tasks), true);
+        assert!(listing.contains("disk usage of the build hosts"));
+        assert!(!listing.contains("kept for tests"));
+        let listing = list(None, None, false);
+        assert!(!listing.contains("comment"), "{}", listing);
+        assert!(listing.contains("/usr/bin/df -h"));
+        let document = listing::document(&config, Some("complete"), None, true).unwrap();
+        let text = listing::render(&document, &data::ListFormat::Text).unwrap();
+        assert!(text.contains("kept for tests"));
+        assert!(text.contains("disk usage of the build hosts"));
+        let document = listing::document(&config, Some("complete"), None, false).unwrap();
+        assert!(!document.to_string().contains("comment"));
+        assert!(
+            main(&Storage::JSON(config.clone()), "list --verbose".split(" ")).is_ok_and(|b| !b)
+        );
+        assert!(main(&Storage::JSON(config.clone()), "l".split(" ")).is_ok_and(|b| !b));
+        teardown("list_verbose");
+    }
+    #[test]
+    fn test_list_format() {
+        setup("list_format");
+        let path = format!("{}.{}", ROOTASROLE, "list_format");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in [
+            "list --format json",
+            "list -v --format=yaml",
+            "r complete show --format text",
+        ] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r unknown show --format json".split(" "),
+        )
+        .is_err());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "list --format xml".split(" ")
+        )
+        .is_err());
+        teardown("list_format");
+    }
+    #[test]
+    fn test_check() {
+        setup("check");
+        let path = format!("{}.{}", ROOTASROLE, "check");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        // the setuid and setgid of the complete role do not exist
+        for args in ["check", "validate --format=json"] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_err(),
+                "{}",
+                args
+            );
+        }
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete delete".split(" ")
+        )
+        .is_ok());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "check --format yaml".split(" ")
+        )
+        .is_ok_and(|b| !b));
+        teardown("check");
+    }
+    #[test]
+    fn test_export() {
+        setup("export");
+        let path = format!("{}.{}", ROOTASROLE, "export");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in [
+            "export --for-user root",
+            "export --for-user=root --format yaml",
+        ] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "export --for-user rar_unknown_user".split(" "),
+        )
+        .is_err());
+        assert!(main(&Storage::JSON(config.clone()), "export".split(" ")).is_err());
+        teardown("export");
+    }
+    #[test]
+    fn test_list_mine() {
+        setup("list_mine");
+        let path = format!("{}.{}", ROOTASROLE, "list_mine");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in [
+            "list --mine",
+            "l --format=json --mine",
+            "show --mine --format yaml",
+        ] {
+            assert!(lists_own_roles(args.split(" ")), "{}", args);
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        // the other commands need the privileges to read the whole policy
+        for args in ["list", "list --mine -v", "r complete show --mine", "whoami"] {
+            assert!(!lists_own_roles(args.split(" ")), "{}", args);
+        }
+        teardown("list_mine");
+    }
+    #[test]
+    fn test_report() {
+        setup("report");
+        let path = format!("{}.{}", ROOTASROLE, "report");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in ["report", "report --format=json", "report --format yaml"] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        teardown("report");
+    }
+    #[test]
+    fn test_init() {
+        setup("init");
+        let path = format!("{}.{}", ROOTASROLE, "init");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        // only an empty policy is initialized
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "init --admin root".split(" ")
+        )
+        .is_err());
+        assert!(main(&Storage::JSON(config.clone()), "init".split(" ")).is_err());
+        teardown("init");
+    }
+    #[test]
+    fn test_diff() {
+        setup("diff");
+        let path = format!("{}.{}", ROOTASROLE, "diff");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in [
+            format!("diff {}", path),
+            format!("diff {} {} --format=json", path, path),
+            format!("diff {} --format yaml", path),
+        ] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "diff missing.json".split(" ")
+        )
+        .is_err());
+        teardown("diff");
+    }
+    #[test]
+    fn test_debug_last() {
+        setup("debug_last");
+        let path = format!("{}.{}", ROOTASROLE, "debug_last");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        for args in [
+            "debug last",
+            "debug last --user alice",
+            "debug last -u 1000",
+        ] {
+            assert!(
+                main(&Storage::JSON(config.clone()), args.split(" ")).is_ok_and(|b| !b),
+                "{}",
+                args
+            );
+        }
+        teardown("debug_last");
+    }
+    #[test]
+    fn test_r_complete_disable_enable() {
+        setup("r_complete_disable_enable");
+        let path = format!("{}.{}", ROOTASROLE, "r_complete_disable_enable");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete disable".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(!config
+            .role("complete")
+            .unwrap()
+            .as_ref()
+            .borrow()
+            .is_enabled());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete disable".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(!config
+            .task("complete", "t_complete")
+            .unwrap()
+            .as_ref()
+            .borrow()
+            .is_enabled());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete enable".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert_eq!(
+            config.role("complete").unwrap().as_ref().borrow().enabled,
+            None
+        );
+        teardown("r_complete_disable_enable");
+    }
+    #[test]
+    fn test_import_from_sudo_logs() {
+        setup("import_from_sudo_logs");
+        let path = format!("{}.{}", ROOTASROLE, "import_from_sudo_logs");
+        let log = format!("{}.auth.log", path);
+        std::fs::write(
+            &log,
+            "Oct 10 12:00:00 host sudo:    user1 : TTY=pts/0 ; PWD=/home/user1 ; USER=root ; COMMAND=/usr/bin/apt update\n",
+        )
+        .unwrap();
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("import --from-sudo-logs {}", log).split(" "),
+        )
+        .inspect_err(|e| {
+            error!("{}", e);
+        })
+        .is_ok_and(|b| b));
+        let role = config.role("sudo_user1").expect("Role not imported");
+        assert!(role.task(&IdTask::Name("apt".to_string())).is_some());
+        // nothing new to import
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("--progress json import --from-sudo-logs {}", log).split(" "),
+        )
+        .is_ok_and(|b| !b));
+        std::fs::remove_file(&log).unwrap();
+        teardown("import_from_sudo_logs");
+    }
+    #[test]
+    fn test_import_from_sudoers() {
+        setup("import_from_sudoers");
+        let path = format!("{}.{}", ROOTASROLE, "import_from_sudoers");
+        let sudoers = format!("{}.sudoers", path);
+        std::fs::write(
+            &sudoers,
+            "Defaults env_reset\nuser1 ALL = (root) NOPASSWD: /usr/bin/apt update\n",
+        )
+        .unwrap();
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("import --from-sudoers {}", sudoers).split(" "),
+        )
+        .inspect_err(|e| {
+            error!("{}", e);
+        })
+        .is_ok_and(|b| b));
+        let role = config.role("sudoers_user1").expect("Role not imported");
+        assert!(role.task(&IdTask::Name("line_2".to_string())).is_some());
+        // the role is merged with the new rules only
+        std::fs::write(
+            &sudoers,
+            "user1 ALL = (root) NOPASSWD: /usr/bin/apt update\nuser1 ALL = /usr/bin/id\n",
+        )
+        .unwrap();
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("import --from-sudoers {}", sudoers).split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert_eq!(
+            config
+                .role("sudoers_user1")
+                .unwrap()
+                .as_ref()
+                .borrow()
+                .tasks
+                .len(),
+            1
+        );
+        let role = config.role("sudoers_user1_2").expect("Role not imported");
+        assert!(role.task(&IdTask::Name("line_2".to_string())).is_some());
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("import --from-sudoers {}", sudoers).split(" "),
+        )
+        .is_ok_and(|b| !b));
+        std::fs::remove_file(&sudoers).unwrap();
+        teardown("import_from_sudoers");
+    }
+    #[test]
+    fn test_review_decisions() {
+        setup("review_decisions");
+        let path = format!("{}.{}", ROOTASROLE, "review_decisions");
+        let decisions = format!("{}.decisions", path);
+        std::fs::write(
+            &decisions,
+            "complete group:groupB,groupA keep\ncomplete user:user1 revoke\n",
+        )
+        .unwrap();
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        config
+            .role("complete")
+            .unwrap()
+            .as_ref()
+            .borrow_mut()
+            .actors
+            .push(
+                SActor::user("user1")
+                    .granted("2020-01-01".parse().unwrap())
+                    .build(),
+            );
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("review --older-than 30 --decisions {}", decisions).split(" "),
+        )
+        .is_ok_and(|b| b));
+        let role = config.role("complete").unwrap();
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .all(|a| !a.same_actor(&SActor::user("user1").build())));
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .find(|a| a.same_actor(&SActor::group(["groupA", "groupB"]).build()))
+            .is_some_and(|a| a.last_certified().is_some()));
+        // the remaining grants are either certified today or not in the decisions
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("review --decisions {}", decisions).split(" "),
+        )
+        .is_ok_and(|b| !b));
+        std::fs::remove_file(&decisions).unwrap();
+        teardown("review_decisions");
+    }
+    #[test]
+    fn test_grant_revoke_from_csv() {
+        setup("grant_revoke_from_csv");
+        let path = format!("{}.{}", ROOTASROLE, "grant_revoke_from_csv");
+        let csv = format!("{}.csv", path);
+        std::fs::write(
+            &csv,
+            "actor,role,expiry\nuser:user1,complete,2099-12-31\nuser:user2,missing,\n",
+        )
+        .unwrap();
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("grant --from-csv {}", csv).split(" "),
+        )
+        .is_ok_and(|b| b));
+        let role = config.role("complete").unwrap();
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .any(|a| a.same_actor(&SActor::user("user1").build()) && a.last_certified().is_some()));
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("revoke --from-csv {}", csv).split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(role
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .all(|a| !a.same_actor(&SActor::user("user1").build())));
+        std::fs::remove_file(&csv).unwrap();
+        teardown("grant_revoke_from_csv");
+    }
+    #[test]
     fn test_r_complete_grant_u_user1_g_group1_g_group2_group3() {
         setup("r_complete_grant_u_user1_g_group1_g_group2_group3");
         let path = format!(
@@ -429,17 +949,45 @@ mod tests {
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::user("user1").build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::user("user1").build())));
         assert!(config.as_ref().borrow()[0]
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::group("group1").build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::group("group1").build())));
         assert!(config.as_ref().borrow()[0]
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::group(["group2", "group3"]).build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::group(["group2", "group3"]).build())));
+        // a numeric id, for the users only known on the target hosts
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete grant -u #4242 -g #4242".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(config.as_ref().borrow()[0]
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .any(|a| a.same_actor(&SActor::user(4242).build())));
+        assert!(config.as_ref().borrow()[0]
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .any(|a| a.same_actor(&SActor::group(4242).build())));
+        assert!(config.as_ref().borrow()[0]
+            .as_ref()
+            .borrow()
+            .actors
+            .iter()
+            .all(|a| !a.same_actor(&SActor::user("user1").build())
+                || a.last_certified() == Some(chrono::Local::now().date_naive())));
         assert!(main(
             &Storage::JSON(config.clone()),
             "r complete revoke -u user1 -g group1 -g group2&group3".split(" "),
@@ -455,17 +1003,20 @@ mod tests {
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::user("user1").build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::user("user1").build())));
         assert!(!config.as_ref().borrow()[0]
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::group("group1").build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::group("group1").build())));
         assert!(!config.as_ref().borrow()[0]
             .as_ref()
             .borrow()
             .actors
-            .contains(&SActor::group(["group2", "group3"]).build()));
+            .iter()
+            .any(|a| a.same_actor(&SActor::group(["group2", "group3"]).build())));
         teardown("r_complete_grant_u_user1_g_group1_g_group2_group3");
     }
     #[test]
@@ -716,6 +1267,55 @@ mod tests {
         teardown("r_complete_t_t_complete_cmd_whitelist_add_super_command_with_spaces");
     }
     #[test]
+    fn test_cmd_digest() {
+        setup("cmd_digest");
+        let path = format!("{}.{}", ROOTASROLE, "cmd_digest");
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        let binary = format!("{}.bin", path);
+        std::fs::write(&binary, "test").unwrap();
+        let binary = std::fs::canonicalize(&binary).unwrap();
+        let binary = binary.to_str().unwrap();
+        let commands = || {
+            config.as_ref().borrow()[0].as_ref().borrow().tasks[0]
+                .as_ref()
+                .borrow()
+                .commands
+                .add
+                .clone()
+        };
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!(
+                "r complete t t_complete cmd whitelist add --digest {} -l",
+                binary
+            )
+            .split(" "),
+        )
+        .is_ok_and(|b| b));
+        let pinned = SCommand::Complex(serde_json::json!({
+            "hash_type": "sha256",
+            "hash": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+            "command": format!("{} -l", binary),
+        }));
+        assert!(commands().contains(&pinned));
+        std::fs::write(binary, "upgraded").unwrap();
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete cmd digest update".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(!commands().contains(&pinned));
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            format!("r complete t t_complete cmd whitelist del {} -l", binary).split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(!commands().iter().any(|c| matches!(c, SCommand::Complex(_))));
+        std::fs::remove_file(binary).unwrap();
+        teardown("cmd_digest");
+    }
+    #[test]
     fn test_r_complete_t_t_complete_cmd_blacklist_del_super_command_with_spaces() {
         setup("r_complete_t_t_complete_cmd_blacklist_del_super_command_with_spaces");
         let path = format!(
@@ -866,6 +1466,34 @@ mod tests {
         teardown("r_complete_t_t_complete_cred_set_caps_cap_dac_override_cap_sys_admin_cap_sys_boot_setuid_user1_setgid_group1_group2");
     }
     #[test]
+    fn test_r_complete_t_t_complete_cred_set_setgid_patterns() {
+        setup("r_complete_t_t_complete_cred_set_setgid_patterns");
+        let path = format!(
+            "{}.{}",
+            ROOTASROLE, "r_complete_t_t_complete_cred_set_setgid_patterns"
+        );
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete cred set --setgid @target-user,docker*,1000-1999".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert_eq!(
+            config.as_ref().borrow()[0].as_ref().borrow().tasks[0]
+                .as_ref()
+                .borrow()
+                .cred
+                .setgid,
+            Some(SGroupschooser::Group(SGroups::from([
+                "@target-user",
+                "docker*",
+                "1000-1999"
+            ])))
+        );
+        teardown("r_complete_t_t_complete_cred_set_setgid_patterns");
+    }
+    #[test]
     fn test_r_complete_t_t_complete_cred_caps_setpolicy_deny_all() {
         setup("r_complete_t_t_complete_cred_caps_setpolicy_deny_all");
         let path = format!(
@@ -976,6 +1604,42 @@ mod tests {
         teardown("r_complete_t_t_complete_cred_caps_whitelist_add_cap_dac_override_cap_sys_admin_cap_sys_boot");
     }
     #[test]
+    fn test_r_complete_t_t_complete_cred_caps_whitelist_add_network() {
+        setup("r_complete_t_t_complete_cred_caps_whitelist_add_network");
+        let path = format!(
+            "{}.{}",
+            ROOTASROLE, "r_complete_t_t_complete_cred_caps_whitelist_add_network"
+        );
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete cred caps whitelist add @network cap_kill".split(" "),
+        )
+        .inspect_err(|e| {
+            error!("{}", e);
+        })
+        .is_ok_and(|b| b));
+        let task = config.as_ref().borrow()[0].as_ref().borrow().tasks[0].clone();
+        let add = task
+            .as_ref()
+            .borrow()
+            .cred
+            .capabilities
+            .as_ref()
+            .unwrap()
+            .add;
+        assert!(add.has(Cap::NET_RAW));
+        assert!(add.has(Cap::NET_BIND_SERVICE));
+        assert!(add.has(Cap::KILL));
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete cred caps whitelist add @unknown".split(" "),
+        )
+        .is_err());
+        teardown("r_complete_t_t_complete_cred_caps_whitelist_add_network");
+    }
+    #[test]
     fn test_r_complete_t_t_complete_cred_caps_blacklist_add_cap_dac_override_cap_sys_admin_cap_sys_boot(
     ) {
         setup("r_complete_t_t_complete_cred_caps_blacklist_add_cap_dac_override_cap_sys_admin_cap_sys_boot");
@@ -2910,9 +3574,72 @@ mod tests {
                 .unwrap(),
             &SAuthentication::Inherit
         );
+        debug!("=====");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete o auth none".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert_eq!(
+            config.as_ref().borrow()[0].as_ref().borrow().tasks[0]
+                .as_ref()
+                .borrow()
+                .options
+                .as_ref()
+                .unwrap()
+                .as_ref()
+                .borrow()
+                .authentication,
+            Some(SAuthentication::Skip)
+        );
         teardown("r_complete_t_t_complete_o_auth_skip");
     }
     #[test]
+    fn test_r_complete_t_t_complete_o_wildcard_denied_preset() {
+        setup("r_complete_t_t_complete_o_wildcard_denied_preset");
+        let path = format!(
+            "{}.{}",
+            ROOTASROLE, "r_complete_t_t_complete_o_wildcard_denied_preset"
+        );
+        let settings = get_settings(&path).expect("Failed to get settings");
+        let config = read_json_config(settings.clone(), &path).expect("Failed to read json");
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete o wildcard-denied preset shell whitespace".split(" "),
+        )
+        .inspect_err(|e| {
+            error!("{}", e);
+        })
+        .is_ok_and(|b| b));
+        let task = config.as_ref().borrow()[0].as_ref().borrow().tasks[0].clone();
+        let opt = task.as_ref().borrow().options.as_ref().unwrap().clone();
+        assert_eq!(
+            opt.as_ref().borrow().wildcard_denied_preset,
+            [
+                SWildcardPreset::ShellMetacharacters,
+                SWildcardPreset::Whitespace
+            ]
+        );
+        assert_eq!(
+            opt.as_ref().borrow().wildcard_denied.as_deref(),
+            Some(
+                SWildcardPreset::expand(&[
+                    SWildcardPreset::ShellMetacharacters,
+                    SWildcardPreset::Whitespace
+                ])
+                .as_str()
+            )
+        );
+        // a manual edit forgets the presets
+        assert!(main(
+            &Storage::JSON(config.clone()),
+            "r complete t t_complete o wildcard-denied add ~".split(" "),
+        )
+        .is_ok_and(|b| b));
+        assert!(opt.as_ref().borrow().wildcard_denied_preset.is_empty());
+        teardown("r_complete_t_t_complete_o_wildcard_denied_preset");
+    }
+    #[test]
     fn test_r_complete_t_t_complete_o_wildcard_denied_set() {
         setup("r_complete_t_t_complete_o_wildcard_denied_set");
         let path = format!(
@@ -10,7 +10,8 @@ use crate::cli::data::{RoleType, TaskType};
 use rar_common::database::{
     actor::{SActor, SGroupType},
     options::{
-        EnvBehavior, OptType, PathBehavior, SAuthentication, SBounding, SPrivileged, TimestampType,
+        EnvBehavior, OptType, PathBehavior, SAuthentication, SBounding, SPrivileged,
+        SWildcardPreset, TimestampType,
     },
     structs::{IdTask, SetBehavior},
 };
@@ -55,6 +56,68 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
         Rule::purge => {
             inputs.action = InputAction::Purge;
         }
+        Rule::mine => {
+            inputs.action = InputAction::List;
+            inputs.mine = true;
+        }
+        Rule::whoami => {
+            inputs.whoami = true;
+        }
+        Rule::init_admin_name => {
+            inputs.init_admin = Some(pair.as_str().to_string());
+        }
+        Rule::check => {
+            inputs.check = true;
+        }
+        Rule::report => {
+            inputs.report = true;
+        }
+        Rule::diff_path => {
+            inputs.diff_paths.push(pair.as_str().to_string());
+        }
+        Rule::tui => {
+            inputs.tui = true;
+        }
+        Rule::debug_last => {
+            inputs.debug_last = true;
+        }
+        Rule::debug_user_name => {
+            inputs.debug_user = Some(pair.as_str().to_string());
+        }
+        Rule::export_user_name => {
+            inputs.export_user = Some(pair.as_str().to_string());
+        }
+        Rule::activate => {
+            inputs.activate = true;
+        }
+        Rule::remote_refresh => {
+            inputs.remote_refresh = true;
+        }
+        Rule::audit_gc => {
+            inputs.audit_gc = true;
+        }
+        Rule::audit_id => {
+            inputs.audit_show = Some(pair.as_str().to_string());
+        }
+        Rule::verbose => {
+            inputs.verbose = true;
+        }
+        Rule::list_format_value => {
+            inputs.list_format = Some(match pair.as_str() {
+                "json" => ListFormat::Json,
+                "yaml" => ListFormat::Yaml,
+                _ => ListFormat::Text,
+            });
+        }
+        Rule::progress_format => {
+            inputs.progress = true;
+        }
+        Rule::enable => {
+            inputs.enabled = Some(true);
+        }
+        Rule::disable => {
+            inputs.enabled = Some(false);
+        }
         Rule::whitelist => {
             inputs.setlist_type = Some(SetListType::White);
         }
@@ -182,6 +245,32 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
                 unreachable!("Unknown role type: {}", pair.as_str())
             }
         }
+        Rule::template_name => {
+            inputs.role_template = Some(pair.as_str().to_string());
+        }
+        Rule::role_file_name => {
+            inputs.role_file = Some(pair.as_str().to_string());
+        }
+        // === import ===
+        Rule::import_path => {
+            inputs.import_sudo_logs = Some(pair.as_str().to_string());
+        }
+        Rule::import_sudoers_path => {
+            inputs.import_sudoers = Some(pair.as_str().to_string());
+        }
+        // === review ===
+        Rule::review => {
+            inputs.review = true;
+        }
+        Rule::review_days => {
+            inputs.review_days = Some(pair.as_str().parse()?);
+        }
+        Rule::batch_path => {
+            inputs.batch_csv = Some(pair.as_str().to_string());
+        }
+        Rule::review_path => {
+            inputs.review_decisions = Some(pair.as_str().to_string());
+        }
         // === actors ===
         Rule::user => {
             if inputs.actors.is_none() {
@@ -251,6 +340,12 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
             }
         }
         // === commands ===
+        Rule::cmd_digest => {
+            inputs.cmd_digest = true;
+        }
+        Rule::cmd_digest_update => {
+            inputs.cmd_digest_update = true;
+        }
         Rule::cmd => {
             inputs.cmd_id = Some(shell_words::split(pair.as_str())?);
         }
@@ -260,7 +355,9 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
                 let caps = CapSet::empty();
                 inputs.cred_caps = Some(caps);
             }
-            if let Ok(cap) = Cap::from_str(pair.as_str()) {
+            if let Some(name) = pair.as_str().strip_prefix('@') {
+                inputs.cred_cap_sets.push(name.to_string());
+            } else if let Ok(cap) = Cap::from_str(pair.as_str()) {
                 inputs.cred_caps.as_mut().unwrap().add(cap);
             } else {
                 warn!("Unknown capability: {}", pair.as_str())
@@ -278,8 +375,8 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
         }
         Rule::cred_g => {
             let mut vec: Vec<SGroupType> = Vec::new();
-            for pair in pair.clone().into_inner() {
-                if pair.as_rule() == Rule::actor_name {
+            for pair in pair.clone().into_inner().flatten() {
+                if pair.as_rule() == Rule::setgid_entry {
                     vec.push(pair.as_str().into());
                 }
             }
@@ -391,9 +488,9 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
         }
         Rule::opt_skip_auth_args => {
             inputs.action = InputAction::Set;
-            if pair.as_str() == "skip" {
+            if pair.as_str() == "skip" || pair.as_str() == "none" {
                 inputs.options_auth = Some(SAuthentication::Skip);
-            } else if pair.as_str() == "perform" {
+            } else if pair.as_str() == "perform" || pair.as_str() == "required" {
                 inputs.options_auth = Some(SAuthentication::Perform);
             } else if pair.as_str() == "inherit" {
                 inputs.options_auth = Some(SAuthentication::Inherit);
@@ -404,6 +501,17 @@ fn match_pair(pair: &Pair<Rule>, inputs: &mut Inputs) -> Result<(), Box<dyn Erro
         Rule::wildcard_value => {
             inputs.options_wildcard = Some(pair.as_str().to_string());
         }
+        Rule::wildcard_preset => {
+            let preset = match pair.as_str() {
+                "whitespace" => SWildcardPreset::Whitespace,
+                "redirections" => SWildcardPreset::Redirections,
+                _ => SWildcardPreset::ShellMetacharacters,
+            };
+            inputs
+                .options_wildcard_preset
+                .get_or_insert_with(Vec::new)
+                .push(preset);
+        }
         Rule::all => {
             if inputs.role_id.is_some() && inputs.task_id.is_none() {
                 inputs.role_type = Some(RoleType::All);
@@ -482,6 +590,24 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_role_add_file() {
+        let inputs = get_inputs("role r_nginx add --file nginx.json");
+        assert_eq!(inputs.role_id, Some("r_nginx".to_string()));
+        assert_eq!(inputs.action, InputAction::Add);
+        assert_eq!(inputs.role_file, Some("nginx.json".to_string()));
+        let inputs = get_inputs("role r_nginx add --file nginx.json --template network-debug");
+        assert_eq!(inputs.role_file, Some("nginx.json".to_string()));
+        assert_eq!(inputs.role_template, Some("network-debug".to_string()));
+    }
+
+    #[test]
+    fn test_audit_show() {
+        let inputs = get_inputs("audit show 7f3a");
+        assert_eq!(inputs.audit_show, Some("7f3a".to_string()));
+        assert!(!inputs.audit_gc);
+    }
+
     #[test]
     fn test_list_roles() {
         let inputs = get_inputs("list");
@@ -1,30 +1,102 @@
-mod json;
+pub(crate) mod json;
 
-use std::{cell::RefCell, error::Error, rc::Rc};
+use std::{
+    cell::RefCell,
+    error::Error,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 
 use json::*;
 
-use log::debug;
+use capctl::CapSet;
+use chrono::Local;
+use log::{debug, warn};
 
 use rar_common::{
+    activation::activate,
     database::{
+        capsets,
         options::{Opt, OptType},
         structs::{IdTask, RoleGetter},
     },
+    federation::refresh,
+    get_settings,
+    retention::{collect, CONTEXT_DIR},
+    trace::{self, TRACE_FILE},
     Storage,
 };
 
 use super::{
-    data::{InputAction, Inputs},
-    usage,
+    audit,
+    data::{InputAction, Inputs, ListFormat},
+    diff::{self, read_policy},
+    init::init,
+    listing,
+    progress::Progress,
+    report::{self, HISTORY_FILE},
+    tui, usage,
 };
+use crate::ROOTASROLE;
 
-pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn Error>> {
+#[cfg(feature = "finder")]
+use super::{
+    check,
+    export::{cred_of, export},
+    whoami::{caller, whoami},
+};
+#[cfg(feature = "finder")]
+use rar_common::plugin::update_digests;
+
+pub fn process_input(storage: &Storage, mut inputs: Inputs) -> Result<bool, Box<dyn Error>> {
+    if !inputs.cred_cap_sets.is_empty() {
+        let Storage::JSON(rconfig) = storage;
+        let sets = capsets::sets(&rconfig.as_ref().borrow())?;
+        let caps = inputs.cred_caps.get_or_insert_with(CapSet::empty);
+        for name in inputs.cred_cap_sets.iter() {
+            let set = sets
+                .iter()
+                .find(|set| set.name == *name)
+                .ok_or_else(|| format!("Unknown capability set @{}", name))?;
+            *caps = caps.union(set.caps);
+        }
+    }
     match inputs {
         Inputs {
             action: InputAction::Help,
             ..
         } => usage::help(),
+        #[cfg(feature = "finder")]
+        Inputs {
+            // chsr list --mine --format json|yaml|text
+            mine: true,
+            list_format,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let document = export(rconfig, &caller()?)?;
+                let format = list_format.unwrap_or(ListFormat::Text);
+                println!("{}", listing::render(&document, &format)?);
+                Ok(false)
+            }
+        },
+        Inputs {
+            // chsr list --format json|yaml|text
+            action: InputAction::List,
+            list_format: Some(format),
+            role_id,
+            task_id,
+            verbose,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let document =
+                    listing::document(rconfig, role_id.as_deref(), task_id.as_ref(), verbose)?;
+                println!("{}", listing::render(&document, &format)?);
+                Ok(false)
+            }
+        },
         Inputs {
             action: InputAction::List,
             options, // show options ?
@@ -33,6 +105,7 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
             task_id,
             task_type,    // what to show
             options_type, // in json
+            verbose,      // with comments
             ..
         } => match storage {
             Storage::JSON(rconfig) => {
@@ -45,9 +118,11 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
                     options_type,
                     task_type,
                     role_type,
+                    verbose,
                 ) {
-                    Ok(_) => {
+                    Ok(listing) => {
                         debug!("chsr list ok");
+                        println!("{}", listing);
                         Ok(false)
                     }
                     Err(e) => {
@@ -57,6 +132,279 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
                 }
             }
         },
+        #[cfg(feature = "finder")]
+        Inputs { whoami: true, .. } => match storage {
+            Storage::JSON(rconfig) => {
+                print!("{}", whoami(rconfig, &caller()?)?);
+                Ok(false)
+            }
+        },
+        Inputs {
+            // chsr init --admin alice
+            init_admin: Some(admin),
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                init(rconfig, &admin)?;
+                println!("The role r_root is granted to {}", admin);
+                Ok(true)
+            }
+        },
+        #[cfg(feature = "finder")]
+        Inputs {
+            // chsr check --format=json
+            check: true,
+            list_format,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let findings = check::check(rconfig);
+                match list_format {
+                    Some(ListFormat::Json) => println!(
+                        "{}",
+                        serde_json::to_string_pretty(&check::document(&findings))?
+                    ),
+                    Some(ListFormat::Yaml) => {
+                        print!("{}", listing::to_yaml(&check::document(&findings)))
+                    }
+                    _ => println!("{}", check::to_text(&findings)),
+                }
+                match check::errors(&findings) {
+                    0 => Ok(false),
+                    errors => Err(format!("{} errors found in the policy", errors).into()),
+                }
+            }
+        },
+        #[cfg(feature = "finder")]
+        Inputs {
+            // chsr export --for-user alice --format=yaml
+            export_user: Some(user),
+            list_format,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let document = export(rconfig, &cred_of(&user)?)?;
+                match list_format {
+                    Some(ListFormat::Yaml) => print!("{}", listing::to_yaml(&document)),
+                    _ => println!("{}", serde_json::to_string_pretty(&document)?),
+                }
+                Ok(false)
+            }
+        },
+        Inputs {
+            // chsr report --format=json
+            report: true,
+            list_format,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let entry = report::Entry {
+                    time: Local::now(),
+                    metrics: report::metrics(rconfig),
+                };
+                let history = report::history(HISTORY_FILE)?;
+                let document = || serde_json::json!({ "metrics": &entry, "history": &history });
+                match list_format {
+                    Some(ListFormat::Json) => {
+                        println!("{}", serde_json::to_string_pretty(&document())?)
+                    }
+                    Some(ListFormat::Yaml) => print!("{}", listing::to_yaml(&document())),
+                    _ => print!("{}", report::to_text(&entry.metrics, history.last())),
+                }
+                if let Err(e) = report::record(HISTORY_FILE, &entry, history.last()) {
+                    warn!("Unable to journal the policy metrics: {}", e);
+                }
+                Ok(false)
+            }
+        },
+        Inputs {
+            // chsr diff [old.json] new.json --format=json
+            diff_paths,
+            list_format,
+            ..
+        } if !diff_paths.is_empty() => match storage {
+            Storage::JSON(rconfig) => {
+                let changes = match diff_paths.as_slice() {
+                    [new] => diff::diff(rconfig, &read_policy(new)?),
+                    [old, new, ..] => diff::diff(&read_policy(old)?, &read_policy(new)?),
+                    [] => unreachable!(),
+                };
+                match list_format {
+                    Some(ListFormat::Json) => println!(
+                        "{}",
+                        serde_json::to_string_pretty(&diff::document(&changes))?
+                    ),
+                    Some(ListFormat::Yaml) => {
+                        print!("{}", listing::to_yaml(&diff::document(&changes)))
+                    }
+                    _ => println!("{}", diff::to_text(&changes)),
+                }
+                Ok(false)
+            }
+        },
+        Inputs {
+            // chsr tui
+            tui: true,
+            ..
+        } => tui::tui(storage),
+        Inputs {
+            // chsr debug last --user alice
+            debug_last: true,
+            debug_user,
+            ..
+        } => {
+            let traces = trace::render(&trace::read(TRACE_FILE)?, debug_user.as_deref());
+            if traces.is_empty() {
+                println!("No evaluation trace recorded");
+            } else {
+                print!("{}", traces);
+            }
+            Ok(false)
+        }
+        Inputs { activate: true, .. } => {
+            let generation = activate(&get_settings(ROOTASROLE)?.as_ref().borrow(), ROOTASROLE)?;
+            println!("Policy generation {} activated", generation);
+            Ok(false)
+        }
+        Inputs {
+            remote_refresh: true,
+            ..
+        } => {
+            let include = get_settings(ROOTASROLE)?
+                .as_ref()
+                .borrow()
+                .storage
+                .include
+                .clone()
+                .unwrap_or_default();
+            if include.remote.is_empty() {
+                return Err("No remote include source is configured".into());
+            }
+            let mut failed = 0;
+            for source in include.remote.iter() {
+                match refresh(source, &include.cache) {
+                    Ok(roles) => println!("{}: {} roles", source.url, roles),
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("{}: {}", source.url, e);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(format!(
+                    "{} sources not refreshed, their previous roles are kept",
+                    failed
+                )
+                .into());
+            }
+            Ok(false)
+        }
+        Inputs { audit_gc: true, .. } => {
+            let retention = get_settings(ROOTASROLE)?
+                .as_ref()
+                .borrow()
+                .storage
+                .retention
+                .clone()
+                .unwrap_or_default();
+            let report = collect(Path::new(CONTEXT_DIR), &retention, SystemTime::now())?;
+            println!(
+                "{} context snapshots removed, {} bytes freed",
+                report.removed, report.freed
+            );
+            Ok(false)
+        }
+        Inputs {
+            audit_show: Some(id),
+            ..
+        } => {
+            let settings = get_settings(ROOTASROLE)?;
+            print!(
+                "{}",
+                audit::show(&id, settings.as_ref().borrow().storage.log.as_ref())?
+            );
+            Ok(false)
+        }
+        Inputs {
+            // chsr import --from-sudo-logs /var/log/auth.log
+            import_sudo_logs: Some(path),
+            progress,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => import_sudo_logs(rconfig, path, Progress::new(progress)),
+        },
+        Inputs {
+            // chsr import --from-sudoers /etc/sudoers
+            import_sudoers: Some(path),
+            progress,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => import_sudoers(rconfig, path, Progress::new(progress)),
+        },
+        Inputs {
+            // chsr grant|revoke --from-csv users.csv
+            batch_csv: Some(path),
+            action,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => batch_grant_revoke(rconfig, path, action),
+        },
+        Inputs {
+            // chsr review --older-than 90 --decisions review.txt
+            review: true,
+            review_days,
+            review_decisions,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => review_grants(rconfig, review_days, review_decisions),
+        },
+        Inputs {
+            // chsr role r1 [task t1] enable|disable
+            role_id: Some(role_id),
+            task_id,
+            enabled: Some(enabled),
+            options: false,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => set_enabled(rconfig, role_id, task_id, enabled),
+        },
+        Inputs {
+            // chsr role r1 add --file f1.json [--template t1 -u u1 -g g1]
+            action: InputAction::Add,
+            role_id: Some(role_id),
+            role_template,
+            role_file: Some(file),
+            actors,
+            task_id: None,
+            options: false,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let path = included_file(&file)?;
+                match role_template {
+                    Some(template) => {
+                        role_from_template(rconfig, role_id.clone(), template, actors)?
+                    }
+                    None => role_add_del(rconfig, InputAction::Add, role_id.clone(), None)?,
+                };
+                if let Some(role) = rconfig.role(&role_id) {
+                    role.as_ref().borrow_mut()._file = Some(path);
+                }
+                Ok(true)
+            }
+        },
+        Inputs {
+            // chsr role r1 add --template t1 -u u1 -g g1
+            action: InputAction::Add,
+            role_id: Some(role_id),
+            role_template: Some(template),
+            actors,
+            task_id: None,
+            options: false,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => role_from_template(rconfig, role_id, template, actors),
+        },
         Inputs {
             // chsr role r1 add|del
             action,
@@ -81,6 +429,21 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
             Storage::JSON(rconfig) => grant_revoke(rconfig, role_id, action, actors),
         },
 
+        #[cfg(feature = "finder")]
+        Inputs {
+            // chsr role r1 task t1 command digest update
+            role_id: Some(role_id),
+            task_id: Some(task_id),
+            cmd_digest_update: true,
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => {
+                let task = rconfig.task(&role_id, task_id)?;
+                let updated = update_digests(&mut task.as_ref().borrow_mut())?;
+                println!("{} digests updated", updated);
+                Ok(updated > 0)
+            }
+        },
         Inputs {
             // chsr role r1 task t1 add|del
             action,
@@ -174,12 +537,19 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
             role_id: Some(role_id),
             task_id: Some(task_id),
             cmd_id: Some(cmd_id),
+            cmd_digest,
             setlist_type: Some(setlist_type),
             ..
         } => match storage {
-            Storage::JSON(rconfig) => {
-                cmd_whitelist_action(rconfig, role_id, task_id, cmd_id, setlist_type, action)
-            }
+            Storage::JSON(rconfig) => cmd_whitelist_action(
+                rconfig,
+                role_id,
+                task_id,
+                cmd_id,
+                cmd_digest,
+                setlist_type,
+                action,
+            ),
         },
         Inputs {
             role_id: Some(role_id),
@@ -234,6 +604,16 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
         } => match storage {
             Storage::JSON(rconfig) => set_authentication(rconfig, role_id, task_id, options_auth),
         },
+        Inputs {
+            // chsr o wildcard-denied preset shell-metacharacters whitespace
+            role_id,
+            task_id,
+            options: true,
+            options_wildcard_preset: Some(presets),
+            ..
+        } => match storage {
+            Storage::JSON(rconfig) => json_wildcard_preset(rconfig, role_id, task_id, presets),
+        },
         Inputs {
             // chsr o wildcard-denied set ";&*$"
             action,
@@ -392,6 +772,19 @@ pub fn process_input(storage: &Storage, inputs: Inputs) -> Result<bool, Box<dyn
         _ => Err("Unknown Input".into()),
     }
 }
+
+/// The included file of `chsr role r1 add --file`, in the directory of the `include` setting.
+fn included_file(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    get_settings(ROOTASROLE)?
+        .as_ref()
+        .borrow()
+        .storage
+        .include
+        .as_ref()
+        .ok_or("Inclusion is not configured in the storage settings")?
+        .file(name)
+}
+
 pub fn perform_on_target_opt(
     rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
     role_id: Option<String>,
@@ -1,22 +1,39 @@
-use std::{cell::RefCell, collections::HashMap, error::Error, ops::Deref, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, error::Error, io::BufReader, ops::Deref, rc::Rc};
 
+use chrono::Local;
 use linked_hash_set::LinkedHashSet;
 use log::{debug, warn};
+use serde::Serialize;
 
-use crate::cli::data::{InputAction, RoleType, SetListType, TaskType, TimeoutOpt};
+use crate::cli::{
+    batch::{apply, parse_csv},
+    data::{InputAction, RoleType, SetListType, TaskType, TimeoutOpt},
+    listing::strip_comments,
+    progress::Progress,
+    review::{ask, from_decisions, parse_decisions, review},
+    sudo_logs::{parse_logs, propose_roles},
+    sudoers::parse_sudoers,
+    templates::template_tasks,
+};
 
 use rar_common::database::{
     options::{
         EnvBehavior, EnvKey, Opt, OptStack, OptType, PathBehavior, SEnvOptions, SPathOptions,
-        STimeout,
+        STimeout, SWildcardPreset,
     },
     structs::{
         IdTask, RoleGetter, SCapabilities, SCommand, SGroupschooser, SRole, STask, SUserChooser,
+        TaskGetter,
     },
 };
 
+use rar_common::util::open_with_privileges;
+
 use super::perform_on_target_opt;
 
+const REVIEW_DAYS: u64 = 90;
+
+#[allow(clippy::too_many_arguments)]
 pub fn list_json(
     rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
     role_id: Option<String>,
@@ -25,21 +42,38 @@ pub fn list_json(
     options_type: Option<OptType>,
     task_type: Option<TaskType>,
     role_type: Option<RoleType>,
-) -> Result<(), Box<dyn Error>> {
+    verbose: bool,
+) -> Result<String, Box<dyn Error>> {
     let config = rconfig.as_ref().borrow();
     debug!("list_json {:?}", config);
     if let Some(role_id) = role_id {
         if let Some(role) = rconfig.role(&role_id) {
-            list_task(task_id, &role, options, options_type, task_type, role_type)
+            list_task(
+                task_id,
+                &role,
+                options,
+                options_type,
+                task_type,
+                role_type,
+                verbose,
+            )
         } else {
             Err("Role not found".into())
         }
     } else {
-        println!("{}", serde_json::to_string_pretty(config.deref()).unwrap());
-        Ok(())
+        to_listing(config.deref(), verbose)
     }
 }
 
+/// Policy comments are only displayed in verbose listings.
+fn to_listing<T: Serialize>(value: &T, verbose: bool) -> Result<String, Box<dyn Error>> {
+    let mut value = serde_json::to_value(value)?;
+    if !verbose {
+        strip_comments(&mut value);
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
 fn list_task(
     task_id: Option<IdTask>,
     role: &Rc<RefCell<rar_common::database::structs::SRole>>,
@@ -47,99 +81,76 @@ fn list_task(
     options_type: Option<OptType>,
     task_type: Option<TaskType>,
     role_type: Option<RoleType>,
-) -> Result<(), Box<dyn Error>> {
-    if let Some(task_id) = task_id {
-        if let Some(task) = role.as_ref().borrow().task(&task_id) {
-            if options {
-                let rcopt = OptStack::from_task(task.clone()).to_opt();
-                let opt = rcopt.as_ref().borrow();
-                if let Some(opttype) = options_type {
-                    match opttype {
-                        OptType::Env => {
-                            println!("{}", serde_json::to_string_pretty(&opt.env).unwrap());
-                        }
-                        OptType::Path => {
-                            println!("{}", serde_json::to_string_pretty(&opt.path).unwrap());
-                        }
-                        OptType::Root => {
-                            println!("{}", serde_json::to_string_pretty(&opt.root).unwrap());
-                        }
-                        OptType::Bounding => {
-                            println!("{}", serde_json::to_string_pretty(&opt.bounding).unwrap());
-                        }
-                        OptType::Wildcard => {
-                            println!(
-                                "{}",
-                                serde_json::to_string_pretty(&opt.wildcard_denied).unwrap()
-                            );
-                        }
-                        OptType::Timeout => {
-                            println!("{}", serde_json::to_string_pretty(&opt.timeout).unwrap());
-                        }
-                    }
-                } else {
-                    println!("{}", serde_json::to_string_pretty(&rcopt)?);
-                }
-            } else {
-                print_task(task, task_type.unwrap_or(TaskType::All));
-            }
-        } else {
-            return Err("Task not found".into());
+    verbose: bool,
+) -> Result<String, Box<dyn Error>> {
+    let Some(task_id) = task_id else {
+        if options {
+            return Ok(serde_json::to_string_pretty(
+                &OptStack::from_role(role.clone()).to_opt(),
+            )?);
         }
-    } else if options {
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&OptStack::from_role(role.clone()).to_opt())?
-        );
-    } else {
-        print_role(role, &role_type.unwrap_or(RoleType::All));
+        return print_role(role, &role_type.unwrap_or(RoleType::All), verbose);
+    };
+    let task = role
+        .as_ref()
+        .borrow()
+        .task(&task_id)
+        .cloned()
+        .ok_or("Task not found")?;
+    if !options {
+        return print_task(&task, task_type.unwrap_or(TaskType::All), verbose);
     }
-    Ok(())
+    let rcopt = OptStack::from_task(task.clone()).to_opt();
+    let opt = rcopt.as_ref().borrow();
+    let Some(opttype) = options_type else {
+        return Ok(serde_json::to_string_pretty(&rcopt)?);
+    };
+    Ok(match opttype {
+        OptType::Env => serde_json::to_string_pretty(&opt.env)?,
+        OptType::Path => serde_json::to_string_pretty(&opt.path)?,
+        OptType::Root => serde_json::to_string_pretty(&opt.root)?,
+        OptType::Bounding => serde_json::to_string_pretty(&opt.bounding)?,
+        OptType::Wildcard => {
+            let mut output = serde_json::to_string_pretty(&opt.wildcard_denied)?;
+            if !opt.wildcard_denied_preset.is_empty() {
+                output.push_str(&format!(
+                    "\nFrom the presets: {}",
+                    opt.wildcard_denied_preset
+                        .iter()
+                        .map(|preset| preset.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            output
+        }
+        OptType::Timeout => serde_json::to_string_pretty(&opt.timeout)?,
+    })
 }
 
 fn print_task(
     task: &std::rc::Rc<std::cell::RefCell<rar_common::database::structs::STask>>,
     task_type: TaskType,
-) {
+    verbose: bool,
+) -> Result<String, Box<dyn Error>> {
     match task_type {
-        TaskType::All => {
-            println!("{}", serde_json::to_string_pretty(&task).unwrap());
-        }
-        TaskType::Commands => {
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&task.as_ref().borrow().commands).unwrap()
-            );
-        }
-        TaskType::Credentials => {
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&task.as_ref().borrow().cred).unwrap()
-            );
-        }
+        TaskType::All => to_listing(&task, verbose),
+        TaskType::Commands => to_listing(&task.as_ref().borrow().commands, verbose),
+        TaskType::Credentials => Ok(serde_json::to_string_pretty(&task.as_ref().borrow().cred)?),
     }
 }
 
 fn print_role(
     role: &std::rc::Rc<std::cell::RefCell<rar_common::database::structs::SRole>>,
     role_type: &RoleType,
-) {
+    verbose: bool,
+) -> Result<String, Box<dyn Error>> {
     match role_type {
-        RoleType::All => {
-            println!("{}", serde_json::to_string_pretty(&role).unwrap());
-        }
-        RoleType::Actors => {
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&role.as_ref().borrow().actors).unwrap()
-            );
-        }
-        RoleType::Tasks => {
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&role.as_ref().borrow().tasks).unwrap()
-            );
-        }
+        RoleType::All => to_listing(&role, verbose),
+        RoleType::Actors => Ok(serde_json::to_string_pretty(
+            &role.as_ref().borrow().actors,
+        )?),
+        RoleType::Tasks => to_listing(&role.as_ref().borrow().tasks, verbose),
     }
 }
 
@@ -269,6 +280,220 @@ pub fn task_add_del(
     }
 }
 
+pub fn role_from_template(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    role_id: String,
+    template: String,
+    actors: Option<Vec<rar_common::database::actor::SActor>>,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr role r1 add --template {}", template);
+    if rconfig.role(&role_id).is_some() {
+        return Err("Role already exists".into());
+    }
+    let mut role = SRole::builder(role_id);
+    for task in template_tasks(&template)? {
+        role = role.task(task);
+    }
+    for actor in actors.unwrap_or_default() {
+        role = role.actor(actor);
+    }
+    let role = role.build();
+    role.as_ref().borrow_mut()._config = Some(Rc::downgrade(rconfig));
+    rconfig.as_ref().borrow_mut().roles.push(role);
+    Ok(true)
+}
+
+pub fn set_enabled(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    role_id: String,
+    task_id: Option<IdTask>,
+    enabled: bool,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr role r1 [task t1] enable|disable");
+    // enabled is the default, it is not written in the policy
+    let state = if enabled { None } else { Some(false) };
+    match task_id {
+        Some(task_id) => {
+            let task = rconfig.task(&role_id, task_id)?;
+            task.as_ref().borrow_mut().enabled = state;
+        }
+        None => {
+            let role = rconfig.role(&role_id).ok_or("Role not found")?;
+            role.as_ref().borrow_mut().enabled = state;
+        }
+    }
+    Ok(true)
+}
+
+pub fn import_sudo_logs(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    path: String,
+    progress: Progress,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr import --from-sudo-logs {}", path);
+    let file = open_with_privileges(&path)?;
+    let entries = parse_logs(BufReader::new(file));
+    let roles = propose_roles(&entries);
+    progress.start("import", roles.len());
+    let mut skipped = 0;
+    for (i, role) in roles.iter().enumerate() {
+        let name = role.as_ref().borrow().name.clone();
+        progress.step("import", i + 1, roles.len(), &name);
+        if rconfig.role(&name).is_some() {
+            println!("Role {} already exists, skipping", name);
+            progress.failure("import", &name, "Role already exists");
+            skipped += 1;
+            continue;
+        }
+        println!(
+            "Proposing role {} with {} tasks, disabled until reviewed",
+            name,
+            role.as_ref().borrow().tasks.len()
+        );
+        role.as_ref().borrow_mut()._config = Some(Rc::downgrade(rconfig));
+        rconfig.as_ref().borrow_mut().roles.push(role.clone());
+    }
+    progress.done("import", roles.len() - skipped, skipped);
+    Ok(roles.len() > skipped)
+}
+
+// the line numbers of a sudoers file change, a rule is identified by its content
+fn same_rule(a: &Rc<RefCell<STask>>, b: &Rc<RefCell<STask>>) -> bool {
+    let rule = |task: &Rc<RefCell<STask>>| {
+        let mut value = serde_json::to_value(&*task.as_ref().borrow()).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.remove("name");
+            object.remove("purpose");
+        }
+        value
+    };
+    rule(a) == rule(b)
+}
+
+/// Roles of the same name are merged, with their missing actors and tasks.
+pub fn import_sudoers(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    path: String,
+    progress: Progress,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr import --from-sudoers {}", path);
+    let import = parse_sudoers(BufReader::new(open_with_privileges(&path)?))?;
+    for untranslated in &import.untranslated {
+        println!(
+            "line {}: {}: {}",
+            untranslated.line, untranslated.reason, untranslated.text
+        );
+    }
+    progress.start("import", import.roles.len());
+    let mut changed = false;
+    let mut unchanged = 0;
+    for (i, role) in import.roles.iter().enumerate() {
+        let name = role.as_ref().borrow().name.clone();
+        progress.step("import", i + 1, import.roles.len(), &name);
+        let Some(existing) = rconfig.role(&name) else {
+            println!(
+                "Adding role {} with {} tasks",
+                name,
+                role.as_ref().borrow().tasks.len()
+            );
+            role.as_ref().borrow_mut()._config = Some(Rc::downgrade(rconfig));
+            rconfig.as_ref().borrow_mut().roles.push(role.clone());
+            changed = true;
+            continue;
+        };
+        let mut merged = 0;
+        let actors = std::mem::take(&mut role.as_ref().borrow_mut().actors);
+        for actor in actors {
+            let mut existing = existing.as_ref().borrow_mut();
+            if !existing.actors.iter().any(|a| a.same_actor(&actor)) {
+                existing.actors.push(actor);
+                merged += 1;
+            }
+        }
+        for task in role.as_ref().borrow().tasks.iter() {
+            if existing
+                .as_ref()
+                .borrow()
+                .tasks
+                .iter()
+                .any(|t| same_rule(t, task))
+            {
+                continue;
+            }
+            let name = task.as_ref().borrow().name.to_string();
+            let mut i = 1;
+            while existing.task(&task.as_ref().borrow().name).is_some() {
+                i += 1;
+                task.as_ref().borrow_mut().name = IdTask::Name(format!("{}_{}", name, i));
+            }
+            task.as_ref().borrow_mut()._role = Some(Rc::downgrade(&existing));
+            existing.as_ref().borrow_mut().tasks.push(task.clone());
+            merged += 1;
+        }
+        if merged == 0 {
+            println!("Role {} is already imported, skipping", name);
+            progress.failure("import", &name, "Role already imported");
+            unchanged += 1;
+        } else {
+            println!("Merging {} actors and tasks into role {}", merged, name);
+            changed = true;
+        }
+    }
+    progress.done("import", import.roles.len() - unchanged, unchanged);
+    println!(
+        "{} roles imported, {} unchanged, {} entries not translated",
+        import.roles.len() - unchanged,
+        unchanged,
+        import.untranslated.len()
+    );
+    Ok(changed)
+}
+
+pub fn review_grants(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    older_than: Option<u64>,
+    decisions: Option<String>,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr review {:?} {:?}", older_than, decisions);
+    let today = Local::now().date_naive();
+    let cutoff = today - chrono::Duration::days(older_than.unwrap_or(REVIEW_DAYS) as i64);
+    if let Some(path) = decisions {
+        let entries = parse_decisions(BufReader::new(open_with_privileges(&path)?))?;
+        review(rconfig, cutoff, today, |role, actor| {
+            Ok(from_decisions(&entries, role, actor))
+        })
+    } else {
+        let mut stdin = std::io::stdin().lock();
+        let mut stdout = std::io::stdout();
+        review(rconfig, cutoff, today, |role, actor| {
+            ask(&mut stdin, &mut stdout, role, actor)
+        })
+    }
+}
+
+pub fn batch_grant_revoke(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    path: String,
+    action: InputAction,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr grant|revoke --from-csv {}", path);
+    let grant = action == InputAction::Add;
+    let (rows, mut errors) = parse_csv(BufReader::new(open_with_privileges(&path)?))?;
+    let summary = apply(rconfig, rows, grant, Local::now().date_naive());
+    errors.extend(summary.errors);
+    for error in errors.iter() {
+        eprintln!("{}", error);
+    }
+    println!(
+        "{} {}, {} unchanged, {} failed",
+        summary.applied,
+        if grant { "granted" } else { "revoked" },
+        summary.unchanged,
+        errors.len()
+    );
+    Ok(summary.applied > 0)
+}
+
 pub fn grant_revoke(
     rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
     role_id: String,
@@ -282,25 +507,39 @@ pub fn grant_revoke(
             //verify if actor is already in role
             //remove already existing actors
             actors.retain(|a| {
-                if role.as_ref().borrow().actors.contains(a) {
+                if role
+                    .as_ref()
+                    .borrow()
+                    .actors
+                    .iter()
+                    .any(|r| r.same_actor(a))
+                {
                     println!("Actor {} already in role", a);
                     false
                 } else {
                     true
                 }
             });
+            let today = Local::now().date_naive();
+            actors.iter_mut().for_each(|a| a.set_granted(today));
             role.as_ref().borrow_mut().actors.extend(actors);
             Ok(true)
         }
         InputAction::Del => {
             //if actor is not in role, warns
-            if !role.as_ref().borrow().actors.contains(&actors[0]) {
+            if !role
+                .as_ref()
+                .borrow()
+                .actors
+                .iter()
+                .any(|r| r.same_actor(&actors[0]))
+            {
                 println!("Actor {} not in role", actors[0]);
             }
             role.as_ref()
                 .borrow_mut()
                 .actors
-                .retain(|a| !actors.contains(a));
+                .retain(|a| !actors.iter().any(|r| r.same_actor(a)));
             Ok(true)
         }
         _ => unreachable!("Invalid action"),
@@ -479,6 +718,8 @@ pub fn json_wildcard(
 ) -> Result<bool, Box<dyn Error>> {
     debug!("chsr o wildcard add|del");
     perform_on_target_opt(rconfig, role_id, task_id, |opt: Rc<RefCell<Opt>>| {
+        // the characters no longer are those of the presets
+        opt.as_ref().borrow_mut().wildcard_denied_preset.clear();
         match action {
             InputAction::Set => {
                 opt.as_ref().borrow_mut().wildcard_denied = Some(options_wildcard.clone());
@@ -515,17 +756,39 @@ pub fn json_wildcard(
     Ok(true)
 }
 
+pub fn json_wildcard_preset(
+    rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
+    role_id: Option<String>,
+    task_id: Option<IdTask>,
+    presets: Vec<SWildcardPreset>,
+) -> Result<bool, Box<dyn Error>> {
+    debug!("chsr o wildcard preset");
+    perform_on_target_opt(rconfig, role_id, task_id, |opt: Rc<RefCell<Opt>>| {
+        let mut opt = opt.as_ref().borrow_mut();
+        opt.wildcard_denied = Some(SWildcardPreset::expand(&presets));
+        opt.wildcard_denied_preset = presets.clone();
+        Ok(())
+    })?;
+    Ok(true)
+}
+
 pub fn cmd_whitelist_action(
     rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
     role_id: String,
     task_id: IdTask,
     cmd_id: Vec<String>,
+    digest: bool,
     setlist_type: SetListType,
     action: InputAction,
 ) -> Result<bool, Box<dyn Error>> {
     debug!("chsr role r1 task t1 command whitelist add c1");
     let task = rconfig.task(&role_id, task_id)?;
-    let cmd = SCommand::Simple(shell_words::join(cmd_id.iter()));
+    let joined = shell_words::join(cmd_id.iter());
+    let cmd = if digest {
+        pin_command(&cmd_id)?
+    } else {
+        SCommand::Simple(joined.clone())
+    };
     match setlist_type {
         SetListType::White => match action {
             InputAction::Add => {
@@ -540,9 +803,11 @@ pub fn cmd_whitelist_action(
                 if !task.as_ref().borrow().commands.add.contains(&cmd) {
                     println!("Command {:?} not in task", cmd);
                 }
+                // with its pinned digests
                 task.as_ref().borrow_mut().commands.add.retain(|c| {
                     debug!("'{:?}' != '{:?}' : {}", c, &cmd, *c != cmd);
                     *c != cmd
+                        && !matches!(c, SCommand::Complex(c) if c["hash"].is_string() && c["command"] == joined.as_str())
                 });
             }
             _ => unreachable!("Unknown action {:?}", action),
@@ -573,6 +838,16 @@ pub fn cmd_whitelist_action(
     Ok(true)
 }
 
+#[cfg(feature = "finder")]
+fn pin_command(cmd_id: &[String]) -> Result<SCommand, Box<dyn Error>> {
+    rar_common::plugin::pin_command(cmd_id)
+}
+
+#[cfg(not(feature = "finder"))]
+fn pin_command(_: &[String]) -> Result<SCommand, Box<dyn Error>> {
+    Err("chsr is built without the finder feature, the digests cannot be pinned".into())
+}
+
 pub fn cmd_setpolicy(
     rconfig: &Rc<RefCell<rar_common::database::structs::SConfig>>,
     role_id: String,
@@ -0,0 +1,53 @@
+use serde_json::{json, Value};
+
+/// Progress events of long operations, written as JSON lines on stderr
+/// so wrapping tools can follow them without parsing the human output.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Progress {
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Progress { enabled }
+    }
+
+    fn emit(&self, event: &str, operation: &str, mut fields: Value) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(fields) = fields.as_object_mut() {
+            fields.insert("event".to_string(), event.into());
+            fields.insert("operation".to_string(), operation.into());
+        }
+        eprintln!("{}", fields);
+    }
+
+    pub fn start(&self, operation: &str, total: usize) {
+        self.emit("start", operation, json!({ "total": total }));
+    }
+
+    pub fn step(&self, operation: &str, current: usize, total: usize, item: &str) {
+        self.emit(
+            "progress",
+            operation,
+            json!({ "current": current, "total": total, "item": item }),
+        );
+    }
+
+    pub fn failure(&self, operation: &str, item: &str, reason: &str) {
+        self.emit(
+            "failure",
+            operation,
+            json!({ "item": item, "reason": reason }),
+        );
+    }
+
+    pub fn done(&self, operation: &str, succeeded: usize, failed: usize) {
+        self.emit(
+            "done",
+            operation,
+            json!({ "succeeded": succeeded, "failed": failed }),
+        );
+    }
+}
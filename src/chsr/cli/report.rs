@@ -0,0 +1,320 @@
+// `chsr report`: size and complexity metrics of the policy, journaled to watch it grow.
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use chrono::{DateTime, Local};
+use rar_common::{
+    database::{
+        options::Opt,
+        structs::{SCommand, SConfig, SetBehavior},
+    },
+    util::{append_with_privileges, create_dir_all_with_privileges, open_with_privileges},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+pub const HISTORY_FILE: &str = "/var/log/rootasrole/metrics.jsonl";
+#[cfg(test)]
+pub const HISTORY_FILE: &str = "target/metrics.jsonl";
+
+// largest option overrides shown
+const OVERRIDES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Wildcards {
+    /// Tasks allowing every command
+    pub all_commands: usize,
+    /// Commands given as patterns or regular expressions
+    pub command_patterns: usize,
+    /// Tasks granting every capability
+    pub all_capabilities: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    pub scope: String,
+    pub options: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Metrics {
+    pub roles: usize,
+    pub tasks: usize,
+    pub actors: usize,
+    pub commands: usize,
+    pub wildcards: Wildcards,
+    pub capabilities_per_task: f64,
+    pub overrides: Vec<Override>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    #[serde(flatten)]
+    pub metrics: Metrics,
+}
+
+fn is_pattern(command: &SCommand) -> bool {
+    match command {
+        SCommand::Simple(command) => command.contains(['*', '?', '[', '^', '$', '|', '(']),
+        SCommand::Complex(_) => false,
+    }
+}
+
+// the options set at this level
+fn overridden(options: &Option<Rc<RefCell<Opt>>>) -> usize {
+    options
+        .as_ref()
+        .and_then(|opt| serde_json::to_value(&*opt.as_ref().borrow()).ok())
+        .and_then(|value| value.as_object().map(|map| map.len()))
+        .unwrap_or(0)
+}
+
+pub fn metrics(config: &Rc<RefCell<SConfig>>) -> Metrics {
+    let mut metrics = Metrics::default();
+    let mut capabilities = 0;
+    for role in config.as_ref().borrow().roles.iter() {
+        let role = role.as_ref().borrow();
+        metrics.roles += 1;
+        metrics.actors += role.actors.len();
+        metrics.overrides.push(Override {
+            scope: role.name.clone(),
+            options: overridden(&role.options),
+        });
+        for task in role.tasks.iter() {
+            let task = task.as_ref().borrow();
+            metrics.tasks += 1;
+            metrics.commands += task.commands.add.len();
+            metrics.wildcards.command_patterns += task
+                .commands
+                .add
+                .iter()
+                .filter(|command| is_pattern(command))
+                .count();
+            if task.commands.default_behavior == Some(SetBehavior::All) {
+                metrics.wildcards.all_commands += 1;
+            }
+            if let Some(caps) = &task.cred.capabilities {
+                if caps.default_behavior.is_all() {
+                    metrics.wildcards.all_capabilities += 1;
+                }
+                capabilities += caps.to_capset().size();
+            }
+            metrics.overrides.push(Override {
+                scope: format!("{}/{}", role.name, task.name),
+                options: overridden(&task.options),
+            });
+        }
+    }
+    if metrics.tasks > 0 {
+        metrics.capabilities_per_task = capabilities as f64 / metrics.tasks as f64;
+    }
+    metrics.overrides.retain(|o| o.options > 0);
+    metrics
+        .overrides
+        .sort_by(|a, b| b.options.cmp(&a.options).then(a.scope.cmp(&b.scope)));
+    metrics.overrides.truncate(OVERRIDES);
+    metrics
+}
+
+/// The journaled metrics, oldest first. Lines that cannot be read are skipped.
+pub fn history<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(BufReader::new(open_with_privileges(path)?)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+/// Journals the metrics when they differ from the last journaled ones.
+pub fn record<P: AsRef<Path>>(
+    path: P,
+    entry: &Entry,
+    last: Option<&Entry>,
+) -> Result<bool, Box<dyn Error>> {
+    if last.is_some_and(|last| last.metrics == entry.metrics) {
+        return Ok(false);
+    }
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        create_dir_all_with_privileges(parent)?;
+    }
+    writeln!(
+        append_with_privileges(path)?,
+        "{}",
+        serde_json::to_string(entry)?
+    )?;
+    Ok(true)
+}
+
+fn delta(now: usize, before: Option<usize>) -> String {
+    match before.map(|before| now as i64 - before as i64) {
+        Some(delta) if delta != 0 => format!(" ({:+})", delta),
+        _ => String::new(),
+    }
+}
+
+/// The metrics, with their evolution since the `last` journaled ones.
+pub fn to_text(metrics: &Metrics, last: Option<&Entry>) -> String {
+    let before = last.map(|entry| &entry.metrics);
+    let mut output = String::new();
+    if let Some(last) = last {
+        let _ = writeln!(
+            output,
+            "Compared to {}",
+            last.time.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    for (name, now, before) in [
+        ("Roles", metrics.roles, before.map(|m| m.roles)),
+        ("Tasks", metrics.tasks, before.map(|m| m.tasks)),
+        ("Actors", metrics.actors, before.map(|m| m.actors)),
+        ("Commands", metrics.commands, before.map(|m| m.commands)),
+        (
+            "Tasks allowing every command",
+            metrics.wildcards.all_commands,
+            before.map(|m| m.wildcards.all_commands),
+        ),
+        (
+            "Command patterns",
+            metrics.wildcards.command_patterns,
+            before.map(|m| m.wildcards.command_patterns),
+        ),
+        (
+            "Tasks granting every capability",
+            metrics.wildcards.all_capabilities,
+            before.map(|m| m.wildcards.all_capabilities),
+        ),
+    ] {
+        let _ = writeln!(output, "{}: {}{}", name, now, delta(now, before));
+    }
+    let _ = writeln!(
+        output,
+        "Capabilities per task: {:.2}",
+        metrics.capabilities_per_task
+    );
+    if !metrics.overrides.is_empty() {
+        let _ = writeln!(output, "Largest option overrides:");
+        for o in metrics.overrides.iter() {
+            let _ = writeln!(output, "  {}: {} options", o.scope, o.options);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use rar_common::database::{
+        options::SPrivileged,
+        structs::{SCapabilities, SCommands, SCredentials, SRole, STask},
+    };
+
+    use super::*;
+
+    fn config() -> Rc<RefCell<SConfig>> {
+        SConfig::builder()
+            .role(
+                SRole::builder("r_admin")
+                    .task(
+                        STask::builder("t_all")
+                            .cred(
+                                SCredentials::builder()
+                                    .capabilities(SCapabilities::builder(SetBehavior::All).build())
+                                    .build(),
+                            )
+                            .commands(SCommands::builder(SetBehavior::All).build())
+                            .build(),
+                    )
+                    .task(
+                        STask::builder("t_net")
+                            .cred(
+                                SCredentials::builder()
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::NET_RAW)
+                                            .add_cap(Cap::NET_ADMIN)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([
+                                        SCommand::Simple("/usr/bin/ip link".to_string()),
+                                        SCommand::Simple("/usr/bin/ping .*".to_string()),
+                                    ])
+                                    .build(),
+                            )
+                            .options(|opt| {
+                                opt.root(SPrivileged::Privileged)
+                                    .wildcard_denied(";")
+                                    .build()
+                            })
+                            .build(),
+                    )
+                    .options(|opt| opt.root(SPrivileged::User).build())
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = metrics(&config());
+        assert_eq!((metrics.roles, metrics.tasks, metrics.commands), (1, 2, 2));
+        assert_eq!(
+            metrics.wildcards,
+            Wildcards {
+                all_commands: 1,
+                command_patterns: 1,
+                all_capabilities: 1,
+            }
+        );
+        assert_eq!(
+            metrics.overrides,
+            [
+                Override {
+                    scope: "r_admin/t_net".to_string(),
+                    options: 2
+                },
+                Override {
+                    scope: "r_admin".to_string(),
+                    options: 1
+                }
+            ]
+        );
+        assert!(metrics.capabilities_per_task > 2.0);
+    }
+
+    #[test]
+    fn test_record() {
+        let path = Path::new("target/rar_metrics_test.jsonl");
+        let _ = std::fs::remove_file(path);
+        let entry = Entry {
+            time: Local::now(),
+            metrics: metrics(&config()),
+        };
+        assert!(record(path, &entry, None).unwrap());
+        let history = history(path).unwrap();
+        assert_eq!(history.len(), 1);
+        // unchanged metrics are not journaled again
+        assert!(!record(path, &entry, history.last()).unwrap());
+        let text = to_text(&Metrics::default(), history.last());
+        assert!(text.contains("Roles: 0 (-1)"));
+        assert!(text.contains("Commands: 0 (-2)"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
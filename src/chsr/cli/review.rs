@@ -0,0 +1,232 @@
+// Periodic access review: each grant older than the cutoff is kept or revoked.
+use std::{
+    cell::RefCell,
+    error::Error,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use chrono::NaiveDate;
+use rar_common::database::{
+    actor::{SActor, SGroupType, SGroups},
+    structs::SConfig,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Decision {
+    Keep,
+    Revoke,
+    Skip,
+}
+
+pub struct DecisionEntry {
+    role: String,
+    actor: SActor,
+    decision: Decision,
+}
+
+fn is_due(actor: &SActor, cutoff: NaiveDate) -> bool {
+    actor.last_certified().map_or(true, |date| date < cutoff)
+}
+
+/// Ask a decision for each due grant, returns whether the policy changed.
+pub fn review<F>(
+    config: &Rc<RefCell<SConfig>>,
+    cutoff: NaiveDate,
+    today: NaiveDate,
+    mut decide: F,
+) -> Result<bool, Box<dyn Error>>
+where
+    F: FnMut(&str, &SActor) -> Result<Decision, Box<dyn Error>>,
+{
+    let mut changed = false;
+    for role in config.as_ref().borrow().roles.iter() {
+        let mut role = role.as_ref().borrow_mut();
+        let name = role.name.clone();
+        let mut revoked = Vec::new();
+        for (i, actor) in role.actors.iter_mut().enumerate() {
+            if !is_due(actor, cutoff) {
+                continue;
+            }
+            match decide(&name, actor)? {
+                Decision::Keep => {
+                    actor.set_reviewed(today);
+                    changed = true;
+                }
+                Decision::Revoke => {
+                    revoked.push(i);
+                    changed = true;
+                }
+                Decision::Skip => {}
+            }
+        }
+        let mut i = 0;
+        role.actors.retain(|_| {
+            i += 1;
+            !revoked.contains(&(i - 1))
+        });
+    }
+    Ok(changed)
+}
+
+// policies written by hand or by other tools may hold numeric ids
+pub(crate) fn parse_actor(actor: &str) -> Result<SActor, Box<dyn Error>> {
+    match actor.split_once(':') {
+        Some(("user", name)) => Ok(match name.parse::<u32>() {
+            Ok(id) => SActor::user(id).build(),
+            Err(_) => SActor::user(name).build(),
+        }),
+        Some(("group", names)) => {
+            let groups: Vec<SGroupType> = names
+                .split(',')
+                .map(|name| {
+                    name.parse::<u32>()
+                        .map_or_else(|_| SGroupType::from(name), SGroupType::from)
+                })
+                .collect();
+            Ok(SActor::group(if groups.len() == 1 {
+                SGroups::Single(groups[0].clone())
+            } else {
+                SGroups::Multiple(groups)
+            })
+            .build())
+        }
+        _ => Err(format!(
+            "Invalid actor {}, expected user:<name> or group:<names>",
+            actor
+        )
+        .into()),
+    }
+}
+
+/// Lines are `<role> user:<name>|group:<name>[,<name>...] keep|revoke`, `#` starts a comment.
+pub fn parse_decisions<R: BufRead>(reader: R) -> Result<Vec<DecisionEntry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [role, actor, decision] = fields[..] else {
+            return Err(format!("Invalid decision line: {}", line).into());
+        };
+        let decision = match decision {
+            "keep" => Decision::Keep,
+            "revoke" => Decision::Revoke,
+            _ => {
+                return Err(
+                    format!("Invalid decision {}, expected keep or revoke", decision).into(),
+                )
+            }
+        };
+        entries.push(DecisionEntry {
+            role: role.to_string(),
+            actor: parse_actor(actor)?,
+            decision,
+        });
+    }
+    Ok(entries)
+}
+
+pub fn from_decisions(entries: &[DecisionEntry], role: &str, actor: &SActor) -> Decision {
+    entries
+        .iter()
+        .find(|entry| entry.role == role && entry.actor.same_actor(actor))
+        .map_or(Decision::Skip, |entry| entry.decision)
+}
+
+pub fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    role: &str,
+    actor: &SActor,
+) -> Result<Decision, Box<dyn Error>> {
+    let certified = actor
+        .last_certified()
+        .map_or("never".to_string(), |date| date.to_string());
+    write!(
+        output,
+        "[{}] {} (last certified: {}) keep, revoke or skip? [k/r/S] ",
+        role, actor, certified
+    )?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(match answer.trim() {
+        "k" | "keep" => Decision::Keep,
+        "r" | "revoke" => Decision::Revoke,
+        _ => Decision::Skip,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::structs::SRole;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_review() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_admin")
+                    .actor(SActor::user("alice").granted(date("2024-01-10")).build())
+                    .actor(SActor::user("bob").build())
+                    .actor(SActor::user("carol").granted(date("2026-10-01")).build())
+                    .actor(SActor::group(["adm", "wheel"]).build())
+                    .build(),
+            )
+            .build();
+        let decisions = parse_decisions(
+            "# recertification 2026-Q4\nr_admin user:alice keep\nr_admin user:bob revoke\nr_admin user:carol revoke\n".as_bytes(),
+        )
+        .unwrap();
+        let today = date("2026-10-14");
+        let changed = review(&config, date("2026-07-16"), today, |role, actor| {
+            Ok(from_decisions(&decisions, role, actor))
+        })
+        .unwrap();
+        assert!(changed);
+        let role = config.as_ref().borrow().roles[0].clone();
+        let actors = &role.as_ref().borrow().actors;
+        assert_eq!(actors.len(), 3);
+        assert_eq!(actors[0].last_certified(), Some(today));
+        // carol was granted after the cutoff, so her grant was not due
+        assert!(actors[1].same_actor(&SActor::user("carol").build()));
+        // skipped, still due at the next review
+        assert_eq!(actors[2].last_certified(), None);
+    }
+
+    #[test]
+    fn test_parse_decisions() {
+        assert!(parse_decisions("r1 user:alice maybe".as_bytes()).is_err());
+        assert!(parse_decisions("r1 alice keep".as_bytes()).is_err());
+        let entries = parse_decisions("r1 group:adm,wheel revoke".as_bytes()).unwrap();
+        assert_eq!(
+            from_decisions(&entries, "r1", &SActor::group(["wheel", "adm"]).build()),
+            Decision::Revoke
+        );
+        assert_eq!(
+            from_decisions(&entries, "r2", &SActor::group(["wheel", "adm"]).build()),
+            Decision::Skip
+        );
+    }
+
+    #[test]
+    fn test_ask() {
+        let mut output = Vec::new();
+        let actor = SActor::user("alice").build();
+        let decision = ask(&mut "r\n".as_bytes(), &mut output, "r1", &actor).unwrap();
+        assert_eq!(decision, Decision::Revoke);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[r1] User: alice (last certified: never) keep, revoke or skip? [k/r/S] "
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    io::BufRead,
+    path::Path,
+    rc::Rc,
+};
+
+use glob::Pattern;
+use rar_common::database::{
+    actor::SActor,
+    structs::{SCommand, SCommands, SCredentials, SRole, STask, SetBehavior},
+};
+
+/// A successful sudo invocation found in an authentication log
+#[derive(Debug, PartialEq, Eq)]
+pub struct SudoEntry {
+    pub user: String,
+    pub runas: String,
+    pub command: String,
+}
+
+/// Parse a sudo syslog line such as
+/// `Oct 10 12:00:00 host sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update`
+/// Refused and failed attempts are ignored as they are not an observed usage.
+pub fn parse_line(line: &str) -> Option<SudoEntry> {
+    let (_, rest) = line.split_once(" sudo:")?;
+    let (user, fields) = rest.split_once(" : ")?;
+    let user = user.trim();
+    if user.is_empty() || user.contains(char::is_whitespace) {
+        return None;
+    }
+    // COMMAND is the last field and may contain the field separator
+    let (fields, command) = fields.split_once("COMMAND=")?;
+    let mut runas = "root";
+    for field in fields.split(';').map(str::trim).filter(|f| !f.is_empty()) {
+        match field.split_once('=')? {
+            ("USER", value) => runas = value,
+            (key, _) if key.contains(char::is_whitespace) => return None,
+            _ => {}
+        }
+    }
+    Some(SudoEntry {
+        user: user.to_string(),
+        runas: runas.to_string(),
+        command: command.trim().to_string(),
+    })
+}
+
+pub fn parse_logs<R: BufRead>(reader: R) -> Vec<SudoEntry> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| parse_line(&line))
+        .collect()
+}
+
+struct ObservedTask {
+    count: usize,
+    commands: BTreeMap<String, usize>,
+}
+
+fn task_name(runas: &str, executable: &str, used: &mut HashSet<String>) -> String {
+    let base = Path::new(executable)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| executable.to_string());
+    let base = if runas == "root" {
+        base
+    } else {
+        format!("{}_as_{}", base, runas)
+    };
+    let mut name = base.clone();
+    let mut i = 1;
+    while !used.insert(name.clone()) {
+        i += 1;
+        name = format!("{}_{}", base, i);
+    }
+    name
+}
+
+// the logged arguments only match themselves, whether the role argument is read as a
+// shell pattern or as a regex
+fn literal_arg(arg: &str) -> String {
+    arg.chars()
+        .map(|c| match c {
+            '*' | '?' | '[' | '.' | '+' | '(' | ')' | '|' | '{' | '}' | '$' => format!("[{}]", c),
+            '\\' | '^' | ']' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// The logged command line as a command of the policy, without arguments when none were given.
+fn literal_command(command: &str) -> SCommand {
+    let mut words = command.split_whitespace();
+    let path = Pattern::escape(words.next().unwrap_or_default());
+    let mut args: Vec<String> = words.map(literal_arg).collect();
+    if args.is_empty() {
+        args.push("^$".to_string());
+    }
+    SCommand::Simple(shell_words::join(std::iter::once(path).chain(args)))
+}
+
+/// Propose one role per user, with one task per executable and target user.
+/// Tasks and commands are ordered by decreasing usage. The tasks only change the user,
+/// capabilities are left for the administrator to grant. The roles are disabled until the
+/// administrator reviewed them.
+pub fn propose_roles(entries: &[SudoEntry]) -> Vec<Rc<RefCell<SRole>>> {
+    let mut users: BTreeMap<&str, BTreeMap<(&str, &str), ObservedTask>> = BTreeMap::new();
+    for entry in entries {
+        let executable = entry
+            .command
+            .split_whitespace()
+            .next()
+            .unwrap_or(&entry.command);
+        let task = users
+            .entry(&entry.user)
+            .or_default()
+            .entry((&entry.runas, executable))
+            .or_insert_with(|| ObservedTask {
+                count: 0,
+                commands: BTreeMap::new(),
+            });
+        task.count += 1;
+        *task.commands.entry(entry.command.clone()).or_default() += 1;
+    }
+    users
+        .into_iter()
+        .map(|(user, tasks)| {
+            let mut tasks: Vec<_> = tasks.into_iter().collect();
+            tasks.sort_by_key(|(_, task)| std::cmp::Reverse(task.count));
+            let mut used = HashSet::new();
+            let mut role = SRole::builder(format!("sudo_{}", user))
+                .enabled(false)
+                .actor(SActor::user(user).build());
+            for ((runas, executable), observed) in tasks {
+                let mut commands: Vec<_> = observed.commands.into_iter().collect();
+                commands.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+                role = role.task(
+                    STask::builder(task_name(runas, executable, &mut used))
+                        .purpose(format!("Observed {} times in sudo logs", observed.count))
+                        .cred(SCredentials::builder().setuid(runas).build())
+                        .commands(
+                            SCommands::builder(SetBehavior::None)
+                                .add(commands.iter().map(|(cmd, _)| literal_command(cmd)))
+                                .build(),
+                        )
+                        .build(),
+                );
+            }
+            role.build()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::{
+        finder::{Cred, TaskMatcher},
+        structs::{IdTask, RoleGetter, SConfig},
+    };
+
+    use super::*;
+
+    const LOG: &str = "\
+Oct 10 12:00:00 host sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update
+Oct 10 12:00:00 host sudo: pam_unix(sudo:session): session opened for user root(uid=0) by alice(uid=1000)
+Oct 10 12:01:00 host sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt upgrade
+Oct 10 12:02:00 host sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update
+Oct 10 12:03:00 host sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=postgres ; COMMAND=/usr/bin/psql
+Oct 10 12:04:00 host sudo:      bob : 3 incorrect password attempts ; TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/usr/bin/id
+Oct 10 12:05:00 host sudo:      bob : command not allowed ; TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/usr/bin/su
+Oct 10 12:06:00 host sudo:      bob : TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/usr/bin/systemctl restart nginx
+";
+
+    #[test]
+    fn test_parse_logs() {
+        let entries = parse_logs(LOG.as_bytes());
+        assert_eq!(entries.len(), 5);
+        assert_eq!(
+            entries[0],
+            SudoEntry {
+                user: "alice".to_string(),
+                runas: "root".to_string(),
+                command: "/usr/bin/apt update".to_string(),
+            }
+        );
+        assert_eq!(entries[3].runas, "postgres");
+        assert_eq!(entries[4].user, "bob");
+    }
+
+    #[test]
+    fn test_propose_roles() {
+        let roles = propose_roles(&parse_logs(LOG.as_bytes()));
+        assert_eq!(roles.len(), 2);
+        let alice = roles[0].as_ref().borrow();
+        assert_eq!(alice.name, "sudo_alice");
+        assert_eq!(alice.enabled, Some(false));
+        assert!(alice.actors.contains(&SActor::user("alice").build()));
+        assert_eq!(alice.tasks.len(), 2);
+        let apt = alice.tasks[0].as_ref().borrow();
+        assert_eq!(apt.name, IdTask::Name("apt".to_string()));
+        assert_eq!(
+            apt.commands.add,
+            vec![
+                SCommand::Simple("/usr/bin/apt update".to_string()),
+                SCommand::Simple("/usr/bin/apt upgrade".to_string())
+            ]
+        );
+        let psql = alice.tasks[1].as_ref().borrow();
+        assert_eq!(psql.name, IdTask::Name("psql_as_postgres".to_string()));
+        assert_eq!(
+            psql.commands.add,
+            vec![SCommand::Simple("/usr/bin/psql '^$'".to_string())]
+        );
+        assert_eq!(roles[1].as_ref().borrow().tasks.len(), 1);
+    }
+
+    #[test]
+    fn test_literal_command() {
+        assert_eq!(
+            literal_command("/usr/bin/rm -rf /tmp/*.log"),
+            SCommand::Simple("/usr/bin/rm -rf '/tmp/[*][.]log'".to_string())
+        );
+        assert_eq!(
+            literal_command("/usr/bin/[ -f a|b ]"),
+            SCommand::Simple("'/usr/bin/[[]' -f 'a[|]b' '\\]'".to_string())
+        );
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r1")
+                    .task(
+                        STask::builder("t1")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([
+                                        literal_command("/usr/bin/rm -rf /tmp/*.log"),
+                                        literal_command("/usr/bin/id"),
+                                    ])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let task = config.task("r1", "t1").unwrap();
+        let cred = Cred::builder().build();
+        let line = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>();
+        assert!(task
+            .matches(&cred, &None, &line("/usr/bin/rm -rf /tmp/*.log"))
+            .is_ok());
+        assert!(task
+            .matches(&cred, &None, &line("/usr/bin/rm -rf /tmp/a.log"))
+            .is_err());
+        assert!(task.matches(&cred, &None, &line("/usr/bin/id")).is_ok());
+        assert!(task.matches(&cred, &None, &line("/usr/bin/id -u")).is_err());
+    }
+}
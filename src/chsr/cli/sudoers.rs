@@ -0,0 +1,647 @@
+// Translation of a sudoers policy into roles and tasks.
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::BufRead,
+    rc::Rc,
+};
+
+use rar_common::database::{
+    actor::{SActor, SGroupType, SGroups, SUserType},
+    options::{Level, Opt, SAuthentication},
+    structs::{
+        SCommand, SCommands, SCredentials, SGroupschooser, SRole, SSetgidSet, SSetuidSet, STask,
+        SUserChooser, SetBehavior,
+    },
+};
+
+/// A part of the sudoers file that has no equivalent, it is left out of the import.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Untranslated {
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Default)]
+pub struct Import {
+    pub roles: Vec<Rc<RefCell<SRole>>>,
+    pub untranslated: Vec<Untranslated>,
+}
+
+#[derive(Default)]
+struct Aliases {
+    users: HashMap<String, Vec<String>>,
+    runas: HashMap<String, Vec<String>>,
+    hosts: HashMap<String, Vec<String>>,
+    commands: HashMap<String, Vec<String>>,
+}
+
+// the commands of a rule sharing the same target and tags
+#[derive(Default)]
+struct Rule {
+    runas_users: Vec<String>,
+    runas_groups: Vec<String>,
+    nopasswd: bool,
+    all: bool,
+    add: Vec<SCommand>,
+    sub: Vec<SCommand>,
+}
+
+fn is_alias_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        && name != "ALL"
+}
+
+// a comment starts with #, except #<uid> and the include directives
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    for (i, c) in line.char_indices() {
+        if c == '#'
+            && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+            && !line[i..].starts_with("#include")
+        {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+// lines ending with a backslash continue on the next line
+fn logical_lines<R: BufRead>(reader: R) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = strip_comment(&line).trim_end();
+        let (start, mut text) = current.take().unwrap_or((i + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                text.push_str(line);
+                text.push(' ');
+                current = Some((start, text));
+            }
+            None => {
+                text.push_str(line);
+                if !text.trim().is_empty() {
+                    lines.push((start, text.trim().to_string()));
+                }
+            }
+        }
+    }
+    if let Some((start, text)) = current {
+        lines.push((start, text.trim().to_string()));
+    }
+    Ok(lines)
+}
+
+// splits on the separator, except when it is escaped or within parentheses
+fn split_list(list: &str, separator: char) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                if let Some(next) = chars.next() {
+                    item.push(next);
+                }
+            }
+            '(' => {
+                depth += 1;
+                item.push(c);
+            }
+            ')' => {
+                depth -= 1;
+                item.push(c);
+            }
+            c if c == separator && depth == 0 => {
+                items.push(item.trim().to_string());
+                item.clear();
+            }
+            c => item.push(c),
+        }
+    }
+    items.push(item.trim().to_string());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn expand(
+    items: &[String],
+    aliases: &HashMap<String, Vec<String>>,
+    visited: &mut HashSet<String>,
+) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for item in items {
+        let (negated, name) = match item.strip_prefix('!') {
+            Some(name) => (true, name.trim()),
+            None => (false, item.as_str()),
+        };
+        match aliases.get(name) {
+            Some(members) if visited.insert(name.to_string()) => {
+                for member in expand(members, aliases, visited) {
+                    expanded.push(match (negated, member.strip_prefix('!')) {
+                        (true, Some(member)) => member.to_string(),
+                        (true, None) => format!("!{}", member),
+                        (false, _) => member,
+                    });
+                }
+                visited.remove(name);
+            }
+            _ => expanded.push(item.clone()),
+        }
+    }
+    expanded
+}
+
+fn parse_alias(
+    aliases: &mut HashMap<String, Vec<String>>,
+    definitions: &str,
+) -> Result<(), String> {
+    for definition in split_list(definitions, ':') {
+        let (name, members) = definition
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid alias definition {}", definition))?;
+        let name = name.trim();
+        if !is_alias_name(name) {
+            return Err(format!("Invalid alias name {}", name));
+        }
+        aliases.insert(name.to_string(), split_list(members, ','));
+    }
+    Ok(())
+}
+
+fn actor(user: &str) -> Result<SActor, String> {
+    if let Some(group) = user.strip_prefix("%#") {
+        let gid = group
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid group id {}", group))?;
+        return Ok(SActor::group(SGroups::Single(SGroupType::from(gid))).build());
+    }
+    if user.starts_with("%:") || user.starts_with('+') {
+        return Err(format!(
+            "Non-Unix group or netgroup {} has no equivalent",
+            user
+        ));
+    }
+    if let Some(group) = user.strip_prefix('%') {
+        return Ok(SActor::group(group).build());
+    }
+    if let Some(uid) = user.strip_prefix('#') {
+        let uid = uid
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid user id {}", uid))?;
+        return Ok(SActor::user(uid).build());
+    }
+    match user {
+        "ALL" => Err("Granting every user has no equivalent, grant the role explicitly".into()),
+        user if user.starts_with('!') => Err(format!("Negated user {} has no equivalent", user)),
+        user => Ok(SActor::user(user).build()),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next)) if ",:=\\ ".contains(next) => {
+                result.push(next);
+                chars.next();
+            }
+            (c, _) => result.push(c),
+        }
+    }
+    result
+}
+
+// sudo wildcards in the arguments become an anchored regex, they need the pcre2 feature
+fn glob_to_regex(args: &str) -> Result<String, String> {
+    let mut regex = String::from("^");
+    for c in args.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' | ']' => return Err("Character classes in arguments have no equivalent".into()),
+            c if "\\.+()|{}^$".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+fn command(command: &str) -> Result<SCommand, String> {
+    if ["sha224:", "sha256:", "sha384:", "sha512:"]
+        .iter()
+        .any(|digest| command.starts_with(digest))
+    {
+        return Err("Command digests have no equivalent".into());
+    }
+    if command == "sudoedit" || command.starts_with("sudoedit ") {
+        return Err("sudoedit has no equivalent".into());
+    }
+    let (path, args) = match command.split_once(char::is_whitespace) {
+        Some((path, args)) => (path, Some(args.trim())),
+        None => (command, None),
+    };
+    if !path.starts_with('/') {
+        return Err(format!("The command path {} is not absolute", path));
+    }
+    if path.ends_with('/') || path.contains(['*', '?', '[']) {
+        return Err(format!(
+            "The command path {} designates several commands",
+            path
+        ));
+    }
+    Ok(SCommand::Simple(match args {
+        // any arguments
+        None => path.to_string(),
+        // no arguments
+        Some("\"\"") => format!("{} ^$", path),
+        Some(args) if args.contains(['*', '?', '[']) => {
+            format!("{} {}", path, glob_to_regex(&unescape(args))?)
+        }
+        Some(args) => format!("{} {}", path, unescape(args)),
+    }))
+}
+
+const IGNORED_TAGS: [&str; 6] = [
+    "PASSWD",
+    "EXEC",
+    "FOLLOW",
+    "MAIL",
+    "LOG_INPUT",
+    "LOG_OUTPUT",
+];
+
+// runas and tags apply to the following commands of the list until they are overridden
+fn parse_commands(
+    line: usize,
+    list: &str,
+    aliases: &Aliases,
+    untranslated: &mut Vec<Untranslated>,
+) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut runas_users = vec!["root".to_string()];
+    let mut runas_groups = Vec::new();
+    let mut nopasswd = false;
+    for spec in split_list(list, ',') {
+        let mut rest = spec.as_str();
+        if let Some(runas) = rest.strip_prefix('(') {
+            let Some((runas, after)) = runas.split_once(')') else {
+                untranslated.push(Untranslated {
+                    line,
+                    text: spec.clone(),
+                    reason: "Unterminated runas specification".into(),
+                });
+                continue;
+            };
+            let (users, groups) = runas.split_once(':').unwrap_or((runas, ""));
+            let mut visited = HashSet::new();
+            runas_users = expand(&split_list(users, ','), &aliases.runas, &mut visited);
+            runas_groups = expand(&split_list(groups, ','), &aliases.runas, &mut visited);
+            // (:group) keeps the caller as user in sudo, the role runs as root by default
+            if runas_users.is_empty() && runas_groups.is_empty() {
+                runas_users = vec!["root".to_string()];
+            }
+            rest = after.trim_start();
+        }
+        while let Some((tag, after)) = rest.split_once(':').filter(|(tag, _)| {
+            !tag.is_empty() && tag.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+        }) {
+            match tag {
+                "NOPASSWD" => nopasswd = true,
+                "PASSWD" => nopasswd = false,
+                tag if IGNORED_TAGS.contains(&tag) => {}
+                tag => untranslated.push(Untranslated {
+                    line,
+                    text: spec.clone(),
+                    reason: format!("Tag {} has no equivalent", tag),
+                }),
+            }
+            rest = after.trim_start();
+        }
+        let rule_index = match rules.iter().position(|rule| {
+            rule.runas_users == runas_users
+                && rule.runas_groups == runas_groups
+                && rule.nopasswd == nopasswd
+        }) {
+            Some(index) => index,
+            None => {
+                rules.push(Rule {
+                    runas_users: runas_users.clone(),
+                    runas_groups: runas_groups.clone(),
+                    nopasswd,
+                    ..Default::default()
+                });
+                rules.len() - 1
+            }
+        };
+        let rule = &mut rules[rule_index];
+        for cmd in expand(&[rest.to_string()], &aliases.commands, &mut HashSet::new()) {
+            let (negated, cmd) = match cmd.strip_prefix('!') {
+                Some(cmd) => (true, cmd.trim().to_string()),
+                None => (false, cmd),
+            };
+            if cmd == "ALL" {
+                if negated {
+                    rule.add.clear();
+                    rule.all = false;
+                } else {
+                    rule.all = true;
+                }
+                continue;
+            }
+            match command(&cmd) {
+                Ok(cmd) if negated => rule.sub.push(cmd),
+                Ok(cmd) => rule.add.push(cmd),
+                Err(reason) => untranslated.push(Untranslated {
+                    line,
+                    text: cmd,
+                    reason,
+                }),
+            }
+        }
+    }
+    rules.retain(|rule| rule.all || !rule.add.is_empty());
+    rules
+}
+
+fn credentials(rule: &Rule) -> SCredentials {
+    let setuid: Option<SUserChooser> = match &rule.runas_users[..] {
+        [] => None,
+        users if users.iter().any(|user| user == "ALL") => {
+            Some(SSetuidSet::builder("root", SetBehavior::All).build().into())
+        }
+        [user] => Some(SUserType::from(user.as_str()).into()),
+        [first, rest @ ..] => Some(
+            SSetuidSet::builder(first.as_str(), SetBehavior::None)
+                .add(rest.iter().map(|user| SUserType::from(user.as_str())))
+                .build()
+                .into(),
+        ),
+    };
+    let setgid: Option<SGroupschooser> = match &rule.runas_groups[..] {
+        [] => None,
+        groups if groups.iter().any(|group| group == "ALL") => {
+            Some(SSetgidSet::builder("root", SetBehavior::All).build().into())
+        }
+        groups => Some(
+            SGroups::Multiple(
+                groups
+                    .iter()
+                    .map(|group| SGroupType::from(group.as_str()))
+                    .collect(),
+            )
+            .into(),
+        ),
+    };
+    SCredentials::builder()
+        .maybe_setuid(setuid)
+        .maybe_setgid(setgid)
+        .build()
+}
+
+fn role_name(users: &[String], used: &mut HashSet<String>) -> String {
+    let first = users.first().map(String::as_str).unwrap_or("all");
+    let base = format!(
+        "sudoers_{}",
+        first
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+            .trim_matches('_')
+    );
+    let mut name = base.clone();
+    let mut i = 1;
+    while !used.insert(name.clone()) {
+        i += 1;
+        name = format!("{}_{}", base, i);
+    }
+    name
+}
+
+/// Translate the user specifications into one role per line, with one task per target and tags.
+/// Aliases are expanded, Defaults, includes and host restrictions are reported as untranslated.
+pub fn parse_sudoers<R: BufRead>(reader: R) -> Result<Import, Box<dyn Error>> {
+    let mut import = Import::default();
+    let mut aliases = Aliases::default();
+    let mut used = HashSet::new();
+    for (line, text) in logical_lines(reader)? {
+        let mut untranslated = |reason: &str| {
+            import.untranslated.push(Untranslated {
+                line,
+                text: text.clone(),
+                reason: reason.to_string(),
+            })
+        };
+        let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((&text, ""));
+        let aliases_of = match keyword {
+            "User_Alias" => Some(&mut aliases.users),
+            "Runas_Alias" => Some(&mut aliases.runas),
+            "Host_Alias" => Some(&mut aliases.hosts),
+            "Cmnd_Alias" | "Cmd_Alias" => Some(&mut aliases.commands),
+            _ => None,
+        };
+        if let Some(aliases_of) = aliases_of {
+            if let Err(reason) = parse_alias(aliases_of, rest) {
+                untranslated(&reason);
+            }
+            continue;
+        }
+        if keyword.starts_with("Defaults") {
+            untranslated("Defaults have no equivalent");
+            continue;
+        }
+        if ["#include", "#includedir", "@include", "@includedir"].contains(&keyword) {
+            untranslated("Included files are not followed, import them separately");
+            continue;
+        }
+        let Some((who, commands)) = text.split_once('=') else {
+            untranslated("Unrecognized line");
+            continue;
+        };
+        // user lists may contain spaces after their commas
+        let who = split_list(who, ',').join(",");
+        let [users, hosts] = who.split_whitespace().collect::<Vec<_>>()[..] else {
+            untranslated("Unrecognized user specification");
+            continue;
+        };
+        let hosts = expand(&split_list(hosts, ','), &aliases.hosts, &mut HashSet::new());
+        if hosts != ["ALL"] {
+            untranslated("Host restrictions have no equivalent, the policy applies to this host");
+            continue;
+        }
+        let users = expand(&split_list(users, ','), &aliases.users, &mut HashSet::new());
+        let actors: Result<Vec<SActor>, String> = users.iter().map(|user| actor(user)).collect();
+        let actors = match actors {
+            Ok(actors) => actors,
+            Err(reason) => {
+                untranslated(&reason);
+                continue;
+            }
+        };
+        let rules = parse_commands(line, commands, &aliases, &mut import.untranslated);
+        if rules.is_empty() {
+            continue;
+        }
+        let mut role = SRole::builder(role_name(&users, &mut used));
+        for actor in actors {
+            role = role.actor(actor);
+        }
+        for (i, rule) in rules.iter().enumerate() {
+            let name = match i {
+                0 => format!("line_{}", line),
+                i => format!("line_{}_{}", line, i + 1),
+            };
+            let task = STask::builder(name)
+                .purpose(format!("Imported from line {} of sudoers", line))
+                .cred(credentials(rule))
+                .commands(
+                    SCommands::builder(if rule.all {
+                        SetBehavior::All
+                    } else {
+                        SetBehavior::None
+                    })
+                    .add(if rule.all { vec![] } else { rule.add.clone() })
+                    .sub(rule.sub.clone())
+                    .build(),
+                )
+                .build();
+            if rule.nopasswd {
+                task.as_ref().borrow_mut().options = Some(
+                    Opt::builder(Level::Task)
+                        .authentication(SAuthentication::Skip)
+                        .build(),
+                );
+            }
+            role = role.task(task);
+        }
+        import.roles.push(role.build());
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::structs::IdTask;
+
+    use super::*;
+
+    const SUDOERS: &str = r#"# sample policy
+Defaults env_reset
+User_Alias ADMINS = alice, %wheel
+Cmnd_Alias SERVICES = /usr/bin/systemctl restart *, \
+                      /usr/bin/systemctl status
+Cmnd_Alias SHELLS = /bin/sh, /bin/bash
+Host_Alias WEB = web1, web2
+
+root    ALL = (ALL:ALL) ALL
+ADMINS  ALL = (root) NOPASSWD: SERVICES, PASSWD: /usr/bin/apt update, (postgres) /usr/bin/psql ""
+bob, carol ALL = ALL, !SHELLS
+dave    WEB = /usr/bin/id
+ALL     ALL = /usr/bin/id
+erin    ALL = sha256:abcd /usr/bin/id, /usr/bin/* , /usr/bin/uptime
+#include /etc/sudoers.local
+"#;
+
+    #[test]
+    fn test_parse_sudoers() {
+        let import = parse_sudoers(SUDOERS.as_bytes()).unwrap();
+        let lines: Vec<usize> = import.untranslated.iter().map(|u| u.line).collect();
+        assert_eq!(lines, [2, 12, 13, 14, 14, 15]);
+        assert_eq!(import.roles.len(), 4);
+
+        let root = import.roles[0].as_ref().borrow();
+        assert_eq!(root.name, "sudoers_root");
+        let task = root.tasks[0].as_ref().borrow();
+        assert_eq!(task.commands.default_behavior, Some(SetBehavior::All));
+        let cred = &task.cred;
+        assert_eq!(
+            cred.setuid,
+            Some(SSetuidSet::builder("root", SetBehavior::All).build().into())
+        );
+        assert_eq!(
+            cred.setgid,
+            Some(SSetgidSet::builder("root", SetBehavior::All).build().into())
+        );
+
+        let admins = import.roles[1].as_ref().borrow();
+        assert_eq!(admins.name, "sudoers_alice");
+        assert!(admins.actors.contains(&SActor::group("wheel").build()));
+        assert_eq!(admins.tasks.len(), 3);
+        let services = admins.tasks[0].as_ref().borrow();
+        assert_eq!(services.name, IdTask::Name("line_10".to_string()));
+        assert_eq!(
+            services.commands.add,
+            vec![
+                SCommand::Simple("/usr/bin/systemctl ^restart .*$".to_string()),
+                SCommand::Simple("/usr/bin/systemctl status".to_string()),
+            ]
+        );
+        assert_eq!(
+            services
+                .options
+                .as_ref()
+                .unwrap()
+                .as_ref()
+                .borrow()
+                .authentication,
+            Some(SAuthentication::Skip)
+        );
+        let apt = admins.tasks[1].as_ref().borrow();
+        assert!(apt.options.is_none());
+        assert_eq!(apt.cred.setuid, Some("root".into()));
+        let psql = admins.tasks[2].as_ref().borrow();
+        assert_eq!(psql.name, IdTask::Name("line_10_3".to_string()));
+        assert_eq!(psql.cred.setuid, Some("postgres".into()));
+        assert_eq!(
+            psql.commands.add,
+            vec![SCommand::Simple("/usr/bin/psql ^$".to_string())]
+        );
+
+        let users = import.roles[2].as_ref().borrow();
+        assert_eq!(users.actors.len(), 2);
+        let task = users.tasks[0].as_ref().borrow();
+        assert_eq!(task.commands.default_behavior, Some(SetBehavior::All));
+        assert_eq!(
+            task.commands.sub,
+            vec![
+                SCommand::Simple("/bin/sh".to_string()),
+                SCommand::Simple("/bin/bash".to_string())
+            ]
+        );
+
+        // the translatable commands of a line are kept
+        let erin = import.roles[3].as_ref().borrow();
+        assert_eq!(
+            erin.tasks[0].as_ref().borrow().commands.add,
+            vec![SCommand::Simple("/usr/bin/uptime".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_command() {
+        assert!(command("ls").is_err());
+        assert_eq!(
+            command(r"/usr/bin/printf a\,b").unwrap(),
+            SCommand::Simple("/usr/bin/printf a,b".to_string())
+        );
+        assert_eq!(
+            command("/usr/bin/cat /var/log/*.log").unwrap(),
+            SCommand::Simple(r"/usr/bin/cat ^/var/log/.*\.log$".to_string())
+        );
+        assert_eq!(strip_comment("#1000 ALL = ALL # uid"), "#1000 ALL = ALL ");
+    }
+}
@@ -0,0 +1,97 @@
+use std::{cell::RefCell, error::Error, rc::Rc};
+
+use rar_common::database::structs::STask;
+
+// Role templates shipped within chsr, each one is the task list of the role.
+const TEMPLATES: [(&str, &str); 3] = [
+    (
+        "network-debug",
+        include_str!("../../../resources/templates/network-debug.json"),
+    ),
+    (
+        "web-server-admin",
+        include_str!("../../../resources/templates/web-server-admin.json"),
+    ),
+    (
+        "db-admin",
+        include_str!("../../../resources/templates/db-admin.json"),
+    ),
+];
+
+pub fn template_names() -> impl Iterator<Item = &'static str> {
+    TEMPLATES.iter().map(|(name, _)| *name)
+}
+
+pub fn template_tasks(name: &str) -> Result<Vec<Rc<RefCell<STask>>>, Box<dyn Error>> {
+    let (_, content) = TEMPLATES
+        .iter()
+        .find(|(template, _)| *template == name)
+        .ok_or_else(|| {
+            format!(
+                "Unknown template {}, available templates: {}",
+                name,
+                template_names().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+    Ok(serde_json::from_str(content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::finder::{Cred, TaskMatcher};
+
+    use super::*;
+
+    #[test]
+    fn test_templates_are_valid() {
+        for name in template_names() {
+            let tasks = template_tasks(name).unwrap();
+            assert!(!tasks.is_empty(), "template {} has no task", name);
+        }
+        assert!(template_tasks("unknown").is_err());
+    }
+
+    fn allows(template: &str, task: &str, command: &str) -> bool {
+        let task = template_tasks(template)
+            .unwrap()
+            .into_iter()
+            .find(|t| t.as_ref().borrow().name.to_string() == task)
+            .unwrap();
+        let command = shell_words::split(command).unwrap();
+        let commands = task.as_ref().borrow().commands.clone();
+        commands
+            .matches(&Cred::builder().build(), &None, &command)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_templates_commands() {
+        let logs = |command| allows("web-server-admin", "logs", command);
+        assert!(logs("/usr/bin/cat /var/log/nginx/access.log"));
+        assert!(logs("/usr/bin/tail -n 100 -f /var/log/apache2/error.log"));
+        assert!(logs("/usr/bin/grep -i -e 404 /var/log/nginx/access.log"));
+        assert!(logs("/usr/bin/journalctl --no-pager -u nginx -n 50"));
+        assert!(!logs("/usr/bin/cat /etc/shadow /var/log/nginx/x"));
+        assert!(!logs("/usr/bin/cat /var/log/nginx/../../../etc/shadow"));
+        assert!(!logs("/usr/bin/cat '/var/log/nginx/a /etc/shadow'"));
+        assert!(!logs(
+            "/usr/bin/grep -e x /etc/shadow /var/log/nginx/access.log"
+        ));
+        assert!(!logs("/usr/bin/less /var/log/nginx/access.log"));
+        assert!(!logs("/usr/bin/journalctl -u nginx"));
+
+        let capture = |command| allows("network-debug", "capture", command);
+        assert!(capture("/usr/sbin/tcpdump"));
+        assert!(capture(
+            "/usr/sbin/tcpdump -nn -i eth0 -c 10 tcp port 443 and host 192.0.2.1"
+        ));
+        assert!(capture("/usr/bin/tcpdump -i any '(port 53 or port 853)'"));
+        assert!(!capture("/usr/sbin/tcpdump -i eth0 -w /tmp/x -z /bin/sh"));
+        assert!(!capture("/usr/sbin/tcpdump -z /bin/sh"));
+        assert!(!capture("/usr/sbin/tcpdump -r /etc/shadow"));
+        assert!(!capture("/usr/sbin/tcpdump -w /tmp/capture"));
+        assert!(!capture("/usr/sbin/tcpdump port 80 -z sh"));
+        assert!(!capture("/usr/sbin/tcpdump -nz sh"));
+        assert!(!capture("/usr/sbin/tcpdump 'port 80 -z sh'"));
+    }
+}
@@ -0,0 +1,618 @@
+// `chsr tui`: browse the roles and tasks and edit them in the terminal. Each edit is run as a
+// chsr command, so it is validated exactly like on the command line.
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    error::Error,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    rc::Rc,
+};
+
+use pest::Parser;
+use rar_common::{database::structs::SConfig, Storage};
+
+use super::{
+    data::{Cli, Inputs, Rule},
+    pair::recurse_pair,
+    process::process_input,
+};
+use crate::util::escape_parser_string_vec;
+
+const KEYS: &str = "↑↓ move  ←→ fold  n new  e edit  d delete  q quit";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Role(String),
+    Task(String, String),
+}
+
+impl Item {
+    fn role(&self) -> &str {
+        match self {
+            Item::Role(role) | Item::Task(role, _) => role,
+        }
+    }
+
+    // the words of chsr designating the item
+    fn words(&self) -> Vec<String> {
+        match self {
+            Item::Role(role) => vec!["r".to_string(), role.clone()],
+            Item::Task(role, task) => {
+                vec!["r".to_string(), role.clone(), "t".to_string(), task.clone()]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    item: Item,
+    label: String,
+}
+
+/// The roles, with the tasks of the expanded ones.
+fn rows(config: &Rc<RefCell<SConfig>>, expanded: &HashSet<String>) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for role in config.as_ref().borrow().roles.iter() {
+        let role = role.as_ref().borrow();
+        let open = expanded.contains(&role.name);
+        rows.push(Row {
+            item: Item::Role(role.name.clone()),
+            label: format!(
+                "{} {}{}",
+                if open { "▾" } else { "▸" },
+                role.name,
+                if role.is_enabled() { "" } else { " (disabled)" }
+            ),
+        });
+        if !open {
+            continue;
+        }
+        for task in role.tasks.iter() {
+            let task = task.as_ref().borrow();
+            rows.push(Row {
+                item: Item::Task(role.name.clone(), task.name.to_string()),
+                label: format!(
+                    "    {}{}",
+                    task.name,
+                    if task.is_enabled() { "" } else { " (disabled)" }
+                ),
+            });
+        }
+    }
+    rows
+}
+
+/// The actors, options and tasks of a role, or the whole task.
+fn details(config: &Rc<RefCell<SConfig>>, item: &Item) -> Vec<String> {
+    let config = config.as_ref().borrow();
+    let Some(role) = config
+        .roles
+        .iter()
+        .find(|r| r.as_ref().borrow().name == item.role())
+    else {
+        return Vec::new();
+    };
+    let role = role.as_ref().borrow();
+    let mut text = String::new();
+    match item {
+        Item::Role(_) => {
+            let _ = writeln!(text, "Role {}", role.name);
+            let _ = writeln!(text, "Actors:");
+            for actor in role.actors.iter() {
+                let _ = writeln!(
+                    text,
+                    "  {}",
+                    serde_json::to_string(actor).unwrap_or_default()
+                );
+            }
+            if let Some(options) = &role.options {
+                let _ = writeln!(
+                    text,
+                    "Options: {}",
+                    serde_json::to_string_pretty(&*options.as_ref().borrow()).unwrap_or_default()
+                );
+            }
+            let _ = writeln!(text, "Tasks: {}", role.tasks.len());
+        }
+        Item::Task(_, name) => {
+            if let Some(task) = role
+                .tasks
+                .iter()
+                .find(|t| t.as_ref().borrow().name.to_string() == *name)
+            {
+                let _ = writeln!(text, "Task {}", name);
+                let _ = write!(
+                    text,
+                    "{}",
+                    serde_json::to_string_pretty(&*task.as_ref().borrow()).unwrap_or_default()
+                );
+            }
+        }
+    }
+    text.lines().map(str::to_string).collect()
+}
+
+/// Runs a chsr command on the policy being edited, parsing errors are returned instead of
+/// printing the usage.
+fn run<S: AsRef<str>>(storage: &Storage, words: &[S]) -> Result<bool, Box<dyn Error>> {
+    let args = escape_parser_string_vec(words.iter().map(AsRef::as_ref));
+    let pairs = Cli::parse(Rule::cli, &args)
+        .map_err(|e| format!("invalid command: {}", e.variant.message()))?;
+    let mut inputs = Inputs::default();
+    for pair in pairs {
+        recurse_pair(pair, &mut inputs)?;
+    }
+    process_input(storage, inputs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Backspace,
+    Char(char),
+}
+
+fn parse_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        [0x1b, b'[', b'A', ..] | [0x1b, b'O', b'A', ..] => Some(Key::Up),
+        [0x1b, b'[', b'B', ..] | [0x1b, b'O', b'B', ..] => Some(Key::Down),
+        [0x1b, b'[', b'C', ..] | [0x1b, b'O', b'C', ..] => Some(Key::Right),
+        [0x1b, b'[', b'D', ..] | [0x1b, b'O', b'D', ..] => Some(Key::Left),
+        [0x1b] => Some(Key::Escape),
+        [0x1b, ..] => None,
+        [b'\r' | b'\n', ..] => Some(Key::Enter),
+        [0x7f | 0x08, ..] => Some(Key::Backspace),
+        // Ctrl-C and Ctrl-D quit, the signals are disabled to restore the terminal
+        [0x03 | 0x04, ..] => Some(Key::Char('q')),
+        bytes => std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .filter(|c| !c.is_control())
+            .map(Key::Char),
+    }
+}
+
+/// The terminal in raw mode, on the alternate screen until dropped.
+struct Screen {
+    saved: libc::termios,
+}
+
+impl Screen {
+    fn open() -> io::Result<Self> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Err(io::Error::other("chsr tui needs a terminal"));
+        }
+        let mut saved = MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let screen = Screen {
+            saved: unsafe { saved.assume_init() },
+        };
+        screen.resume()?;
+        Ok(screen)
+    }
+
+    fn resume(&self) -> io::Result<()> {
+        let mut raw = self.saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()
+    }
+
+    /// Back to the normal screen, to run a command showing its output.
+    fn suspend(&self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?25h\x1b[?1049l")?;
+        stdout.flush()?;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> (usize, usize) {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        let size = if unsafe {
+            libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr())
+        } == 0
+        {
+            unsafe { size.assume_init() }
+        } else {
+            return (24, 80);
+        };
+        match (size.ws_row as usize, size.ws_col as usize) {
+            (0, _) | (_, 0) => (24, 80),
+            size => size,
+        }
+    }
+
+    fn key(&self) -> io::Result<Key> {
+        let mut buffer = [0u8; 8];
+        loop {
+            let n = io::stdin().read(&mut buffer)?;
+            if n == 0 {
+                return Ok(Key::Char('q'));
+            }
+            if let Some(key) = parse_key(&buffer[..n]) {
+                return Ok(key);
+            }
+        }
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+fn fit(text: &str, width: usize) -> String {
+    let mut line: String = text.chars().take(width).collect();
+    let len = line.chars().count();
+    line.push_str(&" ".repeat(width - len));
+    line
+}
+
+struct Tui<'a> {
+    storage: &'a Storage,
+    config: Rc<RefCell<SConfig>>,
+    expanded: HashSet<String>,
+    selected: usize,
+    status: String,
+    modified: bool,
+}
+
+impl<'a> Tui<'a> {
+    fn new(storage: &'a Storage) -> Self {
+        let config = match storage {
+            Storage::JSON(config) => config.clone(),
+        };
+        Tui {
+            storage,
+            config,
+            expanded: HashSet::new(),
+            selected: 0,
+            status: String::new(),
+            modified: false,
+        }
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        rows(&self.config, &self.expanded)
+    }
+
+    fn current(&self) -> Option<Item> {
+        self.rows().get(self.selected).map(|row| row.item.clone())
+    }
+
+    fn select(&mut self, item: &Item) {
+        if let Some(i) = self.rows().iter().position(|row| row.item == *item) {
+            self.selected = i;
+        }
+    }
+
+    fn apply(&mut self, words: &[String]) -> bool {
+        match run(self.storage, words) {
+            Ok(modified) => {
+                self.modified |= modified;
+                true
+            }
+            Err(e) => {
+                self.status = e.to_string();
+                false
+            }
+        }
+    }
+
+    /// The whole screen, `prompt` replacing the status line.
+    fn render(&self, height: usize, width: usize, prompt: Option<&str>) -> String {
+        let rows = self.rows();
+        let left = (width / 3).clamp(12, 32).min(width);
+        let right = width.saturating_sub(left + 3);
+        let body = height.saturating_sub(4);
+        let offset = self.selected.saturating_sub(body.saturating_sub(1));
+        let details = self
+            .current()
+            .map(|item| details(&self.config, &item))
+            .unwrap_or_default();
+        let mut frame = String::from("\x1b[H\x1b[2J");
+        let title = format!("chsr tui{}", if self.modified { " [modified]" } else { "" });
+        let _ = write!(frame, "\x1b[1m{}\x1b[0m\r\n", fit(&title, width));
+        let _ = write!(frame, "{}\r\n", "─".repeat(width));
+        for i in 0..body {
+            let label = rows
+                .get(offset + i)
+                .map(|row| fit(&row.label, left))
+                .unwrap_or_else(|| fit("", left));
+            if offset + i == self.selected && offset + i < rows.len() {
+                let _ = write!(frame, "\x1b[7m{}\x1b[0m", label);
+            } else {
+                frame.push_str(&label);
+            }
+            let _ = write!(
+                frame,
+                " │ {}\r\n",
+                fit(details.get(i).map_or("", String::as_str), right)
+            );
+        }
+        let _ = write!(frame, "{}\r\n", "─".repeat(width));
+        match prompt {
+            Some(prompt) => frame.push_str(&fit(prompt, width)),
+            None if !self.status.is_empty() => frame.push_str(&fit(&self.status, width)),
+            None => frame.push_str(&fit(KEYS, width)),
+        }
+        frame
+    }
+
+    fn draw(&self, screen: &Screen, prompt: Option<&str>) -> io::Result<()> {
+        let (height, width) = screen.size();
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", self.render(height, width, prompt))?;
+        stdout.flush()
+    }
+
+    /// A line typed on the status line, `None` when escaped.
+    fn input(&self, screen: &Screen, question: &str) -> io::Result<Option<String>> {
+        let mut answer = String::new();
+        loop {
+            self.draw(screen, Some(&format!("{}{}_", question, answer)))?;
+            match screen.key()? {
+                Key::Enter => return Ok(Some(answer.trim().to_string())),
+                Key::Escape => return Ok(None),
+                Key::Backspace => {
+                    answer.pop();
+                }
+                Key::Char(c) => answer.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// One of the `choices` keys, `None` when escaped or when another key is pressed.
+    fn choose(&self, screen: &Screen, question: &str, choices: &str) -> io::Result<Option<char>> {
+        self.draw(screen, Some(question))?;
+        Ok(match screen.key()? {
+            Key::Char(c) if choices.contains(c.to_ascii_lowercase()) => {
+                Some(c.to_ascii_lowercase())
+            }
+            _ => None,
+        })
+    }
+
+    fn create(&mut self, screen: &Screen) -> io::Result<()> {
+        let role = self.current().map(|item| item.role().to_string());
+        let kind = match &role {
+            None => Some('r'),
+            Some(role) => self.choose(
+                screen,
+                &format!("Create a (r)ole or a (t)ask of {} ?", role),
+                "rt",
+            )?,
+        };
+        let item = match (kind, role) {
+            (Some('r'), _) => match self.input(screen, "Name of the new role: ")? {
+                Some(name) if !name.is_empty() => Item::Role(name),
+                _ => return Ok(()),
+            },
+            (Some('t'), Some(role)) => match self.input(screen, "Name of the new task: ")? {
+                Some(name) if !name.is_empty() => Item::Task(role, name),
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let mut words = item.words();
+        words.push("add".to_string());
+        if self.apply(&words) {
+            self.expanded.insert(item.role().to_string());
+            self.select(&item);
+            self.status.clear();
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, screen: &Screen) -> io::Result<()> {
+        let Some(item) = self.current() else {
+            return Ok(());
+        };
+        let question = match &item {
+            Item::Role(role) => format!("Delete the role {} and its tasks ? (y/N)", role),
+            Item::Task(role, task) => format!("Delete the task {} of {} ? (y/N)", task, role),
+        };
+        if self.choose(screen, &question, "y")? == Some('y') {
+            let mut words = item.words();
+            words.push("del".to_string());
+            if self.apply(&words) {
+                self.status.clear();
+                self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+            }
+        }
+        Ok(())
+    }
+
+    /// The rest of a chsr command for the selected item, run on the normal screen so that its
+    /// output is shown.
+    fn edit(&mut self, screen: &Screen) -> Result<(), Box<dyn Error>> {
+        let Some(item) = self.current() else {
+            return Ok(());
+        };
+        let mut words = item.words();
+        screen.suspend()?;
+        print!("chsr {} ", words.join(" "));
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        match shell_words::split(&line) {
+            Ok(args) if !args.is_empty() => {
+                words.extend(args);
+                if let Err(e) = run(self.storage, &words).map(|modified| self.modified |= modified)
+                {
+                    println!("chsr: {}", e);
+                }
+                print!("Press Enter to continue");
+                io::stdout().flush()?;
+                io::stdin().read_line(&mut line)?;
+            }
+            Ok(_) => {}
+            Err(e) => self.status = format!("invalid command: {}", e),
+        }
+        screen.resume()?;
+        // the command may have deleted or renamed the item
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn fold(&mut self, open: bool) {
+        match self.current() {
+            Some(Item::Role(role)) if open => {
+                self.expanded.insert(role);
+            }
+            Some(item) if !open => {
+                self.expanded.remove(item.role());
+                self.select(&Item::Role(item.role().to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the changes are to be saved.
+    fn main_loop(&mut self, screen: &Screen) -> Result<bool, Box<dyn Error>> {
+        loop {
+            self.draw(screen, None)?;
+            let key = screen.key()?;
+            if !matches!(key, Key::Char('e')) {
+                self.status.clear();
+            }
+            match key {
+                Key::Up => self.selected = self.selected.saturating_sub(1),
+                Key::Down => {
+                    self.selected = (self.selected + 1).min(self.rows().len().saturating_sub(1))
+                }
+                Key::Right | Key::Enter => self.fold(true),
+                Key::Left => self.fold(false),
+                Key::Char('n') => self.create(screen)?,
+                Key::Char('d') => self.delete(screen)?,
+                Key::Char('e') => self.edit(screen)?,
+                Key::Char('q') | Key::Escape => {
+                    if !self.modified {
+                        return Ok(false);
+                    }
+                    match self.choose(screen, "Save the changes ? (y)es, (n)o, (c)ancel", "ync")? {
+                        Some('y') => return Ok(true),
+                        Some('n') => return Ok(false),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether the policy was modified and is to be saved.
+pub fn tui(storage: &Storage) -> Result<bool, Box<dyn Error>> {
+    let screen = Screen::open()?;
+    Tui::new(storage).main_loop(&screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use rar_common::database::structs::{SRole, STask};
+
+    use super::*;
+
+    fn storage() -> Storage {
+        Storage::JSON(
+            SConfig::builder()
+                .role(
+                    SRole::builder("r_web")
+                        .task(STask::builder("t_nginx").build())
+                        .task(STask::builder("t_off").enabled(false).build())
+                        .build(),
+                )
+                .role(SRole::builder("r_db").build())
+                .build(),
+        )
+    }
+
+    #[test]
+    fn test_rows() {
+        let storage = storage();
+        let mut tui = Tui::new(&storage);
+        assert_eq!(
+            tui.rows().iter().map(|r| &r.item).collect::<Vec<_>>(),
+            [&Item::Role("r_web".into()), &Item::Role("r_db".into())]
+        );
+        tui.fold(true);
+        let rows = tui.rows();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1].item, Item::Task("r_web".into(), "t_nginx".into()));
+        assert!(rows[2].label.ends_with("t_off (disabled)"));
+        tui.selected = 2;
+        tui.fold(false);
+        assert_eq!(tui.current(), Some(Item::Role("r_web".into())));
+        assert_eq!(tui.rows().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key(b"\x1b[A"), Some(Key::Up));
+        assert_eq!(parse_key(b"\x1bOB"), Some(Key::Down));
+        assert_eq!(parse_key(b"\x1b"), Some(Key::Escape));
+        assert_eq!(parse_key(b"\x1b[5~"), None);
+        assert_eq!(parse_key(b"\r"), Some(Key::Enter));
+        assert_eq!(parse_key(b"\x7f"), Some(Key::Backspace));
+        assert_eq!(parse_key(b"\x03"), Some(Key::Char('q')));
+        assert_eq!(parse_key("é".as_bytes()), Some(Key::Char('é')));
+    }
+
+    #[test]
+    fn test_apply() {
+        let storage = storage();
+        let mut tui = Tui::new(&storage);
+        let mut words = Item::Task("r_db".into(), "t_dump".into()).words();
+        words.push("add".to_string());
+        assert!(tui.apply(&words));
+        assert!(tui.modified);
+        tui.expanded.insert("r_db".into());
+        assert!(tui
+            .rows()
+            .iter()
+            .any(|row| row.item == Item::Task("r_db".into(), "t_dump".into())));
+        assert!(tui.apply(&["r".into(), "r_web".into(), "del".into()]));
+        assert_eq!(tui.rows().len(), 2);
+        // the command line validation applies
+        assert!(!tui.apply(&["r".into(), "r_db".into(), "frobnicate".into()]));
+        assert!(tui.status.starts_with("invalid command"));
+    }
+
+    #[test]
+    fn test_render() {
+        let storage = storage();
+        let mut tui = Tui::new(&storage);
+        tui.fold(true);
+        tui.selected = 1;
+        let frame = tui.render(12, 80, None);
+        assert!(frame.contains("▾ r_web"));
+        assert!(frame.contains("Task t_nginx"));
+        assert!(frame.contains(KEYS));
+        let frame = tui.render(12, 80, Some("Name of the new role: r_x_"));
+        assert!(frame.contains("Name of the new role: r_x_"));
+        assert!(!frame.contains(KEYS));
+    }
+}
@@ -17,14 +17,45 @@ const RAR_USAGE_GENERAL: &str = formatcp!("{UNDERLINE}{BOLD}Usage:{RST} {BOLD}ch
 
 {UNDERLINE}{BOLD}Commands:{RST}
   {BOLD}-h, --help{RST}                    Show help for commands and options.
+  {BOLD}--progress{RST} json               Report the progress of long operations as JSON lines on stderr.
+  {BOLD}--force{RST}                       Save even when the policy was modified by someone else meanwhile (first argument).
   {BOLD}list, show, l{RST}                 List available items; use with specific commands for detailed views.
+    {BOLD}-v, --verbose{RST}                Also show the comments attached to roles, tasks and commands.
+    {BOLD}--format{RST} [json|yaml|text]    Show the roles and tasks with their effective options, for scripts.
+    {BOLD}--mine{RST}                       Only show your own roles and tasks, allowed without privileges.
   {BOLD}role, r{RST}                       Manage roles and related operations.
+  {BOLD}init{RST} --admin [user]           Create the first policy, with the default options and the r_root role for this user.
+  {BOLD}activate{RST}                      Sign the current policy so that sr uses it, when activation is configured.
+  {BOLD}audit gc{RST}                      Remove the context snapshots that the retention settings do not keep.
+  {BOLD}audit show{RST} [id]                Show the trace and the log lines of an execution of sr, by its RAR_SESSION_ID.
+  {BOLD}remote refresh{RST}                Download the signed roles of the remote include sources.
+  {BOLD}whoami{RST}                        Show your roles, their option levels and the chsr operations you may perform on them.
+  {BOLD}check, validate{RST}               Lint the policy, exit with an error status when it has errors.
+    {BOLD}--format{RST} [json|yaml|text]    Report the findings for scripts.
+  {BOLD}report{RST}                        Show the size and complexity metrics of the policy and their evolution.
+    {BOLD}--format{RST} [json|yaml|text]    Print the metrics with their history, for scripts.
+  {BOLD}diff{RST} [old] [new]              Show the changes from the current policy, or from the old file, to the new file.
+    {BOLD}--format{RST} [json|yaml|text]    Print the changes for scripts.
+  {BOLD}tui{RST}                           Browse and edit the roles and tasks in the terminal, saved on exit.
+  {BOLD}export{RST} --for-user [user]      Print a policy with only the roles, tasks and effective options of this user.
+    {BOLD}--format{RST} [json|yaml]         Output format, json by default.
+  {BOLD}debug last{RST}                    Show the last policy evaluations recorded by sr, when the trace setting is set.
+    {BOLD}-u, --user{RST} [user]           Only show the evaluations of this user name or uid.
+  {BOLD}import{RST} --from-sudo-logs [file]  Propose roles from the sudo usage of an authentication log.
+  {BOLD}import{RST} --from-sudoers [file]    Translate the rules of a sudoers file into roles and tasks.
+  {BOLD}grant, revoke{RST} --from-csv [file]  Grant or revoke roles in bulk, one actor,role[,expiry] row per grant.
+  {BOLD}review{RST}                        Keep or revoke each grant not certified for a while.
+    {BOLD}--older-than{RST} [days]         Review grants last certified before this many days (default 90).
+    {BOLD}--decisions{RST} [file]          Read the keep or revoke decisions from a file instead of asking.
 ",UNDERLINE=UNDERLINE, BOLD=BOLD, RST=RST);
 
 const RAR_USAGE_ROLE: &str = formatcp!("{UNDERLINE}{BOLD}Role Operations:{RST}
 chsr role [role_name] [operation] [options]
   {BOLD}add, create{RST}                   Add a new role.
+    {BOLD}-T, --template{RST} [template]   Create the role from a shipped template (network-debug, web-server-admin, db-admin).
+    {BOLD}--file{RST} [file]               Create the role in a file of the include directory, for roles shipped by packages.
   {BOLD}del, delete, unset, d, rm{RST}     Delete a specified role.
+  {BOLD}enable, disable{RST}               Resume or suspend a role without removing it.
   {BOLD}show, list, l{RST}                 Show details of a specified role (actors, tasks, all).
   {BOLD}purge{RST}                         Remove all items from a role (actors, tasks, all).
   
@@ -40,6 +71,7 @@ chsr role [role_name] task [task_name] [operation]
   {BOLD}purge{RST}                         Purge configurations or credentials of a task (all, cmd, cred).
   {BOLD}add, create{RST}                   Add a new task.
   {BOLD}del, delete, unset, d, rm{RST}     Remove a task.
+  {BOLD}enable, disable{RST}               Resume or suspend a task without removing it.
 ",UNDERLINE=UNDERLINE, BOLD=BOLD, RST=RST);
 
 const RAR_USAGE_CMD: &str = formatcp!(
@@ -49,6 +81,8 @@ chsr role [role_name] task [task_name] command [cmd]
   {BOLD}setpolicy{RST} [policy]            Set policy for commands (allow-all, deny-all).
   {BOLD}whitelist, wl{RST} [listing]       Manage the whitelist for commands.
   {BOLD}blacklist, bl{RST} [listing]       Manage the blacklist for commands.
+  {BOLD}digest update{RST}                 Compute again the pinned digests of the commands.
+Example : chsr r r1 t t1 cmd whitelist add --digest /usr/bin/ls -l
 ",
     UNDERLINE = UNDERLINE,
     BOLD = BOLD,
@@ -73,6 +107,7 @@ chsr role [role_name] task [task_name] credentials caps [operation]
   {BOLD}setpolicy{RST} [policy]            Set policy for capabilities (allow-all, deny-all).
   {BOLD}whitelist, wl{RST} [listing]       Manage whitelist for credentials.
   {BOLD}blacklist, bl{RST} [listing]       Manage blacklist for credentials.
+  A capability may be a named set, e.g. @network, @filesystem, @process or a set of the policy.
 ",
     UNDERLINE = UNDERLINE,
     BOLD = BOLD,
@@ -87,7 +122,7 @@ chsr role [role_name] task [task_name] options [option] [operation]
   {BOLD}env{RST}                           Manage environment variable settings (set, whitelist, blacklist, checklist).
   {BOLD}root{RST} [policy]                 Defines when the root user (uid == 0) gets his privileges by default. (privileged, user, inherit)
   {BOLD}bounding{RST} [policy]             Defines when dropped capabilities are permanently removed in the instantiated process. (strict, ignore, inherit)
-  {BOLD}wildcard-denied{RST}               Manage chars that are denied in binary path.
+  {BOLD}wildcard-denied{RST}               Manage chars that are denied in binary path (set, add, del, preset).
   {BOLD}timeout{RST}                       Manage timeout settings (set, unset).
 ",UNDERLINE=UNDERLINE, BOLD=BOLD, RST=RST);
 
@@ -226,7 +261,8 @@ pub fn print_usage(e: pest::error::Error<Rule>) -> Result<bool, Box<dyn Error>>
             | Rule::opt_bounding_args
             | Rule::opt_wildcard
             | Rule::opt_wildcard_args
-            | Rule::wildcard_value => {
+            | Rule::wildcard_value
+            | Rule::wildcard_preset => {
                 usage = usage_concat(&[
                     RAR_USAGE_OPTIONS_GENERAL,
                     RAR_USAGE_OPTIONS_PATH,
@@ -0,0 +1,211 @@
+use std::{cell::RefCell, error::Error, ffi::CString, fmt::Write, rc::Rc};
+
+use nix::unistd::{getgid, getgrouplist, getgroups, User};
+use rar_common::database::{
+    finder::{Cred, CredMatcher, TaskMatcher},
+    options::OptStack,
+    structs::SConfig,
+};
+
+const CHSR: &str = "/usr/bin/chsr";
+
+/// The user who runs chsr: the caller of sr, exported in RAR_USER, when chsr runs through sr.
+pub fn caller() -> Result<Cred, Box<dyn Error>> {
+    cred_of(std::env::var("RAR_USER").ok())
+}
+
+fn cred_of(name: Option<String>) -> Result<Cred, Box<dyn Error>> {
+    let Some(name) = name else {
+        let mut groups = getgroups()?;
+        groups.insert(0, getgid());
+        return Ok(Cred::builder().groups(groups).build());
+    };
+    let user = User::from_name(&name)?.ok_or_else(|| format!("Unknown user {}", name))?;
+    let groups = getgrouplist(&CString::new(name)?, user.gid)?;
+    Ok(Cred::builder().user_id(user.uid).groups(groups).build())
+}
+
+// whether a representative command line of an administrative scope is allowed to the caller
+fn probe(
+    report: &mut String,
+    rconfig: &Rc<RefCell<SConfig>>,
+    cred: &Cred,
+    scope: &str,
+    args: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let command: Vec<String> = std::iter::once(CHSR)
+        .chain(args.iter().copied())
+        .map(str::to_string)
+        .collect();
+    match rconfig.matches(cred, &None, &command) {
+        Ok(taskmatch) if taskmatch.fully_matching() => writeln!(
+            report,
+            "{}: allowed by role {}, task {}",
+            scope,
+            taskmatch.role().as_ref().borrow().name,
+            taskmatch.task().as_ref().borrow().name
+        )?,
+        _ => writeln!(report, "{}: denied", scope)?,
+    }
+    Ok(())
+}
+
+pub fn whoami(rconfig: &Rc<RefCell<SConfig>>, cred: &Cred) -> Result<String, Box<dyn Error>> {
+    let mut report = String::new();
+    writeln!(report, "User: {} ({})", cred.user.name, cred.user.uid)?;
+    writeln!(
+        report,
+        "Groups: {}",
+        cred.groups
+            .iter()
+            .map(|g| g.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    let roles: Vec<_> = rconfig
+        .as_ref()
+        .borrow()
+        .roles
+        .iter()
+        .filter(|role| !role.user_matches(cred).is_no_match())
+        .cloned()
+        .collect();
+    writeln!(report, "Roles:")?;
+    for role in &roles {
+        let optstack = OptStack::from_role(role.clone());
+        let (root_level, root) = optstack.get_root_behavior();
+        let (bounding_level, bounding) = optstack.get_bounding();
+        let (auth_level, auth) = optstack.get_authentication();
+        writeln!(
+            report,
+            "  {}: root {} ({:?}), bounding {} ({:?}), authentication {} ({:?})",
+            role.as_ref().borrow().name,
+            root,
+            root_level,
+            bounding,
+            bounding_level,
+            auth,
+            auth_level
+        )?;
+    }
+    writeln!(report, "chsr operations:")?;
+    let user = cred.user.name.as_str();
+    probe(&mut report, rconfig, cred, "  show the policy", &["list"])?;
+    probe(
+        &mut report,
+        rconfig,
+        cred,
+        "  manage global options",
+        &["options", "root", "user"],
+    )?;
+    for role in &roles {
+        let role = role.as_ref().borrow();
+        let name = role.name.as_str();
+        writeln!(report, "  role {}:", name)?;
+        probe(
+            &mut report,
+            rconfig,
+            cred,
+            "    grant it",
+            &["role", name, "grant", "-u", user],
+        )?;
+        probe(
+            &mut report,
+            rconfig,
+            cred,
+            "    manage its options",
+            &["role", name, "options", "root", "user"],
+        )?;
+        for task in &role.tasks {
+            let id = task.as_ref().borrow().name.to_string();
+            writeln!(report, "    task {}:", id)?;
+            let task = ["role", name, "task", id.as_str()];
+            for (scope, operation) in [
+                (
+                    "      manage its commands",
+                    &["cmd", "whitelist", "add", "/usr/bin/true"][..],
+                ),
+                (
+                    "      manage its credentials",
+                    &["cred", "set", "--setuid", user],
+                ),
+                ("      manage its options", &["options", "root", "user"]),
+            ] {
+                probe(
+                    &mut report,
+                    rconfig,
+                    cred,
+                    scope,
+                    &[&task[..], operation].concat(),
+                )?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        structs::{SCommand, SCommands, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_whoami() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_auditor")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_show")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple(format!("{} list", CHSR))])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_team")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_manage")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple(format!(
+                                        "{} role r_team task t_manage cmd .*",
+                                        CHSR
+                                    ))])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .role(SRole::builder("r_other").build())
+            .build();
+        let report = whoami(&config, &Cred::builder().build()).unwrap();
+        assert!(report.contains("  r_auditor: root User (Default)"));
+        assert!(!report.contains("r_other"));
+        assert!(report.contains("  show the policy: allowed by role r_auditor, task t_show"));
+        assert!(report.contains("  manage global options: denied"));
+        assert!(report.contains(
+            "  role r_team:\n    grant it: denied\n    manage its options: denied\n    task t_manage:\n      manage its commands: allowed by role r_team, task t_manage\n      manage its credentials: denied\n"
+        ), "{}", report);
+    }
+
+    #[test]
+    fn test_caller() {
+        let root = cred_of(Some("root".to_string())).unwrap();
+        assert!(root.user.uid.is_root());
+        assert!(root.groups.iter().any(|group| group.gid == root.user.gid));
+        assert!(cred_of(Some("rar_unknown_user".to_string())).is_err());
+        assert_eq!(cred_of(None).unwrap().user.uid, getuid());
+    }
+}
@@ -1,9 +1,14 @@
 //extern crate sudoers_reader;
 
-use log::{debug, error};
+use capctl::{Cap, CapState};
+use log::{debug, error, warn};
+use nix::unistd::geteuid;
 use rar_common::{
     database::{read_json_config, save_json},
-    plugin::register_plugins,
+    integrity::{integrity_settings, policy_digest, policy_path},
+    lock::PolicyLock,
+    logger::configure as configure_logger,
+    plugin::{check_hierarchy, register_plugins},
     util::{drop_effective, read_effective, subsribe},
     Storage,
 };
@@ -16,14 +21,32 @@ const ROOTASROLE: &str = "/etc/security/rootasrole.json";
 #[cfg(test)]
 const ROOTASROLE: &str = "target/rootasrole.json";
 
+// sr passes the granted capabilities through the inheritable set, the file capabilities of
+// chsr only permit it to read the policy
+fn privileged() -> Result<bool, capctl::Error> {
+    let inheritable = CapState::get_current()?.inheritable;
+    Ok(geteuid().is_root()
+        || inheritable.has(Cap::DAC_READ_SEARCH)
+        || inheritable.has(Cap::DAC_OVERRIDE))
+}
+
 #[cfg(not(tarpaulin_include))]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     use rar_common::{get_settings, StorageMethod};
 
     subsribe("chsr")?;
     drop_effective()?;
+    let mut args = std::env::args().skip(1).peekable();
+    // overwrite the changes saved by another administrator since the policy was read
+    let force = args.next_if(|arg| arg == "--force").is_some();
+    let args: Vec<String> = args.collect();
+    if !privileged()? && !cli::lists_own_roles(&args) {
+        eprintln!("chsr: only chsr list --mine is allowed without privileges, run chsr with sr");
+        std::process::exit(1);
+    }
     register_plugins();
     let settings = get_settings(ROOTASROLE).expect("Error on config read");
+    configure_logger("chsr", settings.as_ref().borrow().storage.log.as_ref())?;
     let config = match settings.clone().as_ref().borrow().storage.method {
         StorageMethod::JSON => Storage::JSON(read_json_config(settings.clone(), ROOTASROLE)?),
         _ => {
@@ -33,11 +56,49 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     read_effective(false).expect("Operation not permitted");
 
-    if cli::main(&config, std::env::args().skip(1)).is_ok_and(|b| b) {
+    let modified = match cli::main(&config, args) {
+        Ok(modified) => modified,
+        Err(e) => {
+            eprintln!("chsr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if modified {
         match config {
             Storage::JSON(config) => {
+                if let Err(e) = check_hierarchy(&config) {
+                    error!("{}", e);
+                    eprintln!("chsr: the policy was not saved: {}", e);
+                    std::process::exit(1);
+                }
+                let path = policy_path(&settings.as_ref().borrow(), ROOTASROLE);
+                let lock = PolicyLock::acquire(&path)?;
+                if let Err(e) = lock.check_unmodified(&config.as_ref().borrow()) {
+                    if !force {
+                        eprintln!(
+                            "chsr: the policy was not saved: {}, run chsr again or use --force to overwrite it",
+                            e
+                        );
+                        std::process::exit(1);
+                    }
+                    warn!("Overwriting the policy: {}", e);
+                }
+                let sealed = integrity_settings()?.map(|_| policy_digest(&path).ok());
                 debug!("Saving configuration");
-                save_json(settings, config)?;
+                save_json(settings.clone(), config)?;
+                drop(lock);
+                if settings.as_ref().borrow().storage.activation.is_some() {
+                    println!("The policy changed, run chsr activate to deploy it");
+                }
+                if let Some(sealed) = sealed {
+                    let digest = policy_digest(&path)?;
+                    if sealed.as_ref() != Some(&digest) {
+                        println!(
+                            "The policy changed, seal its new digest in the TPM: {}",
+                            digest
+                        );
+                    }
+                }
                 Ok(())
             }
         }
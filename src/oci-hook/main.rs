@@ -0,0 +1,298 @@
+//! OCI runtime hook granting the capabilities of the policy to containers.
+//!
+//! It runs at the `precreate` stage: the runtime reads the container configuration from its
+//! stdout after giving it on stdin. The tasks with a `container` field select the containers by
+//! image and annotations, and their actors by the user of the container process.
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    error::Error,
+    io::{stdin, stdout, Read},
+    rc::Rc,
+};
+
+use capctl::CapSet;
+use log::{error, info};
+use nix::unistd::{Gid, Group, Pid, Uid, User};
+use rar_common::{
+    activation::activated_config,
+    database::{
+        finder::{Cred, CredMatcher},
+        read_json_config,
+        structs::SConfig,
+    },
+    logger::configure as configure_logger,
+    StorageMethod,
+};
+use serde_json::Value;
+
+#[cfg(not(test))]
+const ROOTASROLE: &str = "/etc/security/rootasrole.json";
+#[cfg(test)]
+const ROOTASROLE: &str = "target/rootasrole.json";
+
+// where the runtimes and orchestrators give the image reference
+const IMAGE_ANNOTATIONS: [&str; 2] = [
+    "org.opencontainers.image.ref.name",
+    "io.kubernetes.cri.image-name",
+];
+
+fn annotations(spec: &Value) -> BTreeMap<String, String> {
+    spec["annotations"]
+        .as_object()
+        .map(|annotations| {
+            annotations
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn image(annotations: &BTreeMap<String, String>) -> Option<&str> {
+    IMAGE_ANNOTATIONS
+        .iter()
+        .find_map(|key| annotations.get(*key).map(String::as_str))
+}
+
+/// The user of the container process, as known by the host.
+fn container_cred(spec: &Value) -> Result<Cred, Box<dyn Error>> {
+    let user = &spec["process"]["user"];
+    let uid = user["uid"].as_u64().unwrap_or(0) as u32;
+    let gid = user["gid"].as_u64().unwrap_or(0) as u32;
+    let user = User::from_uid(Uid::from_raw(uid))?
+        .ok_or_else(|| format!("The container user {} is unknown to the host", uid))?;
+    let groups = std::iter::once(gid)
+        .chain(
+            user_groups(&spec["process"]["user"]["additionalGids"])
+                .into_iter()
+                .filter(|g| *g != gid),
+        )
+        .filter_map(|gid| Group::from_gid(Gid::from_raw(gid)).ok().flatten())
+        .collect();
+    Ok(Cred {
+        user,
+        groups,
+        tty: None,
+        ppid: Pid::this(),
+        orchestrated: false,
+    })
+}
+
+fn user_groups(gids: &Value) -> Vec<u32> {
+    gids.as_array()
+        .map(|gids| {
+            gids.iter()
+                .filter_map(|gid| gid.as_u64().map(|gid| gid as u32))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The capabilities of every enabled container task granted to this container, with their names.
+fn granted(
+    config: &Rc<RefCell<SConfig>>,
+    cred: &Cred,
+    image: Option<&str>,
+    annotations: &BTreeMap<String, String>,
+) -> (CapSet, Vec<String>) {
+    let mut caps = CapSet::empty();
+    let mut tasks = Vec::new();
+    for role in config.as_ref().borrow().roles.iter() {
+        if !role.as_ref().borrow().is_enabled() || role.user_matches(cred).is_no_match() {
+            continue;
+        }
+        for task in role.as_ref().borrow().tasks.iter() {
+            let task = task.as_ref().borrow();
+            if !task.is_enabled()
+                || !task
+                    .container
+                    .as_ref()
+                    .is_some_and(|container| container.matches(image, annotations))
+            {
+                continue;
+            }
+            if let Some(capabilities) = &task.cred.capabilities {
+                caps = caps.union(capabilities.to_capset());
+            }
+            tasks.push(format!("{}/{}", role.as_ref().borrow().name, task.name));
+        }
+    }
+    (caps, tasks)
+}
+
+/// Add the capabilities to the container process, also as ambient for a non-root process.
+fn inject(spec: &mut Value, caps: CapSet) {
+    let root = spec["process"]["user"]["uid"].as_u64().unwrap_or(0) == 0;
+    let sets: &[&str] = if root {
+        &["bounding", "effective", "permitted"]
+    } else {
+        &[
+            "bounding",
+            "effective",
+            "permitted",
+            "inheritable",
+            "ambient",
+        ]
+    };
+    for set in sets {
+        let current = &mut spec["process"]["capabilities"][*set];
+        if !current.is_array() {
+            *current = Value::Array(Vec::new());
+        }
+        let Value::Array(current) = current else {
+            unreachable!()
+        };
+        for cap in caps.iter() {
+            let name = Value::String(cap.to_string());
+            if !current.contains(&name) {
+                current.push(name);
+            }
+        }
+    }
+}
+
+fn read_config() -> Result<Rc<RefCell<SConfig>>, Box<dyn Error>> {
+    let settings = rar_common::get_settings(ROOTASROLE)?;
+    configure_logger(
+        "rootasrole-oci-hook",
+        settings.as_ref().borrow().storage.log.as_ref(),
+    )?;
+    let method = settings.as_ref().borrow().storage.method.clone();
+    match method {
+        StorageMethod::JSON if settings.as_ref().borrow().storage.activation.is_some() => {
+            activated_config(&settings.as_ref().borrow(), ROOTASROLE)
+        }
+        StorageMethod::JSON => read_json_config(settings, ROOTASROLE),
+        _ => Err("Unsupported storage method".into()),
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+    let mut spec: Value = serde_json::from_str(&input)?;
+    let config = read_config()?;
+    let annotations = annotations(&spec);
+    let image = image(&annotations);
+    let (caps, tasks) = match container_cred(&spec) {
+        Ok(cred) => granted(&config, &cred, image, &annotations),
+        Err(e) => {
+            info!("{}, no capability granted", e);
+            (CapSet::empty(), Vec::new())
+        }
+    };
+    if !tasks.is_empty() {
+        inject(&mut spec, caps);
+        info!(
+            "image={} tasks={} caps={}",
+            image.unwrap_or("unknown"),
+            tasks.join(","),
+            caps.iter()
+                .map(|cap| cap.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
+    serde_json::to_writer(stdout(), &spec)?;
+    Ok(())
+}
+
+// a failing hook prevents the creation of the container
+fn main() {
+    if let Err(e) = run() {
+        error!("{}", e);
+        eprintln!("rootasrole-oci-hook: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        structs::{SCapabilities, SContainer, SCredentials, SRole, STask, SetBehavior},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_granted() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_monitoring")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_exporter")
+                            .container(
+                                SContainer::builder()
+                                    .image("registry.example.com/monitoring/*")
+                                    .build(),
+                            )
+                            .cred(
+                                SCredentials::builder()
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::SYS_PTRACE)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(
+                        STask::builder("t_other")
+                            .container(SContainer::builder().image("docker.io/*").build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let spec = json!({
+            "process": { "user": { "uid": getuid().as_raw(), "gid": 0 } },
+            "annotations": {
+                "org.opencontainers.image.ref.name": "registry.example.com/monitoring/node-exporter:1.8"
+            }
+        });
+        let annotations = annotations(&spec);
+        let cred = container_cred(&spec).unwrap();
+        let (caps, tasks) = granted(&config, &cred, image(&annotations), &annotations);
+        assert_eq!(tasks, ["r_monitoring/t_exporter"]);
+        assert!(caps.has(Cap::SYS_PTRACE));
+        let (caps, tasks) = granted(&config, &cred, None, &annotations);
+        assert!(tasks.is_empty());
+        assert!(caps.is_empty());
+    }
+
+    #[test]
+    fn test_inject() {
+        let mut caps = CapSet::empty();
+        caps.add(Cap::NET_ADMIN);
+        let mut spec = json!({
+            "ociVersion": "1.0.2",
+            "process": {
+                "user": { "uid": 1000, "gid": 1000 },
+                "capabilities": { "bounding": ["CAP_CHOWN", "CAP_NET_ADMIN"], "effective": ["CAP_CHOWN"] }
+            }
+        });
+        inject(&mut spec, caps);
+        let capabilities = &spec["process"]["capabilities"];
+        assert_eq!(
+            capabilities["bounding"],
+            json!(["CAP_CHOWN", "CAP_NET_ADMIN"])
+        );
+        assert_eq!(
+            capabilities["effective"],
+            json!(["CAP_CHOWN", "CAP_NET_ADMIN"])
+        );
+        assert_eq!(capabilities["ambient"], json!(["CAP_NET_ADMIN"]));
+        assert_eq!(spec["ociVersion"], "1.0.2");
+        spec["process"]["user"]["uid"] = json!(0);
+        spec["process"]["capabilities"] = json!({});
+        inject(&mut spec, caps);
+        assert!(spec["process"]["capabilities"]["ambient"].is_null());
+    }
+}
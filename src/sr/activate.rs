@@ -0,0 +1,161 @@
+//! `sr --activate <role> [--for <duration>]`, the explicit activation of a role.
+//!
+//! The roles with an `activation` entry are not matched until their actor activates them for
+//! the terminal they work in. The duration is written `90s`, `30m`, `2h` or `HH:MM:SS`.
+
+use std::{cell::RefCell, error::Error, rc::Rc};
+
+use chrono::Duration;
+use rar_common::{
+    database::{
+        finder::{actors_match, Cred},
+        structs::{RoleGetter, SConfig, SRole},
+    },
+    plugin::requires_activation,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Activation {
+    pub role: String,
+    pub duration: Option<Duration>,
+}
+
+fn parse_duration(duration: &str) -> Result<Duration, Box<dyn Error>> {
+    let invalid = || {
+        format!(
+            "invalid duration {}, expected 90s, 30m, 2h or HH:MM:SS",
+            duration
+        )
+    };
+    if let [hours, minutes, seconds] = duration.split(':').collect::<Vec<_>>()[..] {
+        let [hours, minutes, seconds] = [hours, minutes, seconds].map(|part| part.parse::<i64>());
+        return Ok(Duration::hours(hours.map_err(|_| invalid())?)
+            + Duration::minutes(minutes.map_err(|_| invalid())?)
+            + Duration::seconds(seconds.map_err(|_| invalid())?));
+    }
+    let unit = duration.chars().last().ok_or_else(invalid)?;
+    let value: i64 = duration[..duration.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    match unit {
+        's' => Ok(Duration::seconds(value)),
+        'm' => Ok(Duration::minutes(value)),
+        'h' => Ok(Duration::hours(value)),
+        _ => Err(invalid().into()),
+    }
+}
+
+/// The activation asked by the `--activate` and `--for` options, if any.
+pub fn parse(
+    role: Option<String>,
+    duration: Option<String>,
+) -> Result<Option<Activation>, Box<dyn Error>> {
+    match (role, duration) {
+        (Some(role), duration) => Ok(Some(Activation {
+            role,
+            duration: duration.as_deref().map(parse_duration).transpose()?,
+        })),
+        (None, Some(_)) => Err("--for is only accepted with --activate".into()),
+        (None, None) => Ok(None),
+    }
+}
+
+/// The role to activate, when it exists, needs an activation and is granted to `user`.
+pub fn role(
+    config: &Rc<RefCell<SConfig>>,
+    user: &Cred,
+    name: &str,
+) -> Result<Rc<RefCell<SRole>>, Box<dyn Error>> {
+    let role = config
+        .role(name)
+        .filter(|role| !actors_match(role, user).is_no_match())
+        .ok_or_else(|| format!("the role {} is not granted to you", name))?;
+    if !requires_activation(&role.as_ref().borrow()) {
+        return Err(format!("the role {} does not need to be activated", name).into());
+    }
+    Ok(role)
+}
+
+/// The duration as written in the policy, `HH:MM:SS`.
+pub fn display(duration: Duration) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        duration.num_hours(),
+        duration.num_minutes() % 60,
+        duration.num_seconds() % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use rar_common::database::{actor::SActor, make_weak_config};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(Some("r_dba".to_string()), Some("30m".to_string()))
+                .unwrap()
+                .unwrap(),
+            Activation {
+                role: "r_dba".to_string(),
+                duration: Some(Duration::minutes(30)),
+            }
+        );
+        assert_eq!(
+            parse(Some("r_dba".to_string()), None)
+                .unwrap()
+                .unwrap()
+                .duration,
+            None
+        );
+        assert!(parse(None, None).unwrap().is_none());
+        assert!(parse(None, Some("30m".to_string())).is_err());
+        assert!(parse(Some("r_dba".to_string()), Some("30".to_string())).is_err());
+        assert_eq!(parse_duration("01:30:00").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::seconds(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("").is_err());
+        assert_eq!(display(Duration::minutes(90)), "01:30:00");
+    }
+
+    #[test]
+    fn test_role() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_dba")
+                    .actor(SActor::user(0).build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_web")
+                    .actor(SActor::user(0).build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_other")
+                    .actor(SActor::user("nobody").build())
+                    .build(),
+            )
+            .build();
+        for name in ["r_dba", "r_other"] {
+            config
+                .role(name)
+                .unwrap()
+                .as_ref()
+                .borrow_mut()
+                ._extra_fields
+                .insert("activation".to_string(), json!({}));
+        }
+        make_weak_config(&config);
+        let root = Cred::builder().user_id(0).build();
+        assert!(role(&config, &root, "r_dba").is_ok());
+        assert!(role(&config, &root, "r_web").is_err());
+        assert!(role(&config, &root, "r_other").is_err());
+        assert!(role(&config, &root, "r_none").is_err());
+    }
+}
@@ -0,0 +1,689 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::{DirBuilder, OpenOptions},
+    io::{Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, OpenOptionsExt},
+        net::UnixDatagram,
+    },
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::SystemTime,
+};
+
+use capctl::Cap;
+use libc::dev_t;
+use log::{info, warn};
+use nix::{
+    sys::stat::{major, minor},
+    unistd::Pid,
+};
+use rar_common::{
+    database::{
+        finder::{Cred, ExecSettings},
+        options::{SAudit, SAuditBackend, SAuditContext},
+    },
+    logger,
+    retention::{collect, RetentionSettings, CONTEXT_DIR, SNAPSHOT_RECIPIENT},
+    util::{cap_effective, dac_override_effective},
+};
+use serde::Serialize;
+use sha2::Digest;
+
+const OPENSSL: &str = "/usr/bin/openssl";
+
+/// What the command was launched from, as seen by sr at authorization time.
+#[derive(Serialize)]
+struct Context {
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+    ppid: i32,
+    parent: Vec<String>,
+}
+
+impl Context {
+    /// Without `args`, the arguments of the command and of the parent process are left out,
+    /// without `env`, the environment is.
+    fn capture(ppid: Pid, command: &[String], args: bool, env: bool) -> Self {
+        let mut parent: Vec<String> = std::fs::read(format!("/proc/{}/cmdline", ppid))
+            .map(|cmdline| {
+                cmdline
+                    .split(|b| *b == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut argv: Vec<String> = std::env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        if !args {
+            // the command line of sr ends with the command
+            argv.truncate(argv.len().saturating_sub(command.len().saturating_sub(1)));
+            parent.truncate(1);
+        }
+        Context {
+            argv,
+            env: std::env::vars_os()
+                .filter(|_| env)
+                .map(|(k, v)| {
+                    (
+                        k.to_string_lossy().into_owned(),
+                        v.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect(),
+            ppid: ppid.as_raw(),
+            parent,
+        }
+    }
+
+    // the environment is sorted, so the same context always gives the same digest
+    fn digest(&self) -> String {
+        hex::encode(sha2::Sha256::digest(
+            serde_json::to_vec(self).unwrap_or_default(),
+        ))
+    }
+}
+
+// the task is saved in clear along the encrypted context for the retention quotas
+#[derive(Serialize)]
+struct Snapshot {
+    role: String,
+    task: String,
+    context: String,
+}
+
+/// The context may hold secrets, it is only readable with the key of the recipient
+/// certificate: `openssl cms -decrypt -inform PEM -inkey <key>`.
+fn encrypt(recipient: &Path, context: &Context) -> Result<String, Box<dyn Error>> {
+    let input = serde_json::to_vec(context)?;
+    let mut child = Command::new(OPENSSL)
+        .args(["cms", "-encrypt", "-aes256", "-binary", "-outform", "PEM"])
+        .arg(recipient)
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let writer = child
+        .stdin
+        .take()
+        .map(|mut stdin| thread::spawn(move || stdin.write_all(&input)));
+    let output = child.wait_with_output()?;
+    if let Some(Ok(Err(e))) = writer.map(|writer| writer.join()) {
+        return Err(e.into());
+    }
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to encrypt the context to {}: {}",
+            recipient.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+fn save_context(dir: &Path, snapshot: &Snapshot, digest: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = dir.join(format!("{}.json", digest));
+    dac_override_effective(true)?;
+    let file = DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .and_then(|_| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&path)
+        });
+    dac_override_effective(false)?;
+    let mut file = file?;
+    // sr runs with the caller uid, the snapshot must not be owned by the caller
+    cap_effective(Cap::CHOWN, true)?;
+    let chown = std::os::unix::fs::fchown(&file, Some(0), Some(0));
+    cap_effective(Cap::CHOWN, false)?;
+    chown?;
+    serde_json::to_writer_pretty(&mut file, snapshot)?;
+    file.flush()?;
+    Ok(path)
+}
+
+/// A random identifier of the sr execution, to correlate its records, logs and command actions.
+pub fn new_id() -> String {
+    let mut id = [0u8; 16];
+    if let Err(e) = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut id)) {
+        warn!("Unable to read a random audit id: {}", e);
+    }
+    hex::encode(id)
+}
+
+const EXPORTED: [&str; 6] = [
+    "RAR_USER",
+    "RAR_ROLE",
+    "RAR_TASK",
+    "RAR_CAPS",
+    "RAR_AUDIT_ID",
+    "RAR_SESSION_ID",
+];
+
+/// Give the caller and the decision to the command environment, unless the audit `export` option
+/// is false.
+pub fn export(user: &Cred, execcfg: &ExecSettings, id: &str, env: &mut HashMap<String, String>) {
+    // the caller must not be able to forge them
+    env.retain(|key, _| !EXPORTED.contains(&key.as_str()));
+    if !execcfg.opt.get_audit().export.unwrap_or(true) {
+        return;
+    }
+    let caps = execcfg
+        .caps
+        .unwrap_or_default()
+        .into_iter()
+        .map(|cap| cap.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    for (key, value) in EXPORTED.into_iter().zip([
+        user.user.name.clone(),
+        execcfg.role().as_ref().borrow().name.clone(),
+        execcfg.task().as_ref().borrow().name.to_string(),
+        caps,
+        id.to_string(),
+        id.to_string(),
+    ]) {
+        env.insert(key.to_string(), value);
+    }
+}
+
+// a full disk must not prevent the command, unless the retention fails closed
+fn snapshot(
+    execcfg: &ExecSettings,
+    context: &Context,
+    digest: &str,
+    retention: Option<&RetentionSettings>,
+) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(CONTEXT_DIR);
+    let saved = encrypt(Path::new(SNAPSHOT_RECIPIENT), context).and_then(|context| {
+        let snapshot = Snapshot {
+            role: execcfg.role().as_ref().borrow().name.clone(),
+            task: execcfg.task().as_ref().borrow().name.to_string(),
+            context,
+        };
+        save_context(dir, &snapshot, digest).or_else(|e| match retention {
+            Some(retention) => collect(dir, retention, SystemTime::now())
+                .and_then(|_| save_context(dir, &snapshot, digest)),
+            None => Err(e),
+        })
+    });
+    match saved {
+        Ok(_) => Ok(()),
+        Err(e) if retention.is_some_and(|retention| retention.on_failure.is_fail_closed()) => {
+            Err(format!("Unable to save the execution context {}: {}", digest, e).into())
+        }
+        Err(e) => {
+            warn!("Unable to save the execution context {}: {}", digest, e);
+            Ok(())
+        }
+    }
+}
+
+const JOURNAL: &str = "/run/systemd/journal/socket";
+// fields always quoted, even without spaces, so that the record can be split on spaces
+const QUOTED: [&str; 2] = ["args", "post_run"];
+
+type Fields = Vec<(&'static str, String)>;
+
+fn format_fields(fields: &Fields) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| {
+            if QUOTED.contains(key) || value.contains(|c: char| c.is_whitespace() || c == '"') {
+                format!("{}={:?}", key, value)
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// native journal protocol, values holding a newline are sent with their length
+fn journal_entry(fields: &Fields) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let mut field = |key: &str, value: &str| {
+        entry.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+    field("MESSAGE", &format_fields(fields));
+    field("PRIORITY", "6");
+    // LOG_AUTHPRIV
+    field("SYSLOG_FACILITY", "10");
+    field("SYSLOG_IDENTIFIER", "sr");
+    for (key, value) in fields {
+        field(&format!("RAR_{}", key.to_uppercase()), value);
+    }
+    entry
+}
+
+fn send(backend: SAuditBackend, fields: &Fields) -> Result<(), Box<dyn Error>> {
+    // the log destinations already redact the secrets
+    let fields: Fields = fields
+        .iter()
+        .map(|(key, value)| (*key, logger::scrub(value).into_owned()))
+        .collect();
+    let fields = &fields;
+    match backend {
+        SAuditBackend::Log => info!("{}", format_fields(fields)),
+        SAuditBackend::Syslog => logger::authpriv("sr", &format_fields(fields))?,
+        SAuditBackend::Journald => {
+            UnixDatagram::unbound()?.send_to(&journal_entry(fields), JOURNAL)?;
+        }
+    }
+    Ok(())
+}
+
+// an unavailable backend must not lose the record
+fn emit(audit: &SAudit, fields: Fields) {
+    let backend = audit.backend.unwrap_or_default();
+    if let Err(e) = send(backend, &fields) {
+        warn!("Unable to send the audit record to {}: {}", backend, e);
+        info!("{}", format_fields(&fields));
+    }
+}
+
+/// Log an event of the execution, such as a post_run command result.
+pub fn event(execcfg: &ExecSettings, id: &str, fields: Fields) {
+    let mut event = vec![("id", id.to_string())];
+    event.extend(fields);
+    emit(&execcfg.opt.get_audit(), event);
+}
+
+/// Log the exit status of the command, unless the audit verbosity is minimal.
+pub fn exit_status(execcfg: &ExecSettings, id: &str, code: i32) {
+    let audit = execcfg.opt.get_audit();
+    if audit.verbosity.unwrap_or_default().is_standard() {
+        emit(
+            &audit,
+            vec![("id", id.to_string()), ("status", code.to_string())],
+        );
+    }
+}
+
+/// The digest of the execution context, saved when the task asks for a snapshot. It must be
+/// called before sr drops the capabilities it writes the snapshot with.
+pub fn context(
+    user: &Cred,
+    execcfg: &ExecSettings,
+    command: &[String],
+    retention: Option<&RetentionSettings>,
+) -> Result<Option<String>, Box<dyn Error>> {
+    match execcfg.opt.get_audit().context.unwrap_or_default() {
+        SAuditContext::None => Ok(None),
+        kind => {
+            let audit = execcfg.opt.get_audit();
+            let context = Context::capture(
+                user.ppid,
+                command,
+                records_arguments(execcfg, &audit),
+                audit.env.unwrap_or_default().is_names(),
+            );
+            let digest = context.digest();
+            if kind.is_snapshot() {
+                snapshot(execcfg, &context, &digest, retention)?;
+            }
+            Ok(Some(digest))
+        }
+    }
+}
+
+/// Log the granted execution, as much as the task audit options allow.
+pub fn record(
+    user: &Cred,
+    execcfg: &ExecSettings,
+    env: &HashMap<String, String>,
+    context: Option<&str>,
+    id: &str,
+) {
+    let audit = execcfg.opt.get_audit();
+    let fields = record_fields(user, execcfg, &audit, env, context, id);
+    emit(&audit, fields);
+}
+
+/// The arguments of a task passing them through the environment are never recorded.
+pub fn records_arguments(execcfg: &ExecSettings, audit: &SAudit) -> bool {
+    audit.args.unwrap_or_default().is_full()
+        && !execcfg
+            .task()
+            .as_ref()
+            .borrow()
+            .args_via_env
+            .unwrap_or(false)
+}
+
+fn tty_name(tty: dev_t) -> String {
+    match (major(tty), minor(tty)) {
+        (major @ 136..=143, minor) => format!("/dev/pts/{}", (major - 136) * 256 + minor),
+        (4, minor) if minor < 64 => format!("/dev/tty{}", minor),
+        (major, minor) => format!("{}:{}", major, minor),
+    }
+}
+
+fn record_fields(
+    user: &Cred,
+    execcfg: &ExecSettings,
+    audit: &SAudit,
+    env: &HashMap<String, String>,
+    context: Option<&str>,
+    id: &str,
+) -> Fields {
+    let standard = audit.verbosity.unwrap_or_default().is_standard();
+    let mut fields = vec![("id", id.to_string()), ("user", user.user.name.clone())];
+    if standard {
+        if let Some(tty) = user.tty {
+            fields.push(("tty", tty_name(tty)));
+        }
+        fields.push(("ppid", user.ppid.to_string()));
+        if let Ok(cwd) = std::env::current_dir() {
+            fields.push(("cwd", cwd.display().to_string()));
+        }
+    }
+    fields.extend([
+        ("role", execcfg.role().as_ref().borrow().name.clone()),
+        ("task", execcfg.task().as_ref().borrow().name.to_string()),
+        ("command", execcfg.exec_path.display().to_string()),
+    ]);
+    if standard {
+        let caps = execcfg.caps.unwrap_or_default();
+        fields.push((
+            "caps",
+            caps.into_iter()
+                .map(|cap| cap.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ));
+    }
+    if records_arguments(execcfg, audit) && !execcfg.exec_args.is_empty() {
+        fields.push(("args", shell_words::join(&execcfg.exec_args)));
+    }
+    if audit.env.unwrap_or_default().is_names() {
+        let mut names: Vec<&str> = env.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        fields.push(("env", names.join(",")));
+    }
+    if let Some(digest) = context {
+        fields.push(("context", digest.to_string()));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::{getuid, Pid, User};
+    use rar_common::database::{
+        actor::SActor,
+        finder::TaskMatcher,
+        options::{SAuditArgs, SAuditEnv, SAuditVerbosity},
+        structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_format_record() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_backup")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_upload")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls -l".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let user = Cred {
+            user: User::from_uid(getuid()).unwrap().unwrap(),
+            groups: vec![],
+            tty: None,
+            ppid: Pid::parent(),
+            orchestrated: false,
+        };
+        let mut taskmatch = config
+            .matches(&user, &None, &["/usr/bin/ls".to_string(), "-l".to_string()])
+            .unwrap();
+        // the resolution of the executable depends on the test host
+        taskmatch.settings.exec_path = "/usr/bin/ls".into();
+        taskmatch.settings.exec_args = vec!["-l".to_string()];
+        let env = HashMap::from([
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ]);
+        let record = format_fields(&record_fields(
+            &user,
+            &taskmatch.settings,
+            &SAudit::default(),
+            &env,
+            None,
+            "7f",
+        ));
+        assert_eq!(
+            record,
+            format!(
+                "id=7f user={} ppid={} cwd={} role=r_backup task=t_upload command=/usr/bin/ls caps= args=\"-l\" env=HOME,PATH",
+                user.user.name,
+                user.ppid,
+                std::env::current_dir().unwrap().display()
+            )
+        );
+        let audit = SAudit::builder()
+            .args(SAuditArgs::CommandOnly)
+            .env(SAuditEnv::None)
+            .verbosity(SAuditVerbosity::Minimal)
+            .build();
+        let record = format_fields(&record_fields(
+            &user,
+            &taskmatch.settings,
+            &audit,
+            &env,
+            Some("00ff"),
+            "7f",
+        ));
+        assert_eq!(
+            record,
+            format!(
+                "id=7f user={} role=r_backup task=t_upload command=/usr/bin/ls context=00ff",
+                user.user.name
+            )
+        );
+        taskmatch.settings.task().as_ref().borrow_mut().args_via_env = Some(true);
+        let record = format_fields(&record_fields(
+            &user,
+            &taskmatch.settings,
+            &SAudit::default(),
+            &env,
+            None,
+            "7f",
+        ));
+        assert!(!record.contains("args="));
+    }
+
+    #[test]
+    fn test_journal_entry() {
+        let fields = vec![("id", "7f".to_string()), ("args", "-c 'a\nb'".to_string())];
+        assert_eq!(tty_name(nix::sys::stat::makedev(136, 3)), "/dev/pts/3");
+        assert_eq!(format_fields(&fields), "id=7f args=\"-c 'a\\nb'\"");
+        let entry = journal_entry(&fields);
+        let mut expected = b"MESSAGE=id=7f args=\"-c 'a\\nb'\"\nPRIORITY=6\nSYSLOG_FACILITY=10\nSYSLOG_IDENTIFIER=sr\nRAR_ID=7f\nRAR_ARGS\n".to_vec();
+        expected.extend_from_slice(&8u64.to_le_bytes());
+        expected.extend_from_slice(b"-c 'a\nb'\n");
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn test_export() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_backup")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_upload")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(
+                        STask::builder("t_quiet")
+                            .options(|opt| {
+                                opt.audit(SAudit::builder().export(false).build()).build()
+                            })
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/cat".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let user = Cred::builder().build();
+        let id = new_id();
+        assert_eq!(id.len(), 32);
+        let taskmatch = config
+            .matches(&user, &None, &["/usr/bin/ls".to_string()])
+            .unwrap();
+        let mut env = HashMap::from([("RAR_ROLE".to_string(), "forged".to_string())]);
+        export(&user, &taskmatch.settings, &id, &mut env);
+        assert_eq!(env.get("RAR_USER").unwrap(), &user.user.name);
+        assert_eq!(env.get("RAR_ROLE").unwrap(), "r_backup");
+        assert_eq!(env.get("RAR_TASK").unwrap(), "t_upload");
+        assert_eq!(env.get("RAR_CAPS").unwrap(), "");
+        assert_eq!(env.get("RAR_AUDIT_ID").unwrap(), &id);
+        assert_eq!(env.get("RAR_SESSION_ID").unwrap(), &id);
+        let taskmatch = config
+            .matches(&user, &None, &["/usr/bin/cat".to_string()])
+            .unwrap();
+        let mut env = HashMap::from([("RAR_USER".to_string(), "forged".to_string())]);
+        export(&user, &taskmatch.settings, &id, &mut env);
+        assert!(env.is_empty());
+    }
+
+    #[test]
+    fn test_context_digest() {
+        let context = |env: &[(&str, &str)]| Context {
+            argv: vec!["sr".to_string(), "ls".to_string()],
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ppid: 42,
+            parent: vec!["-bash".to_string()],
+        };
+        let digest = context(&[("A", "1"), ("B", "2")]).digest();
+        assert_eq!(digest.len(), 64);
+        assert_eq!(digest, context(&[("B", "2"), ("A", "1")]).digest());
+        assert_ne!(digest, context(&[("A", "1"), ("B", "3")]).digest());
+        assert_eq!(
+            Context::capture(Pid::parent(), &[], true, true).ppid,
+            Pid::parent().as_raw()
+        );
+    }
+
+    #[test]
+    fn test_context_capture() {
+        let full = Context::capture(Pid::parent(), &[], true, true);
+        let command = ["ls".to_string(), "s3cr3t".to_string()];
+        let hidden = Context::capture(Pid::parent(), &command, false, false);
+        assert_eq!(hidden.argv.len(), full.argv.len() - 1);
+        assert!(hidden.parent.len() <= 1);
+        assert!(hidden.env.is_empty());
+        assert_eq!(hidden.env.len() < full.env.len(), !full.env.is_empty());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        if !Path::new(OPENSSL).exists() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("rar_encrypt_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (key, cert) = (dir.join("audit.key"), dir.join("audit.pem"));
+        let generated = Command::new(OPENSSL)
+            .args([
+                "req",
+                "-x509",
+                "-newkey",
+                "rsa:2048",
+                "-nodes",
+                "-subj",
+                "/CN=audit",
+            ])
+            .arg("-keyout")
+            .arg(&key)
+            .arg("-out")
+            .arg(&cert)
+            .output()
+            .unwrap();
+        assert!(generated.status.success());
+        let context = Context::capture(Pid::parent(), &[], true, true);
+        let encrypted = encrypt(&cert, &context).unwrap();
+        assert!(encrypted.starts_with("-----BEGIN CMS-----"));
+        let mut decrypt = Command::new(OPENSSL)
+            .args(["cms", "-decrypt", "-inform", "PEM", "-inkey"])
+            .arg(&key)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        decrypt
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(encrypted.as_bytes())
+            .unwrap();
+        let decrypted = decrypt.wait_with_output().unwrap().stdout;
+        assert!(encrypt(&dir.join("missing.pem"), &context).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(decrypted, serde_json::to_vec(&context).unwrap());
+    }
+
+    #[test]
+    fn test_save_context() {
+        let dir = std::env::temp_dir().join(format!("rar_context_{}", std::process::id()));
+        let context = Context::capture(Pid::parent(), &[], true, true);
+        let snapshot = Snapshot {
+            role: "r_backup".to_string(),
+            task: "t_upload".to_string(),
+            context: "-----BEGIN CMS-----".to_string(),
+        };
+        let path = save_context(&dir, &snapshot, &context.digest());
+        let metadata = path.as_ref().map(std::fs::metadata);
+        let _ = std::fs::remove_dir_all(&dir);
+        let metadata = metadata.unwrap().unwrap();
+        assert_eq!(std::os::unix::fs::MetadataExt::uid(&metadata), 0);
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777,
+            0o600
+        );
+    }
+}
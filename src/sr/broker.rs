@@ -0,0 +1,98 @@
+//! Files declared by a task are opened by sr and inherited read-only by the command,
+//! so the command does not need CAP_DAC_READ_SEARCH to read them.
+use std::{collections::HashMap, error::Error, fs::File, os::fd::AsRawFd};
+
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use rar_common::{database::finder::ExecSettings, util::open_with_privileges};
+
+pub fn open_files(execcfg: &ExecSettings) -> Result<Vec<File>, Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    let mut files = Vec::new();
+    for path in task.read_files.iter() {
+        if !path.is_absolute() {
+            return Err(format!("{} is not an absolute path", path.display()).into());
+        }
+        let file = open_with_privileges(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !file.metadata()?.is_file() {
+            return Err(format!("{} is not a regular file", path.display()).into());
+        }
+        // stays open across exec
+        fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// The descriptors are given as RAR_FD1..N, in the order of the task `read_files`.
+pub fn export_files(files: &[File], envset: &mut HashMap<String, String>) {
+    envset.retain(|key, _| !key.starts_with("RAR_FD"));
+    for (i, file) in files.iter().enumerate() {
+        envset.insert(format!("RAR_FD{}", i + 1), file.as_raw_fd().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, io::Read, path::PathBuf};
+
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        finder::{Cred, TaskMatcher},
+        structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    fn settings(read_files: Vec<PathBuf>) -> ExecSettings {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("reader")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("read")
+                            .read_files(read_files)
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/cat".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/cat".to_string()],
+            )
+            .unwrap()
+            .settings
+    }
+
+    #[test]
+    fn test_broker_files() {
+        let path = temp_dir().join("rar_test_broker_files");
+        std::fs::write(&path, "secret").unwrap();
+        let files = open_files(&settings(vec![path.clone()])).unwrap();
+        let flags = fcntl(files[0].as_raw_fd(), FcntlArg::F_GETFD).unwrap();
+        assert!(!FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC));
+        let mut content = String::new();
+        (&files[0]).read_to_string(&mut content).unwrap();
+        assert_eq!(content, "secret");
+        let mut envset = HashMap::from([("RAR_FD7".to_string(), "0".to_string())]);
+        export_files(&files, &mut envset);
+        assert_eq!(
+            envset.get("RAR_FD1").unwrap(),
+            &files[0].as_raw_fd().to_string()
+        );
+        assert!(!envset.contains_key("RAR_FD7"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(open_files(&settings(vec!["relative".into()])).is_err());
+        assert!(open_files(&settings(vec![temp_dir()])).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! The cgroup v2 of an execution, shared by the memory limit and the network allow-list.
+//!
+//! The cgroups are created in `/sys/fs/cgroup/rootasrole`, one per execution named after the
+//! pid of sr, and removed when the command exits, or by a next sr once no process is left in
+//! them.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub const CGROUP_DIR: &str = "rootasrole";
+
+/// The name of the cgroup of this execution, relative to the cgroup root.
+pub fn name() -> String {
+    name_of(std::process::id())
+}
+
+/// The name of the cgroup of the execution made by the process `pid`.
+pub fn name_of(pid: u32) -> String {
+    format!("{}/sr-{}", CGROUP_DIR, pid)
+}
+
+/// Remove the cgroup `name` under `root`, false when processes are still in it.
+pub fn remove(root: &Path, name: &str) -> bool {
+    let cgroup = root.join(name);
+    match fs::remove_dir(&cgroup) {
+        Ok(()) => {
+            debug!("Removed the cgroup {}", cgroup.display());
+            true
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+        Err(e) => {
+            debug!("Unable to remove the cgroup {}: {}", cgroup.display(), e);
+            false
+        }
+    }
+}
+
+/// Move sr into its cgroup `name` under `root`, created on the first call.
+pub fn join(root: &Path, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let cgroup = root.join(name);
+    if cgroup.exists() {
+        return Ok(cgroup);
+    }
+    let parent = cgroup.parent().ok_or("Invalid cgroup")?;
+    fs::create_dir_all(parent)?;
+    // the cgroups of the previous commands, only removed when no process is left in them
+    for entry in fs::read_dir(parent)?.flatten() {
+        if entry.path().is_dir() && fs::remove_dir(entry.path()).is_ok() {
+            debug!("Removed the cgroup {}", entry.path().display());
+        }
+    }
+    fs::create_dir(&cgroup)?;
+    fs::write(cgroup.join("cgroup.procs"), std::process::id().to_string())?;
+    Ok(cgroup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        let root = std::env::temp_dir().join(format!("rar_cgroup_join_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        // an empty cgroup left by a previous command, and one still used
+        fs::create_dir_all(root.join("rootasrole/sr-1")).unwrap();
+        fs::create_dir_all(root.join("rootasrole/sr-2")).unwrap();
+        fs::write(root.join("rootasrole/sr-2/cgroup.procs"), "2").unwrap();
+        let cgroup = join(&root, "rootasrole/sr-3").unwrap();
+        assert_eq!(cgroup, root.join("rootasrole/sr-3"));
+        assert_eq!(
+            fs::read_to_string(cgroup.join("cgroup.procs")).unwrap(),
+            std::process::id().to_string()
+        );
+        assert!(!root.join("rootasrole/sr-1").exists());
+        assert!(root.join("rootasrole/sr-2").exists());
+        // joined once, the memory limit and the network share it
+        fs::remove_file(cgroup.join("cgroup.procs")).unwrap();
+        assert_eq!(join(&root, "rootasrole/sr-3").unwrap(), cgroup);
+        assert!(!cgroup.join("cgroup.procs").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_remove() {
+        let root = std::env::temp_dir().join(format!("rar_cgroup_remove_{}", std::process::id()));
+        fs::create_dir_all(root.join("rootasrole/sr-1")).unwrap();
+        fs::create_dir_all(root.join("rootasrole/sr-2")).unwrap();
+        // a process left by the command, a real cgroup cannot be removed while it is used
+        fs::write(root.join("rootasrole/sr-2/cgroup.procs"), "2").unwrap();
+        assert!(remove(&root, &name_of(1)));
+        assert!(!root.join("rootasrole/sr-1").exists());
+        assert!(!remove(&root, &name_of(2)));
+        assert!(remove(&root, &name_of(3)));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
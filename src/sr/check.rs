@@ -0,0 +1,123 @@
+use std::{error::Error, fmt::Write};
+
+use rar_common::{
+    database::finder::TaskMatch,
+    policy::Info,
+    trace::{Step, Trace},
+};
+use serde::Serialize;
+
+use crate::info;
+
+/// The answer of `sr --check`, for CI scripts verifying that a user may run a command.
+#[derive(Serialize, Debug)]
+struct Check {
+    allowed: bool,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    info: Option<Info>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<Step>,
+}
+
+/// What `sr --check` prints, and whether the command is allowed.
+pub fn report(
+    result: &Result<TaskMatch, Box<dyn Error>>,
+    why_not: Option<Trace>,
+    json: bool,
+) -> Result<(String, bool), Box<dyn Error>> {
+    let matched = result.as_ref().ok().filter(|m| m.fully_matching());
+    let check = Check {
+        allowed: matched.is_some(),
+        info: matched.map(|m| Info::new(&m.settings)).transpose()?,
+        reason: match result {
+            Err(e) => Some(e.to_string()),
+            Ok(_) if matched.is_none() => Some("No task matched".to_string()),
+            Ok(_) => None,
+        },
+        steps: why_not.map(|trace| trace.steps).unwrap_or_default(),
+    };
+    if json {
+        return Ok((serde_json::to_string_pretty(&check)?, check.allowed));
+    }
+    let mut output = match (&check.info, &check.reason) {
+        (Some(info), _) => format!("Allowed\n{}", info::render(info, false)?),
+        (None, reason) => format!("Denied: {}", reason.as_deref().unwrap_or_default()),
+    };
+    for step in check.steps.iter() {
+        let _ = match &step.task {
+            Some(task) => write!(output, "\n  {}/{}: {}", step.role, task, step.result),
+            None => write!(output, "\n  {}: {}", step.role, step.result),
+        };
+    }
+    Ok((output, check.allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use nix::unistd::getuid;
+    use rar_common::{
+        database::{
+            actor::SActor,
+            finder::{Cred, TaskMatcher},
+            make_weak_config,
+            structs::{
+                SCapabilities, SCommand, SCommands, SConfig, SCredentials, SRole, STask,
+                SetBehavior,
+            },
+        },
+        trace,
+    };
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_deploy")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_restart")
+                            .cred(
+                                SCredentials::builder()
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::KILL)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        make_weak_config(&config);
+        let user = Cred::builder().build();
+        let allowed = vec!["/usr/bin/ls".to_string()];
+        let result = config.matches(&user, &None, &allowed).map_err(|e| e.into());
+        let (text, ok) = report(&result, None, false).unwrap();
+        assert!(ok);
+        assert!(text.starts_with("Allowed\nRole: r_deploy\nTask: t_restart"));
+        assert!(text.contains("CAP_KILL"));
+
+        let denied = vec!["/usr/bin/cat".to_string()];
+        let result = config.matches(&user, &None, &denied).map_err(|e| e.into());
+        let trace = trace::evaluate(&config, &user, &None, &denied, &result);
+        let (json, ok) = report(&result, Some(trace), true).unwrap();
+        assert!(!ok);
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["allowed"], false);
+        assert!(json.get("role").is_none());
+        assert_eq!(json["steps"][0]["task"], "t_restart");
+    }
+}
@@ -0,0 +1,224 @@
+//! `sr --discover`: run a command under the capable probe and suggest the capabilities to grant.
+use std::{error::Error, path::Path, process::Command, str::FromStr};
+
+use capctl::{Cap, CapSet};
+use rar_common::{
+    database::structs::{SCapabilities, SCommand, SCommands, SCredentials, STask, SetBehavior},
+    util::final_path,
+};
+use serde::Deserialize;
+
+const CAPABLE: &str = "/usr/bin/capable";
+
+// usually asked by programs reading files of other users, rarely needed by the task
+const DAC: [Cap; 2] = [Cap::DAC_OVERRIDE, Cap::DAC_READ_SEARCH];
+
+// capable prints the capability names, or the checks with their cause when it traces them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Name(String),
+    Check {
+        capability: String,
+        #[serde(default)]
+        syscall: Option<String>,
+        #[serde(default)]
+        path: Option<String>,
+    },
+}
+
+/// A capability checked by the command, with the syscall and the file that caused the check
+/// when the probe knows them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub cap: Cap,
+    pub syscall: Option<String>,
+    pub path: Option<String>,
+}
+
+impl Check {
+    fn cause(&self) -> Option<String> {
+        match (&self.syscall, &self.path) {
+            (Some(syscall), Some(path)) => Some(format!("{} {}", syscall, path)),
+            (Some(cause), None) | (None, Some(cause)) => Some(cause.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+fn parse(output: &[u8]) -> Result<Vec<Check>, Box<dyn Error>> {
+    let entries: Vec<Entry> = serde_json::from_slice(output)
+        .map_err(|e| format!("Unexpected output of {}: {}", CAPABLE, e))?;
+    entries
+        .into_iter()
+        .map(|entry| {
+            let (name, syscall, path) = match entry {
+                Entry::Name(name) => (name, None, None),
+                Entry::Check {
+                    capability,
+                    syscall,
+                    path,
+                } => (capability, syscall, path),
+            };
+            let cap = Cap::from_str(&name).map_err(|_| format!("Unknown capability {}", name))?;
+            Ok(Check { cap, syscall, path })
+        })
+        .collect()
+}
+
+/// The capabilities of the checks.
+pub fn caps(checks: &[Check]) -> CapSet {
+    checks.iter().map(|check| check.cap).collect()
+}
+
+/// The capability checks of the command, which runs without any granted capability.
+pub fn discover(command: &[String]) -> Result<Vec<Check>, Box<dyn Error>> {
+    if command.is_empty() {
+        return Err("--discover needs a command".into());
+    }
+    let output = Command::new(CAPABLE)
+        .arg("-j")
+        .args(command)
+        .output()
+        .map_err(|e| format!("Unable to run {}: {}", CAPABLE, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            CAPABLE,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    parse(&output.stdout)
+}
+
+pub fn report(command: &[String], checks: &[Check]) -> String {
+    let caps = caps(checks);
+    if caps.is_empty() {
+        return format!(
+            "{} did not check any capability, it needs no privilege.\n",
+            shell_words::join(command)
+        );
+    }
+    let mut report = format!(
+        "{} checked these capabilities:\n",
+        shell_words::join(command)
+    );
+    for cap in caps.iter() {
+        report.push_str(&format!("  {}\n", cap));
+        let mut causes: Vec<String> = checks
+            .iter()
+            .filter(|check| check.cap == cap)
+            .filter_map(Check::cause)
+            .collect();
+        causes.sort();
+        causes.dedup();
+        for cause in causes {
+            report.push_str(&format!("    by {}\n", cause));
+        }
+    }
+    let needed: Vec<String> = caps
+        .iter()
+        .filter(|cap| !DAC.contains(cap))
+        .map(|cap| cap.to_string().to_lowercase())
+        .collect();
+    if caps.iter().any(|cap| DAC.contains(&cap)) {
+        report.push_str("CAP_DAC_OVERRIDE and CAP_DAC_READ_SEARCH are often not needed, running the command as the owner of the files is usually enough.\n");
+    }
+    if !needed.is_empty() {
+        report.push_str(&format!(
+            "Suggested grant:\n  chsr role <role> task <task> credentials caps whitelist add {}\n",
+            needed.join(",")
+        ));
+    }
+    report
+}
+
+/// A task granting the command the capabilities it checked, to paste in the `tasks` of a role.
+pub fn snippet(command: &[String], caps: &CapSet) -> Result<String, Box<dyn Error>> {
+    let mut command = command.to_vec();
+    let path = final_path(&command[0]);
+    let name = Path::new(&command[0])
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    command[0] = path.to_string_lossy().into_owned();
+    let mut needed = *caps;
+    needed.drop_all(DAC);
+    let task = STask::builder(format!("t_{}", name))
+        .cred(
+            SCredentials::builder()
+                .capabilities(
+                    SCapabilities::builder(SetBehavior::None)
+                        .add_all(needed)
+                        .build(),
+                )
+                .build(),
+        )
+        .commands(
+            SCommands::builder(SetBehavior::None)
+                .add([SCommand::Simple(shell_words::join(&command))])
+                .build(),
+        )
+        .build();
+    let snippet = serde_json::to_string_pretty(&task)?;
+    Ok(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let caps = caps(&parse(br#"["CAP_NET_RAW","CAP_DAC_OVERRIDE"]"#).unwrap());
+        assert!(caps.has(Cap::NET_RAW) && caps.has(Cap::DAC_OVERRIDE));
+        let checks = parse(
+            br#"[{"capability": "CAP_DAC_OVERRIDE", "syscall": "openat", "path": "/etc/shadow"}, {"capability": "CAP_NET_RAW"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            checks[0],
+            Check {
+                cap: Cap::DAC_OVERRIDE,
+                syscall: Some("openat".to_string()),
+                path: Some("/etc/shadow".to_string())
+            }
+        );
+        assert_eq!(checks[1].cause(), None);
+        assert!(parse(b"[]").unwrap().is_empty());
+        assert!(parse(br#"["CAP_NOPE"]"#).is_err());
+        assert!(parse(b"ping: permission denied").is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let command = ["ping".to_string(), "-c1".to_string(), "::1".to_string()];
+        let checks = parse(
+            br#"["CAP_NET_RAW", {"capability": "CAP_DAC_OVERRIDE", "syscall": "openat", "path": "/etc/shadow"}, {"capability": "CAP_DAC_OVERRIDE", "syscall": "openat", "path": "/etc/shadow"}]"#,
+        )
+        .unwrap();
+        let report = report(&command, &checks);
+        assert!(report.starts_with("ping -c1 ::1 checked these capabilities:\n"));
+        assert!(report.contains("  CAP_DAC_OVERRIDE\n    by openat /etc/shadow\n  CAP_NET_RAW\n"));
+        assert!(report.contains("often not needed"));
+        assert!(report.ends_with("credentials caps whitelist add cap_net_raw\n"));
+        assert!(super::report(&command, &[]).contains("needs no privilege"));
+    }
+
+    #[test]
+    fn test_snippet() {
+        let command = ["/usr/bin/ping".to_string(), "::1".to_string()];
+        let caps = caps(&parse(br#"["CAP_NET_RAW","CAP_DAC_OVERRIDE"]"#).unwrap());
+        let snippet: serde_json::Value =
+            serde_json::from_str(&snippet(&command, &caps).unwrap()).unwrap();
+        assert_eq!(
+            snippet,
+            serde_json::json!({
+                "name": "t_ping",
+                "cred": { "capabilities": ["CAP_NET_RAW"] },
+                "commands": { "default": "none", "add": ["/usr/bin/ping ::1"] }
+            })
+        );
+    }
+}
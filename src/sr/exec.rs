@@ -0,0 +1,264 @@
+//! Execution of the command.
+//!
+//! sr replaces itself with the command when nothing is left to do after it, so no privileged
+//! process remains and the command receives the signals itself. When the exit status must be
+//! recorded or `post_run` commands run, sr forks first: the child stays in the process group of
+//! sr, so it keeps the foreground of the terminal, and the parent waits for it, relaying the
+//! signals which are not sent by the terminal.
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    error::Error,
+    ffi::CString,
+    fs::File,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use capctl::Cap;
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{
+            kill, raise, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal,
+            Signal::{
+                SIGCONT, SIGHUP, SIGINT, SIGQUIT, SIGSTOP, SIGTERM, SIGTSTP, SIGUSR1, SIGUSR2,
+            },
+        },
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{execve, fexecve, fork, ForkResult, Pid},
+};
+use rar_common::database::finder::ExecSettings;
+
+use crate::{
+    jail::{self, Directories},
+    selinux,
+};
+
+/// Exit code of the command when it cannot be executed.
+pub const EXEC_FAILED: i32 = 127;
+
+// sent by the terminal to the whole foreground process group, the child already receives them,
+// like SIGWINCH whose default action is to be ignored
+const TERMINAL: [Signal; 3] = [SIGINT, SIGQUIT, SIGTSTP];
+const FORWARDED: [Signal; 4] = [SIGTERM, SIGHUP, SIGUSR1, SIGUSR2];
+
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: libc::c_int) {
+    let child = CHILD.load(Ordering::SeqCst);
+    if child > 0 {
+        // SAFETY: kill is async-signal-safe
+        unsafe { libc::kill(child, signal) };
+    }
+}
+
+/// Whether sr must stay after the command to record its exit status or run its hooks.
+pub fn needs_parent(execcfg: &ExecSettings) -> bool {
+    !execcfg.task().as_ref().borrow().post_run.is_empty()
+        || execcfg
+            .opt
+            .get_audit()
+            .verbosity
+            .unwrap_or_default()
+            .is_standard()
+}
+
+fn cstring(bytes: &[u8]) -> Result<CString, Box<dyn Error>> {
+    Ok(CString::new(bytes)?)
+}
+
+/// Replaces sr with the command, after setting its SELinux context and entering its root and
+/// working directory. An executable whose digest was verified is run from its descriptor.
+pub fn exec(
+    execcfg: &ExecSettings,
+    executable: Option<&File>,
+    args: &[String],
+    env: &HashMap<String, String>,
+    directories: Option<&Directories>,
+) -> Result<Infallible, Box<dyn Error>> {
+    let path = cstring(execcfg.exec_path.as_os_str().as_bytes())?;
+    let argv = std::iter::once(Ok(path.clone()))
+        .chain(args.iter().map(|arg| cstring(arg.as_bytes())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let envp = env
+        .iter()
+        .map(|(key, value)| cstring(format!("{}={}", key, value).as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // /proc may not be reachable in the new root
+    selinux::apply(execcfg)?;
+    if let Some(directories) = directories {
+        let keep_chroot = execcfg.caps.is_some_and(|caps| caps.has(Cap::SYS_CHROOT));
+        jail::enter(directories, keep_chroot)?;
+    }
+    match executable {
+        Some(executable) => Ok(fexecve(executable.as_raw_fd(), &argv, &envp)?),
+        None => Ok(execve(&path, &argv, &envp)?),
+    }
+}
+
+fn set_handler(signal: Signal, handler: SigHandler) -> nix::Result<()> {
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    // SAFETY: the handler only loads an atomic and calls kill
+    unsafe { sigaction(signal, &action) }.map(|_| ())
+}
+
+fn wait(child: Pid) -> Result<i32, Box<dyn Error>> {
+    loop {
+        match waitpid(child, Some(WaitPidFlag::WUNTRACED)) {
+            Ok(WaitStatus::Exited(_, code)) => return Ok(code),
+            Ok(WaitStatus::Signaled(_, signal, _)) => return Ok(128 + signal as i32),
+            // suspended from the terminal: sr stops with it, so the shell sees the job stopped
+            Ok(WaitStatus::Stopped(_, _)) => {
+                raise(SIGSTOP)?;
+                kill(child, SIGCONT)?;
+            }
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Forks, the child gets None and the parent waits for it, relaying the signals, and gets its
+/// pid and exit code, 128 + the signal number when it was killed.
+pub fn fork_and_wait() -> Result<Option<(Pid, i32)>, Box<dyn Error>> {
+    // SAFETY: sr is single-threaded
+    match unsafe { fork() }? {
+        ForkResult::Child => Ok(None),
+        ForkResult::Parent { child } => {
+            CHILD.store(child.as_raw(), Ordering::SeqCst);
+            for signal in TERMINAL {
+                set_handler(signal, SigHandler::SigIgn)?;
+            }
+            for signal in FORWARDED {
+                set_handler(signal, SigHandler::Handler(forward))?;
+            }
+            wait(child).map(|code| Some((child, code)))
+        }
+    }
+}
+
+/// Runs the command in a child process and returns its exit code.
+pub fn spawn(
+    execcfg: &ExecSettings,
+    executable: Option<&File>,
+    args: &[String],
+    env: &HashMap<String, String>,
+    directories: Option<&Directories>,
+) -> Result<i32, Box<dyn Error>> {
+    if let Some((_, code)) = fork_and_wait()? {
+        return Ok(code);
+    }
+    let e = exec(execcfg, executable, args, env, directories).unwrap_err();
+    eprintln!("sr: {} : {}", execcfg.exec_path.display(), e);
+    // SAFETY: the child must not run the exit handlers of sr
+    unsafe { libc::_exit(EXEC_FAILED) }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        finder::{Cred, TaskMatcher},
+        options::{SAudit, SAuditVerbosity},
+        structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    fn needs_parent_of(post_run: &[&str], verbosity: SAuditVerbosity) -> bool {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_exec")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_exec")
+                            .post_run(post_run.iter().map(|hook| hook.to_string()))
+                            .options(match verbosity {
+                                SAuditVerbosity::Minimal => |opt| {
+                                    opt.audit(
+                                        SAudit::builder()
+                                            .verbosity(SAuditVerbosity::Minimal)
+                                            .build(),
+                                    )
+                                    .build()
+                                },
+                                SAuditVerbosity::Standard => |opt| opt.build(),
+                            })
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let taskmatch = config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/ls".to_string()],
+            )
+            .unwrap();
+        needs_parent(&taskmatch.settings)
+    }
+
+    #[test]
+    fn test_exec_verified() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_exec")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_exec")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/true".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let mut taskmatch = config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/true".to_string()],
+            )
+            .unwrap();
+        // the path is not used when the verified executable is given
+        taskmatch.settings.exec_path = "/nonexistent/true".into();
+        let executable = File::open("/usr/bin/true").unwrap();
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                let _ = exec(
+                    &taskmatch.settings,
+                    Some(&executable),
+                    &[],
+                    &HashMap::new(),
+                    None,
+                );
+                unsafe { libc::_exit(EXEC_FAILED) }
+            }
+            ForkResult::Parent { child } => {
+                assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_needs_parent() {
+        assert!(!needs_parent_of(&[], SAuditVerbosity::Minimal));
+        assert!(needs_parent_of(&[], SAuditVerbosity::Standard));
+        assert!(needs_parent_of(
+            &["/usr/bin/true"],
+            SAuditVerbosity::Minimal
+        ));
+    }
+}
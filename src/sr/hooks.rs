@@ -0,0 +1,91 @@
+//! Commands of the task `post_run`, run after the command whatever its result.
+use std::{collections::HashMap, error::Error, os::unix::process::CommandExt, process::Command};
+
+use capctl::CapState;
+use log::warn;
+use rar_common::database::finder::ExecSettings;
+
+use crate::audit;
+
+// the capabilities granted to the command are not given to its hooks
+fn drop_capabilities() -> std::io::Result<()> {
+    capctl::ambient::clear()?;
+    CapState::empty().set_current()?;
+    Ok(())
+}
+
+fn run(command: &str, env: &HashMap<String, String>) -> Result<i32, Box<dyn Error>> {
+    let args = shell_words::split(command)?;
+    let (program, args) = args.split_first().ok_or("Empty post_run command")?;
+    let mut command = Command::new(program);
+    command.args(args).env_clear().envs(env);
+    // SAFETY: only async-signal-safe syscalls are made between fork and exec
+    unsafe { command.pre_exec(drop_capabilities) };
+    Ok(command.status()?.code().unwrap_or(1))
+}
+
+/// Run each hook in order, a failing hook does not prevent the next ones.
+pub fn post_run(execcfg: &ExecSettings, id: &str, env: &HashMap<String, String>) -> Vec<i32> {
+    let hooks = execcfg.task().as_ref().borrow().post_run.clone();
+    hooks
+        .iter()
+        .map(|hook| {
+            let code = run(hook, env).unwrap_or_else(|e| {
+                warn!("Unable to run the post_run command {:?}: {}", hook, e);
+                127
+            });
+            audit::event(
+                execcfg,
+                id,
+                vec![("post_run", hook.clone()), ("status", code.to_string())],
+            );
+            code
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        finder::{Cred, TaskMatcher},
+        structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_post_run() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_deploy")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_edit")
+                            .post_run([
+                                "/bin/sh -c 'exit 3'".to_string(),
+                                "/nonexistent/relock".to_string(),
+                                "/bin/sh -c 'test \"$HOOK\" = ok'".to_string(),
+                            ])
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let taskmatch = config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/ls".to_string()],
+            )
+            .unwrap();
+        let env = HashMap::from([("HOOK".to_string(), "ok".to_string())]);
+        assert_eq!(post_run(&taskmatch.settings, "7f", &env), [3, 127, 0]);
+    }
+}
@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use rar_common::policy::Info;
+
+/// What `sr --info` prints, as text or as JSON for tools presenting what a command would run with.
+pub fn render(info: &Info, json: bool) -> Result<String, Box<dyn Error>> {
+    if json {
+        return Ok(serde_json::to_string_pretty(info)?);
+    }
+    let mut output = format!(
+        "Role: {}\nTask: {}\nWith capabilities: {}",
+        info.role,
+        info.task,
+        info.capabilities
+            .iter()
+            .fold(String::new(), |acc, cap| acc + cap + " ")
+    );
+    if !info.impact.is_empty() {
+        output.push_str(&format!("\nWhich means: {}", info.impact.join(", ")));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let info = Info {
+            role: "r_web".to_string(),
+            task: "t_bind".to_string(),
+            command: vec!["/usr/bin/ls".to_string()],
+            user: None,
+            groups: None,
+            capabilities: vec!["CAP_NET_BIND_SERVICE".to_string()],
+            impact: vec!["can listen on privileged ports".to_string()],
+            options: BTreeMap::new(),
+        };
+        assert_eq!(
+            render(&info, false).unwrap(),
+            "Role: r_web\nTask: t_bind\nWith capabilities: CAP_NET_BIND_SERVICE \nWhich means: can listen on privileged ports"
+        );
+        let json: Value = serde_json::from_str(&render(&info, true).unwrap()).unwrap();
+        assert_eq!(json["task"], "t_bind");
+        assert!(json.get("user").is_none());
+    }
+}
@@ -0,0 +1,430 @@
+//! The `cwd` and `chroot` fields of a task: the directory the command starts in, and its root.
+//!
+//! With `sr --chroot-safe`, the executable and its interpreters are looked up in the new root
+//! before sr enters it, so that a missing file is reported with its path on the host instead of
+//! a bare ENOENT once the privileges are dropped.
+use std::{
+    collections::VecDeque,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{self, Read},
+    os::{
+        fd::AsRawFd,
+        unix::{ffi::OsStrExt, fs::PermissionsExt},
+    },
+    path::{Component, Path, PathBuf},
+};
+
+use capctl::{Cap, CapState};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    unistd::{chdir, chroot},
+};
+use rar_common::{
+    database::finder::ExecSettings, plugin::digest_matches, util::open_with_privileges,
+};
+
+/// Symbolic links followed to resolve a path in the new root, as the kernel does.
+const MAX_LINKS: usize = 40;
+/// Interpreters chained by `#!` lines, as the kernel does.
+const MAX_INTERPRETERS: usize = 4;
+const PT_INTERP: usize = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Directories {
+    pub root: Option<PathBuf>,
+    pub cwd: PathBuf,
+}
+
+/// The new root and working directory of the command, None when the task sets neither.
+pub fn directories(execcfg: &ExecSettings) -> Result<Option<Directories>, Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    for path in [&task.chroot, &task.cwd].into_iter().flatten() {
+        if !path.is_absolute() {
+            return Err(format!("{} must be an absolute path", path.display()).into());
+        }
+    }
+    Ok(match (&task.chroot, &task.cwd) {
+        (None, None) => None,
+        (Some(root), cwd) => Some(Directories {
+            root: Some(root.clone()),
+            cwd: cwd.clone().unwrap_or_else(|| PathBuf::from("/")),
+        }),
+        (None, Some(cwd)) => Some(Directories {
+            root: None,
+            cwd: cwd.clone(),
+        }),
+    })
+}
+
+/// Whether sr must keep CAP_SYS_CHROOT until the command is started.
+pub fn needs_chroot(execcfg: &ExecSettings) -> bool {
+    execcfg.task().as_ref().borrow().chroot.is_some()
+}
+
+fn components(path: &Path) -> impl DoubleEndedIterator<Item = OsString> + '_ {
+    path.components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name.to_owned()),
+        Component::ParentDir => Some("..".into()),
+        _ => None,
+    })
+}
+
+/// The file of the host that `path` names in `root`: the symbolic links are followed relative
+/// to `root`, and `..` never leaves it.
+fn resolve(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut pending: VecDeque<_> = components(path).collect();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(name) = pending.pop_front() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+        let host = root.join(&resolved).join(&name);
+        if !host.symlink_metadata()?.is_symlink() {
+            resolved.push(name);
+            continue;
+        }
+        links += 1;
+        if links > MAX_LINKS {
+            return Err(io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        let target = host.read_link()?;
+        if target.is_absolute() {
+            resolved = PathBuf::new();
+        }
+        for name in components(&target).rev() {
+            pending.push_front(name);
+        }
+    }
+    Ok(root.join(resolved))
+}
+
+/// The loader named by the PT_INTERP header of a dynamic ELF binary.
+fn elf_interpreter(content: &[u8]) -> Option<PathBuf> {
+    let little_endian = match content.get(5)? {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let read = |offset: usize, size: usize| {
+        let bytes = content.get(offset..offset.checked_add(size)?)?;
+        let value = bytes.iter().enumerate().fold(0u64, |value, (i, byte)| {
+            let shift = if little_endian { i } else { size - 1 - i } * 8;
+            value | (*byte as u64) << shift
+        });
+        usize::try_from(value).ok()
+    };
+    // the offsets of the program headers in the file header, then of the segment in a header
+    let (phoff, phentsize, phnum, offset, filesz) = match content.get(4)? {
+        1 => (
+            read(0x1c, 4)?,
+            read(0x2a, 2)?,
+            read(0x2c, 2)?,
+            (0x04, 4),
+            (0x10, 4),
+        ),
+        2 => (
+            read(0x20, 8)?,
+            read(0x36, 2)?,
+            read(0x38, 2)?,
+            (0x08, 8),
+            (0x20, 8),
+        ),
+        _ => return None,
+    };
+    (0..phnum).find_map(|i| {
+        let header = phoff.checked_add(i.checked_mul(phentsize)?)?;
+        if read(header, 4)? != PT_INTERP {
+            return None;
+        }
+        let start = read(header.checked_add(offset.0)?, offset.1)?;
+        let size = read(header.checked_add(filesz.0)?, filesz.1)?;
+        let name = content.get(start..start.checked_add(size)?)?;
+        let name = name.split(|byte| *byte == 0).next()?;
+        Some(PathBuf::from(OsStr::from_bytes(name)))
+    })
+}
+
+/// The interpreter the kernel runs for a file: the program of its `#!` line, or its loader when
+/// it is a dynamic ELF binary.
+fn interpreter(content: &[u8]) -> Option<PathBuf> {
+    if let Some(line) = content.strip_prefix(b"#!") {
+        let line = line.split(|byte| *byte == b'\n').next()?;
+        let program = line
+            .split(|byte| *byte == b' ' || *byte == b'\t')
+            .find(|word| !word.is_empty())?;
+        return Some(PathBuf::from(OsStr::from_bytes(program)));
+    }
+    if content.starts_with(b"\x7fELF") {
+        return elf_interpreter(content);
+    }
+    None
+}
+
+/// `sr --chroot-safe`: an executable and its interpreters must exist in the new root, be
+/// executable and have the digests the task pins, the errors name their path on the host. The
+/// executable is returned opened in the new root when its digest is pinned, to be run from its
+/// descriptor.
+pub fn verify(execcfg: &ExecSettings, executable: &Path) -> Result<Option<File>, Box<dyn Error>> {
+    let Some(Directories {
+        root: Some(root), ..
+    }) = directories(execcfg)?
+    else {
+        return Ok(None);
+    };
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    let mut path = executable.to_path_buf();
+    let mut interpreted: Option<PathBuf> = None;
+    let mut pinned = None;
+    for _ in 0..=MAX_INTERPRETERS {
+        let shown = root.join(path.strip_prefix("/").unwrap_or(&path));
+        let not_found = |e: io::Error| match &interpreted {
+            Some(of) => format!(
+                "{}: interpreter of {}: {}",
+                shown.display(),
+                of.display(),
+                e
+            ),
+            None => format!("{}: {}", shown.display(), e),
+        };
+        let mut file = resolve(&root, &path)
+            .and_then(open_with_privileges)
+            .map_err(not_found)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("{}: not an executable file", shown.display()).into());
+        }
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        match digest_matches(&task, &path, &content) {
+            Some(false) => {
+                return Err(format!(
+                    "The digest of {} does not match the policy",
+                    shown.display()
+                )
+                .into())
+            }
+            Some(true) if interpreted.is_none() => {
+                // the interpreter of a script reads it again from /dev/fd
+                if content.starts_with(b"#!") {
+                    fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+                }
+                pinned = Some(file);
+            }
+            _ => (),
+        }
+        match interpreter(&content) {
+            Some(next) => {
+                path = next;
+                interpreted = Some(shown);
+            }
+            None => return Ok(pinned),
+        }
+    }
+    Err(format!(
+        "{}{}: too many levels of interpreters",
+        root.display(),
+        executable.display()
+    )
+    .into())
+}
+
+/// Run after the credentials switch, just before the command is executed.
+pub fn enter(directories: &Directories, keep_chroot: bool) -> io::Result<()> {
+    if let Some(root) = &directories.root {
+        let mut state = CapState::get_current().map_err(io::Error::from)?;
+        state.effective.add(Cap::SYS_CHROOT);
+        state.set_current().map_err(io::Error::from)?;
+        chroot(root)?;
+        // the command must not be able to escape the new root
+        state.effective.drop(Cap::SYS_CHROOT);
+        if !keep_chroot {
+            state.permitted.drop(Cap::SYS_CHROOT);
+        }
+        state.set_current().map_err(io::Error::from)?;
+    }
+    chdir(&directories.cwd)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
+
+    use nix::unistd::getuid;
+    use rar_common::{
+        database::{
+            actor::SActor,
+            finder::{Cred, TaskMatch, TaskMatcher},
+            structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+        },
+        plugin::register_plugins,
+    };
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    fn matching(root: Option<&str>, cwd: Option<&str>, command: SCommand) -> TaskMatch {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_jail")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_jail")
+                            .maybe_chroot(root)
+                            .maybe_cwd(cwd)
+                            .commands(SCommands::builder(SetBehavior::None).add([command]).build())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/ls".to_string()],
+            )
+            .unwrap()
+    }
+
+    fn directories_of(
+        root: Option<&str>,
+        cwd: Option<&str>,
+    ) -> Result<Option<Directories>, Box<dyn Error>> {
+        let taskmatch = matching(root, cwd, SCommand::Simple("/usr/bin/ls".to_string()));
+        directories(&taskmatch.settings)
+    }
+
+    fn write_executable(path: &Path, content: &[u8]) {
+        fs::write(path, content).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_directories() {
+        assert_eq!(directories_of(None, None).unwrap(), None);
+        assert_eq!(
+            directories_of(None, Some("/var/www")).unwrap(),
+            Some(Directories {
+                root: None,
+                cwd: "/var/www".into()
+            })
+        );
+        assert_eq!(
+            directories_of(Some("/srv/jail"), None).unwrap(),
+            Some(Directories {
+                root: Some("/srv/jail".into()),
+                cwd: "/".into()
+            })
+        );
+        assert!(directories_of(Some("srv/jail"), None).is_err());
+        assert!(directories_of(None, Some("www")).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("rar_test_resolve_{}", std::process::id()));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("usr")).unwrap();
+        fs::write(root.join("bin/busybox"), b"").unwrap();
+        symlink("/bin", root.join("usr/bin")).unwrap();
+        symlink("busybox", root.join("bin/sh")).unwrap();
+        symlink("../../../../..", root.join("bin/up")).unwrap();
+        symlink("loop", root.join("bin/loop")).unwrap();
+        assert_eq!(
+            resolve(&root, Path::new("/usr/bin/sh")).unwrap(),
+            root.join("bin/busybox")
+        );
+        // neither an absolute link nor .. leaves the new root
+        assert_eq!(
+            resolve(&root, Path::new("/bin/up/bin/busybox")).unwrap(),
+            root.join("bin/busybox")
+        );
+        assert_eq!(
+            resolve(&root, Path::new("/usr/bin/bash"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            resolve(&root, Path::new("/bin/loop"))
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::ELOOP)
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_interpreter() {
+        assert_eq!(
+            interpreter(b"#! /bin/sh -e\necho"),
+            Some(PathBuf::from("/bin/sh"))
+        );
+        assert_eq!(interpreter(b"#!\n"), None);
+        assert_eq!(interpreter(b"echo"), None);
+        let ls = fs::read("/usr/bin/ls").unwrap();
+        assert!(interpreter(&ls).is_some_and(|loader| loader.is_absolute()));
+        assert_eq!(interpreter(b"\x7fELF\x02\x01"), None);
+    }
+
+    #[test]
+    fn test_verify() {
+        register_plugins();
+        let root = std::env::temp_dir().join(format!("rar_test_verify_{}", std::process::id()));
+        let jail = root.to_str().unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(root.join("bin")).unwrap();
+        let ls = Path::new("/usr/bin/ls");
+        let simple = SCommand::Simple("/usr/bin/ls".to_string());
+        let taskmatch = matching(Some(jail), None, simple.clone());
+        let e = verify(&taskmatch.settings, ls).unwrap_err().to_string();
+        assert!(e.starts_with(&format!("{}/usr/bin/ls: ", jail)), "{}", e);
+
+        write_executable(&root.join("usr/bin/ls"), b"#!/bin/sh\n");
+        let e = verify(&taskmatch.settings, ls).unwrap_err().to_string();
+        assert!(
+            e.starts_with(&format!(
+                "{}/bin/sh: interpreter of {}/usr/bin/ls: ",
+                jail, jail
+            )),
+            "{}",
+            e
+        );
+
+        write_executable(&root.join("bin/sh"), b"");
+        assert!(verify(&taskmatch.settings, ls).unwrap().is_none());
+        fs::set_permissions(root.join("bin/sh"), fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(verify(&taskmatch.settings, ls).is_err());
+        fs::set_permissions(root.join("bin/sh"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // the digest pinned for the executable of the host
+        let content = fs::read(ls).unwrap();
+        let pinned = SCommand::Complex(serde_json::json!({
+            "hash_type": "sha256",
+            "hash": hex::encode(Sha256::digest(&content)),
+            "command": "/usr/bin/ls",
+        }));
+        let taskmatch = matching(Some(jail), None, pinned);
+        let e = verify(&taskmatch.settings, ls).unwrap_err().to_string();
+        assert!(e.contains("does not match the policy"), "{}", e);
+        assert!(e.contains(jail), "{}", e);
+        write_executable(&root.join("usr/bin/ls"), &content);
+        let loader = interpreter(&content).unwrap();
+        let host_loader = root.join(loader.strip_prefix("/").unwrap());
+        fs::create_dir_all(host_loader.parent().unwrap()).unwrap();
+        write_executable(&host_loader, b"");
+        assert!(verify(&taskmatch.settings, ls).unwrap().is_some());
+
+        assert!(verify(&matching(None, None, simple).settings, ls)
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+//! The kernel features required or recommended by a task, checked by sr before authenticating,
+//! so that a missing feature is reported as such instead of failing while the credentials of
+//! the command are being set.
+use std::{fs, path::Path};
+
+use log::warn;
+use rar_common::database::{finder::ExecSettings, structs::KernelFeature};
+
+fn read(root: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(root.join(file)).ok()
+}
+
+/// Whether the kernel mounted at `root`, its /proc and /sys, supports the feature.
+fn supported_in(root: &Path, feature: KernelFeature) -> bool {
+    match feature {
+        KernelFeature::AmbientCapabilities => capctl::ambient::is_supported(),
+        KernelFeature::UserNamespaces => read(root, "proc/sys/user/max_user_namespaces")
+            .and_then(|max| max.trim().parse::<u64>().ok())
+            .is_some_and(|max| max > 0),
+        // present when the bpf syscall is built in
+        KernelFeature::Ebpf => root
+            .join("proc/sys/kernel/unprivileged_bpf_disabled")
+            .exists(),
+        KernelFeature::Seccomp => read(root, "proc/self/status")
+            .is_some_and(|status| status.lines().any(|line| line.starts_with("Seccomp:"))),
+        KernelFeature::CgroupV2 => root.join("sys/fs/cgroup/cgroup.controllers").exists(),
+        KernelFeature::Landlock => read(root, "sys/kernel/security/lsm")
+            .is_some_and(|lsm| lsm.trim().split(',').any(|name| name == "landlock")),
+        KernelFeature::Unknown => false,
+    }
+}
+
+fn supported(feature: KernelFeature) -> bool {
+    supported_in(Path::new("/"), feature)
+}
+
+/// Refuse the task when the kernel lacks one of its required features.
+pub fn check(execcfg: &ExecSettings) -> Result<(), String> {
+    check_with(execcfg, supported)
+}
+
+fn check_with(
+    execcfg: &ExecSettings,
+    supported: impl Fn(KernelFeature) -> bool,
+) -> Result<(), String> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    let role = execcfg.role().as_ref().borrow().name.clone();
+    for feature in task.recommends.iter().filter(|f| !supported(**f)) {
+        warn!(
+            "The kernel lacks {} recommended by the task {} of the role {}",
+            feature, task.name, role
+        );
+    }
+    match task.requires.iter().find(|f| !supported(**f)) {
+        Some(feature) => Err(format!(
+            "The kernel lacks {} required by the task {} of the role {}",
+            feature, task.name, role
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getuid;
+    use rar_common::database::{
+        actor::SActor,
+        finder::{Cred, TaskMatcher},
+        make_weak_config,
+        structs::{SCommand, SCommands, SConfig, SRole, STask, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_supported_in() {
+        let root = std::env::temp_dir().join(format!("rar_kernel_{}", std::process::id()));
+        fs::create_dir_all(root.join("proc/sys/user")).unwrap();
+        fs::create_dir_all(root.join("sys/kernel/security")).unwrap();
+        fs::write(root.join("proc/sys/user/max_user_namespaces"), "0\n").unwrap();
+        fs::write(
+            root.join("sys/kernel/security/lsm"),
+            "capability,landlock,yama\n",
+        )
+        .unwrap();
+        assert!(!supported_in(&root, KernelFeature::UserNamespaces));
+        assert!(supported_in(&root, KernelFeature::Landlock));
+        assert!(!supported_in(&root, KernelFeature::Ebpf));
+        assert!(!supported_in(&root, KernelFeature::CgroupV2));
+        assert!(!supported_in(&root, KernelFeature::Unknown));
+        fs::write(root.join("proc/sys/user/max_user_namespaces"), "15000\n").unwrap();
+        assert!(supported_in(&root, KernelFeature::UserNamespaces));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_sandbox")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_bwrap")
+                            .requires([KernelFeature::UserNamespaces])
+                            .recommends([KernelFeature::Landlock])
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        make_weak_config(&config);
+        let taskmatch = config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/ls".to_string()],
+            )
+            .unwrap();
+        assert!(check_with(&taskmatch.settings, |_| true).is_ok());
+        // a missing recommended feature is only a warning
+        assert!(check_with(&taskmatch.settings, |f| f != KernelFeature::Landlock).is_ok());
+        assert_eq!(
+            check_with(&taskmatch.settings, |_| false).unwrap_err(),
+            "The kernel lacks user-namespaces required by the task t_bwrap of the role r_sandbox"
+        );
+    }
+}
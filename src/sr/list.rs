@@ -0,0 +1,242 @@
+//! `sr --list`: every task the user may run, whatever the command.
+use std::{cell::RefCell, collections::BTreeMap, error::Error, fmt::Write, rc::Rc};
+
+use rar_common::{
+    database::{
+        finder::{Cred, CredMatcher},
+        options::OptStack,
+        structs::{SCommands, SConfig, STask},
+    },
+    policy::{options, Provenance},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+struct Entry {
+    role: String,
+    task: String,
+    commands: SCommands,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Value>,
+    capabilities: Vec<String>,
+    options: BTreeMap<String, Provenance>,
+}
+
+fn entry(task: &Rc<RefCell<STask>>) -> Result<Entry, Box<dyn Error>> {
+    let optstack = OptStack::from_task(task.clone());
+    let task = task.as_ref().borrow();
+    Ok(Entry {
+        role: task
+            .role()
+            .map(|role| role.as_ref().borrow().name.clone())
+            .unwrap_or_default(),
+        task: task.name.to_string(),
+        commands: task.commands.clone(),
+        user: task
+            .cred
+            .setuid
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?,
+        groups: task
+            .cred
+            .setgid
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?,
+        capabilities: task
+            .cred
+            .capabilities
+            .as_ref()
+            .map(|caps| caps.to_capset())
+            .unwrap_or_default()
+            .iter()
+            .map(|cap| cap.to_string())
+            .collect(),
+        options: options(&optstack)?,
+    })
+}
+
+fn entries(config: &Rc<RefCell<SConfig>>, user: &Cred) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for role in config.as_ref().borrow().roles.iter() {
+        if !role.as_ref().borrow().is_enabled() || role.user_matches(user).is_no_match() {
+            continue;
+        }
+        for task in role.as_ref().borrow().tasks.iter() {
+            if task.as_ref().borrow().is_enabled() {
+                entries.push(entry(task)?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn commands_text(commands: &SCommands) -> String {
+    let list = |commands: &[_]| {
+        commands
+            .iter()
+            .map(|command| text(&serde_json::to_value(command).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut result = if commands
+        .default_behavior
+        .as_ref()
+        .is_some_and(|b| b.is_all())
+    {
+        "all".to_string()
+    } else {
+        list(&commands.add)
+    };
+    if let Some(file) = &commands.file {
+        if !result.is_empty() {
+            result.push_str(", ");
+        }
+        let _ = write!(result, "those of {}", file.display());
+    }
+    if commands
+        .default_behavior
+        .as_ref()
+        .is_some_and(|b| b.is_all())
+        && !commands.sub.is_empty()
+    {
+        let _ = write!(result, " except {}", list(&commands.sub));
+    }
+    if result.is_empty() {
+        result.push_str("none");
+    }
+    result
+}
+
+/// What `sr --list` prints, as text or as JSON.
+pub fn list(
+    config: &Rc<RefCell<SConfig>>,
+    user: &Cred,
+    json: bool,
+) -> Result<String, Box<dyn Error>> {
+    let entries = entries(config, user)?;
+    if json {
+        return Ok(serde_json::to_string_pretty(&entries)?);
+    }
+    if entries.is_empty() {
+        return Ok(format!("User {} may not run any command.", user.user.name));
+    }
+    let mut output = format!("User {} may run the following tasks:", user.user.name);
+    for entry in entries {
+        let _ = write!(
+            output,
+            "\n\nRole: {}\nTask: {}\nCommands: {}",
+            entry.role,
+            entry.task,
+            commands_text(&entry.commands)
+        );
+        if let Some(user) = &entry.user {
+            let _ = write!(output, "\nAs user: {}", text(user));
+        }
+        if let Some(groups) = &entry.groups {
+            let _ = write!(output, "\nAs groups: {}", text(groups));
+        }
+        let _ = write!(
+            output,
+            "\nWith capabilities: {}\nOptions:",
+            entry.capabilities.join(" ")
+        );
+        for (name, provenance) in &entry.options {
+            let _ = write!(
+                output,
+                "\n  {}: {} ({})",
+                name,
+                text(&provenance.value),
+                provenance.level
+            );
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use capctl::Cap;
+    use nix::unistd::{getuid, User};
+    use rar_common::database::{
+        actor::SActor,
+        structs::{SCapabilities, SCommand, SCredentials, SRole, SetBehavior},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_list() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_web")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_bind")
+                            .cred(
+                                SCredentials::builder()
+                                    .setuid("www-data")
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::NET_BIND_SERVICE)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/sbin/nginx".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .task(STask::builder("t_off").enabled(false).build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_other")
+                    .actor(SActor::group("rar_list_test").build())
+                    .task(STask::builder("t_other").build())
+                    .build(),
+            )
+            .build();
+        let user = Cred::builder().build();
+        let entries: Value = serde_json::from_str(&list(&config, &user, true).unwrap()).unwrap();
+        let entries = entries.as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["role"], "r_web");
+        assert_eq!(entries[0]["task"], "t_bind");
+        assert_eq!(entries[0]["user"], "www-data");
+        assert_eq!(entries[0]["capabilities"][0], "CAP_NET_BIND_SERVICE");
+        assert_eq!(entries[0]["options"]["root"]["level"], "default");
+        let text = list(&config, &user, false).unwrap();
+        let name = User::from_uid(getuid()).unwrap().unwrap().name;
+        assert!(text.starts_with(&format!("User {} may run", name)));
+        assert!(text.contains("Commands: /usr/sbin/nginx"));
+        assert!(text.contains("As user: www-data"));
+        assert!(!text.contains("t_off"));
+    }
+
+    #[test]
+    fn test_commands_text() {
+        let commands = SCommands::builder(SetBehavior::All)
+            .sub([SCommand::Simple("/usr/bin/passwd".to_string())])
+            .build();
+        assert_eq!(commands_text(&commands), "all except /usr/bin/passwd");
+        assert_eq!(
+            commands_text(&SCommands::builder(SetBehavior::None).build()),
+            "none"
+        );
+    }
+}
@@ -1,36 +1,86 @@
+mod activate;
+mod audit;
+mod broker;
+mod cgroup;
+mod check;
+#[cfg(feature = "ebpf")]
+mod discover;
+mod exec;
+mod hooks;
+mod info;
+mod jail;
+mod kernel;
+mod list;
+mod memory;
+mod network;
+mod package;
 pub mod pam;
+mod selinux;
+mod state;
+#[cfg_attr(
+    not(feature = "pam"),
+    allow(dead_code, reason = "The timestamps skip the PAM authentication.")
+)]
 mod timeout;
+mod upgrade;
 
-use capctl::CapState;
+use capctl::{Cap, CapSet, CapState};
 use const_format::formatcp;
 use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
     libc::dev_t,
     sys::stat,
-    unistd::{getgroups, getuid, isatty, Group, User},
+    unistd::{
+        getgid, getgroups, getuid, isatty, setgroups, setresgid, setresuid, Gid, Group, Uid, User,
+    },
 };
 use rar_common::database::{
     actor::{SGroupType, SGroups, SUserType},
-    finder::{Cred, TaskMatch, TaskMatcher},
+    finder::{Cred, ExecSettings, TaskMatch, TaskMatcher},
     options::EnvBehavior,
     FilterMatcher,
 };
-use rar_common::database::{options::OptStack, structs::SConfig};
+use rar_common::database::{
+    options::OptStack,
+    structs::{RoleGetter, SConfig},
+};
 use rar_common::util::escape_parser_string;
 
-use log::{debug, error};
-use pam::PAM_PROMPT;
-use pty_process::blocking::{Command, Pty};
-use std::{cell::RefCell, error::Error, io::stdout, os::fd::AsRawFd, rc::Rc};
+use activate::Activation;
+use log::{debug, error, info, warn};
+use pam::{Askpass, Cli as CliFrontend, Frontend, Machine, PAM_PROMPT};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::stdout,
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
 
-use rar_common::plugin::register_plugins;
+use rar_common::agent::{self, Answer, Query};
+use rar_common::plugin::{activate_role, register_plugins, verify_digest};
+use rar_common::policy::Info;
+use rar_common::runtime::RuntimeDirs;
 use rar_common::{
     self,
+    activation::activated_config,
+    clock::check_policy,
     database::read_json_config,
+    groups::{self, GroupsSettings},
+    integrity::{integrity_settings, policy_path, verify_policy},
+    logger::{self, configure as configure_logger},
+    orchestrator::check_token,
+    trace,
     util::{
-        activates_no_new_privs, dac_override_effective, drop_effective, read_effective,
-        setgid_effective, setpcap_effective, setuid_effective, subsribe, BOLD, RST, UNDERLINE,
+        activates_no_new_privs, dac_override_effective, drop_effective, harden_process,
+        read_effective, setgid_effective, setpcap_effective, setuid_effective, subsribe, BOLD, RST,
+        UNDERLINE,
     },
-    Storage,
+    SettingsFile, Storage,
 };
 
 #[cfg(not(test))]
@@ -47,6 +97,7 @@ const ROOTASROLE: &str = "target/rootasrole.json";
 
 const USAGE: &str = formatcp!(
     r#"{UNDERLINE}{BOLD}Usage:{RST} {BOLD}sr{RST} [OPTIONS] [COMMAND]...
+       {BOLD}sr{RST} --activate <ROLE> [--for <DURATION>]
 
 {UNDERLINE}{BOLD}Arguments:{RST}
   [COMMAND]...
@@ -54,7 +105,7 @@ const USAGE: &str = formatcp!(
 
 {UNDERLINE}{BOLD}Options:{RST}
   {BOLD}-r, --role <ROLE>{RST}
-          Role option allows you to select a specific role to use
+          Role option allows you to select a specific role to use, without command the default command of the role is executed
 
   {BOLD}-t, --task <TASK>{RST}
           Task option allows you to select a specific task to use in the selected role. Note: You must specify a role to designate a task
@@ -75,6 +126,50 @@ const USAGE: &str = formatcp!(
   {BOLD}-i, --info{RST}
           Display rights of executor
 
+  {BOLD}--check{RST}
+          Report whether the command would be allowed, with its role, task and capabilities, without authenticating nor running it. The exit status is 1 when denied
+
+  {BOLD}--why-not{RST}
+          Like --check, also explaining the answer of each role and task
+
+  {BOLD}-l, --list{RST}
+          List the tasks you may run, with their commands, credentials and options
+
+  {BOLD}--discover{RST}
+          Run the command without privileges under the capable probe, and print the capabilities it checked
+
+  {BOLD}-S, --stdin{RST}
+          Read the password from the standard input instead of the terminal
+
+  {BOLD}-A, --askpass{RST}
+          Ask the password with the program named by the SR_ASKPASS variable
+
+  {BOLD}--auth-fd <FD>{RST}
+          Authenticate through JSON lines exchanged on the file descriptor FD, for frontends without terminal
+
+  {BOLD}-n, --non-interactive{RST}
+          Fail with the exit status 2 instead of asking for a password
+
+  {BOLD}-q, --quiet{RST}
+          Hide the banner of the role, when the role allows it
+
+  {BOLD}--chroot-safe{RST}
+          When the task sets a chroot, check that the command and its interpreters exist in it, with the digests the task pins, before entering it
+
+  {BOLD}--activate <ROLE>{RST}
+          Activate a role that requires it, in the current terminal, after authenticating
+
+  {BOLD}--for <DURATION>{RST}
+          Duration of the activation, written 90s, 30m, 2h or HH:MM:SS, at most the max-duration of the role
+
+  {BOLD}-k, --reset-timestamp{RST}
+          Forget the cached authentications of the user, the next command asks for the password again
+
+  {BOLD}--format <FORMAT>{RST}
+          Format of the --info, --list and --discover outputs, text or json
+          
+          [default: text]
+
   {BOLD}-h, --help{RST}
           Print help (see a summary with '-h')"#,
     UNDERLINE = UNDERLINE,
@@ -93,6 +188,18 @@ struct Cli {
     /// Display rights of executor
     info: bool,
 
+    /// List the tasks of the executor
+    list: bool,
+
+    /// Report the decision without executing
+    check: bool,
+
+    /// Explain the answer of each role and task
+    why_not: bool,
+
+    /// Print the information as JSON
+    json: bool,
+
     /// Display help
     help: bool,
 
@@ -101,6 +208,30 @@ struct Cli {
 
     /// Use stdin for password prompt
     stdin: bool,
+
+    /// Use the askpass program for password prompt
+    askpass: bool,
+
+    /// Authenticate through the machine protocol on this file descriptor
+    auth_fd: Option<i32>,
+
+    /// Forget the authentication cookies
+    reset_timestamp: bool,
+
+    /// Never prompt for authentication
+    non_interactive: bool,
+
+    /// Report the capabilities checked by the command
+    discover: bool,
+
+    /// Hide the banner of the role, if allowed
+    quiet: bool,
+
+    /// Activate a role instead of executing a command
+    activation: Option<Activation>,
+
+    /// Look up the command in the chroot of the task before entering it
+    chroot_safe: bool,
 }
 
 impl Default for Cli {
@@ -109,8 +240,20 @@ impl Default for Cli {
             opt_filter: None,
             prompt: PAM_PROMPT.to_string(),
             info: false,
+            list: false,
+            check: false,
+            why_not: false,
+            json: false,
             help: false,
             stdin: false,
+            askpass: false,
+            auth_fd: None,
+            reset_timestamp: false,
+            non_interactive: false,
+            discover: false,
+            quiet: false,
+            activation: None,
+            chroot_safe: false,
             command: vec![],
         }
     }
@@ -125,6 +268,24 @@ fn cap_effective_error(caplist: &str) -> String {
     )
 }
 
+/// The capabilities sr needs to switch the credentials and grant the task capabilities, but
+/// does not have, e.g. when sr is on a nosuid mount.
+fn missing_capabilities(execcfg: &ExecSettings, permitted: CapSet) -> Option<String> {
+    let mut required = execcfg.caps.unwrap_or_default();
+    required.add_all([Cap::SETUID, Cap::SETGID, Cap::SETPCAP]);
+    if execcfg.task().as_ref().borrow().chroot.is_some() {
+        required.add(Cap::SYS_CHROOT);
+    }
+    let missing = required & !permitted;
+    (!missing.is_empty()).then(|| {
+        missing
+            .iter()
+            .map(|cap| cap.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
 fn from_json_execution_settings(
     args: &Cli,
     config: &Rc<RefCell<SConfig>>,
@@ -135,6 +296,24 @@ fn from_json_execution_settings(
         .map_err(|m| m.into())
 }
 
+/// `sr -r <role>` without command runs the default command of the role, if any.
+fn apply_default_command(
+    args: &mut Cli,
+    config: &Rc<RefCell<SConfig>>,
+) -> Result<(), Box<dyn Error>> {
+    if !args.command.is_empty() {
+        return Ok(());
+    }
+    let role = args.opt_filter.as_ref().and_then(|f| f.role.as_ref());
+    if let Some(command) = role
+        .and_then(|role| config.role(role))
+        .and_then(|role| role.as_ref().borrow().default_command.clone())
+    {
+        args.command = shell_words::split(&command)?;
+    }
+    Ok(())
+}
+
 fn getopt<S, I>(s: I) -> Result<Cli, Box<dyn Error>>
 where
     I: IntoIterator<Item = S>,
@@ -147,6 +326,8 @@ where
     let mut user: Option<SUserType> = None;
     let mut group: Option<SGroups> = None;
     let mut env = None;
+    let mut role_to_activate = None;
+    let mut duration = None;
 
     while let Some(arg) = iter.next() {
         // matches only first options
@@ -155,21 +336,29 @@ where
                 user = iter.next().map(|s| escape_parser_string(s).as_str().into());
             }
             "-g" | "--group" => {
-                group = iter
-                    .next()
-                    .map(|s| {
-                        SGroups::Multiple(
-                            s.as_ref()
-                                .split(',')
-                                .map(|g| g.into())
-                                .collect::<Vec<SGroupType>>(),
-                        )
-                    })
-                    .into();
+                group = iter.next().map(|s| {
+                    SGroups::Multiple(
+                        s.as_ref()
+                            .split(',')
+                            .map(|g| g.into())
+                            .collect::<Vec<SGroupType>>(),
+                    )
+                });
             }
             "-S" | "--stdin" => {
                 args.stdin = true;
             }
+            "-A" | "--askpass" => {
+                args.askpass = true;
+            }
+            "--auth-fd" => {
+                args.auth_fd = Some(
+                    iter.next()
+                        .and_then(|fd| fd.as_ref().parse().ok())
+                        .filter(|fd| *fd > 2)
+                        .ok_or("--auth-fd expects a file descriptor greater than 2")?,
+                );
+            }
             "-r" | "--role" => {
                 role = iter.next().map(|s| escape_parser_string(s));
             }
@@ -188,6 +377,56 @@ where
             "-i" | "--info" => {
                 args.info = true;
             }
+            "-l" | "--list" => {
+                args.list = true;
+            }
+            "--check" => {
+                args.check = true;
+            }
+            "--why-not" => {
+                args.check = true;
+                args.why_not = true;
+            }
+            "-k" | "--reset-timestamp" => {
+                args.reset_timestamp = true;
+            }
+            "-n" | "--non-interactive" => {
+                args.non_interactive = true;
+            }
+            "--discover" => {
+                args.discover = true;
+            }
+            "-q" | "--quiet" => {
+                args.quiet = true;
+            }
+            "--chroot-safe" => {
+                args.chroot_safe = true;
+            }
+            "--activate" => {
+                role_to_activate = Some(
+                    iter.next()
+                        .map(|s| escape_parser_string(s))
+                        .ok_or("--activate expects a role")?,
+                );
+            }
+            "--for" => {
+                duration = Some(
+                    iter.next()
+                        .map(|s| s.as_ref().to_string())
+                        .ok_or("--for expects a duration")?,
+                );
+            }
+            "--format" => match iter.next().as_ref().map(|s| s.as_ref()) {
+                Some("json") => args.json = true,
+                Some("text") => args.json = false,
+                format => {
+                    return Err(format!(
+                        "Unknown format: {}, expected text or json",
+                        format.unwrap_or_default()
+                    )
+                    .into())
+                }
+            },
             "-h" | "--help" => {
                 args.help = true;
             }
@@ -213,13 +452,21 @@ where
     for arg in iter {
         args.command.push(escape_parser_string(arg));
     }
+    args.activation = activate::parse(role_to_activate, duration)?;
+    if args.activation.is_some() && !args.command.is_empty() {
+        return Err("usage: sr --activate <role> [--for <duration>]".into());
+    }
     Ok(args)
 }
 
 #[cfg(not(tarpaulin_include))]
 fn main() -> Result<(), Box<dyn Error>> {
-    use crate::{pam::check_auth, ROOTASROLE};
+    use crate::{
+        pam::{check_auth, AuthenticationRequired, AUTH_REQUIRED_STATUS},
+        ROOTASROLE,
+    };
 
+    harden_process()?;
     subsribe("sr")?;
     drop_effective()?;
     register_plugins();
@@ -228,20 +475,93 @@ fn main() -> Result<(), Box<dyn Error>> {
         println!("{}", USAGE);
         return Ok(());
     }
-    let args = getopt(args)?;
+    let mut args = getopt(args)?;
 
     if args.help {
         println!("{}", USAGE);
         return Ok(());
     }
+    #[cfg(not(feature = "ebpf"))]
+    if args.discover {
+        eprintln!("sr: sr is built without eBPF support, --discover is not available");
+        std::process::exit(1);
+    }
+    #[cfg(feature = "ebpf")]
+    if args.discover {
+        let checks = discover::discover(&args.command)?;
+        if args.json {
+            println!(
+                "{}",
+                discover::snippet(&args.command, &discover::caps(&checks))?
+            );
+        } else {
+            print!("{}", discover::report(&args.command, &checks));
+        }
+        return Ok(());
+    }
+    if let Some(info) = agent_info(&args) {
+        println!("{}", info::render(&info, args.json)?);
+        return Ok(());
+    }
+    // the file capabilities are ignored on a nosuid mount or without extended attributes
+    if CapState::get_current()?.permitted.is_empty() {
+        error!("sr has no capabilities, its file capabilities are not effective");
+        eprintln!("sr: sr has no capabilities, its file capabilities are ignored on a nosuid mount or a filesystem without extended attributes");
+        std::process::exit(1);
+    }
     read_effective(true)
         .or(dac_override_effective(true))
         .unwrap_or_else(|_| panic!("{}", cap_effective_error("dac_read_search or dac_override")));
     let settings = rar_common::get_settings(ROOTASROLE).expect("Failed to get settings");
+    configure_logger("sr", settings.as_ref().borrow().storage.log.as_ref())?;
+    // every log line and audit record of this execution can be found by this id
+    let audit_id = audit::new_id();
+    logger::set_session(&audit_id);
+    let retention = settings.as_ref().borrow().storage.retention.clone();
+    let traces = settings.as_ref().borrow().storage.trace.clone();
+    let orchestrator = settings.as_ref().borrow().storage.orchestrator.clone();
+    let directory_groups = settings.as_ref().borrow().storage.groups.clone();
+    if unconfigured(&settings.as_ref().borrow()) {
+        error!("No policy found, every command is denied");
+        eprintln!("sr: RootAsRole is not configured, every command is denied, run chsr init --admin <user> as root");
+        std::process::exit(1);
+    }
     read_effective(false)
         .and(dac_override_effective(false))
         .unwrap_or_else(|_| panic!("{}", cap_effective_error("dac_read")));
+    if let Some(integrity) = integrity_settings()? {
+        verify_policy(
+            policy_path(&settings.as_ref().borrow(), ROOTASROLE),
+            &integrity,
+        )?;
+    }
+    let clock = settings
+        .as_ref()
+        .borrow()
+        .storage
+        .clock
+        .clone()
+        .unwrap_or_default();
+    let clock_skewed = check_policy(
+        policy_path(&settings.as_ref().borrow(), ROOTASROLE),
+        &clock,
+        SystemTime::now(),
+    )?;
+    let mut policies = vec![
+        PathBuf::from(ROOTASROLE),
+        policy_path(&settings.as_ref().borrow(), ROOTASROLE),
+    ];
+    if let Some(activation) = &settings.as_ref().borrow().storage.activation {
+        policies.push(activation.marker.clone());
+    }
+    policies.dedup();
+    let snapshot = upgrade::Snapshot::take(&policies);
     let config = match settings.clone().as_ref().borrow().storage.method {
+        rar_common::StorageMethod::JSON
+            if settings.as_ref().borrow().storage.activation.is_some() =>
+        {
+            Storage::JSON(activated_config(&settings.as_ref().borrow(), ROOTASROLE)?)
+        }
         rar_common::StorageMethod::JSON => {
             Storage::JSON(read_json_config(settings, ROOTASROLE).expect("Failed to read config"))
         }
@@ -249,18 +569,155 @@ fn main() -> Result<(), Box<dyn Error>> {
             return Err("Unsupported storage method".into());
         }
     };
-    let user = make_cred();
-    let taskmatch = match config {
-        Storage::JSON(ref config) => from_json_execution_settings(&args, config, &user)
-            .inspect_err(|e| {
+    match config {
+        Storage::JSON(ref config) => apply_default_command(&mut args, config)?,
+    }
+    let mut user = make_cred(directory_groups.as_ref());
+    let mut token = None;
+    if let Some(orchestrator) = &orchestrator {
+        // the token is never given to the command
+        let value = std::env::var(&orchestrator.env).ok();
+        std::env::remove_var(&orchestrator.env);
+        token = check_token(
+            orchestrator,
+            value.as_deref(),
+            &user.user.name,
+            &args.command,
+        )
+        .unwrap_or_else(|e| {
+            warn!("Refused orchestrator token: {}", e);
+            None
+        });
+        user.orchestrated = token.is_some();
+    }
+    if args.reset_timestamp {
+        timeout::invalidate(&user)?;
+        if args.command.is_empty() && !args.info && !args.list {
+            return Ok(());
+        }
+    }
+    if let Some(activation) = &args.activation {
+        let activated = match config {
+            Storage::JSON(ref json) => {
+                activate::role(json, &user, &activation.role).and_then(|role| {
+                    check_auth(
+                        &OptStack::from_role(role.clone()),
+                        &config,
+                        &user,
+                        &args.prompt,
+                        args.non_interactive,
+                        frontend(&args)?,
+                    )?;
+                    activate_role(&role.as_ref().borrow(), &user, activation.duration)
+                })
+            }
+        };
+        match activated {
+            Ok(duration) => {
+                info!(
+                    "User {} activated the role {}",
+                    user.user.name, activation.role
+                );
+                println!(
+                    "Role {} activated for {}",
+                    activation.role,
+                    activate::display(duration)
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                if e.downcast_ref::<AuthenticationRequired>().is_some() {
+                    eprintln!("sr: a password is required");
+                    std::process::exit(AUTH_REQUIRED_STATUS);
+                }
                 error!("{}", e);
-            })
-            .unwrap_or_default(),
+                eprintln!("sr: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if args.list {
+        match config {
+            Storage::JSON(ref config) => println!("{}", list::list(config, &user, args.json)?),
+        }
+        return Ok(());
+    }
+    let taskmatch = match config {
+        Storage::JSON(ref config) => {
+            let result = from_json_execution_settings(&args, config, &user);
+            if let Some(traces) = &traces {
+                let mut trace =
+                    trace::evaluate(config, &user, &args.opt_filter, &args.command, &result);
+                trace.id = Some(audit_id.clone());
+                trace::redact(&mut trace, config, &result);
+                if let Err(e) = trace::record(traces, &trace) {
+                    warn!("Unable to record the evaluation trace: {}", e);
+                }
+            }
+            if args.check {
+                let why_not = args.why_not.then(|| {
+                    trace::evaluate(config, &user, &args.opt_filter, &args.command, &result)
+                });
+                let (report, allowed) = check::report(&result, why_not, args.json)?;
+                println!("{}", report);
+                std::process::exit(if allowed { 0 } else { 1 });
+            }
+            result
+                .inspect_err(|e| {
+                    error!("{}", e);
+                })
+                .unwrap_or_default()
+        }
     };
     let execcfg = &taskmatch.settings;
 
     let optstack = &execcfg.opt;
-    check_auth(optstack, &config, &user, &args.prompt)?;
+    if clock_skewed && clock.invalidate_cookies {
+        if let Err(e) = timeout::invalidate(&user) {
+            warn!("Unable to forget the authentication cookies: {}", e);
+        }
+    }
+    // before the password is asked for a command that cannot run
+    if taskmatch.fully_matching() && !args.info {
+        if let Err(e) = kernel::check(execcfg) {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let frontend = match frontend(&args) {
+        Ok(frontend) => frontend,
+        Err(e) => {
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = check_auth(
+        optstack,
+        &config,
+        &user,
+        &args.prompt,
+        args.non_interactive,
+        frontend,
+    ) {
+        if e.downcast_ref::<AuthenticationRequired>().is_some() {
+            eprintln!("sr: a password is required");
+            std::process::exit(AUTH_REQUIRED_STATUS);
+        }
+        return Err(e);
+    }
+    // the decision must not be made by a replaced binary or on a replaced policy
+    if let Err(e) = snapshot.reexec_if_changed() {
+        error!("{}", e);
+        eprintln!("sr: {}", e);
+        std::process::exit(1);
+    }
+    // after the reexecution, which checks the same token again
+    if let Err(e) = token.as_ref().map_or(Ok(()), |token| token.consume()) {
+        error!("Refused orchestrator token: {}", e);
+        eprintln!("sr: Refused orchestrator token: {}", e);
+        std::process::exit(1);
+    }
 
     if !taskmatch.fully_matching() {
         println!("You are not allowed to execute this command, this incident will be reported.");
@@ -273,69 +730,268 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     if args.info {
-        println!("Role: {}", execcfg.role().as_ref().borrow().name);
-        println!("Task: {}", execcfg.task().as_ref().borrow().name);
-        println!(
-            "With capabilities: {}",
-            execcfg
-                .caps
-                .unwrap_or_default()
-                .into_iter()
-                .fold(String::new(), |acc, cap| acc + &cap.to_string() + " ")
-        );
+        println!("{}", info::render(&Info::new(execcfg)?, args.json)?);
         std::process::exit(0);
     }
 
+    show_banner(execcfg, &user, args.quiet);
+
     // disable root
     if !optstack.get_root_behavior().1.is_privileged() {
         activates_no_new_privs().expect("Failed to activate no new privs");
     }
 
-    debug!("setuid : {:?}", execcfg.setuid);
+    if optstack.get_verify_package_integrity() {
+        for path in executables(&args.command, execcfg) {
+            if let Err(e) = package::verify_package_integrity(&path) {
+                error!("{}", e);
+                eprintln!("sr: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    setuid_setgid(execcfg);
-    let cred = make_cred();
+    let ids = match target_ids(execcfg) {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // the executable may have been swapped since the policy was matched, it is opened while sr
+    // can still read it and run from the descriptor its digest is verified on
+    let (executable, script) = match open_pinned(&args.command, execcfg, args.chroot_safe) {
+        Ok(pinned) => pinned,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // the snapshot is written with the capabilities of sr, before they are dropped
+    let context = match audit::context(&user, execcfg, &args.command, retention.as_ref()) {
+        Ok(context) => context,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(missing) = missing_capabilities(execcfg, CapState::get_current()?.permitted) {
+        error!("sr lacks the capabilities {} to run this task", missing);
+        eprintln!(
+            "sr: sr lacks the capabilities {} to run this task, its file capabilities may be ignored on this mount",
+            missing
+        );
+        std::process::exit(1);
+    }
+    let session_env;
+    let target = ids
+        .0
+        .and_then(|uid| User::from_uid(uid.into()).ok().flatten())
+        .unwrap_or_else(|| user.user.clone());
+    let session = match pam::open_session(optstack, &user, &target.name) {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Unable to open the PAM session: {}", e);
+            eprintln!("sr: Unable to open the PAM session: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // a privileged sr stays to close the session, the command is run by its child
+    match exec::fork_and_wait() {
+        Ok(Some((child, code))) => {
+            network::release(&optstack.get_network().1, child.as_raw() as u32);
+            if let Err(e) = session.close() {
+                warn!("Unable to close the PAM session: {}", e);
+            }
+            std::process::exit(code);
+        }
+        Ok(None) => {
+            session_env = session.env();
+            // only the parent closes the session
+            #[cfg_attr(not(feature = "pam"), allow(clippy::forget_non_drop))]
+            std::mem::forget(session);
+        }
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) =
+        network::restrict_network(optstack.get_network().1).and_then(|_| memory::apply(execcfg))
+    {
+        error!("{}", e);
+        eprintln!("sr: {}", e);
+        std::process::exit(1);
+    }
 
-    set_capabilities(execcfg, optstack);
+    let files = match broker::open_files(execcfg) {
+        Ok(files) => files,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    //execute command
-    let envset = optstack
-        .calculate_filtered_env(args.opt_filter, cred, std::env::vars())
-        .expect("Failed to calculate env");
+    debug!("setuid : {:?}", execcfg.setuid);
 
-    let pty = Pty::new().expect("Failed to create pty");
+    let (state_dir, cred) = match switch_to_target(execcfg, optstack, &ids) {
+        Ok(switched) => switched,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    debug!(
-        "Command: {:?} {:?}",
-        execcfg.exec_path,
-        execcfg.exec_args.join(" ")
-    );
-    let command = Command::new(&execcfg.exec_path)
-        .args(execcfg.exec_args.iter())
-        .env_clear()
-        .envs(envset)
-        .stdin(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .spawn(&pty.pts().expect("Failed to get pts"));
-    let mut command = match command {
-        Ok(command) => command,
+    //execute command
+    let mut envset = match command_env(
+        optstack,
+        args.opt_filter,
+        cred,
+        session_env,
+        std::env::vars(),
+    ) {
+        Ok(envset) => envset,
+        Err(e) => {
+            error!("Unable to build the environment of the command: {}", e);
+            eprintln!("sr: Unable to build the environment of the command: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    audit::export(&user, execcfg, &audit_id, &mut envset);
+    audit::record(&user, execcfg, &envset, context.as_deref(), &audit_id);
+    state::export(state_dir.as_deref(), &mut envset);
+    let directories = match jail::directories(execcfg) {
+        Ok(directories) => directories,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("sr: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let exec_args = args_via_env(&args.command, execcfg, &mut envset);
+    let exec_args = match enforce_arguments(&args.command, execcfg, exec_args) {
+        Ok(exec_args) => exec_args,
         Err(e) => {
             error!("{}", e);
-            eprintln!("sr: {} : {}", execcfg.exec_path.display(), e);
+            eprintln!("sr: {}", e);
             std::process::exit(1);
         }
     };
-    let status = command.wait().expect("Failed to wait for command");
-    std::process::exit(status.code().unwrap_or(1));
+    let exec_args = match &script {
+        Some(script) => script_from_fd(&args.command, exec_args, script.as_raw_fd()),
+        None => exec_args,
+    };
+    broker::export_files(&files, &mut envset);
+
+    // the arguments given through the environment are kept out of the logs
+    debug!("Command: {:?} {:?}", execcfg.exec_path, exec_args.join(" "));
+    if !exec::needs_parent(execcfg) {
+        let e = exec::exec(
+            execcfg,
+            executable.as_ref(),
+            &exec_args,
+            &envset,
+            directories.as_ref(),
+        )
+        .unwrap_err();
+        error!("{}", e);
+        eprintln!("sr: {} : {}", execcfg.exec_path.display(), e);
+        std::process::exit(exec::EXEC_FAILED);
+    }
+    let code = exec::spawn(
+        execcfg,
+        executable.as_ref(),
+        &exec_args,
+        &envset,
+        directories.as_ref(),
+    )
+    .unwrap_or_else(|e| {
+        error!("{}", e);
+        eprintln!("sr: {} : {}", execcfg.exec_path.display(), e);
+        1
+    });
+    // the hooks must not inherit the brokered files
+    drop(files);
+    audit::exit_status(execcfg, &audit_id, code);
+    hooks::post_run(execcfg, &audit_id, &envset);
+    std::process::exit(code);
+}
+
+/// The answer of the agent to `sr --info`, when no authentication is needed to show it.
+fn agent_info(args: &Cli) -> Option<Info> {
+    if !args.info || args.command.is_empty() || args.reset_timestamp {
+        return None;
+    }
+    match agent::query(
+        RuntimeDirs::default().agent_socket(),
+        &Query::new(&args.command, args.opt_filter.as_ref()),
+    ) {
+        Ok(Answer::Allow(info))
+            if info
+                .options
+                .get("authentication")
+                .is_some_and(|auth| auth.value == "skip") =>
+        {
+            Some(info)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            debug!("No answer from the agent: {}", e);
+            None
+        }
+    }
+}
+
+/// The environment of the command. The variables of the session modules, which the target user
+/// may choose with pam_env, are filtered by the policy like those of the caller, which prevail.
+fn command_env(
+    optstack: &OptStack,
+    opt_filter: Option<FilterMatcher>,
+    target: Cred,
+    session_env: Vec<(String, String)>,
+    caller_env: impl Iterator<Item = (String, String)>,
+) -> Result<HashMap<String, String>, String> {
+    optstack.calculate_filtered_env(
+        opt_filter,
+        target,
+        session_env.into_iter().chain(caller_env),
+    )
+}
+
+/// The frontend answering the PAM conversation.
+fn frontend(args: &Cli) -> Result<Box<dyn Frontend>, Box<dyn Error>> {
+    if let Some(fd) = args.auth_fd {
+        // SAFETY: the descriptor is given to sr for the authentication only
+        return Ok(Box::new(unsafe { Machine::from_raw_fd(fd) }));
+    }
+    if args.askpass {
+        return Ok(Box::new(Askpass::from_env()?));
+    }
+    Ok(Box::new(CliFrontend {
+        use_stdin: args.stdin,
+    }))
 }
 
-fn make_cred() -> Cred {
+/// The caller with the groups of the process, and those of the name service when configured.
+fn make_cred(directory: Option<&GroupsSettings>) -> Cred {
     let user = User::from_uid(getuid())
         .expect("Failed to get user")
         .expect("Failed to get user");
-    let mut groups = getgroups()
-        .expect("Failed to get groups")
+    let mut gids = getgroups().expect("Failed to get groups");
+    if let Some(settings) = directory {
+        match groups::resolve(&user, settings) {
+            Ok(resolved) => gids.extend(groups::missing(&gids, &resolved)),
+            Err(e) => warn!("Unable to resolve the groups of {}: {}", user.name, e),
+        }
+    }
+    let mut groups = gids
         .iter()
         .map(|g| {
             Group::from_gid(*g)
@@ -366,9 +1022,145 @@ fn make_cred() -> Cred {
         groups,
         tty,
         ppid,
+        orchestrated: false,
+    }
+}
+
+// commands that are not found in PATH are run through sh
+fn is_shell_wrapped(command: &[String], execcfg: &ExecSettings) -> bool {
+    execcfg.exec_args == ["-c".to_string(), shell_words::join(command)]
+}
+
+fn executables(command: &[String], execcfg: &ExecSettings) -> Vec<PathBuf> {
+    let mut executables = vec![execcfg.exec_path.clone()];
+    if is_shell_wrapped(command, execcfg) {
+        executables.push(PathBuf::from(&command[0]));
+    }
+    executables
+}
+
+/// The executable and the shell-wrapped script whose digest the task pins, opened once. With
+/// `--chroot-safe`, they are looked up in the chroot of the task.
+fn open_pinned(
+    command: &[String],
+    execcfg: &ExecSettings,
+    chroot_safe: bool,
+) -> Result<(Option<File>, Option<File>), Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    let pinned = |path: &Path| {
+        if chroot_safe && jail::needs_chroot(execcfg) && path.is_absolute() {
+            jail::verify(execcfg, path)
+        } else {
+            verify_digest(&task, path)
+        }
+    };
+    let executable = pinned(&execcfg.exec_path)?;
+    if !is_shell_wrapped(command, execcfg) {
+        return Ok((executable, None));
+    }
+    let script = pinned(Path::new(&command[0]))?;
+    if let Some(script) = &script {
+        // the shell reads it from /dev/fd
+        fcntl(script.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+    }
+    Ok((executable, script))
+}
+
+/// The shell runs the verified script from its descriptor rather than from its path.
+fn script_from_fd(command: &[String], exec_args: Vec<String>, fd: RawFd) -> Vec<String> {
+    let quoted = shell_words::quote(&command[0]);
+    match exec_args.as_slice() {
+        [flag, line] if flag == "-c" => match line.strip_prefix(quoted.as_ref()) {
+            Some(rest) if rest.is_empty() || rest.starts_with(' ') => {
+                vec![flag.clone(), format!("/dev/fd/{}{}", fd, rest)]
+            }
+            _ => exec_args,
+        },
+        _ => exec_args,
     }
 }
 
+/// The banner of the role, once per timestamp session, `--quiet` hides it if the role allows it.
+fn show_banner(execcfg: &ExecSettings, user: &Cred, quiet: bool) {
+    let role = execcfg.role();
+    let role = role.as_ref().borrow();
+    let Some(banner) = &role.banner else {
+        return;
+    };
+    if quiet && banner.allow_quiet.unwrap_or(false) {
+        return;
+    }
+    if timeout::first_banner(
+        user,
+        &execcfg.opt.get_timeout().1,
+        &pam::service(&execcfg.opt).unwrap_or_default(),
+        &role.name,
+    ) {
+        eprintln!("{}", banner.text);
+    }
+}
+
+/// Before any policy is written, sr denies everything.
+fn unconfigured(settings: &SettingsFile) -> bool {
+    !policy_path(settings, ROOTASROLE).exists()
+}
+
+/// Tasks with `args_via_env` receive the user arguments as RAR_ARG1..N instead of argv.
+fn args_via_env(
+    command: &[String],
+    execcfg: &ExecSettings,
+    envset: &mut HashMap<String, String>,
+) -> Vec<String> {
+    if !execcfg
+        .task()
+        .as_ref()
+        .borrow()
+        .args_via_env
+        .unwrap_or(false)
+    {
+        return execcfg.exec_args.clone();
+    }
+    envset.retain(|key, _| !key.starts_with("RAR_ARG"));
+    for (i, arg) in command.iter().enumerate().skip(1) {
+        envset.insert(format!("RAR_ARG{}", i), arg.clone());
+    }
+    envset.insert(
+        "RAR_ARGC".to_string(),
+        command.len().saturating_sub(1).to_string(),
+    );
+    if is_shell_wrapped(command, execcfg) {
+        vec![
+            "-c".to_string(),
+            shell_words::quote(&command[0]).to_string(),
+        ]
+    } else {
+        vec![]
+    }
+}
+
+/// The arguments enforced by the task, around the arguments of the user.
+fn enforce_arguments(
+    command: &[String],
+    execcfg: &ExecSettings,
+    exec_args: Vec<String>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    let Some(arguments) = &task.arguments else {
+        return Ok(exec_args);
+    };
+    if !is_shell_wrapped(command, execcfg) {
+        return Ok(arguments.apply(&exec_args));
+    }
+    // the shell runs the command line given after -c
+    let line = shell_words::split(&exec_args[1])?;
+    let (program, args) = line.split_first().ok_or("Empty command line")?;
+    let mut line = vec![program.clone()];
+    line.extend(arguments.apply(args));
+    Ok(vec!["-c".to_string(), shell_words::join(line)])
+}
+
 fn set_capabilities(execcfg: &rar_common::database::finder::ExecSettings, optstack: &OptStack) {
     //set capabilities
     if let Some(caps) = execcfg.caps {
@@ -386,6 +1178,10 @@ fn set_capabilities(execcfg: &rar_common::database::finder::ExecSettings, optsta
         }
         capstate.permitted = caps;
         capstate.inheritable = caps;
+        if jail::needs_chroot(execcfg) {
+            // only used by sr to enter the chroot, never inherited
+            capstate.permitted.add(Cap::SYS_CHROOT);
+        }
         debug!("caps : {:?}", caps);
         capstate.set_current().expect("Failed to set current cap");
         for cap in caps.iter() {
@@ -397,63 +1193,105 @@ fn set_capabilities(execcfg: &rar_common::database::finder::ExecSettings, optsta
         if !optstack.get_bounding().1.is_ignore() {
             capctl::bounding::clear().expect("Failed to clear bounding cap");
         }
-        let capstate = CapState::empty();
+        let mut capstate = CapState::empty();
+        if jail::needs_chroot(execcfg) {
+            capstate.permitted.add(Cap::SYS_CHROOT);
+        }
         capstate.set_current().expect("Failed to set current cap");
         setpcap_effective(false).unwrap_or_else(|_| panic!("{}", cap_effective_error("setpcap")));
     }
 }
 
-fn setuid_setgid(execcfg: &rar_common::database::finder::ExecSettings) {
-    let uid = execcfg.setuid.as_ref().and_then(|u| {
-        let res = u.fetch_user();
-        if let Some(user) = res {
-            Some(user.uid.as_raw())
-        } else {
-            None
+type Ids = (Option<u32>, Option<u32>, Option<Vec<u32>>);
+
+// the uid, gid and groups to switch to, None keeps the current ones. Every name must resolve,
+// a command must not run with part of the groups the policy gives.
+fn resolve_ids(setuid: Option<&SUserType>, setgroups: Option<&SGroups>) -> Result<Ids, String> {
+    let uid = match setuid {
+        Some(u) => Some(
+            u.fetch_user()
+                .ok_or_else(|| format!("Unknown user {}", u))?
+                .uid
+                .as_raw(),
+        ),
+        None => None,
+    };
+    let requested = match setgroups {
+        Some(SGroups::Single(g)) => vec![g],
+        Some(SGroups::Multiple(g)) if g.is_empty() => {
+            return Err("The task gives an empty list of groups".to_string())
         }
-    });
-    let gid = execcfg.setgroups.as_ref().and_then(|g| match g {
-        SGroups::Single(g) => {
-            let res = g.fetch_group();
-            if let Some(group) = res {
-                Some(group.gid.as_raw())
-            } else {
-                None
-            }
+        Some(SGroups::Multiple(g)) => g.iter().collect(),
+        None => return Ok((uid, None, None)),
+    };
+    let mut groups = Vec::new();
+    let mut unresolved = Vec::new();
+    for group in requested {
+        match group.fetch_group() {
+            Some(g) => groups.push(g.gid.as_raw()),
+            None => unresolved.push(group.to_string()),
         }
-        SGroups::Multiple(g) => {
-            let res = g.first().unwrap().fetch_group();
-            if let Some(group) = res {
-                Some(group.gid.as_raw())
-            } else {
-                None
-            }
+    }
+    if !unresolved.is_empty() {
+        return Err(format!("Unknown groups: {}", unresolved.join(", ")));
+    }
+    Ok((uid, Some(groups[0]), Some(groups)))
+}
+
+fn target_ids(execcfg: &ExecSettings) -> Result<Ids, String> {
+    resolve_ids(execcfg.setuid.as_ref(), execcfg.setgroups.as_ref())
+}
+
+// the supplementary groups first, the new gid would not be allowed to change them otherwise,
+// then the gid and the uid, keeping the capabilities of sr when it runs as root
+fn set_ids((uid, gid, groups): &Ids) -> Result<(), Box<dyn Error>> {
+    let keepcaps = capctl::prctl::get_keepcaps()?;
+    capctl::prctl::set_keepcaps(true)?;
+    let res = (|| -> nix::Result<()> {
+        if let Some(groups) = groups {
+            let groups: Vec<Gid> = groups.iter().map(|g| Gid::from_raw(*g)).collect();
+            setgroups(&groups)?;
         }
-    });
-    let groups = execcfg.setgroups.as_ref().and_then(|g| match g {
-        SGroups::Single(g) => {
-            let res = g.fetch_group();
-            if let Some(group) = res {
-                Some(vec![group.gid.as_raw()])
-            } else {
-                None
-            }
+        if let Some(gid) = gid {
+            let gid = Gid::from_raw(*gid);
+            setresgid(gid, gid, gid)?;
         }
-        SGroups::Multiple(g) => {
-            let res = g.iter().map(|g| g.fetch_group());
-            let mut groups = Vec::new();
-            for group in res.flatten() {
-                groups.push(group.gid.as_raw());
-            }
-            Some(groups)
+        if let Some(uid) = uid {
+            let uid = Uid::from_raw(*uid);
+            setresuid(uid, uid, uid)?;
         }
-    });
+        Ok(())
+    })();
+    capctl::prctl::set_keepcaps(keepcaps)?;
+    Ok(res?)
+}
 
+/// Switch to the target user and capabilities, once what needs the privileges of sr is done:
+/// the state directory is created with the caller uid and the capabilities of sr.
+fn switch_to_target(
+    execcfg: &ExecSettings,
+    optstack: &OptStack,
+    ids: &Ids,
+) -> Result<(Option<PathBuf>, Cred), Box<dyn Error>> {
+    let (uid, gid, _) = ids;
+    let state_dir = state::prepare(
+        execcfg,
+        uid.unwrap_or(getuid().as_raw()),
+        gid.unwrap_or(getgid().as_raw()),
+    )?;
+    setuid_setgid(ids).map_err(|e| format!("Unable to switch to the target ids: {}", e))?;
+    let cred = make_cred(None);
+    set_capabilities(execcfg, optstack);
+    Ok((state_dir, cred))
+}
+
+fn setuid_setgid(ids: &Ids) -> Result<(), Box<dyn Error>> {
     setgid_effective(true).unwrap_or_else(|_| panic!("{}", cap_effective_error("setgid")));
     setuid_effective(true).unwrap_or_else(|_| panic!("{}", cap_effective_error("setuid")));
-    capctl::cap_set_ids(uid, gid, groups.as_deref()).expect("Failed to set ids");
+    let res = set_ids(ids);
     setgid_effective(false).unwrap_or_else(|_| panic!("{}", cap_effective_error("setgid")));
     setuid_effective(false).unwrap_or_else(|_| panic!("{}", cap_effective_error("setuid")));
+    res
 }
 
 #[cfg(test)]
@@ -465,7 +1303,10 @@ mod tests {
 
     use super::*;
     use rar_common::database::make_weak_config;
-    use rar_common::database::structs::{IdTask, SCommand, SCommands, SConfig, SRole, STask};
+    use rar_common::database::structs::{
+        IdTask, SArguments, SCapabilities, SCommand, SCommands, SConfig, SCredentials, SRole,
+        STask, SetBehavior,
+    };
 
     #[test]
     fn test_from_json_execution_settings() {
@@ -473,8 +1314,20 @@ mod tests {
             opt_filter: None,
             prompt: PAM_PROMPT.to_string(),
             info: false,
+            list: false,
+            check: false,
+            why_not: false,
+            json: false,
             help: false,
             stdin: false,
+            askpass: false,
+            auth_fd: None,
+            reset_timestamp: false,
+            non_interactive: false,
+            discover: false,
+            quiet: false,
+            activation: None,
+            chroot_safe: false,
             command: vec!["ls".to_string(), "-l".to_string()],
         };
         let user = Cred {
@@ -482,6 +1335,7 @@ mod tests {
             groups: vec![],
             tty: None,
             ppid: Pid::parent(),
+            orchestrated: false,
         };
         let config = rc_refcell!(SConfig::default());
         let role = rc_refcell!(SRole::default());
@@ -533,6 +1387,156 @@ mod tests {
         assert!(taskmatch.is_err());
     }
 
+    #[test]
+    fn test_apply_default_command() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("backup")
+                    .default_command("/usr/bin/rsync -a /home /backup")
+                    .build(),
+            )
+            .role(SRole::builder("other").build())
+            .build();
+        let mut args = getopt(["sr", "-r", "backup"]).unwrap();
+        apply_default_command(&mut args, &config).unwrap();
+        assert_eq!(args.command, ["/usr/bin/rsync", "-a", "/home", "/backup"]);
+        let mut args = getopt(["sr", "-r", "backup", "/usr/bin/ls"]).unwrap();
+        apply_default_command(&mut args, &config).unwrap();
+        assert_eq!(args.command, ["/usr/bin/ls"]);
+        let mut args = getopt(["sr", "-r", "other"]).unwrap();
+        apply_default_command(&mut args, &config).unwrap();
+        assert!(args.command.is_empty());
+    }
+
+    #[test]
+    fn test_args_via_env() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("deploy")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("push")
+                            .args_via_env(true)
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/echo .*".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let command = ["/usr/bin/echo", "token", "s3cr3t"].map(String::from);
+        let user = Cred::builder().build();
+        let taskmatch = config.matches(&user, &None, &command).unwrap();
+        let mut envset = HashMap::from([("RAR_ARG9".to_string(), "injected".to_string())]);
+        let exec_args = args_via_env(&command, &taskmatch.settings, &mut envset);
+        assert!(!exec_args.join(" ").contains("s3cr3t"));
+        assert_eq!(envset.get("RAR_ARG1").unwrap(), "token");
+        assert_eq!(envset.get("RAR_ARG2").unwrap(), "s3cr3t");
+        assert_eq!(envset.get("RAR_ARGC").unwrap(), "2");
+        assert!(!envset.contains_key("RAR_ARG9"));
+    }
+
+    #[test]
+    fn test_missing_capabilities() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("web")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("bind")
+                            .cred(
+                                SCredentials::builder()
+                                    .capabilities(
+                                        SCapabilities::builder(SetBehavior::None)
+                                            .add_cap(Cap::NET_BIND_SERVICE)
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .chroot("/srv/jail")
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/ls".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let user = Cred::builder().build();
+        let taskmatch = config
+            .matches(&user, &None, &["/usr/bin/ls".to_string()])
+            .unwrap();
+        let execcfg = &taskmatch.settings;
+        let mut permitted = CapSet::empty();
+        permitted.add_all([Cap::SETUID, Cap::SETGID, Cap::SETPCAP]);
+        assert_eq!(
+            missing_capabilities(execcfg, permitted).unwrap(),
+            "CAP_NET_BIND_SERVICE, CAP_SYS_CHROOT"
+        );
+        permitted.add_all([Cap::NET_BIND_SERVICE, Cap::SYS_CHROOT]);
+        assert!(missing_capabilities(execcfg, permitted).is_none());
+        assert!(missing_capabilities(execcfg, CapSet::empty()).is_some());
+    }
+
+    #[test]
+    fn test_enforce_arguments() {
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("app")
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("run")
+                            .arguments(SArguments::builder().prepend(["-n"]).strip(["-e"]).build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/echo .*".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let user = Cred::builder().build();
+        let command = ["echo", "-e", "hello"].map(String::from);
+        let taskmatch = config.matches(&user, &None, &command).unwrap();
+        let exec_args = taskmatch.settings.exec_args.clone();
+        assert_eq!(
+            enforce_arguments(&command, &taskmatch.settings, exec_args).unwrap(),
+            ["-n", "hello"]
+        );
+        // an absolute path is run through the shell
+        let command = ["/usr/bin/echo", "-e", "hello"].map(String::from);
+        let taskmatch = config.matches(&user, &None, &command).unwrap();
+        let exec_args = taskmatch.settings.exec_args.clone();
+        assert_eq!(
+            enforce_arguments(&command, &taskmatch.settings, exec_args).unwrap(),
+            ["-c", "/usr/bin/echo -n hello"]
+        );
+    }
+
+    #[test]
+    fn test_unconfigured() {
+        let mut settings = SettingsFile::default();
+        settings.storage.settings = Some(
+            rar_common::RemoteStorageSettings::builder()
+                .path("target/missing_policy.json")
+                .build(),
+        );
+        assert!(unconfigured(&settings));
+        settings.storage.settings = Some(
+            rar_common::RemoteStorageSettings::builder()
+                .path("Cargo.toml")
+                .build(),
+        );
+        assert!(!unconfigured(&settings));
+    }
+
     #[test]
     fn test_getopt() {
         let args = getopt(vec![
@@ -546,11 +1550,55 @@ mod tests {
         assert!(args.info);
         assert!(args.help);
         assert_eq!(args.command, vec!["ls".to_string(), "-l".to_string()]);
+        let args = getopt(["sr", "-u", "www-data", "ls"]).unwrap();
+        assert_eq!(
+            args.opt_filter.unwrap().user,
+            Some(SUserType::from("www-data"))
+        );
+        let args = getopt(["sr", "-u", "#0", "ls"]).unwrap();
+        assert_eq!(args.opt_filter.unwrap().user, Some(SUserType::from(0)));
+        let args = getopt(["sr", "-i", "--format", "json", "ls"]).unwrap();
+        assert!(args.info && args.json);
+        assert!(getopt(["sr", "-i", "--format", "yaml", "ls"]).is_err());
+        let args = getopt(["sr", "-n", "ls"]).unwrap();
+        assert!(args.non_interactive);
+        let args = getopt(["sr", "--why-not", "-u", "deploy", "ls"]).unwrap();
+        assert!(args.check && args.why_not);
+        let args = getopt(["sr", "--discover", "ping", "-c1", "::1"]).unwrap();
+        assert!(args.discover);
+        assert_eq!(args.command, ["ping", "-c1", "::1"]);
+        let args = getopt(["sr", "-k"]).unwrap();
+        assert!(args.reset_timestamp && args.command.is_empty());
+        let args = getopt(["sr", "-q", "ls"]).unwrap();
+        assert!(args.quiet);
+        let args = getopt(["sr", "--chroot-safe", "ls"]).unwrap();
+        assert!(args.chroot_safe && !getopt(["sr", "ls"]).unwrap().chroot_safe);
+        let args = getopt(["sr", "-A", "--auth-fd", "5", "ls"]).unwrap();
+        assert!(args.askpass);
+        assert_eq!(args.auth_fd, Some(5));
+        assert!(getopt(["sr", "--auth-fd", "0", "ls"]).is_err());
+        let args = getopt(["sr", "-l", "--format", "json"]).unwrap();
+        assert!(args.list && args.json && args.command.is_empty());
+        let args = getopt(["sr", "--activate", "r_dba", "--for", "30m"]).unwrap();
+        assert_eq!(
+            args.activation,
+            Some(Activation {
+                role: "r_dba".to_string(),
+                duration: Some(chrono::Duration::minutes(30)),
+            })
+        );
+        // a program named activate is run as any other command
+        let args = getopt(["sr", "activate", "r_dba", "--for", "30m"]).unwrap();
+        assert!(args.activation.is_none());
+        assert_eq!(args.command, ["activate", "r_dba", "--for", "30m"]);
+        assert!(getopt(["sr", "--activate"]).is_err());
+        assert!(getopt(["sr", "--for", "30m", "ls"]).is_err());
+        assert!(getopt(["sr", "--activate", "r_dba", "ls"]).is_err());
     }
 
     #[test]
     fn test_make_cred() {
-        let user = make_cred();
+        let user = make_cred(None);
         let gid = unsafe { getgid() };
         assert_eq!(user.user.uid, getuid());
         assert_eq!(user.user.gid.as_raw(), gid);
@@ -558,4 +1606,143 @@ mod tests {
         assert_eq!(user.groups[0].gid.as_raw(), gid);
         assert_eq!(user.ppid, Pid::parent());
     }
+
+    #[test]
+    fn test_resolve_ids() {
+        let groups = SGroups::from(vec![
+            SGroupType::from("root"),
+            SGroupType::from("rar_unknown_a"),
+            SGroupType::from(0),
+            SGroupType::from("rar_unknown_b"),
+        ]);
+        // no command may run with part of its groups
+        assert_eq!(
+            resolve_ids(None, Some(&groups)).unwrap_err(),
+            "Unknown groups: rar_unknown_a, rar_unknown_b"
+        );
+        assert_eq!(
+            resolve_ids(Some(&SUserType::from("rar_unknown")), None).unwrap_err(),
+            "Unknown user rar_unknown"
+        );
+        assert!(resolve_ids(None, Some(&SGroups::Multiple(vec![]))).is_err());
+        assert_eq!(
+            resolve_ids(Some(&SUserType::from(0)), Some(&SGroups::from(vec![0, 0]))).unwrap(),
+            (Some(0), Some(0), Some(vec![0, 0]))
+        );
+        assert_eq!(resolve_ids(None, None).unwrap(), (None, None, None));
+    }
+
+    #[test]
+    fn test_script_from_fd() {
+        let command = ["/opt/my script.sh", "-v"].map(String::from);
+        let exec_args = vec!["-c".to_string(), shell_words::join(&command)];
+        assert_eq!(
+            script_from_fd(&command, exec_args, 5),
+            ["-c", "/dev/fd/5 -v"]
+        );
+        // the line is left alone when it does not start with the script
+        let exec_args = vec!["-c".to_string(), "/bin/true".to_string()];
+        assert_eq!(script_from_fd(&command, exec_args.clone(), 5), exec_args);
+    }
+
+    #[test]
+    fn test_switch_to_target() {
+        let role = format!("r_state_{}", std::process::id());
+        let config = SConfig::builder()
+            .role(
+                SRole::builder(role.as_str())
+                    .actor(SActor::user(getuid().as_raw()).build())
+                    .task(
+                        STask::builder("t_state")
+                            .options(|opt| opt.state_dir(true).build())
+                            .commands(
+                                SCommands::builder(SetBehavior::None)
+                                    .add([SCommand::Simple("/usr/bin/true".to_string())])
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let taskmatch = config
+            .matches(
+                &Cred::builder().build(),
+                &None,
+                &["/usr/bin/true".to_string()],
+            )
+            .unwrap();
+        let execcfg = &taskmatch.settings;
+        let path = state::path(execcfg).unwrap().unwrap();
+        // the switch drops the credentials of the test process, it is done by a child
+        match unsafe { nix::unistd::fork() }.unwrap() {
+            nix::unistd::ForkResult::Child => {
+                let ids = (Some(65534), Some(65534), Some(vec![]));
+                let switched = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    switch_to_target(execcfg, &execcfg.opt, &ids)
+                        .is_ok_and(|(state_dir, _)| state_dir.as_ref() == Some(&path))
+                }));
+                unsafe { libc::_exit(if switched.unwrap_or(false) { 0 } else { 1 }) };
+            }
+            nix::unistd::ForkResult::Parent { child } => {
+                let status = nix::sys::wait::waitpid(child, None).unwrap();
+                let owner = std::fs::metadata(&path)
+                    .map(|metadata| std::os::unix::fs::MetadataExt::uid(&metadata));
+                let _ = std::fs::remove_dir_all(path.parent().unwrap());
+                assert_eq!(status, nix::sys::wait::WaitStatus::Exited(child, 0));
+                assert_eq!(owner.unwrap(), 65534);
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_env() {
+        use rar_common::database::options::SEnvOptions;
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_test")
+                    .task(
+                        STask::builder(IdTask::Number(1))
+                            .options(|opt| {
+                                opt.env(
+                                    SEnvOptions::builder(EnvBehavior::Delete)
+                                        .keep(["LD_PRELOAD", "XDG_RUNTIME_DIR", "EDITOR"])
+                                        .unwrap()
+                                        .build(),
+                                )
+                                .build()
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+        let optstack = OptStack::from_task(config.task("r_test", 1).unwrap());
+        // a ~/.pam_environment of the target user
+        let session_env = [
+            ("LD_PRELOAD", "/home/user/evil.so"),
+            ("BASH_ENV", "/home/user/evil.sh"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("LANG", "C"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let caller_env = [("EDITOR", "vim"), ("XDG_RUNTIME_DIR", "/run/user/0")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let env = command_env(
+            &optstack,
+            None,
+            Cred::builder().user_id(0).build(),
+            session_env,
+            caller_env,
+        )
+        .unwrap();
+        assert!(!env.contains_key("LD_PRELOAD"));
+        assert!(!env.contains_key("BASH_ENV"));
+        assert!(!env.contains_key("LANG"));
+        assert_eq!(env.get("EDITOR").unwrap(), "vim");
+        assert_eq!(env.get("XDG_RUNTIME_DIR").unwrap(), "/run/user/0");
+    }
 }
@@ -0,0 +1,104 @@
+//! The `oom_score_adj` and `memory_max` fields of a task.
+//!
+//! sr applies them to itself before switching to the target user, and the command inherits
+//! them: the score of the process for the OOM killer, and a cgroup v2 whose `memory.max` limits
+//! the memory of the command and its children, see the `cgroup` module.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use capctl::Cap;
+use log::debug;
+use rar_common::{database::finder::ExecSettings, util::cap_effective};
+
+use crate::cgroup::{self, CGROUP_DIR, CGROUP_ROOT};
+
+/// `max`, or a number of bytes with an optional K, M, G or T suffix, like cgroup v2 accepts.
+fn valid_memory_max(value: &str) -> bool {
+    let digits = value.trim_end_matches(['K', 'M', 'G', 'T', 'k', 'm', 'g', 't']);
+    value == "max"
+        || (value.len() - digits.len() <= 1
+            && !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn set_oom_score_adj(score: i16) -> Result<(), Box<dyn Error>> {
+    if !(-1000..=1000).contains(&score) {
+        return Err(format!("oom_score_adj {} is not between -1000 and 1000", score).into());
+    }
+    // lowering the score needs CAP_SYS_RESOURCE
+    cap_effective(Cap::SYS_RESOURCE, true)?;
+    let res = fs::write("/proc/self/oom_score_adj", score.to_string());
+    cap_effective(Cap::SYS_RESOURCE, false)?;
+    res.map_err(|e| format!("Unable to set the oom_score_adj {}: {}", score, e).into())
+}
+
+/// Move sr into its cgroup `name` limited to `max`, under `root`.
+fn join_cgroup(root: &Path, name: &str, max: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let cgroup = cgroup::join(root, name)?;
+    // the memory controller must be enabled down to the cgroup of the command
+    fs::write(root.join("cgroup.subtree_control"), "+memory")?;
+    fs::write(
+        root.join(CGROUP_DIR).join("cgroup.subtree_control"),
+        "+memory",
+    )?;
+    fs::write(cgroup.join("memory.max"), max)?;
+    Ok(cgroup)
+}
+
+/// Apply the OOM score and the memory limit of the task, the command inherits them.
+pub fn apply(execcfg: &ExecSettings) -> Result<(), Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    if let Some(score) = task.oom_score_adj {
+        set_oom_score_adj(score)?;
+    }
+    if let Some(max) = &task.memory_max {
+        if !valid_memory_max(max) {
+            return Err(format!("Invalid memory_max {}", max).into());
+        }
+        cap_effective(Cap::DAC_OVERRIDE, true)?;
+        let res = join_cgroup(Path::new(CGROUP_ROOT), &cgroup::name(), max);
+        cap_effective(Cap::DAC_OVERRIDE, false)?;
+        let cgroup = res.map_err(|e| format!("Unable to limit the memory to {}: {}", max, e))?;
+        debug!("Memory of the command limited by {}", cgroup.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_memory_max() {
+        assert!(valid_memory_max("max"));
+        assert!(valid_memory_max("536870912"));
+        assert!(valid_memory_max("512M"));
+        assert!(valid_memory_max("2G"));
+        assert!(!valid_memory_max(""));
+        assert!(!valid_memory_max("M"));
+        assert!(!valid_memory_max("512MB"));
+        assert!(!valid_memory_max("-1"));
+        assert!(!valid_memory_max("1.5G"));
+    }
+
+    #[test]
+    fn test_join_cgroup() {
+        let root = std::env::temp_dir().join(format!("rar_cgroup_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let cgroup = join_cgroup(&root, "rootasrole/sr-3", "512M").unwrap();
+        assert_eq!(cgroup, root.join("rootasrole/sr-3"));
+        assert_eq!(
+            fs::read_to_string(cgroup.join("memory.max")).unwrap(),
+            "512M"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("rootasrole/cgroup.subtree_control")).unwrap(),
+            "+memory"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! The `network` option of a task.
+//!
+//! `deny-all` moves sr into a new network namespace before the command is run. An `allow` list
+//! keeps the network of the caller and loads an nftables table that drops the packets sent from
+//! the cgroup of the execution, except to the listed addresses and ports. The tables are deleted
+//! by a next sr once their cgroup is removed.
+use std::{
+    error::Error,
+    fmt::Write as _,
+    io::{self, Write as _},
+    mem,
+    net::IpAddr,
+    os::unix::process::CommandExt,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use capctl::{Cap, CapState};
+use log::{debug, warn};
+use rar_common::{
+    database::options::{SNetwork, SNetworkRule},
+    util::cap_effective,
+};
+
+use crate::cgroup::{self, CGROUP_DIR, CGROUP_ROOT};
+
+const NFT: &str = "/usr/sbin/nft";
+const TABLE_PREFIX: &str = "rootasrole_sr_";
+
+fn last_os_error(ret: libc::c_int) -> Result<libc::c_int, io::Error> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// A new network namespace only contains a loopback interface, which is down.
+fn loopback_up() -> Result<(), io::Error> {
+    let sock = last_os_error(unsafe {
+        libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0)
+    })?;
+    let mut ifr: libc::ifreq = unsafe { mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(b"lo\0") {
+        *dst = *src as libc::c_char;
+    }
+    let res =
+        last_os_error(unsafe { libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut ifr) }).and_then(|_| {
+            unsafe { ifr.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short };
+            last_os_error(unsafe { libc::ioctl(sock, libc::SIOCSIFFLAGS, &ifr) })
+        });
+    unsafe { libc::close(sock) };
+    res.map(|_| ())
+}
+
+/// The network of `address` with the bits after `prefix` cleared, as nftables expects it.
+fn mask(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(address) & mask).into())
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(address) & mask).into())
+        }
+    }
+}
+
+fn rule(rule: &SNetworkRule) -> Result<String, Box<dyn Error>> {
+    let mut line = String::new();
+    if let Some((address, prefix)) = rule.network()? {
+        let family = if address.is_ipv4() { "ip" } else { "ip6" };
+        write!(
+            line,
+            "{} daddr {}/{} ",
+            family,
+            mask(address, prefix),
+            prefix
+        )?;
+    }
+    if !rule.ports.is_empty() {
+        let ports: Vec<String> = rule.ports.iter().map(u16::to_string).collect();
+        write!(
+            line,
+            "meta l4proto {{ tcp, udp }} th dport {{ {} }} ",
+            ports.join(", ")
+        )?;
+    }
+    line.push_str("accept");
+    Ok(line)
+}
+
+/// The nftables table restricting the packets sent from the cgroup `cgroup` to `rules`.
+fn ruleset(table: &str, cgroup: &str, rules: &[SNetworkRule]) -> Result<String, Box<dyn Error>> {
+    let mut ruleset = format!(
+        "table inet {table} {{\n\
+         \tchain output {{\n\
+         \t\ttype filter hook output priority filter; policy accept;\n\
+         \t\tsocket cgroupv2 level 2 \"{cgroup}\" jump restrict\n\
+         \t}}\n\
+         \tchain restrict {{\n\
+         \t\tct state established,related accept\n"
+    );
+    for r in rules {
+        writeln!(ruleset, "\t\t{}", rule(r)?)?;
+    }
+    ruleset.push_str("\t\tdrop\n\t}\n}\n");
+    Ok(ruleset)
+}
+
+/// The tables of the executions whose cgroup was removed, among the output of
+/// `nft list tables inet`.
+fn stale_tables(tables: &str, root: &Path) -> Vec<String> {
+    tables
+        .lines()
+        .filter_map(|line| line.strip_prefix("table inet "))
+        .filter_map(|table| {
+            let pid = table.strip_prefix(TABLE_PREFIX)?;
+            (!root.join(CGROUP_DIR).join(format!("sr-{}", pid)).exists()).then(|| table.to_string())
+        })
+        .collect()
+}
+
+/// Run nft with `CAP_NET_ADMIN`, which it keeps through the ambient set.
+fn nft(args: &[&str], input: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let mut command = Command::new(NFT);
+    command
+        .args(args)
+        .env_clear()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // SAFETY: only async-signal-safe syscalls are made between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            let mut state = CapState::get_current()?;
+            state.inheritable.add(Cap::NET_ADMIN);
+            state.set_current()?;
+            capctl::ambient::raise(Cap::NET_ADMIN)?;
+            Ok(())
+        })
+    };
+    let mut child = command.spawn()?;
+    let mut stdin = child.stdin.take().ok_or("no input")?;
+    if let Some(input) = input {
+        stdin.write_all(input.as_bytes())?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn allow(rules: &[SNetworkRule]) -> Result<(), Box<dyn Error>> {
+    let name = cgroup::name();
+    cap_effective(Cap::DAC_OVERRIDE, true)?;
+    let res = cgroup::join(Path::new(CGROUP_ROOT), &name);
+    cap_effective(Cap::DAC_OVERRIDE, false)?;
+    res?;
+    match nft(&["list", "tables", "inet"], None) {
+        Ok(tables) => {
+            for table in stale_tables(&tables, Path::new(CGROUP_ROOT)) {
+                match nft(&["delete", "table", "inet", &table], None) {
+                    Ok(_) => debug!("Removed the nftables table {}", table),
+                    Err(e) => warn!("Unable to remove the nftables table {}: {}", table, e),
+                }
+            }
+        }
+        Err(e) => warn!("Unable to list the nftables tables: {}", e),
+    }
+    let table = format!("{}{}", TABLE_PREFIX, std::process::id());
+    nft(&["-f", "-"], Some(&ruleset(&table, &name, rules)?))?;
+    debug!(
+        "Network of the command restricted by the nftables table {}",
+        table
+    );
+    Ok(())
+}
+
+fn deny_all() -> Result<(), Box<dyn Error>> {
+    debug!("Isolating the task in a new network namespace");
+    cap_effective(Cap::SYS_ADMIN, true)?;
+    let res = last_os_error(unsafe { libc::unshare(libc::CLONE_NEWNET) })
+        .map_err(|e| format!("Unable to create a network namespace: {}", e));
+    let res = res.and_then(|_| {
+        cap_effective(Cap::NET_ADMIN, true)
+            .map_err(|e| e.to_string())
+            .and_then(|_| loopback_up().map_err(|e| e.to_string()))
+    });
+    cap_effective(Cap::NET_ADMIN, false)?;
+    cap_effective(Cap::SYS_ADMIN, false)?;
+    Ok(res?)
+}
+
+/// Remove the cgroup and the nftables table of the command run by the child `pid`, once it
+/// exited. The table is kept while processes left by the command are in the cgroup, a next sr
+/// removes both.
+pub fn release(network: &SNetwork, pid: u32) {
+    let name = cgroup::name_of(pid);
+    if cap_effective(Cap::DAC_OVERRIDE, true).is_err() {
+        return;
+    }
+    let removed = cgroup::remove(Path::new(CGROUP_ROOT), &name);
+    let _ = cap_effective(Cap::DAC_OVERRIDE, false);
+    if !removed || !network.is_allow() {
+        return;
+    }
+    let table = format!("{}{}", TABLE_PREFIX, pid);
+    match nft(&["delete", "table", "inet", &table], None) {
+        Ok(_) => debug!("Removed the nftables table {}", table),
+        Err(e) => warn!("Unable to remove the nftables table {}: {}", table, e),
+    }
+}
+
+/// Apply the network restriction of the task to sr, the command inherits it.
+pub fn restrict_network(network: SNetwork) -> Result<(), Box<dyn Error>> {
+    match network {
+        SNetwork::DenyAll => deny_all(),
+        SNetwork::Allow(rules) => {
+            allow(&rules).map_err(|e| format!("Unable to restrict the network: {}", e).into())
+        }
+        SNetwork::AllowAll | SNetwork::Inherit => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_ruleset() {
+        let rules = [
+            SNetworkRule::builder().cidr("10.1.2.3/8").build(),
+            SNetworkRule::builder()
+                .cidr("2001:db8::1")
+                .ports([443, 8443])
+                .build(),
+            SNetworkRule::builder().ports([53]).build(),
+        ];
+        assert_eq!(
+            ruleset("rootasrole_sr_42", "rootasrole/sr-42", &rules).unwrap(),
+            "table inet rootasrole_sr_42 {
+\tchain output {
+\t\ttype filter hook output priority filter; policy accept;
+\t\tsocket cgroupv2 level 2 \"rootasrole/sr-42\" jump restrict
+\t}
+\tchain restrict {
+\t\tct state established,related accept
+\t\tip daddr 10.0.0.0/8 accept
+\t\tip6 daddr 2001:db8::1/128 meta l4proto { tcp, udp } th dport { 443, 8443 } accept
+\t\tmeta l4proto { tcp, udp } th dport { 53 } accept
+\t\tdrop
+\t}
+}
+"
+        );
+        assert!(ruleset(
+            "rootasrole_sr_42",
+            "rootasrole/sr-42",
+            &[SNetworkRule::builder().cidr("10.0.0.0/40").build()]
+        )
+        .is_err());
+        assert_eq!(
+            mask("192.0.2.77".parse().unwrap(), 0),
+            "0.0.0.0".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stale_tables() {
+        let root = std::env::temp_dir().join(format!("rar_nft_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("rootasrole/sr-2")).unwrap();
+        let tables = "table inet filter\ntable inet rootasrole_sr_1\ntable inet rootasrole_sr_2\n";
+        assert_eq!(stale_tables(tables, &root), ["rootasrole_sr_1"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::debug;
+
+const DPKG: &str = "/usr/bin/dpkg";
+const RPM: &str = "/usr/bin/rpm";
+
+// DPKG_ADMINDIR or the caller's ~/.rpmmacros could point to another package database
+fn command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    command
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .env("HOME", "/");
+    command
+}
+
+/// Refuse an executable that is not owned by a package or differs from the package database.
+pub fn verify_package_integrity(path: &Path) -> Result<(), Box<dyn Error>> {
+    let candidates = candidates(path);
+    if Path::new(DPKG).exists() {
+        for candidate in &candidates {
+            let output = command(DPKG).arg("-S").arg(candidate).output()?;
+            if let Some(packages) = dpkg_owners(&String::from_utf8_lossy(&output.stdout), candidate)
+            {
+                let output = command(DPKG).arg("--verify").args(&packages).output()?;
+                return check_report(&String::from_utf8_lossy(&output.stdout), candidate);
+            }
+        }
+    } else if Path::new(RPM).exists() {
+        for candidate in &candidates {
+            let output = command(RPM).arg("-Vf").arg(candidate).output()?;
+            let report = String::from_utf8_lossy(&output.stdout);
+            if !report.contains("is not owned by any package") {
+                return check_report(&report, candidate);
+            }
+        }
+    } else {
+        return Err("No package manager found to verify the command integrity".into());
+    }
+    Err(format!("{} is not owned by any package", path.display()).into())
+}
+
+// with a merged /usr, the package database may only know the /bin path
+fn candidates(path: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![path.to_path_buf()];
+    if let Some(canonical) = path.canonicalize().ok().filter(|c| c != path) {
+        candidates.push(canonical);
+    }
+    let merged: Vec<PathBuf> = candidates
+        .iter()
+        .filter_map(|p| p.strip_prefix("/usr").ok().map(|p| Path::new("/").join(p)))
+        .collect();
+    for path in merged {
+        if !candidates.contains(&path) {
+            candidates.push(path);
+        }
+    }
+    candidates
+}
+
+fn dpkg_owners(output: &str, path: &Path) -> Option<Vec<String>> {
+    let suffix = format!(": {}", path.display());
+    output
+        .lines()
+        .filter(|line| !line.starts_with("diversion by"))
+        .find_map(|line| line.strip_suffix(&suffix))
+        .map(|owners| owners.split(", ").map(str::to_string).collect())
+}
+
+fn check_report(report: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    debug!("Package verification report for {:?}: {}", path, report);
+    let path = path.to_string_lossy();
+    match report
+        .lines()
+        .find(|line| line.split_whitespace().last() == Some(path.as_ref()))
+    {
+        Some(line) => Err(format!(
+            "{} differs from its package: {}",
+            path,
+            line.split_whitespace().next().unwrap_or_default()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dpkg_owners() {
+        let output = "diversion by dash from: /bin/sh\ndiversion by dash to: /bin/sh.distrib\ndash: /bin/sh\n";
+        assert_eq!(
+            dpkg_owners(output, Path::new("/bin/sh")),
+            Some(vec!["dash".to_string()])
+        );
+        let output = "libc6:amd64, libc6:i386: /lib/ld.so\n";
+        assert_eq!(
+            dpkg_owners(output, Path::new("/lib/ld.so")),
+            Some(vec!["libc6:amd64".to_string(), "libc6:i386".to_string()])
+        );
+        assert_eq!(dpkg_owners("", Path::new("/usr/bin/ls")), None);
+    }
+
+    #[test]
+    fn test_check_report() {
+        let report = "??5??????   /bin/ls\n??5?????? c /etc/ls.conf\n";
+        assert!(check_report(report, Path::new("/bin/ls")).is_err());
+        assert!(check_report(report, Path::new("/bin/cat")).is_ok());
+        let report = "S.5....T.    /usr/bin/ls\n";
+        assert!(check_report(report, Path::new("/usr/bin/ls")).is_err());
+        assert!(check_report("", Path::new("/usr/bin/ls")).is_ok());
+    }
+
+    #[test]
+    fn test_candidates() {
+        assert!(candidates(Path::new("/usr/bin/env")).contains(&PathBuf::from("/bin/env")));
+    }
+}
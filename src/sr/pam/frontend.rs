@@ -0,0 +1,269 @@
+//! Frontends answering the PAM conversation.
+//!
+//! The terminal is used by default. `sr --askpass` runs the program named by `SR_ASKPASS`
+//! with the prompt as argument and reads the answer on its output, like `sudo -A`. `sr --auth-fd`
+//! speaks JSON lines over a socket inherited by sr, for frontends without terminal such as a
+//! relay of the SSH keyboard-interactive authentication:
+//!
+//! ```text
+//! sr -> {"type":"prompt","echo":false,"message":"Password: "}
+//! sr <- {"response":"secret"}
+//! sr -> {"type":"info","message":"..."}
+//! sr -> {"type":"error","message":"..."}
+//! ```
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Error, ErrorKind, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    rpassword::{read_unbuffered, wait_readable, Terminal},
+    securemem::PamBuffer,
+};
+
+/// Variable naming the askpass program.
+pub(crate) const ASKPASS_ENV: &str = "SR_ASKPASS";
+
+pub(crate) trait Frontend {
+    /// Asks the user, waiting at most `timeout` seconds, 0 waits forever.
+    fn prompt(&mut self, message: &str, echo: bool, timeout: u64) -> io::Result<PamBuffer>;
+    fn info(&mut self, message: &str);
+    fn error(&mut self, message: &str);
+}
+
+/// The terminal, or the standard input and error with `--stdin`.
+#[derive(Default)]
+pub(crate) struct Cli {
+    pub use_stdin: bool,
+}
+
+impl Frontend for Cli {
+    fn prompt(&mut self, message: &str, echo: bool, timeout: u64) -> io::Result<PamBuffer> {
+        let mut term = if self.use_stdin {
+            Terminal::open_stdie()
+        } else {
+            Terminal::open_tty()
+        }?;
+        term.prompt(message)?;
+        if echo {
+            term.read_cleartext(timeout)
+        } else {
+            term.read_password(timeout)
+        }
+    }
+
+    fn info(&mut self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn error(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// A graphical helper, run with the credentials of the caller.
+pub(crate) struct Askpass {
+    program: PathBuf,
+}
+
+impl Askpass {
+    pub fn from_env() -> io::Result<Self> {
+        let program = std::env::var_os(ASKPASS_ENV)
+            .map(PathBuf::from)
+            .filter(|program| program.is_absolute())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} must be an absolute path", ASKPASS_ENV),
+                )
+            })?;
+        Ok(Askpass { program })
+    }
+}
+
+// the helper must not get the privileges of sr
+fn unprivileged() -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    capctl::ambient::clear()?;
+    capctl::CapState::empty().set_current()?;
+    Ok(())
+}
+
+impl Frontend for Askpass {
+    fn prompt(&mut self, message: &str, _echo: bool, timeout: u64) -> io::Result<PamBuffer> {
+        let mut command = Command::new(&self.program);
+        command
+            .arg(message)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped());
+        // SAFETY: the closure only performs system calls between fork and exec
+        unsafe {
+            command.pre_exec(unprivileged);
+        }
+        let mut child = command.spawn()?;
+        let mut stdout = child.stdout.take().ok_or(ErrorKind::BrokenPipe)?;
+        let answer =
+            wait_readable(stdout.as_raw_fd(), timeout).and_then(|_| read_unbuffered(&mut stdout));
+        if answer.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        let answer = answer?;
+        if !status.success() {
+            return Err(Error::other(format!(
+                "{} exited with {}",
+                self.program.display(),
+                status
+            )));
+        }
+        Ok(answer)
+    }
+
+    fn info(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn error(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request<'a> {
+    Prompt { echo: bool, message: &'a str },
+    Info { message: &'a str },
+    Error { message: &'a str },
+}
+
+#[derive(Deserialize)]
+struct Response<'a> {
+    #[serde(borrow)]
+    response: Cow<'a, str>,
+}
+
+/// JSON lines over a bidirectional file descriptor, such as one end of a socketpair.
+pub(crate) struct Machine {
+    channel: File,
+}
+
+impl Machine {
+    /// # Safety
+    /// `fd` must be open and owned by nothing else, it is closed with the frontend.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Machine {
+            channel: File::from_raw_fd(fd),
+        }
+    }
+
+    fn send(&mut self, request: &Request) -> io::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.channel.write_all(&line)?;
+        self.channel.flush()
+    }
+}
+
+fn parse_response(line: &[u8]) -> io::Result<PamBuffer> {
+    let length = line.iter().position(|&b| b == 0).unwrap_or(line.len());
+    let response: Response = serde_json::from_slice(&line[..length])?;
+    let mut answer = PamBuffer::default();
+    let bytes = response.response.as_bytes();
+    if bytes.len() >= answer.len() {
+        return Err(Error::new(ErrorKind::OutOfMemory, "response too long"));
+    }
+    answer[..bytes.len()].copy_from_slice(bytes);
+    Ok(answer)
+}
+
+impl Frontend for Machine {
+    fn prompt(&mut self, message: &str, echo: bool, timeout: u64) -> io::Result<PamBuffer> {
+        self.send(&Request::Prompt { echo, message })?;
+        wait_readable(self.channel.as_raw_fd(), timeout)?;
+        parse_response(&read_unbuffered(&mut self.channel)?)
+    }
+
+    fn info(&mut self, message: &str) {
+        let _ = self.send(&Request::Info { message });
+    }
+
+    fn error(&mut self, message: &str) {
+        let _ = self.send(&Request::Error { message });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        os::{fd::IntoRawFd, unix::net::UnixStream},
+    };
+
+    use super::*;
+
+    fn text(buffer: &PamBuffer) -> String {
+        buffer
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect()
+    }
+
+    #[test]
+    fn test_machine() {
+        let (sr, frontend) = UnixStream::pair().unwrap();
+        let mut machine = unsafe { Machine::from_raw_fd(sr.into_raw_fd()) };
+        let mut peer = BufReader::new(frontend.try_clone().unwrap());
+        (&frontend)
+            .write_all(b"{\"response\":\"se\\u0063ret\"}\n")
+            .unwrap();
+        let answer = machine.prompt("Password: ", false, 1).unwrap();
+        assert_eq!(text(&answer), "secret");
+        let mut line = String::new();
+        peer.read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            "{\"type\":\"prompt\",\"echo\":false,\"message\":\"Password: \"}\n"
+        );
+        machine.info("Welcome");
+        line.clear();
+        peer.read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"type\":\"info\",\"message\":\"Welcome\"}\n");
+        let Err(err) = machine.prompt("Password: ", false, 1) else {
+            panic!("no response was given");
+        };
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert!(parse_response(b"{\"answer\":\"x\"}").is_err());
+        assert!(parse_response(b"not json").is_err());
+        let long = format!("{{\"response\":\"{}\"}}", "a".repeat(600));
+        assert!(parse_response(long.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_askpass() {
+        // echo answers the prompt itself
+        let mut askpass = Askpass {
+            program: "/bin/echo".into(),
+        };
+        assert_eq!(text(&askpass.prompt("secret", false, 1).unwrap()), "secret");
+        let mut askpass = Askpass {
+            program: "/bin/false".into(),
+        };
+        assert!(askpass.prompt("secret", false, 1).is_err());
+    }
+}
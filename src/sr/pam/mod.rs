@@ -1,23 +1,34 @@
+use std::error::Error;
+#[cfg(feature = "pam")]
 use std::{
-    error::Error,
     ffi::{CStr, CString},
     ops::Deref,
 };
 
-use log::{debug, error, info, warn};
-use pam_client2::{Context, ConversationHandler, ErrorCode, Flag};
+use log::{debug, warn};
+#[cfg(feature = "pam")]
+use log::{error, info};
+#[cfg(feature = "pam")]
+use pam_client2::{Context, ConversationHandler, ErrorCode, Flag, SessionToken};
+#[cfg(feature = "pam")]
 use pcre2::bytes::RegexBuilder;
 
+#[cfg(feature = "pam")]
 use crate::timeout;
 use rar_common::{
     database::{finder::Cred, options::OptStack},
     Storage,
 };
 
-use self::rpassword::Terminal;
+pub(crate) use self::frontend::{Askpass, Cli, Frontend, Machine};
 
 #[allow(dead_code, reason = "This file is part of sudo-rs.")]
 mod cutils;
+#[cfg_attr(
+    not(feature = "pam"),
+    allow(dead_code, reason = "The frontends answer the PAM modules.")
+)]
+mod frontend;
 #[allow(dead_code, reason = "This file is part of sudo-rs.")]
 mod rpassword;
 #[allow(dead_code, reason = "This file is part of sudo-rs.")]
@@ -30,27 +41,47 @@ const PAM_SERVICE: &str = "sr_test";
 
 pub(crate) const PAM_PROMPT: &str = "Password: ";
 
+/// Exit status of `sr --non-interactive` when a password would have been asked.
+pub(crate) const AUTH_REQUIRED_STATUS: i32 = 2;
+
+#[derive(Debug)]
+pub(crate) struct AuthenticationRequired;
+
+impl std::fmt::Display for AuthenticationRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "An authentication is required")
+    }
+}
+
+impl Error for AuthenticationRequired {}
+
+#[cfg(feature = "pam")]
 struct SrConversationHandler {
     username: Option<String>,
     prompt: String,
-    use_stdin: bool,
+    frontend: Box<dyn Frontend>,
     no_interact: bool,
+    timeout: u64,
+    timed_out: bool,
+    refused: bool,
 }
 
+#[cfg(feature = "pam")]
 impl SrConversationHandler {
-    fn new(prompt: &str) -> Self {
+    fn new(prompt: &str, timeout: u64, frontend: Box<dyn Frontend>) -> Self {
         SrConversationHandler {
             prompt: prompt.to_string(),
-            username: None,
-            use_stdin: false,
-            no_interact: false,
+            frontend,
+            timeout,
+            ..Default::default()
         }
     }
-    fn open(&self) -> std::io::Result<Terminal> {
-        if self.use_stdin {
-            Terminal::open_stdie()
+    fn read_error(&mut self, e: std::io::Error) -> ErrorCode {
+        if e.kind() == std::io::ErrorKind::TimedOut {
+            self.timed_out = true;
+            ErrorCode::CONV_ERR
         } else {
-            Terminal::open_tty()
+            ErrorCode::BUF_ERR
         }
     }
     fn is_pam_password_prompt(&self, prompt: &CStr) -> bool {
@@ -70,52 +101,102 @@ impl SrConversationHandler {
     }
 }
 
+#[cfg(feature = "pam")]
 impl Default for SrConversationHandler {
     fn default() -> Self {
         SrConversationHandler {
             prompt: "Password: ".to_string(),
             username: None,
-            use_stdin: false,
+            frontend: Box::new(Cli::default()),
             no_interact: false,
+            timeout: 0,
+            timed_out: false,
+            refused: false,
         }
     }
 }
 
+#[cfg(feature = "pam")]
 impl ConversationHandler for SrConversationHandler {
     fn prompt_echo_on(&mut self, prompt: &CStr) -> Result<CString, ErrorCode> {
         if self.no_interact {
+            self.refused = true;
             return Err(ErrorCode::CONV_ERR);
         }
-        let mut term = self.open().map_err(|_| ErrorCode::CONV_ERR)?;
-        term.prompt(prompt.to_string_lossy().as_ref())
-            .map_err(|_| ErrorCode::CONV_ERR)?;
-        let read = term.read_cleartext().map_err(|_| ErrorCode::BUF_ERR)?;
+        let read = self
+            .frontend
+            .prompt(prompt.to_string_lossy().as_ref(), true, self.timeout)
+            .map_err(|e| self.read_error(e))?;
         Ok(unsafe { CString::from_vec_unchecked(read.deref().to_vec()) })
     }
 
     fn prompt_echo_off(&mut self, prompt: &CStr) -> Result<CString, ErrorCode> {
         if self.no_interact {
+            self.refused = true;
             return Err(ErrorCode::CONV_ERR);
         }
         let pam_prompt = prompt.to_string_lossy();
         if self.prompt == Self::default().prompt && !self.is_pam_password_prompt(prompt) {
             self.prompt = pam_prompt.to_string()
         }
-        let mut term = self.open().map_err(|_| ErrorCode::CONV_ERR)?;
-        term.prompt(pam_prompt.as_ref())
-            .map_err(|_| ErrorCode::CONV_ERR)?;
-        let read = term.read_password().map_err(|_| ErrorCode::BUF_ERR)?;
+        let read = self
+            .frontend
+            .prompt(pam_prompt.as_ref(), false, self.timeout)
+            .map_err(|e| self.read_error(e))?;
         Ok(unsafe { CString::from_vec_unchecked(read.deref().to_vec()) })
     }
 
     fn text_info(&mut self, msg: &CStr) {
         info!("{}", msg.to_string_lossy());
-        println!("{}", msg.to_string_lossy());
+        self.frontend.info(msg.to_string_lossy().as_ref());
     }
 
     fn error_msg(&mut self, msg: &CStr) {
         error!("{}", msg.to_string_lossy());
-        eprintln!("{}", msg.to_string_lossy());
+        self.frontend.error(msg.to_string_lossy().as_ref());
+    }
+}
+
+#[cfg(feature = "pam")]
+fn authenticate(
+    context: &mut Context<SrConversationHandler>,
+    retries: u8,
+) -> Result<(), Box<dyn Error>> {
+    for attempt in 1..=retries {
+        match context.authenticate(Flag::SILENT) {
+            Ok(()) => return Ok(()),
+            Err(_) if context.conversation().timed_out => {
+                return Err("Password prompt timed out".into());
+            }
+            Err(_) if context.conversation().refused => {
+                return Err(Box::new(AuthenticationRequired));
+            }
+            Err(e) if e.code() == ErrorCode::AUTH_ERR && attempt < retries => {
+                eprintln!("Sorry, try again.");
+            }
+            Err(e) if e.code() == ErrorCode::AUTH_ERR => {
+                return Err(format!("{} incorrect password attempts", retries).into());
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    Err("No authentication attempt allowed".into())
+}
+
+/// The service of the matched role or task, a name of /etc/pam.d.
+pub(super) fn service(optstack: &OptStack) -> Result<String, Box<dyn Error>> {
+    match optstack.get_pam_service() {
+        None => Ok(PAM_SERVICE.to_string()),
+        Some(service)
+            if !service.is_empty()
+                && !service.starts_with('.')
+                && service
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) =>
+        {
+            Ok(service)
+        }
+        Some(service) => Err(format!("Invalid PAM service {:?}", service).into()),
     }
 }
 
@@ -124,27 +205,183 @@ pub(super) fn check_auth(
     config: &Storage,
     user: &Cred,
     prompt: &str,
+    non_interactive: bool,
+    frontend: Box<dyn Frontend>,
 ) -> Result<(), Box<dyn Error>> {
     if optstack.get_authentication().1.is_skip() {
         warn!("Skipping authentication, this is a security risk!");
         return Ok(());
     }
+    authenticate_user(optstack, config, user, prompt, non_interactive, frontend)
+}
+
+#[cfg(feature = "pam")]
+fn authenticate_user(
+    optstack: &OptStack,
+    config: &Storage,
+    user: &Cred,
+    prompt: &str,
+    non_interactive: bool,
+    frontend: Box<dyn Frontend>,
+) -> Result<(), Box<dyn Error>> {
     let timeout = optstack.get_timeout().1;
+    let service = service(optstack)?;
+    // a cookie only stands for the service that authenticated the user
     let is_valid = match config {
-        Storage::JSON(_) => timeout::is_valid(user, user, &timeout),
+        Storage::JSON(_) => timeout::is_valid(user, user, &timeout, &service),
     };
     debug!("need to re-authenticate : {}", !is_valid);
     if !is_valid {
-        let conv = SrConversationHandler::new(prompt);
-        let mut context = Context::new(PAM_SERVICE, Some(&user.user.name), conv)
-            .expect("Failed to initialize PAM");
-        context.authenticate(Flag::SILENT)?;
+        let (prompt_timeout, retries) = optstack.get_auth_prompt();
+        let mut conv = SrConversationHandler::new(prompt, prompt_timeout, frontend);
+        // PAM modules that do not prompt, such as pam_permit, still succeed
+        conv.no_interact = non_interactive;
+        debug!("Authenticating with the PAM service {}", service);
+        let mut context =
+            Context::new(&service, Some(&user.user.name), conv).expect("Failed to initialize PAM");
+        authenticate(&mut context, retries)?;
         context.acct_mgmt(Flag::SILENT)?;
     }
     match config {
         Storage::JSON(_) => {
-            timeout::update_cookie(user, user, &timeout)?;
+            timeout::update_cookie(user, user, &timeout, &service)?;
         }
     }
     Ok(())
 }
+
+#[cfg(not(feature = "pam"))]
+fn authenticate_user(
+    _optstack: &OptStack,
+    _config: &Storage,
+    _user: &Cred,
+    _prompt: &str,
+    _non_interactive: bool,
+    _frontend: Box<dyn Frontend>,
+) -> Result<(), Box<dyn Error>> {
+    Err("sr is built without PAM, only the tasks that skip the authentication can be used".into())
+}
+
+/// The PAM session of the command, opened for the target user before the credentials switch.
+#[cfg(feature = "pam")]
+pub(super) struct Session {
+    context: Context<SrConversationHandler>,
+    token: Option<SessionToken>,
+}
+
+#[cfg(feature = "pam")]
+impl Session {
+    /// The variables set by the session modules, such as pam_env.
+    pub(super) fn env(&self) -> Vec<(String, String)> {
+        self.context
+            .envlist()
+            .iter_tuples()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().to_string(),
+                    value.to_string_lossy().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Closes the session, with the privileges it was opened with.
+    pub(super) fn close(mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(token) = self.token.take() {
+            self.context
+                .unleak_session(token)
+                .close(Flag::SILENT)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the session of `target`, PAM_RUSER being the caller, with the service that
+/// authenticated the caller.
+#[cfg(feature = "pam")]
+pub(super) fn open_session(
+    optstack: &OptStack,
+    user: &Cred,
+    target: &str,
+) -> Result<Session, Box<dyn Error>> {
+    let conv = SrConversationHandler {
+        no_interact: true,
+        ..Default::default()
+    };
+    let mut context = Context::new(&service(optstack)?, Some(target), conv)?;
+    context.set_ruser(Some(&user.user.name))?;
+    let token = context.open_session(Flag::SILENT)?.leak();
+    debug!("PAM session opened for {}", target);
+    Ok(Session {
+        context,
+        token: Some(token),
+    })
+}
+
+/// Without PAM, the command runs without a session.
+#[cfg(not(feature = "pam"))]
+pub(super) struct Session;
+
+#[cfg(not(feature = "pam"))]
+impl Session {
+    pub(super) fn env(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    pub(super) fn close(self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "pam"))]
+pub(super) fn open_session(
+    _optstack: &OptStack,
+    _user: &Cred,
+    target: &str,
+) -> Result<Session, Box<dyn Error>> {
+    debug!(
+        "sr is built without PAM, no session is opened for {}",
+        target
+    );
+    Ok(Session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "pam")]
+    #[test]
+    fn test_non_interactive_conversation() {
+        let mut conv = SrConversationHandler {
+            no_interact: true,
+            ..Default::default()
+        };
+        let prompt = CString::new("Password: ").unwrap();
+        assert!(conv.prompt_echo_off(&prompt).is_err());
+        assert!(conv.refused);
+    }
+
+    #[test]
+    fn test_service() {
+        use rar_common::database::structs::{RoleGetter, SConfig, SRole};
+        let config = SConfig::builder()
+            .role(
+                SRole::builder("r_totp")
+                    .options(|opt| opt.pam_service("sr-totp").build())
+                    .build(),
+            )
+            .role(
+                SRole::builder("r_escape")
+                    .options(|opt| opt.pam_service("../passwd").build())
+                    .build(),
+            )
+            .role(SRole::builder("r_default").build())
+            .build();
+        let optstack = |role| OptStack::from_role(config.role(role).unwrap());
+        assert_eq!(service(&optstack("r_totp")).unwrap(), "sr-totp");
+        assert_eq!(service(&optstack("r_default")).unwrap(), PAM_SERVICE);
+        assert!(service(&optstack("r_escape")).is_err());
+    }
+}
@@ -16,9 +16,12 @@
 ///
 use std::io::{self, Error, ErrorKind, Read};
 use std::os::fd::{AsRawFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::{fs, mem};
 
 use libc::{tcsetattr, termios, ECHO, ECHONL, TCSANOW};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 
 use super::cutils::cerr;
 
@@ -27,6 +30,59 @@ use super::securemem::PamBuffer;
 pub struct HiddenInput {
     tty: fs::File,
     term_orig: termios,
+    handlers: Vec<(Signal, SigAction)>,
+}
+
+// the terminal settings to restore when sr is interrupted during a prompt
+struct SavedTerm {
+    fd: RawFd,
+    term: termios,
+}
+
+static SAVED_TERM: AtomicPtr<SavedTerm> = AtomicPtr::new(ptr::null_mut());
+
+const INTERRUPTIONS: [Signal; 4] = [
+    Signal::SIGINT,
+    Signal::SIGTERM,
+    Signal::SIGHUP,
+    Signal::SIGQUIT,
+];
+
+extern "C" fn restore_and_raise(signal: libc::c_int) {
+    // only async-signal-safe calls here
+    let saved = SAVED_TERM.swap(ptr::null_mut(), Ordering::SeqCst);
+    unsafe {
+        if !saved.is_null() {
+            tcsetattr((*saved).fd, TCSANOW, &(*saved).term);
+        }
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+// the echo is shown while sr is suspended, then hidden again when it is resumed
+extern "C" fn suspend(signal: libc::c_int) {
+    // only async-signal-safe calls here
+    let saved = SAVED_TERM.load(Ordering::SeqCst);
+    unsafe {
+        let mut hidden: termios = mem::zeroed();
+        if !saved.is_null() && libc::tcgetattr((*saved).fd, &mut hidden) == 0 {
+            tcsetattr((*saved).fd, TCSANOW, &(*saved).term);
+        }
+        // the signal is blocked during its handler, so it must be unblocked to stop now
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::signal(signal, libc::SIG_DFL);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+        libc::raise(signal);
+        // resumed by SIGCONT
+        libc::sigprocmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+        libc::signal(signal, suspend as *const () as libc::sighandler_t);
+        if !saved.is_null() {
+            tcsetattr((*saved).fd, TCSANOW, &hidden);
+        }
+    }
 }
 
 impl HiddenInput {
@@ -51,10 +107,44 @@ impl HiddenInput {
         // But don't hide the NL character when the user hits ENTER.
         term.c_lflag |= ECHONL;
 
+        // Restore the echo even if sr is interrupted before the end of the prompt
+        let saved = Box::into_raw(Box::new(SavedTerm {
+            fd,
+            term: term_orig,
+        }));
+        let previous = SAVED_TERM.swap(saved, Ordering::SeqCst);
+        if !previous.is_null() {
+            drop(unsafe { Box::from_raw(previous) });
+        }
+        let action = SigAction::new(
+            SigHandler::Handler(restore_and_raise),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        let suspension = SigAction::new(
+            SigHandler::Handler(suspend),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        let handlers = INTERRUPTIONS
+            .iter()
+            .map(|signal| (*signal, &action))
+            .chain([(Signal::SIGTSTP, &suspension)])
+            .filter_map(|(signal, action)| {
+                unsafe { sigaction(signal, action) }
+                    .ok()
+                    .map(|old| (signal, old))
+            })
+            .collect();
+
         // Save the settings for now.
         cerr(unsafe { tcsetattr(fd, TCSANOW, &term) })?;
 
-        Ok(Some(HiddenInput { tty, term_orig }))
+        Ok(Some(HiddenInput {
+            tty,
+            term_orig,
+            handlers,
+        }))
     }
 }
 
@@ -64,6 +154,34 @@ impl Drop for HiddenInput {
         unsafe {
             tcsetattr(self.tty.as_raw_fd(), TCSANOW, &self.term_orig);
         }
+        for (signal, old) in &self.handlers {
+            let _ = unsafe { sigaction(*signal, old) };
+        }
+        let saved = SAVED_TERM.swap(ptr::null_mut(), Ordering::SeqCst);
+        if !saved.is_null() {
+            drop(unsafe { Box::from_raw(saved) });
+        }
+    }
+}
+
+/// Wait for the input to be readable, `timeout` is in seconds, 0 waits forever.
+pub(super) fn wait_readable(fd: RawFd, timeout: u64) -> io::Result<()> {
+    if timeout == 0 {
+        return Ok(());
+    }
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.saturating_mul(1000).min(libc::c_int::MAX as u64) as libc::c_int;
+    loop {
+        match cerr(unsafe { libc::poll(&mut pollfd, 1, millis) }) {
+            Ok(0) => return Err(Error::new(ErrorKind::TimedOut, "prompt timed out")),
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
     }
 }
 
@@ -74,7 +192,8 @@ fn safe_tcgetattr(fd: RawFd) -> io::Result<termios> {
 }
 
 /// Reads a password from the given file descriptor
-fn read_unbuffered(source: &mut impl io::Read) -> io::Result<PamBuffer> {
+#[allow(clippy::unbuffered_bytes)]
+pub(super) fn read_unbuffered(source: &mut impl io::Read) -> io::Result<PamBuffer> {
     let mut password = PamBuffer::default();
     let mut pwd_iter = password.iter_mut();
 
@@ -123,15 +242,18 @@ impl Terminal<'_> {
         Ok(Terminal::StdIE(io::stdin().lock(), io::stderr().lock()))
     }
 
-    /// Reads input with TTY echo disabled
-    pub fn read_password(&mut self) -> io::Result<PamBuffer> {
+    /// Reads input with TTY echo disabled, waiting at most `timeout` seconds
+    pub fn read_password(&mut self, timeout: u64) -> io::Result<PamBuffer> {
+        let fd = self.as_raw_fd();
         let mut input = self.source();
         let _hide_input = HiddenInput::new()?;
+        wait_readable(fd, timeout)?;
         read_unbuffered(&mut input)
     }
 
-    /// Reads input with TTY echo enabled
-    pub fn read_cleartext(&mut self) -> io::Result<PamBuffer> {
+    /// Reads input with TTY echo enabled, waiting at most `timeout` seconds
+    pub fn read_cleartext(&mut self, timeout: u64) -> io::Result<PamBuffer> {
+        wait_readable(self.as_raw_fd(), timeout)?;
         read_unbuffered(&mut self.source())
     }
 
@@ -141,6 +263,13 @@ impl Terminal<'_> {
     }
 
     // boilerplate reduction functions
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Terminal::StdIE(x, _) => x.as_raw_fd(),
+            Terminal::Tty(x) => x.as_raw_fd(),
+        }
+    }
+
     fn source(&mut self) -> &mut dyn io::Read {
         match self {
             Terminal::StdIE(x, _) => x,
@@ -158,7 +287,9 @@ impl Terminal<'_> {
 
 #[cfg(test)]
 mod test {
-    use super::{read_unbuffered, write_unbuffered};
+    use std::os::fd::AsRawFd;
+
+    use super::{read_unbuffered, wait_readable, write_unbuffered};
 
     #[test]
     fn miri_test_read() {
@@ -182,6 +313,15 @@ mod test {
         assert!(read_unbuffered(&mut "a".repeat(512).as_bytes()).is_err());
     }
 
+    #[test]
+    fn test_wait_readable() {
+        let (read, write) = nix::unistd::pipe().unwrap();
+        let err = wait_readable(read.as_raw_fd(), 1).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        nix::unistd::write(&write, b"secret\n").unwrap();
+        assert!(wait_readable(read.as_raw_fd(), 1).is_ok());
+    }
+
     #[test]
     fn miri_test_write() {
         let mut data = Vec::new();
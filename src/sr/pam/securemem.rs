@@ -11,7 +11,10 @@ use std::{
     slice,
 };
 
+#[cfg(feature = "pam")]
 use pam_client2::pam_sys::PAM_MAX_RESP_SIZE;
+#[cfg(not(feature = "pam"))]
+const PAM_MAX_RESP_SIZE: u32 = 512;
 
 pub(super) const SIZE: usize = PAM_MAX_RESP_SIZE as usize;
 const ALIGN: usize = mem::align_of::<u8>();
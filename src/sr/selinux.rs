@@ -0,0 +1,93 @@
+//! SELinux context of the command.
+//!
+//! A task may give a `selinux_role` and a `selinux_type`. When sr is built with the `selinux`
+//! feature, they replace the role and type of the context of sr for the next exec, like
+//! setexeccon(3) does, so the SELinux user and range of the caller are kept.
+use std::error::Error;
+
+use rar_common::database::finder::ExecSettings;
+
+#[cfg(feature = "selinux")]
+const CURRENT: &str = "/proc/thread-self/attr/current";
+#[cfg(feature = "selinux")]
+const EXEC: &str = "/proc/thread-self/attr/exec";
+
+/// The context `user:role:type[:range]` with the role and type replaced when given.
+#[cfg(any(feature = "selinux", test))]
+fn exec_context(
+    current: &str,
+    role: Option<&str>,
+    typ: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let current = current.trim_end_matches(['\0', '\n']);
+    let mut fields = current.splitn(4, ':');
+    let (Some(user), Some(current_role), Some(current_type)) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(format!("Invalid SELinux context {}", current).into());
+    };
+    let mut context = format!(
+        "{}:{}:{}",
+        user,
+        role.unwrap_or(current_role),
+        typ.unwrap_or(current_type)
+    );
+    if let Some(range) = fields.next() {
+        context.push(':');
+        context.push_str(range);
+    }
+    Ok(context)
+}
+
+#[cfg(feature = "selinux")]
+fn set_exec_context(role: Option<&str>, typ: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let current =
+        std::fs::read_to_string(CURRENT).map_err(|e| format!("SELinux is not enabled: {}", e))?;
+    let context = exec_context(&current, role, typ)?;
+    std::fs::write(EXEC, &context)
+        .map_err(|e| format!("Unable to set the SELinux context {}: {}", context, e))?;
+    log::debug!("SELinux context of the command: {}", context);
+    Ok(())
+}
+
+/// Sets the context the command is executed with, if the task gives one.
+pub fn apply(execcfg: &ExecSettings) -> Result<(), Box<dyn Error>> {
+    let task = execcfg.task();
+    let task = task.as_ref().borrow();
+    if task.selinux_role.is_none() && task.selinux_type.is_none() {
+        return Ok(());
+    }
+    #[cfg(feature = "selinux")]
+    return set_exec_context(task.selinux_role.as_deref(), task.selinux_type.as_deref());
+    #[cfg(not(feature = "selinux"))]
+    {
+        log::warn!(
+            "Task {} has a SELinux context but sr is built without SELinux support",
+            task.name
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_context() {
+        assert_eq!(
+            exec_context(
+                "staff_u:staff_r:staff_t:s0-s0:c0.c1023\0",
+                Some("sysadm_r"),
+                Some("sysadm_t")
+            )
+            .unwrap(),
+            "staff_u:sysadm_r:sysadm_t:s0-s0:c0.c1023"
+        );
+        assert_eq!(
+            exec_context("user_u:user_r:user_t", None, Some("ping_t")).unwrap(),
+            "user_u:user_r:ping_t"
+        );
+        assert!(exec_context("unconfined", Some("sysadm_r"), None).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! The `state-dir` option: a writable directory of the task, owned by its target user.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::DirBuilder,
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use capctl::Cap;
+use log::debug;
+use rar_common::{
+    database::finder::ExecSettings,
+    util::{cap_effective, dac_override_effective},
+};
+
+const STATE_DIR: &str = "/var/lib/rootasrole/tasks";
+const STATE_DIR_ENV: &str = "RAR_STATE_DIR";
+
+// names are used as path components, they must not escape the base directory
+fn component(name: &str) -> Result<&str, Box<dyn Error>> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') {
+        return Err(format!("{:?} cannot be used as a state directory name", name).into());
+    }
+    Ok(name)
+}
+
+fn state_path(base: &Path, role: &str, task: &str) -> Result<PathBuf, Box<dyn Error>> {
+    Ok(base.join(component(role)?).join(component(task)?))
+}
+
+fn create_dir(path: &Path, mode: u32, uid: u32, gid: u32) -> Result<(), Box<dyn Error>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_dir() => {
+            return Err(format!("{} exists and is not a directory", path.display()).into());
+        }
+        Ok(metadata) if metadata.uid() == uid && metadata.gid() == gid => return Ok(()),
+        Ok(_) => debug!("Changing the owner of {}", path.display()),
+        Err(_) => {
+            DirBuilder::new().mode(mode).create(path)?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+    // sr still runs with the caller uid, the directory must belong to the target user
+    cap_effective(Cap::CHOWN, true)?;
+    let chown = std::os::unix::fs::lchown(path, Some(uid), Some(gid));
+    cap_effective(Cap::CHOWN, false)?;
+    Ok(chown?)
+}
+
+fn create(base: &Path, path: &Path, uid: u32, gid: u32) -> Result<(), Box<dyn Error>> {
+    let mut ancestors: Vec<&Path> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(base))
+        .collect();
+    ancestors.reverse();
+    if let Some(parent) = base.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(parent)?;
+    }
+    for dir in ancestors {
+        create_dir(dir, 0o755, 0, 0)?;
+    }
+    create_dir(path, 0o700, uid, gid)
+}
+
+/// The state directory of the task, when the option is set.
+pub fn path(execcfg: &ExecSettings) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if !execcfg.opt.get_state_dir() {
+        return Ok(None);
+    }
+    state_path(
+        Path::new(STATE_DIR),
+        &execcfg.role().as_ref().borrow().name,
+        &execcfg.task().as_ref().borrow().name.to_string(),
+    )
+    .map(Some)
+}
+
+/// Create the state directory of the task, before sr switches to the target user and drops
+/// the capabilities it needs to.
+pub fn prepare(
+    execcfg: &ExecSettings,
+    uid: u32,
+    gid: u32,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let Some(path) = path(execcfg)? else {
+        return Ok(None);
+    };
+    dac_override_effective(true)?;
+    let created = create(Path::new(STATE_DIR), &path, uid, gid);
+    dac_override_effective(false)?;
+    created?;
+    Ok(Some(path))
+}
+
+/// Give the path of the state directory to the command.
+pub fn export(path: Option<&Path>, env: &mut HashMap<String, String>) {
+    // the caller must not choose the directory of the command
+    env.remove(STATE_DIR_ENV);
+    if let Some(path) = path {
+        env.insert(
+            STATE_DIR_ENV.to_string(),
+            path.to_string_lossy().to_string(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_path() {
+        let base = Path::new(STATE_DIR);
+        assert_eq!(
+            state_path(base, "r_backup", "t_dump").unwrap(),
+            PathBuf::from("/var/lib/rootasrole/tasks/r_backup/t_dump")
+        );
+        assert!(state_path(base, "..", "t_dump").is_err());
+        assert!(state_path(base, "r_backup", "../../etc").is_err());
+        assert!(state_path(base, "r_backup", "").is_err());
+    }
+}
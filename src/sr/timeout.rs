@@ -1,13 +1,13 @@
 use std::{
     error::Error,
-    io::{BufReader, Read, Write},
-    path::Path,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
     thread::sleep,
-    time,
+    time::{self, UNIX_EPOCH},
 };
 
 use chrono::Utc;
-use log::debug;
+use log::{debug, warn};
 use nix::{
     libc::dev_t,
     libc::{pid_t, uid_t},
@@ -20,10 +20,8 @@ use rar_common::{
         finder::Cred,
         options::{STimeout, TimestampType},
     },
-    util::{
-        create_dir_all_with_privileges, create_with_privileges, open_with_privileges,
-        remove_with_privileges,
-    },
+    runtime::RuntimeDirs,
+    util::{create_with_privileges, open_with_privileges, remove_with_privileges},
 };
 
 /// This module checks the validity of a user's credentials
@@ -76,6 +74,12 @@ struct Cookiev1 {
     usage: u64,
     parent_record: ParentRecord,
     auth_uid: uid_t,
+    // roles whose banner was already shown with this cookie
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    banners: Vec<String>,
+    // PAM service that authenticated the user
+    #[serde(default)]
+    service: String,
 }
 
 impl Default for Cookiev1 {
@@ -87,6 +91,8 @@ impl Default for Cookiev1 {
             usage: 0,
             parent_record: ParentRecord::default(),
             auth_uid: uid_t::MAX,
+            banners: Vec::new(),
+            service: String::new(),
         }
     }
 }
@@ -160,15 +166,29 @@ fn write_lockfile(lockfile_path: &Path) {
 }
 
 #[cfg(not(test))]
-const TS_LOCATION: &str = "/var/run/rar/ts";
+fn runtime() -> RuntimeDirs {
+    RuntimeDirs::default()
+}
 #[cfg(test)]
-const TS_LOCATION: &str = "target/ts";
+fn runtime() -> RuntimeDirs {
+    RuntimeDirs::new("target/run")
+}
+
+fn cookies_path(user: &Cred) -> PathBuf {
+    runtime()
+        .timestamps()
+        .join(user.user.uid.as_raw().to_string())
+}
+
+fn lock_path(user: &Cred) -> PathBuf {
+    runtime()
+        .locks()
+        .join(format!("ts-{}.lock", user.user.uid.as_raw()))
+}
 
 fn read_cookies(user: &Cred) -> Result<Vec<CookieVersion>, Box<dyn Error>> {
-    let path = Path::new(TS_LOCATION).join(user.user.uid.as_raw().to_string());
-    let lockpath = Path::new(TS_LOCATION)
-        .join(user.user.uid.as_raw().to_string()) // Convert u32 to String
-        .with_extension("lock");
+    let path = cookies_path(user);
+    let lockpath = lock_path(user);
     if !path.exists() {
         return Ok(Vec::new());
     }
@@ -182,11 +202,9 @@ fn read_cookies(user: &Cred) -> Result<Vec<CookieVersion>, Box<dyn Error>> {
 
 fn save_cookies(user: &Cred, cookies: &[CookieVersion]) -> Result<(), Box<dyn Error>> {
     debug!("Saving cookies: {:?}", cookies);
-    let path = Path::new(TS_LOCATION).join(user.user.uid.as_raw().to_string());
-    create_dir_all_with_privileges(path.parent().unwrap())?;
-    let lockpath = Path::new(TS_LOCATION)
-        .join(user.user.uid.as_raw().to_string())
-        .with_extension("lock");
+    runtime().init()?;
+    let path = cookies_path(user);
+    let lockpath = lock_path(user);
     let mut file = create_with_privileges(&path)?;
     ciborium::ser::into_writer(cookies, &mut file)?;
     if let Err(err) = remove_with_privileges(lockpath) {
@@ -194,15 +212,103 @@ fn save_cookies(user: &Cred, cookies: &[CookieVersion]) -> Result<(), Box<dyn Er
     }
     Ok(())
 }
+const SHADOW: &str = "/etc/shadow";
+const DAY: i64 = 86400;
+
+/// Read the day of the last password change of a user from a shadow file, none when the user
+/// has no entry or the day is not recorded
+fn shadow_last_change<R: BufRead>(shadow: R, name: &str) -> Option<i64> {
+    shadow.lines().map_while(Result::ok).find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != name {
+            return None;
+        }
+        fields.nth(1)?.parse::<i64>().ok()
+    })
+}
+
+/// Timestamp from which the current password of the user is known to be in use.
+/// Shadow records only the day of the change, the shadow file modification time narrows it
+/// when it happened the same day. Another user change that day makes it later, which only
+/// invalidates more cookies.
+fn password_change_time(name: &str) -> Result<i64, String> {
+    let file =
+        open_with_privileges(SHADOW).map_err(|e| format!("Unable to read {}: {}", SHADOW, e))?;
+    let modified = file
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64);
+    let day_start = shadow_last_change(BufReader::new(file), name)
+        .ok_or_else(|| format!("No password change recorded in {}", SHADOW))?
+        * DAY;
+    Ok(match modified {
+        Some(modified) if (day_start..day_start + DAY).contains(&modified) => modified,
+        _ => day_start,
+    })
+}
+
+fn is_cookie_valid(
+    cookie: &Cookiev1,
+    constraint: &STimeout,
+    now: i64,
+    password_change: Option<i64>,
+) -> bool {
+    let max_usage_ok =
+        constraint.max_usage.is_none() || cookie.usage < constraint.max_usage.unwrap();
+    let elapsed = now - cookie.timestamp;
+    debug!(
+        "timestamp: {}, now: {}, elapsed: {}",
+        cookie.timestamp, now, elapsed
+    );
+    // a cookie from the future would be valid for longer than the timeout
+    let timeofuse: bool =
+        elapsed >= 0 && elapsed < constraint.duration.unwrap_or_default().num_seconds();
+    // a cookie obtained with a previous password is no longer trusted, nor one whose password
+    // change cannot be known
+    let password_ok = password_change.is_some_and(|change| cookie.start_time >= change);
+    debug!(
+        "Time of use: {}, max_usage : {}, password unchanged : {}",
+        timeofuse, max_usage_ok, password_ok
+    );
+    timeofuse && max_usage_ok && password_ok
+}
+
+/// When the clock was set back, the age of every cookie is underestimated, they are all forgotten.
+fn forget_skewed(cookies: &mut Vec<CookieVersion>, now: i64) -> bool {
+    let skewed = cookies.iter().any(|cookie| match cookie {
+        CookieVersion::V1(cookie) => cookie.timestamp > now || cookie.start_time > now,
+    });
+    if skewed {
+        warn!("Authentication cookies are dated in the future, the clock may be skewed, forgetting them");
+        cookies.clear();
+    }
+    skewed
+}
+
 fn find_valid_cookie(
     from: &Cred,
     cred_asked: &Cred,
     constraint: &STimeout,
-    editcookie: fn(&mut CookieVersion),
+    service: &str,
+    mut editcookie: impl FnMut(&mut CookieVersion),
 ) -> Option<CookieVersion> {
     let mut cookies = read_cookies(from).unwrap_or_default();
     let mut to_remove = Vec::new();
     let mut res = None;
+    let now = Utc::now().timestamp();
+    forget_skewed(&mut cookies, now);
+    let password_change = password_change_time(&from.user.name)
+        .inspect_err(|e| {
+            if !cookies.is_empty() {
+                warn!(
+                    "Forgetting the authentication cookies of {}, the password may have changed: {}",
+                    from.user.name, e
+                );
+            }
+        })
+        .ok();
     debug!(
         "Constraints for {} : {:?}",
         cred_asked.user.uid.as_raw(),
@@ -214,17 +320,11 @@ fn find_valid_cookie(
                 debug!("Checking cookie: {:?}", cookie);
                 if cookie.auth_uid != cred_asked.user.uid.as_raw()
                     || cookie.timestamp_type != constraint.type_field.unwrap_or_default()
+                    || cookie.service != service
                 {
                     continue;
                 }
-                let max_usage_ok =
-                    constraint.max_usage.is_none() || cookie.usage < constraint.max_usage.unwrap();
-                debug!("timestamp: {}, now: {}, offset {}, now + offset : {}\ntimestamp-now+offset : {}", cookie.timestamp, Utc::now().timestamp(), constraint.duration.unwrap_or_default().num_seconds(), Utc::now().timestamp() + constraint.duration.unwrap_or_default().num_seconds(), cookie.timestamp - Utc::now().timestamp() + constraint.duration.unwrap_or_default().num_seconds());
-                let timeofuse: bool = cookie.timestamp - Utc::now().timestamp()
-                    + constraint.duration.unwrap_or_default().num_seconds()
-                    > 0;
-                debug!("Time of use: {}, max_usage : {}", timeofuse, max_usage_ok);
-                if timeofuse && max_usage_ok && res.is_none() {
+                if is_cookie_valid(cookie, constraint, now, password_change) && res.is_none() {
                     editcookie(cookiev);
                     res = Some(cookiev.clone());
                 } else {
@@ -233,7 +333,7 @@ fn find_valid_cookie(
             }
         }
     }
-    for a in to_remove {
+    for a in to_remove.into_iter().rev() {
         cookies.remove(a);
     }
     if let Err(e) = save_cookies(from, &cookies) {
@@ -242,13 +342,27 @@ fn find_valid_cookie(
     res
 }
 
+/// Forget the cookies of the user, once a clock skew is detected elsewhere.
+pub(crate) fn invalidate(from: &Cred) -> Result<(), Box<dyn Error>> {
+    if read_cookies(from).is_ok_and(|cookies| cookies.is_empty()) {
+        return Ok(());
+    }
+    save_cookies(from, &[])
+}
+
 /// Check if the credentials are valid
 /// @param from: the credentials of the user that want to execute a command
 /// @param cred_asked: the credentials of the user that is asked to execute a command
 /// @param max_offset: the maximum offset between the current time and the time of the credentials, including the type of the offset
+/// @param service: the PAM service the credentials must have been checked by
 /// @return true if the credentials are valid, false otherwise
-pub(crate) fn is_valid(from: &Cred, cred_asked: &Cred, constraint: &STimeout) -> bool {
-    find_valid_cookie(from, cred_asked, constraint, |_c| {
+pub(crate) fn is_valid(
+    from: &Cred,
+    cred_asked: &Cred,
+    constraint: &STimeout,
+    service: &str,
+) -> bool {
+    find_valid_cookie(from, cred_asked, constraint, service, |_c| {
         debug!("Found valid cookie ");
     })
     .is_some()
@@ -259,14 +373,25 @@ pub(crate) fn update_cookie(
     from: &Cred,
     cred_asked: &Cred,
     constraint: &STimeout,
+    service: &str,
 ) -> Result<(), Box<dyn Error>> {
-    let res = find_valid_cookie(from, cred_asked, constraint, |cookie| match cookie {
-        CookieVersion::V1(cookie) => {
-            cookie.usage += 1;
-            cookie.timestamp = Utc::now().timestamp();
-            debug!("Updating cookie: {:?}", cookie);
-        }
-    });
+    // a zero timeout asks the password each time, no cookie is kept
+    if constraint.duration.unwrap_or_default().num_seconds() <= 0 {
+        return Ok(());
+    }
+    let res = find_valid_cookie(
+        from,
+        cred_asked,
+        constraint,
+        service,
+        |cookie| match cookie {
+            CookieVersion::V1(cookie) => {
+                cookie.usage += 1;
+                cookie.timestamp = Utc::now().timestamp();
+                debug!("Updating cookie: {:?}", cookie);
+            }
+        },
+    );
     if res.is_none() {
         let mut cookies = read_cookies(from).unwrap_or_default();
         let parent_record = ParentRecord::new(&constraint.type_field.unwrap_or_default(), from);
@@ -277,6 +402,8 @@ pub(crate) fn update_cookie(
             timestamp: Utc::now().timestamp(),
             usage: 0,
             parent_record,
+            banners: Vec::new(),
+            service: service.to_string(),
         });
         cookies.insert(0, cookie);
         save_cookies(from, &cookies)?;
@@ -284,6 +411,22 @@ pub(crate) fn update_cookie(
     Ok(())
 }
 
+/// Whether the banner of the role is not shown yet in the current timestamp session, it is then
+/// recorded in the cookie. Without a valid cookie, it is shown each time.
+pub(crate) fn first_banner(from: &Cred, constraint: &STimeout, service: &str, role: &str) -> bool {
+    let mut first = true;
+    find_valid_cookie(from, from, constraint, service, |cookie| match cookie {
+        CookieVersion::V1(cookie) => {
+            if cookie.banners.iter().any(|shown| shown == role) {
+                first = false;
+            } else {
+                cookie.banners.push(role.to_string());
+            }
+        }
+    });
+    first
+}
+
 #[cfg(test)]
 mod test {
     use nix::unistd::{Pid, User};
@@ -301,6 +444,74 @@ mod test {
         assert!(wait_for_lockfile(lockpath).is_ok());
     }
 
+    #[test]
+    fn test_shadow_last_change() {
+        let shadow = "root:*:19000:0:99999:7:::\nuser1:$6$salt$hash:19500:0:99999:7:::\nuser2:!::0:99999:7:::\n";
+        assert_eq!(shadow_last_change(shadow.as_bytes(), "root"), Some(19000));
+        assert_eq!(shadow_last_change(shadow.as_bytes(), "user1"), Some(19500));
+        assert_eq!(shadow_last_change(shadow.as_bytes(), "user2"), None);
+        assert_eq!(shadow_last_change(shadow.as_bytes(), "user3"), None);
+        assert!(password_change_time("rar_no_such_user").is_err());
+    }
+
+    fn cookie(start_time: i64, timestamp: i64, usage: u64) -> Cookiev1 {
+        Cookiev1 {
+            timestamp_type: TimestampType::TTY,
+            start_time,
+            timestamp,
+            usage,
+            parent_record: ParentRecord::None,
+            auth_uid: 0,
+            banners: Vec::new(),
+            service: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_cookie_clock_changes() {
+        let constraint = STimeout {
+            type_field: Some(TimestampType::TTY),
+            duration: Some(chrono::Duration::seconds(600)),
+            max_usage: None,
+            _extra_fields: Default::default(),
+        };
+        let now = 1_700_000_000;
+        let used = cookie(now - 900, now - 300, 2);
+        let change = Some(now - 86400);
+        assert!(is_cookie_valid(&used, &constraint, now, change));
+        // the clock moved forward past the timeout
+        assert!(!is_cookie_valid(&used, &constraint, now + 300, change));
+        // the clock was set back, the cookie must not be valid for longer
+        assert!(!is_cookie_valid(&used, &constraint, now - 3600, change));
+        assert!(!is_cookie_valid(&used, &constraint, now, Some(now - 600)));
+        // shadow is unreadable or has no entry for the user
+        assert!(!is_cookie_valid(&used, &constraint, now, None));
+
+        let mut cookies = vec![CookieVersion::V1(used.clone())];
+        assert!(!forget_skewed(&mut cookies, now));
+        assert_eq!(cookies.len(), 1);
+        cookies.push(CookieVersion::V1(cookie(now - 10, now - 10, 0)));
+        assert!(forget_skewed(&mut cookies, now - 3600));
+        assert!(cookies.is_empty());
+    }
+
+    #[test]
+    fn test_zero_timeout() {
+        let constraint = STimeout {
+            type_field: Some(TimestampType::TTY),
+            duration: Some(chrono::Duration::zero()),
+            max_usage: None,
+            _extra_fields: Default::default(),
+        };
+        let now = 1_700_000_000;
+        assert!(!is_cookie_valid(
+            &cookie(now, now, 0),
+            &constraint,
+            now,
+            Some(0)
+        ));
+    }
+
     #[test]
     fn test_cookie() {
         let cred = Cred {
@@ -308,6 +519,7 @@ mod test {
             groups: vec![],
             tty: None,
             ppid: Pid::parent(),
+            orchestrated: false,
         };
         let constraint = STimeout {
             type_field: Some(TimestampType::TTY),
@@ -315,10 +527,47 @@ mod test {
             max_usage: Some(1),
             _extra_fields: Default::default(),
         };
-        assert!(!is_valid(&cred, &cred, &constraint));
-        assert!(update_cookie(&cred, &cred, &constraint).is_ok());
-        assert!(is_valid(&cred, &cred, &constraint));
-        assert!(update_cookie(&cred, &cred, &constraint).is_ok());
-        assert!(!is_valid(&cred, &cred, &constraint));
+        assert!(!is_valid(&cred, &cred, &constraint, "sr"));
+        assert!(update_cookie(&cred, &cred, &constraint, "sr").is_ok());
+        assert!(is_valid(&cred, &cred, &constraint, "sr"));
+        // a second factor is not implied by a cookie of another service
+        assert!(!is_valid(&cred, &cred, &constraint, "sr-totp"));
+        assert!(update_cookie(&cred, &cred, &constraint, "sr").is_ok());
+        assert!(!is_valid(&cred, &cred, &constraint, "sr"));
+    }
+
+    #[test]
+    fn test_first_banner() {
+        let cred = Cred {
+            user: User::from_uid(0.into()).unwrap().unwrap(),
+            groups: vec![],
+            tty: None,
+            ppid: Pid::parent(),
+            orchestrated: false,
+        };
+        let constraint = STimeout {
+            type_field: Some(TimestampType::PPID),
+            duration: Some(chrono::Duration::seconds(10)),
+            max_usage: None,
+            _extra_fields: Default::default(),
+        };
+        let role = format!("r_db{}", std::process::id());
+        // a zero timeout keeps no cookie, the banner is always shown
+        let zero = STimeout {
+            type_field: Some(TimestampType::UID),
+            duration: Some(chrono::Duration::zero()),
+            ..constraint.clone()
+        };
+        assert!(first_banner(&cred, &zero, "sr", &role));
+        assert!(first_banner(&cred, &zero, "sr", &role));
+        update_cookie(&cred, &cred, &constraint, "sr").unwrap();
+        assert!(first_banner(&cred, &constraint, "sr", &role));
+        assert!(!first_banner(&cred, &constraint, "sr", &role));
+        assert!(first_banner(
+            &cred,
+            &constraint,
+            "sr",
+            &format!("{}_web", role)
+        ));
     }
 }
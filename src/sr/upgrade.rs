@@ -0,0 +1,149 @@
+//! Detection of an upgrade of sr or of the policy while the user authenticates.
+//!
+//! The decision is made before the authentication, which may wait for the user for a long
+//! time. When the sr binary or a policy file is replaced meanwhile, sr executes itself again
+//! with the same arguments and environment, so that the command is evaluated by the new binary
+//! with the new policy. It is only done once: a second change is refused.
+use std::{
+    convert::Infallible,
+    error::Error,
+    ffi::{CString, OsString},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use nix::unistd::execve;
+
+const SELF_EXE: &str = "/proc/self/exe";
+const REEXEC_ENV: &str = "RAR_REEXEC";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+}
+
+fn file_id(path: &Path) -> Option<FileId> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some(FileId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        size: metadata.size(),
+        mtime: (metadata.mtime(), metadata.mtime_nsec()),
+        ctime: (metadata.ctime(), metadata.ctime_nsec()),
+    })
+}
+
+#[derive(Debug)]
+pub struct Snapshot {
+    exe: Option<PathBuf>,
+    files: Vec<(PathBuf, Option<FileId>)>,
+    args: Vec<OsString>,
+    env: Vec<(OsString, OsString)>,
+    reexecuted: bool,
+}
+
+impl Snapshot {
+    /// Records the identity of sr and of the given policy files, with the arguments and the
+    /// environment, which are given again to the new binary.
+    pub fn take<P: AsRef<Path>>(policies: &[P]) -> Self {
+        let exe = std::fs::read_link(SELF_EXE).ok();
+        let reexecuted = std::env::var_os(REEXEC_ENV).is_some();
+        // the marker must not reach the command
+        std::env::remove_var(REEXEC_ENV);
+        let files = exe
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(policies.iter().map(AsRef::as_ref))
+            .map(|path| (path.to_path_buf(), file_id(path)))
+            .collect();
+        Snapshot {
+            exe,
+            files,
+            args: std::env::args_os().collect(),
+            env: std::env::vars_os().collect(),
+            reexecuted,
+        }
+    }
+
+    /// The files replaced or modified since the snapshot.
+    pub fn changed(&self) -> Vec<&Path> {
+        self.files
+            .iter()
+            .filter(|(path, id)| file_id(path) != *id)
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+
+    /// Executes sr again when it or the policy changed, Ok when nothing changed.
+    pub fn reexec_if_changed(&self) -> Result<(), Box<dyn Error>> {
+        let changed = self.changed();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let names = changed
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.reexecuted {
+            return Err(format!("{} changed again during the authentication", names).into());
+        }
+        warn!(
+            "{} changed during the authentication, evaluating the command again",
+            names
+        );
+        self.reexec()?;
+        Ok(())
+    }
+
+    fn reexec(&self) -> Result<Infallible, Box<dyn Error>> {
+        let exe = self
+            .exe
+            .as_ref()
+            .ok_or("Unable to find the sr executable")?;
+        let cstring = |s: &[u8]| CString::new(s);
+        let args = self
+            .args
+            .iter()
+            .map(|arg| cstring(arg.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let env = self
+            .env
+            .iter()
+            .filter(|(key, _)| key != REEXEC_ENV)
+            .map(|(key, value)| cstring(&[key.as_bytes(), b"=", value.as_bytes()].concat()))
+            .chain([CString::new(format!("{}=1", REEXEC_ENV))])
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(execve(&cstring(exe.as_os_str().as_bytes())?, &args, &env)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let path = std::env::temp_dir().join("rar_upgrade_test.json");
+        fs::write(&path, "{}").unwrap();
+        let snapshot = Snapshot::take(&[&path]);
+        assert!(snapshot.changed().is_empty());
+        assert!(snapshot.reexec_if_changed().is_ok());
+        // replaced like a package manager does
+        let new = path.with_extension("new");
+        fs::write(&new, "{\"roles\":[]}").unwrap();
+        fs::rename(&new, &path).unwrap();
+        assert_eq!(snapshot.changed(), [path.as_path()]);
+        let mut snapshot = snapshot;
+        snapshot.reexecuted = true;
+        assert!(snapshot.reexec_if_changed().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
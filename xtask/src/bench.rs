@@ -0,0 +1,45 @@
+use std::process::Command;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct BenchOptions {
+    /// Only run the benchmarks whose name contains this filter, e.g. matching/huge
+    pub filter: Option<String>,
+
+    /// Save the results as the baseline of the next runs
+    #[clap(long)]
+    pub save_baseline: bool,
+
+    /// Fail when a benchmark is slower than the baseline
+    #[clap(long)]
+    pub check: bool,
+}
+
+pub fn bench(opts: &BenchOptions) -> Result<(), anyhow::Error> {
+    let mut command = Command::new("cargo");
+    command
+        .arg("bench")
+        .arg("--package")
+        .arg("rootasrole-core")
+        .arg("--features")
+        .arg("finder,pcre2")
+        .arg("--bench")
+        .arg("policy")
+        .arg("--");
+    if let Some(filter) = &opts.filter {
+        command.arg(filter);
+    }
+    if opts.save_baseline {
+        command.arg("--save-baseline");
+    }
+    if opts.check {
+        command.arg("--check");
+    }
+    let status = command.status().context("failed to run cargo bench")?;
+    if !status.success() {
+        bail!("benchmarks failed");
+    }
+    Ok(())
+}
@@ -9,7 +9,6 @@ use capctl::{Cap, CapSet};
 use log::{debug, error, info};
 use nix::sys::stat::{fchmod, Mode};
 use nix::unistd::{Gid, Uid};
-use nix::NixPath;
 use strum::EnumIs;
 
 use crate::installer::Profile;
@@ -89,7 +88,7 @@ fn copy_docs() -> Result<(), anyhow::Error> {
                 anyhow!("Failed to get the file name")
             })?;
         let lang = file.parent();
-        if lang.is_some_and(|p| !p.is_empty()) {
+        if lang.is_some_and(|p| p.components().next().is_some()) {
             let lang = lang.unwrap();
             //println!("lang: {:?}", lang);
             let lang = lang.file_name().ok_or_else(|| {
@@ -140,6 +139,11 @@ fn setfcap() -> Result<(), anyhow::Error> {
     let mut file_caps = capctl::caps::FileCaps::empty();
     file_caps.permitted = !CapSet::empty();
     file_caps.set_for_file(SR_DEST)?;
+    // chsr reads the policy for chsr list --mine, the capabilities granted by sr are inherited
+    let mut file_caps = capctl::caps::FileCaps::empty();
+    file_caps.permitted.add(Cap::DAC_READ_SEARCH);
+    file_caps.inheritable = !CapSet::empty();
+    file_caps.set_for_file(CHSR_DEST)?;
     Ok(())
 }
 
@@ -250,8 +254,8 @@ pub fn install(
     // drop chown, raise setfcap capabilities
     cap_effective(&mut state, Cap::SETFCAP).context("Failed to raise SETFCAP")?;
 
-    // set file capabilities for sr only
-    setfcap().context("Failed to set file capabilities on /usr/bin/sr")?;
+    // set file capabilities for sr and chsr
+    setfcap().context("Failed to set file capabilities on /usr/bin/sr and /usr/bin/chsr")?;
 
     // drop all capabilities
     cap_clear(&mut state).context("Failed to drop effective capabilities")?;
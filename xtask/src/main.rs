@@ -1,3 +1,4 @@
+mod bench;
 mod configure;
 mod deploy;
 mod installer;
@@ -32,6 +33,7 @@ enum Command {
     Uninstall(installer::UninstallOptions),
     #[cfg(feature = "deploy")]
     Deploy(deploy::MakeOptions),
+    Bench(bench::BenchOptions),
 }
 
 fn main() {
@@ -48,6 +50,7 @@ fn main() {
         Configure { os } => installer::configure(os),
         Uninstall(opts) => installer::uninstall(&opts),
         Deploy(opts) => deploy::deploy(&opts),
+        Bench(opts) => bench::bench(&opts),
     };
 
     if let Err(e) = ret {